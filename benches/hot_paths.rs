@@ -0,0 +1,206 @@
+//! Benchmarks of the hot paths: tailing, matching, sharing lines between
+//! threads and running commands, over a generated pgbouncer-like log.
+//!
+//! `cargo bench` runs them all, `cargo bench -- matching` those whose name
+//! contains `matching`. Each prints the mean time per iteration and the
+//! throughput, as a baseline to compare changes to the tailer and matcher
+//! against.
+
+use std::{
+    fs::File,
+    hint::black_box,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use log_watchdog::{pipeline::Matcher, Lines, Tailer};
+
+/// Lines in the generated log, about 10 MB of it.
+const LINES: usize = 100_000;
+
+/// How long each benchmark runs for, after a warm-up iteration.
+const MEASURE_FOR: Duration = Duration::from_secs(3);
+
+fn main() {
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let dir = tempdir::TempDir::new("log-watchdog-bench").unwrap();
+    let log = dir.path().join("pgbouncer.log");
+    write_log(&log);
+    let size = std::fs::metadata(&log).unwrap().len();
+    let lines = Tailer::open_at(&log, 0).unwrap().poll_lines().unwrap();
+    let batches = batches(&lines, 1000);
+    let dispatched = dispatch_log(dir.path(), &lines, 200);
+    let regex = regex::bytes::Regex::new(REGEX).unwrap();
+    assert_eq!(
+        lines.iter().filter(|line| regex.is_match(line)).count(),
+        LINES / 100
+    );
+
+    let benches: [(&str, Bench); 5] = [
+        (
+            "tail",
+            Box::new(|| {
+                let mut tailer = Tailer::open_at(&log, 0).unwrap();
+                while !black_box(tailer.poll_lines().unwrap()).is_empty() {}
+                Throughput::Bytes(size)
+            }),
+        ),
+        (
+            "matching bytes",
+            Box::new(|| {
+                let regex = regex::bytes::Regex::new(REGEX).unwrap();
+                for line in lines.iter() {
+                    black_box(regex.captures_bytes(line));
+                }
+                Throughput::Lines(lines.len())
+            }),
+        ),
+        (
+            "matching strings",
+            Box::new(|| {
+                let regex = regex::Regex::new(REGEX).unwrap();
+                for line in lines.iter() {
+                    black_box(Matcher::captures(&regex, &String::from_utf8_lossy(line)));
+                }
+                Throughput::Lines(lines.len())
+            }),
+        ),
+        ("fan-out", Box::new(|| fan_out(&batches, 8))),
+        (
+            "command dispatch",
+            Box::new(|| dispatch(dir.path(), &dispatched, 200)),
+        ),
+    ];
+
+    for (name, bench) in &benches {
+        if filter
+            .as_ref()
+            .is_none_or(|filter| name.contains(filter.as_str()))
+        {
+            run(name, bench);
+        }
+    }
+}
+
+/// Matches 1 in 100 lines, with a capture, like a typical watchdog.
+const REGEX: &str = r"^\S+ \S+ UTC \[\d+\] (?<level>FATAL|ERROR) ";
+
+fn write_log(path: &Path) {
+    let mut log = BufWriter::new(File::create(path).unwrap());
+    for i in 0..LINES {
+        let level = if i % 100 == 0 { "FATAL" } else { "LOG" };
+        writeln!(
+            log,
+            "2024-11-05 10:{:02}:{:02}.{:03} UTC [{}] {level} C-0x{i:x}: db/user@10.0.{}.{}:5432 \
+             closing because: client close request (age={}s)",
+            i / 60 % 60,
+            i % 60,
+            i % 1000,
+            1000 + i % 7,
+            i % 256,
+            i % 100,
+            i % 3600,
+        )
+        .unwrap();
+    }
+    log.flush().unwrap();
+}
+
+/// The lines in batches of `size`, like a busy log is read.
+fn batches(lines: &Lines, size: usize) -> Vec<Lines> {
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect();
+    lines
+        .chunks(size)
+        .map(|batch| Lines::from(batch.to_vec()))
+        .collect()
+}
+
+/// Sends every batch to `threads` threads sharing them.
+fn fan_out(batches: &[Lines], threads: usize) -> Throughput {
+    let receivers: Vec<_> = (0..threads)
+        .map(|_| {
+            let (tx, rx) = mpsc::channel::<Lines>();
+            let receiver =
+                std::thread::spawn(move || rx.iter().map(|lines| lines.len()).sum::<usize>());
+            (tx, receiver)
+        })
+        .collect();
+    for batch in batches {
+        for (tx, _) in &receivers {
+            tx.send(batch.clone()).unwrap();
+        }
+    }
+    let received: usize = receivers
+        .into_iter()
+        .map(|(tx, receiver)| {
+            drop(tx);
+            receiver.join().unwrap()
+        })
+        .sum();
+    assert_eq!(
+        received,
+        batches.iter().map(Lines::len).sum::<usize>() * threads
+    );
+    Throughput::Lines(received)
+}
+
+/// The start of the log, with `matches` matches.
+fn dispatch_log(dir: &Path, lines: &Lines, matches: usize) -> PathBuf {
+    let path = dir.join("dispatch.log");
+    let mut log = BufWriter::new(File::create(&path).unwrap());
+    for line in lines.iter().take(matches * 100) {
+        log.write_all(line).unwrap();
+        log.write_all(b"\n").unwrap();
+    }
+    log.flush().unwrap();
+    path
+}
+
+/// Scans the log of [`dispatch_log`], running `true` for each match.
+fn dispatch(dir: &Path, log: &Path, matches: usize) -> Throughput {
+    let watchdog = settings::Watchdog::builder()
+        .name("bench")
+        .log_file(log)
+        .regex(REGEX)
+        .output_file(dir.join("bench.out"))
+        .command("true", Vec::<String>::new())
+        .build()
+        .unwrap();
+    log_watchdog::run_once(settings::Settings::new(vec![watchdog]), None).unwrap();
+    Throughput::Matches(matches)
+}
+
+type Bench<'a> = Box<dyn Fn() -> Throughput + 'a>;
+
+enum Throughput {
+    Bytes(u64),
+    Lines(usize),
+    Matches(usize),
+}
+
+fn run(name: &str, bench: impl Fn() -> Throughput) {
+    bench();
+    let started = Instant::now();
+    let mut iterations = 0_u32;
+    let mut throughput = None;
+    while started.elapsed() < MEASURE_FOR {
+        throughput = Some(bench());
+        iterations += 1;
+    }
+    let mean = started.elapsed() / iterations;
+    let per_second = |count: f64| count / mean.as_secs_f64();
+    let rate = match throughput {
+        Some(Throughput::Bytes(bytes)) => format!("{:.1} MB/s", per_second(bytes as f64) / 1e6),
+        Some(Throughput::Lines(lines)) => format!("{:.0} lines/s", per_second(lines as f64)),
+        Some(Throughput::Matches(matches)) => {
+            format!("{:.0} matches/s", per_second(matches as f64))
+        }
+        None => String::new(),
+    };
+    println!("{name:<20} {mean:>12.3?}/iter {rate:>20}  ({iterations} iterations)");
+}
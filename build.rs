@@ -0,0 +1,51 @@
+//! Records what `--version` reports about the build: the git commit, the build
+//! date and the enabled features.
+
+use std::process::Command;
+
+use chrono::DateTime;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map_or_else(
+            || "unknown".to_string(),
+            |output| String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        );
+    println!("cargo:rustc-env=LOG_WATCHDOG_COMMIT={commit}");
+
+    // SOURCE_DATE_EPOCH keeps reproducible builds reproducible
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs().try_into().unwrap_or(0))
+        });
+    let date = DateTime::from_timestamp(timestamp, 0).map_or_else(
+        || "unknown".to_string(),
+        |date| date.format("%Y-%m-%d").to_string(),
+    );
+    println!("cargo:rustc-env=LOG_WATCHDOG_BUILD_DATE={date}");
+
+    let mut features: Vec<_> = std::env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .filter(|feature| *feature != "DEFAULT")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    println!(
+        "cargo:rustc-env=LOG_WATCHDOG_FEATURES={}",
+        features.join(",")
+    );
+}
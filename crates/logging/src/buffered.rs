@@ -0,0 +1,252 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use log::{Level, Record};
+use log4rs::append::Append;
+
+/// An appender that hands records to a background thread through a bounded
+/// queue, so encoding and writing them never blocks the logging thread.
+///
+/// When the queue is full, records are dropped rather than waited for. The
+/// number of dropped records is counted, and reported with a warning once the
+/// queue has room again.
+///
+/// The logging thread's MDC is copied along with each record, so structured
+/// fields like the watchdog name survive the hand-off.
+#[derive(Debug)]
+pub struct BufferedAppender {
+    sender: Option<SyncSender<Message>>,
+    dropped: Arc<AtomicU64>,
+    worker: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+enum Message {
+    Record(OwnedRecord),
+    Flush(mpsc::Sender<()>),
+}
+
+/// The parts of a [`Record`] that are written by the appenders, owned so they
+/// can be sent to the background thread.
+#[derive(Debug)]
+struct OwnedRecord {
+    level: Level,
+    target: String,
+    message: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    mdc: Vec<(String, String)>,
+}
+
+impl OwnedRecord {
+    fn new(record: &Record) -> Self {
+        let mut mdc = Vec::new();
+        log_mdc::iter(|key, value| mdc.push((key.to_string(), value.to_string())));
+
+        Self {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            module_path: record.module_path().map(ToString::to_string),
+            file: record.file().map(ToString::to_string),
+            line: record.line(),
+            mdc,
+        }
+    }
+
+    fn append_to(&self, inner: &dyn Append) -> anyhow::Result<()> {
+        log_mdc::clear();
+        log_mdc::extend(self.mdc.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        inner.append(
+            &Record::builder()
+                .level(self.level)
+                .target(&self.target)
+                .module_path(self.module_path.as_deref())
+                .file(self.file.as_deref())
+                .line(self.line)
+                .args(format_args!("{}", self.message))
+                .build(),
+        )
+    }
+}
+
+impl BufferedAppender {
+    /// Wraps `inner`, queueing at most `capacity` records for it.
+    pub fn new(inner: Box<dyn Append>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let worker = {
+            let dropped = Arc::clone(&dropped);
+            std::thread::spawn(move || write_records(inner.as_ref(), &receiver, &dropped))
+        };
+
+        Self {
+            sender: Some(sender),
+            dropped,
+            worker: Some(worker),
+        }
+    }
+
+    /// Number of records dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Writes queued records until the appender is dropped.
+fn write_records(inner: &dyn Append, receiver: &Receiver<Message>, dropped: &AtomicU64) {
+    let mut reported = 0;
+    for message in receiver {
+        let total = dropped.load(Ordering::Relaxed);
+        if total > reported {
+            let _ = inner.append(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target(module_path!())
+                    .args(format_args!(
+                        "dropped {} log records, the log buffer was full",
+                        total - reported
+                    ))
+                    .build(),
+            );
+            reported = total;
+        }
+
+        match message {
+            Message::Record(record) => {
+                // there is nowhere left to report a failing appender
+                let _ = record.append_to(inner);
+            }
+            Message::Flush(done) => {
+                inner.flush();
+                let _ = done.send(());
+            }
+        }
+    }
+    inner.flush();
+}
+
+impl Append for BufferedAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let Some(sender) = &self.sender else {
+            return Ok(());
+        };
+
+        match sender.try_send(Message::Record(OwnedRecord::new(record))) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                Err(anyhow::anyhow!("log buffer writer thread stopped"))
+            }
+        }
+    }
+
+    /// Waits until every record queued so far is written.
+    fn flush(&self) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let (done, wait) = mpsc::channel();
+        if sender.send(Message::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+impl Drop for BufferedAppender {
+    fn drop(&mut self) {
+        // closing the queue lets the worker write what's left and exit
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    #[derive(Debug, Default, Clone)]
+    struct Collect(Arc<Mutex<Vec<String>>>, Arc<Mutex<()>>);
+
+    impl Collect {
+        /// Holds up the worker thread until the guard is dropped.
+        fn block(&self) -> MutexGuard<'_, ()> {
+            self.1.lock().unwrap()
+        }
+    }
+
+    impl Append for Collect {
+        fn append(&self, record: &Record) -> anyhow::Result<()> {
+            let _blocked = self.1.lock().unwrap();
+            let mut message = record.args().to_string();
+            log_mdc::get("watchdog", |watchdog| {
+                if let Some(watchdog) = watchdog {
+                    message = format!("{watchdog}: {message}");
+                }
+            });
+            self.0.lock().unwrap().push(message);
+            Ok(())
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn append(appender: &BufferedAppender, message: &str) {
+        appender
+            .append(
+                &Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{message}"))
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_when_flushed_then_records_written_with_mdc() {
+        let collect = Collect::default();
+        let appender = BufferedAppender::new(Box::new(collect.clone()), 16);
+
+        let _mdc = log_mdc::insert_scoped("watchdog", "pgbouncer");
+        append(&appender, "match found");
+        appender.flush();
+
+        assert_eq!(*collect.0.lock().unwrap(), vec!["pgbouncer: match found"]);
+    }
+
+    #[test]
+    fn test_when_queue_full_then_dropped_and_reported() {
+        let collect = Collect::default();
+        let appender = BufferedAppender::new(Box::new(collect.clone()), 1);
+
+        let blocked = collect.block();
+        // the worker may already have taken the first record off the queue
+        for i in 0..5 {
+            append(&appender, &format!("record {i}"));
+        }
+        assert!(appender.dropped() >= 3);
+        drop(blocked);
+        appender.flush();
+
+        append(&appender, "after");
+        drop(appender);
+
+        let records = collect.0.lock().unwrap().clone();
+        assert!(records
+            .iter()
+            .any(|r| r.starts_with("dropped ") && r.ends_with("the log buffer was full")));
+        assert_eq!(records.last().unwrap(), "after");
+    }
+}
@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use log::Level;
+
+use crate::ParseError;
+
+/// Syslog facility, as defined by RFC 5424.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Facility {
+    Kern,
+    User,
+    Mail,
+    #[default]
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    Authpriv,
+    Ftp,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl Facility {
+    pub(crate) const fn code(self) -> u8 {
+        match self {
+            Self::Kern => 0,
+            Self::User => 1,
+            Self::Mail => 2,
+            Self::Daemon => 3,
+            Self::Auth => 4,
+            Self::Syslog => 5,
+            Self::Lpr => 6,
+            Self::News => 7,
+            Self::Uucp => 8,
+            Self::Cron => 9,
+            Self::Authpriv => 10,
+            Self::Ftp => 11,
+            Self::Local0 => 16,
+            Self::Local1 => 17,
+            Self::Local2 => 18,
+            Self::Local3 => 19,
+            Self::Local4 => 20,
+            Self::Local5 => 21,
+            Self::Local6 => 22,
+            Self::Local7 => 23,
+        }
+    }
+}
+
+impl std::fmt::Display for Facility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Kern => "kern",
+            Self::User => "user",
+            Self::Mail => "mail",
+            Self::Daemon => "daemon",
+            Self::Auth => "auth",
+            Self::Syslog => "syslog",
+            Self::Lpr => "lpr",
+            Self::News => "news",
+            Self::Uucp => "uucp",
+            Self::Cron => "cron",
+            Self::Authpriv => "authpriv",
+            Self::Ftp => "ftp",
+            Self::Local0 => "local0",
+            Self::Local1 => "local1",
+            Self::Local2 => "local2",
+            Self::Local3 => "local3",
+            Self::Local4 => "local4",
+            Self::Local5 => "local5",
+            Self::Local6 => "local6",
+            Self::Local7 => "local7",
+        })
+    }
+}
+
+impl FromStr for Facility {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "kern" => Self::Kern,
+            "user" => Self::User,
+            "mail" => Self::Mail,
+            "daemon" => Self::Daemon,
+            "auth" => Self::Auth,
+            "syslog" => Self::Syslog,
+            "lpr" => Self::Lpr,
+            "news" => Self::News,
+            "uucp" => Self::Uucp,
+            "cron" => Self::Cron,
+            "authpriv" => Self::Authpriv,
+            "ftp" => Self::Ftp,
+            "local0" => Self::Local0,
+            "local1" => Self::Local1,
+            "local2" => Self::Local2,
+            "local3" => Self::Local3,
+            "local4" => Self::Local4,
+            "local5" => Self::Local5,
+            "local6" => Self::Local6,
+            "local7" => Self::Local7,
+            _ => return Err(ParseError::InvalidFacility(s.into())),
+        })
+    }
+}
+
+/// Syslog severity for a log level.
+pub const fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_facility_then_parsed_case_insensitively() {
+        assert_eq!(Facility::from_str("LOCAL3").unwrap(), Facility::Local3);
+        assert_eq!(Facility::from_str("daemon").unwrap(), Facility::Daemon);
+        assert_eq!(
+            Facility::from_str("local8"),
+            Err(ParseError::InvalidFacility("local8".into()))
+        );
+    }
+}
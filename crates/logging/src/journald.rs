@@ -0,0 +1,199 @@
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+use log::Record;
+use log4rs::append::Append;
+use log4rs::config::{Deserialize, Deserializers};
+
+use crate::facility::severity;
+
+/// Socket journald listens on for its native protocol.
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// An appender that sends records to journald using its native protocol.
+///
+/// Besides `MESSAGE` and `PRIORITY`, every MDC entry of the logging thread is
+/// sent as an uppercased field, so `log_mdc::insert("watchdog", "pgbouncer")`
+/// allows filtering with `journalctl WATCHDOG=pgbouncer`.
+#[derive(Debug)]
+pub struct JournaldAppender {
+    path: PathBuf,
+    socket: UnixDatagram,
+}
+
+impl JournaldAppender {
+    /// Connects to the journald socket.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the socket can't be created.
+    pub fn new() -> io::Result<Self> {
+        Self::with_path(JOURNALD_SOCKET)
+    }
+
+    /// Connects to a journald socket at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the socket can't be created.
+    pub fn with_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(&path)?;
+        Ok(Self { path, socket })
+    }
+
+    fn encode(record: &Record) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        write_field(&mut buf, "MESSAGE", &record.args().to_string())?;
+        write_field(&mut buf, "PRIORITY", &severity(record.level()).to_string())?;
+        write_field(&mut buf, "SYSLOG_IDENTIFIER", "log-watchdog")?;
+        write_field(&mut buf, "TARGET", record.target())?;
+        if let Some(file) = record.file() {
+            write_field(&mut buf, "CODE_FILE", file)?;
+        }
+        if let Some(line) = record.line() {
+            write_field(&mut buf, "CODE_LINE", &line.to_string())?;
+        }
+
+        let mut result = Ok(());
+        log_mdc::iter(|key, value| {
+            if result.is_ok() {
+                result = write_field(&mut buf, &field_name(key), value);
+            }
+        });
+        result?;
+
+        Ok(buf)
+    }
+}
+
+/// Writes a field in the native protocol's format, values containing a
+/// newline are length-prefixed.
+fn write_field(buf: &mut Vec<u8>, name: &str, value: &str) -> io::Result<()> {
+    if value.contains('\n') {
+        writeln!(buf, "{name}")?;
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    } else {
+        writeln!(buf, "{name}={value}")?;
+    }
+    Ok(())
+}
+
+/// Journald field names may only contain uppercase letters, digits and
+/// underscores, and can't start with an underscore.
+fn field_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    name.trim_start_matches('_').to_string()
+}
+
+impl Append for JournaldAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let message = Self::encode(record)?;
+        if let Err(e) = self.socket.send(&message) {
+            // journald may have restarted, reconnect once before giving up
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(&self.path).map_err(|_| e)?;
+            socket.send(&message)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+/// Configuration of a `kind: journald` appender in a log4rs config file.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JournaldAppenderConfig {
+    path: Option<PathBuf>,
+}
+
+/// Deserializes `kind: journald` appenders in a log4rs config file.
+#[derive(Debug)]
+pub struct JournaldAppenderDeserializer;
+
+impl Deserialize for JournaldAppenderDeserializer {
+    type Trait = dyn Append;
+    type Config = JournaldAppenderConfig;
+
+    fn deserialize(
+        &self,
+        config: JournaldAppenderConfig,
+        _: &Deserializers,
+    ) -> anyhow::Result<Box<dyn Append>> {
+        let appender = match config.path {
+            Some(path) => JournaldAppender::with_path(path)?,
+            None => JournaldAppender::new()?,
+        };
+        Ok(Box::new(appender))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_when_field_name_then_sanitized() {
+        assert_eq!(field_name("watchdog"), "WATCHDOG");
+        assert_eq!(field_name("match-id"), "MATCH_ID");
+        assert_eq!(field_name("_file"), "FILE");
+    }
+
+    #[test]
+    fn test_when_multiline_value_then_length_prefixed() {
+        let mut buf = Vec::new();
+        write_field(&mut buf, "MESSAGE", "a\nb").unwrap();
+
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_when_record_appended_then_mdc_fields_are_sent() {
+        let dir = std::env::temp_dir().join(format!("journald-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("journal.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let appender = JournaldAppender::with_path(&path).unwrap();
+        log_mdc::insert("watchdog", "pgbouncer");
+        appender
+            .append(
+                &Record::builder()
+                    .level(Level::Error)
+                    .target("log_watchdog")
+                    .args(format_args!("command failed"))
+                    .build(),
+            )
+            .unwrap();
+        log_mdc::clear();
+
+        let mut buf = [0; 512];
+        let n = server.recv(&mut buf).unwrap();
+        let message = String::from_utf8_lossy(&buf[..n]);
+
+        assert!(message.contains("MESSAGE=command failed\n"));
+        assert!(message.contains("PRIORITY=3\n"));
+        assert!(message.contains("TARGET=log_watchdog\n"));
+        assert!(message.contains("WATCHDOG=pgbouncer\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
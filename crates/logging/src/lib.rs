@@ -1,23 +1,698 @@
-use log::LevelFilter;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
 use log4rs::append::console::ConsoleAppender;
-use log4rs::config::{Appender, Root};
+use log4rs::append::rolling_file::policy::compound::roll::delete::DeleteRoller;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::roll::Roll;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::trigger::time::{
+    TimeTrigger, TimeTriggerConfig,
+};
+use log4rs::append::rolling_file::policy::compound::trigger::Trigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::config::{Appender, Deserializers, Logger, Root};
 use log4rs::encode::json::JsonEncoder;
-use log4rs::Handle;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::Encode;
+use thiserror::Error;
+
+pub use log::LevelFilter;
+pub use log4rs::append::Append;
+pub use log4rs::Handle;
+
+mod buffered;
+mod facility;
+#[cfg(unix)]
+mod journald;
+mod repeat;
+mod sample;
+#[cfg(unix)]
+mod syslog;
+
+pub use buffered::BufferedAppender;
+pub use facility::Facility;
+#[cfg(unix)]
+pub use journald::{JournaldAppender, JournaldAppenderDeserializer};
+pub use repeat::RepeatSuppressingAppender;
+pub use sample::Sampler;
+#[cfg(unix)]
+pub use syslog::{SyslogAppender, SyslogAppenderDeserializer};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("invalid log level: {0}")]
+    InvalidLevel(String),
+    #[error("invalid module filter: {0}")]
+    InvalidFilter(String),
+    #[error("invalid log rotation: {0}, expected `daily` or a size like `10MB`")]
+    InvalidRotation(String),
+    #[error("invalid log format: {0}, expected `json` or `pretty`")]
+    InvalidFormat(String),
+    #[error("invalid syslog facility: {0}")]
+    InvalidFacility(String),
+}
+
+/// How often an ongoing run of repeated records is summarized by default.
+pub const DEFAULT_REPEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How many records are queued for each appender by default.
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+
+/// Human readable single-line format, the level is only colored on a TTY.
+const PRETTY_PATTERN: &str = "{d(%Y-%m-%d %H:%M:%S%.3f)} {h({l:>5})} {t} - {m}{n}";
+
+/// Where, and how, the daemon's own logs are written.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    /// Level of the daemon's own logs
+    pub level: LogLevel,
+    /// If true, log to stdout
+    pub stdout: bool,
+    /// Format of the stdout logs; the log file is always JSON
+    pub format: LogFormat,
+    /// Rolling log file to write to, if any
+    pub file: Option<LogFile>,
+    /// Facility to send logs to the local syslog daemon with, if any
+    #[cfg(unix)]
+    pub syslog: Option<Facility>,
+    /// If true, send logs to journald with structured fields
+    #[cfg(unix)]
+    pub journald: bool,
+    /// Collapse runs of identical records, writing a "last message repeated N
+    /// times" summary at most this often; `None` logs every record
+    pub repeat_interval: Option<Duration>,
+    /// Levels of individual watchdogs' records, by watchdog name; a filter
+    /// for the watchdog's target in `level` takes precedence
+    pub watchdog_levels: Vec<(String, LevelFilter)>,
+    /// Write records from a background thread, queueing at most this many
+    /// and dropping records when the queue is full; `None` writes them on the
+    /// logging thread
+    pub buffer_size: Option<usize>,
+    /// A log4rs config file to use instead of all of the above
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::default(),
+            stdout: true,
+            format: LogFormat::default(),
+            file: None,
+            #[cfg(unix)]
+            syslog: None,
+            #[cfg(unix)]
+            journald: false,
+            repeat_interval: Some(DEFAULT_REPEAT_INTERVAL),
+            watchdog_levels: Vec::new(),
+            buffer_size: Some(DEFAULT_BUFFER_SIZE),
+            config_file: None,
+        }
+    }
+}
+
+/// How log records are encoded on the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// One JSON object per record, for production log collection
+    #[default]
+    Json,
+    /// Single-line human readable records, for interactive debugging
+    Pretty,
+}
+
+impl LogFormat {
+    fn encoder(self) -> Box<dyn Encode> {
+        match self {
+            Self::Json => Box::new(JsonEncoder::new()),
+            Self::Pretty => Box::new(PatternEncoder::new(PRETTY_PATTERN)),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "pretty" => Ok(Self::Pretty),
+            _ => Err(ParseError::InvalidFormat(s.into())),
+        }
+    }
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Json => "json",
+            Self::Pretty => "pretty",
+        })
+    }
+}
+
+/// A log file that is rolled over according to its rotation policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFile {
+    /// Path to the active log file; rolled files are suffixed with `.1`, `.2`, ...
+    pub path: PathBuf,
+    /// When to roll the log file over
+    pub rotation: Rotation,
+    /// Number of rolled files to keep, 0 deletes them on rotation
+    pub retention: u32,
+}
+
+/// When a log file is rolled over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// Roll the file once per day
+    #[default]
+    Daily,
+    /// Roll the file when it exceeds this many bytes
+    Size(u64),
+}
+
+impl FromStr for Rotation {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("daily") {
+            return Ok(Self::Daily);
+        }
+
+        let upper = s.to_ascii_uppercase();
+        let (digits, multiplier) = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)]
+            .into_iter()
+            .find_map(|(suffix, multiplier)| {
+                upper
+                    .strip_suffix(suffix)
+                    .map(|digits| (digits.trim(), multiplier))
+            })
+            .unwrap_or((upper.as_str(), 1));
+
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(multiplier))
+            .filter(|n| *n > 0)
+            .map(Self::Size)
+            .ok_or_else(|| ParseError::InvalidRotation(s.into()))
+    }
+}
+
+/// `daily`, or the size in the largest unit it's a whole number of, e.g.
+/// `10MB`.
+impl std::fmt::Display for Rotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Daily => f.write_str("daily"),
+            Self::Size(size) => {
+                let (unit, multiplier) = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10)]
+                    .into_iter()
+                    .find(|(_, multiplier)| size % multiplier == 0)
+                    .unwrap_or(("B", 1));
+                write!(f, "{}{unit}", size / multiplier)
+            }
+        }
+    }
+}
+
+/// The log level of the daemon, with optional per-module overrides.
+///
+/// Parsed from a comma separated list of directives, where a bare level sets
+/// the default and `module=level` overrides the level for that module, e.g.
+/// `info,notify=warn`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLevel {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogLevel {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Info,
+            modules: Vec::new(),
+        }
+    }
+}
+
+/// A single level for every module.
+impl From<LevelFilter> for LogLevel {
+    fn from(default: LevelFilter) -> Self {
+        Self {
+            default,
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut level = Self::default();
+
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, filter)) => {
+                    let module = module.trim();
+                    if module.is_empty() {
+                        return Err(ParseError::InvalidFilter(directive.into()));
+                    }
+                    level
+                        .modules
+                        .push((module.to_string(), parse_level_filter(filter)?));
+                }
+                None => level.default = parse_level_filter(directive)?,
+            }
+        }
+
+        Ok(level)
+    }
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.default.as_str().to_ascii_lowercase())?;
+        for (module, filter) in &self.modules {
+            write!(f, ",{module}={}", filter.as_str().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a single level, e.g. `debug`, case insensitively.
+///
+/// # Errors
+///
+/// Will return an error if `s` isn't a level.
+pub fn parse_level_filter(s: &str) -> Result<LevelFilter, ParseError> {
+    LevelFilter::from_str(s.trim()).map_err(|_| ParseError::InvalidLevel(s.trim().into()))
+}
+
+/// The log target of everything a watchdog logs, e.g.
+/// `log_watchdog::watchdog::pgbouncer`.
+///
+/// Being nested under the `log_watchdog` module, a watchdog's records are
+/// included in `log_watchdog` module filters unless overridden by its own.
+pub fn watchdog_target(name: &str) -> String {
+    format!("log_watchdog::watchdog::{name}")
+}
 
-/// Initializes logging with a JSON console appender.
+/// Errors initializing or reconfiguring logging.
+#[derive(Error, Debug)]
+pub enum LoggingError {
+    /// A logger is already set for this process; logging keeps working with
+    /// the existing logger, so callers may choose to ignore this.
+    #[error("logging is already initialized")]
+    AlreadyInitialized(#[from] log::SetLoggerError),
+    #[error("failed to create {appender} appender: {source}")]
+    Appender {
+        appender: &'static str,
+        source: anyhow::Error,
+    },
+    #[error("failed to load logging config file {path:?}: {source}")]
+    ConfigFile {
+        path: PathBuf,
+        source: anyhow::Error,
+    },
+    #[error(transparent)]
+    Config(#[from] log4rs::config::runtime::ConfigErrors),
+}
+
+/// Initializes logging with appenders for stdout, a rolling JSON log file,
+/// the local syslog daemon and/or journald, or from a log4rs config file if the
+/// config has one.
 ///
-/// # Panics
+/// # Errors
 ///
-/// Will panic if creating the config fails, or initializing the logger with the
-/// config fails.
-pub fn init_logging() -> Handle {
-    let stdout: ConsoleAppender = ConsoleAppender::builder()
+/// Will return an error if creating the appenders (including connecting to
+/// syslog or journald), loading the log4rs config file, or building the config
+/// fails, and [`LoggingError::AlreadyInitialized`] if a logger is already set.
+pub fn init_logging(config: &LogConfig) -> Result<Handle, LoggingError> {
+    Ok(log4rs::init_config(build_config(config)?)?)
+}
+
+/// Replaces the configuration of the running logger, e.g. to change the log
+/// level without restarting the daemon. On error the current configuration is
+/// kept.
+///
+/// # Errors
+///
+/// Will return an error if creating the appenders or config fails.
+pub fn reconfigure(handle: &Handle, config: &LogConfig) -> Result<(), LoggingError> {
+    handle.set_config(build_config(config)?);
+    Ok(())
+}
+
+/// Deserializers for log4rs config files: the log4rs defaults, plus, on unix,
+/// `syslog` and `journald` appender kinds.
+pub fn deserializers() -> Deserializers {
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut deserializers = Deserializers::default();
+    #[cfg(unix)]
+    deserializers.insert("syslog", SyslogAppenderDeserializer);
+    #[cfg(unix)]
+    deserializers.insert("journald", JournaldAppenderDeserializer);
+    deserializers
+}
+
+fn build_config(config: &LogConfig) -> Result<log4rs::config::Config, LoggingError> {
+    if let Some(path) = &config.config_file {
+        return log4rs::config::load_config_file(path, deserializers()).map_err(|source| {
+            LoggingError::ConfigFile {
+                path: path.clone(),
+                source,
+            }
+        });
+    }
+
+    let mut appenders: Vec<(&str, Box<dyn Append>)> = Vec::new();
+
+    if config.stdout {
+        let stdout: ConsoleAppender = ConsoleAppender::builder()
+            .encoder(config.format.encoder())
+            .build();
+        appenders.push(("stdout", Box::new(stdout)));
+    }
+
+    if let Some(file) = &config.file {
+        appenders.push(("file", Box::new(rolling_file(file)?)));
+    }
+
+    #[cfg(unix)]
+    if let Some(facility) = config.syslog {
+        let syslog = SyslogAppender::new(facility).map_err(|e| appender_error("syslog", e))?;
+        appenders.push(("syslog", Box::new(syslog)));
+    }
+
+    #[cfg(unix)]
+    if config.journald {
+        let journald = JournaldAppender::new().map_err(|e| appender_error("journald", e))?;
+        appenders.push(("journald", Box::new(journald)));
+    }
+
+    let root = Root::builder()
+        .appenders(appenders.iter().map(|(name, _)| *name))
+        .build(config.level.default);
+    let builder = log4rs::config::Config::builder().appenders(appenders.into_iter().map(
+        |(name, appender)| {
+            let appender: Box<dyn Append> = match config.repeat_interval {
+                Some(interval) => Box::new(RepeatSuppressingAppender::new(appender, interval)),
+                None => appender,
+            };
+            let appender: Box<dyn Append> = match config.buffer_size {
+                Some(capacity) => Box::new(BufferedAppender::new(appender, capacity)),
+                None => appender,
+            };
+            Appender::builder().build(name, appender)
+        },
+    ));
+
+    let watchdog_loggers = config
+        .watchdog_levels
+        .iter()
+        .map(|(name, filter)| (watchdog_target(name), *filter))
+        .filter(|(target, _)| !config.level.modules.iter().any(|(m, _)| m == target));
+
+    Ok(builder
+        .loggers(
+            config
+                .level
+                .modules
+                .iter()
+                .cloned()
+                .chain(watchdog_loggers)
+                .map(|(module, filter)| Logger::builder().build(module, filter)),
+        )
+        .build(root)?)
+}
+
+fn appender_error(appender: &'static str, source: impl Into<anyhow::Error>) -> LoggingError {
+    LoggingError::Appender {
+        appender,
+        source: source.into(),
+    }
+}
+
+fn rolling_file(file: &LogFile) -> Result<RollingFileAppender, LoggingError> {
+    let trigger: Box<dyn Trigger> = match file.rotation {
+        Rotation::Daily => {
+            // the time trigger config is only constructible through deserialization
+            let config: TimeTriggerConfig =
+                serde_json::from_value(serde_json::json!({ "interval": "1 day" }))
+                    .map_err(|e| appender_error("file", e))?;
+            Box::new(TimeTrigger::new(config))
+        }
+        Rotation::Size(limit) => Box::new(SizeTrigger::new(limit)),
+    };
+
+    let roller: Box<dyn Roll> = if file.retention == 0 {
+        Box::new(DeleteRoller::new())
+    } else {
+        let pattern = format!("{}.{{}}", file.path.display());
+        Box::new(
+            FixedWindowRoller::builder()
+                .base(1)
+                .build(&pattern, file.retention)
+                .map_err(|e| appender_error("file", e))?,
+        )
+    };
+
+    RollingFileAppender::builder()
         .encoder(Box::new(JsonEncoder::new()))
-        .build();
+        .build(&file.path, Box::new(CompoundPolicy::new(trigger, roller)))
+        .map_err(|e| appender_error("file", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_bare_level_then_default_is_set() {
+        let level = LogLevel::from_str("debug").unwrap();
 
-    let log_config = log4rs::config::Config::builder()
-        .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .build(Root::builder().appender("stdout").build(LevelFilter::Info))
+        assert_eq!(level.default, LevelFilter::Debug);
+        assert!(level.modules.is_empty());
+    }
+
+    #[test]
+    fn test_when_from_level_filter_then_no_overrides() {
+        assert_eq!(
+            LogLevel::from(LevelFilter::Error),
+            LogLevel::from_str("error").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_when_module_filters_then_overrides_are_parsed() {
+        let level = LogLevel::from_str("warn, notify=error,log_watchdog=trace").unwrap();
+
+        assert_eq!(level.default, LevelFilter::Warn);
+        assert_eq!(
+            level.modules,
+            vec![
+                ("notify".to_string(), LevelFilter::Error),
+                ("log_watchdog".to_string(), LevelFilter::Trace)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_only_module_filter_then_default_is_info() {
+        let level = LogLevel::from_str("notify=warn").unwrap();
+
+        assert_eq!(level.default, LevelFilter::Info);
+    }
+
+    #[test]
+    fn test_when_watchdog_target_then_nested_under_crate() {
+        assert_eq!(
+            watchdog_target("pgbouncer"),
+            "log_watchdog::watchdog::pgbouncer"
+        );
+    }
+
+    #[test]
+    fn test_when_level_displayed_then_round_trips() {
+        let level = LogLevel::from_str("DEBUG,notify=warn").unwrap();
+
+        assert_eq!(level.to_string(), "debug,notify=warn");
+        assert_eq!(LogLevel::from_str(&level.to_string()).unwrap(), level);
+    }
+
+    #[test]
+    fn test_when_invalid_level_then_error() {
+        assert_eq!(
+            LogLevel::from_str("loud"),
+            Err(ParseError::InvalidLevel("loud".into()))
+        );
+        assert_eq!(
+            LogLevel::from_str("=warn"),
+            Err(ParseError::InvalidFilter("=warn".into()))
+        );
+    }
+
+    #[test]
+    fn test_when_rotation_then_daily_or_size() {
+        assert_eq!(Rotation::from_str("daily").unwrap(), Rotation::Daily);
+        assert_eq!(Rotation::from_str("1024").unwrap(), Rotation::Size(1024));
+        assert_eq!(
+            Rotation::from_str("10KB").unwrap(),
+            Rotation::Size(10 * 1024)
+        );
+        assert_eq!(
+            Rotation::from_str("5 mb").unwrap(),
+            Rotation::Size(5 * 1024 * 1024)
+        );
+        assert_eq!(
+            Rotation::from_str("weekly"),
+            Err(ParseError::InvalidRotation("weekly".into()))
+        );
+        assert!(Rotation::from_str("0MB").is_err());
+    }
+
+    #[test]
+    fn test_when_rotation_displayed_then_parsed_back() {
+        assert_eq!(Rotation::Daily.to_string(), "daily");
+        assert_eq!(Rotation::Size(5 * 1024 * 1024).to_string(), "5MB");
+        assert_eq!(Rotation::Size(1000).to_string(), "1000B");
+        for rotation in [
+            Rotation::Daily,
+            Rotation::Size(3 << 30),
+            Rotation::Size(1500),
+        ] {
+            assert_eq!(Rotation::from_str(&rotation.to_string()).unwrap(), rotation);
+        }
+    }
+
+    #[test]
+    fn test_when_log_format_then_json_or_pretty() {
+        assert_eq!(LogFormat::from_str("json").unwrap(), LogFormat::Json);
+        assert_eq!(LogFormat::from_str("Pretty").unwrap(), LogFormat::Pretty);
+        assert_eq!(
+            LogFormat::from_str("xml"),
+            Err(ParseError::InvalidFormat("xml".into()))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_when_config_file_then_custom_kinds_are_deserialized() {
+        let dir = std::env::temp_dir().join(format!("logging-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("log.sock");
+        let _server = std::os::unix::net::UnixDatagram::bind(&socket).unwrap();
+        let path = dir.join("log4rs.yml");
+        std::fs::write(
+            &path,
+            format!(
+                "appenders:\n  syslog:\n    kind: syslog\n    facility: local1\n    path: {}\nroot:\n  level: warn\n  appenders:\n    - syslog\n",
+                socket.display()
+            ),
+        )
         .unwrap();
-    log4rs::init_config(log_config).unwrap()
+
+        let config = build_config(&LogConfig {
+            config_file: Some(path),
+            ..LogConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(config.root().level(), LevelFilter::Warn);
+        assert_eq!(config.appenders()[0].name(), "syslog");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_when_initialized_twice_then_already_initialized() {
+        let config = LogConfig {
+            stdout: false,
+            ..LogConfig::default()
+        };
+
+        let handle = init_logging(&config).unwrap();
+
+        assert!(matches!(
+            init_logging(&config),
+            Err(LoggingError::AlreadyInitialized(_))
+        ));
+        assert!(reconfigure(&handle, &config).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_when_appender_fails_then_error() {
+        let config = LogConfig {
+            journald: true,
+            ..LogConfig::default()
+        };
+        let missing = LogConfig {
+            config_file: Some(PathBuf::from("/nonexistent/log4rs.yml")),
+            ..LogConfig::default()
+        };
+
+        // the journald socket doesn't exist in every environment
+        if !std::path::Path::new("/run/systemd/journal/socket").exists() {
+            assert!(matches!(
+                build_config(&config),
+                Err(LoggingError::Appender {
+                    appender: "journald",
+                    ..
+                })
+            ));
+        }
+        assert!(matches!(
+            build_config(&missing),
+            Err(LoggingError::ConfigFile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_when_watchdog_levels_then_per_target_loggers() {
+        let config = build_config(&LogConfig {
+            level: LogLevel::from_str("info,log_watchdog::watchdog::pgbouncer=warn").unwrap(),
+            watchdog_levels: vec![
+                ("pgbouncer".into(), LevelFilter::Debug),
+                ("nginx".into(), LevelFilter::Trace),
+            ],
+            ..LogConfig::default()
+        })
+        .unwrap();
+
+        let loggers: Vec<_> = config
+            .loggers()
+            .iter()
+            .map(|l| (l.name(), l.level()))
+            .collect();
+        assert_eq!(
+            loggers,
+            vec![
+                ("log_watchdog::watchdog::pgbouncer", LevelFilter::Warn),
+                ("log_watchdog::watchdog::nginx", LevelFilter::Trace),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_rolling_file_then_appender_builds() {
+        let dir = std::env::temp_dir().join(format!("logging-test-{}", std::process::id()));
+        let file = LogFile {
+            path: dir.join("log-watchdog.log"),
+            rotation: Rotation::Daily,
+            retention: 3,
+        };
+
+        let appender = rolling_file(&file).unwrap();
+
+        assert!(dir.join("log-watchdog.log").exists());
+        drop(appender);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+use log::{Level, Record};
+use log4rs::append::Append;
+
+/// An appender that collapses runs of identical records into a single
+/// "last message repeated N times" record.
+///
+/// The first record of a run is forwarded as is, repeats are only counted. The
+/// summary is written when a different record arrives, and periodically while
+/// the run lasts, so a tight error loop still shows up in the log.
+#[derive(Debug)]
+pub struct RepeatSuppressingAppender {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    inner: Box<dyn Append>,
+    last: Option<LastRecord>,
+    repeats: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LastRecord {
+    level: Level,
+    target: String,
+    message: String,
+}
+
+impl RepeatSuppressingAppender {
+    /// Wraps `inner`, writing the summary of an ongoing run of repeats every
+    /// `flush_interval`.
+    pub fn new(inner: Box<dyn Append>, flush_interval: Duration) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            inner,
+            last: None,
+            repeats: 0,
+        }));
+
+        let weak = Arc::downgrade(&state);
+        std::thread::spawn(move || flush_periodically(&weak, flush_interval));
+
+        Self { state }
+    }
+
+    fn lock(&self) -> anyhow::Result<MutexGuard<'_, State>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repeat suppression lock poisoned"))
+    }
+}
+
+/// Flushes repeats until the appender is dropped.
+fn flush_periodically(state: &Weak<Mutex<State>>, interval: Duration) {
+    loop {
+        std::thread::sleep(interval);
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        if let Ok(mut state) = state.lock() {
+            let _ = state.flush_repeats();
+        };
+    }
+}
+
+impl State {
+    fn flush_repeats(&mut self) -> anyhow::Result<()> {
+        if self.repeats == 0 {
+            return Ok(());
+        }
+        let Some(last) = &self.last else {
+            return Ok(());
+        };
+
+        let repeats = self.repeats;
+        self.repeats = 0;
+        self.inner.append(
+            &Record::builder()
+                .level(last.level)
+                .target(&last.target)
+                .args(format_args!("last message repeated {repeats} times"))
+                .build(),
+        )
+    }
+}
+
+impl Append for RepeatSuppressingAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let current = LastRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+
+        let mut state = self.lock()?;
+        if state.last.as_ref() == Some(&current) {
+            state.repeats += 1;
+            return Ok(());
+        }
+
+        state.flush_repeats()?;
+        state.last = Some(current);
+        state.inner.append(record)
+    }
+
+    fn flush(&self) {
+        if let Ok(mut state) = self.lock() {
+            let _ = state.flush_repeats();
+            state.inner.flush();
+        }
+    }
+}
+
+impl Drop for RepeatSuppressingAppender {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Clone)]
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    impl Append for Collect {
+        fn append(&self, record: &Record) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn append(appender: &RepeatSuppressingAppender, message: &str) {
+        appender
+            .append(
+                &Record::builder()
+                    .level(Level::Error)
+                    .args(format_args!("{message}"))
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_when_repeated_then_collapsed_when_message_changes() {
+        let collect = Collect::default();
+        let appender =
+            RepeatSuppressingAppender::new(Box::new(collect.clone()), Duration::from_mins(1));
+
+        append(&appender, "permission denied");
+        append(&appender, "permission denied");
+        append(&appender, "permission denied");
+        append(&appender, "recovered");
+        append(&appender, "recovered");
+
+        assert_eq!(
+            *collect.0.lock().unwrap(),
+            vec![
+                "permission denied",
+                "last message repeated 2 times",
+                "recovered"
+            ]
+        );
+
+        drop(appender);
+        assert_eq!(
+            collect.0.lock().unwrap().last().unwrap(),
+            "last message repeated 1 times"
+        );
+    }
+
+    #[test]
+    fn test_when_repeats_continue_then_flushed_periodically() {
+        let collect = Collect::default();
+        let appender =
+            RepeatSuppressingAppender::new(Box::new(collect.clone()), Duration::from_millis(50));
+
+        append(&appender, "permission denied");
+        append(&appender, "permission denied");
+        std::thread::sleep(Duration::from_millis(200));
+        append(&appender, "permission denied");
+
+        assert_eq!(
+            *collect.0.lock().unwrap(),
+            vec!["permission denied", "last message repeated 1 times"]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+/// Decides which of a stream of frequent events are logged: the first of
+/// every `rate` events is, and the number of events that weren't is available
+/// as a summary at most once every `interval`.
+#[derive(Debug)]
+pub struct Sampler {
+    rate: u64,
+    interval: Duration,
+    seen: u64,
+    skipped: u64,
+    since: Instant,
+}
+
+impl Sampler {
+    /// Logs 1 in `rate` events, a rate of 0 or 1 logs every event.
+    pub fn new(rate: u64, interval: Duration) -> Self {
+        Self {
+            rate: rate.max(1),
+            interval,
+            seen: 0,
+            skipped: 0,
+            since: Instant::now(),
+        }
+    }
+
+    /// Counts an event, returning true if it should be logged.
+    pub const fn sample(&mut self) -> bool {
+        let log = self.seen.is_multiple_of(self.rate);
+        self.seen = self.seen.wrapping_add(1);
+        if !log {
+            self.skipped += 1;
+        }
+        log
+    }
+
+    /// Returns the number of events that weren't logged since the last
+    /// summary, if there were any and `interval` has passed since then.
+    pub fn summary(&mut self) -> Option<u64> {
+        if self.skipped == 0 || self.since.elapsed() < self.interval {
+            return None;
+        }
+
+        self.since = Instant::now();
+        Some(std::mem::take(&mut self.skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_rate_then_one_in_rate_sampled() {
+        let mut sampler = Sampler::new(3, Duration::from_mins(1));
+
+        let logged: Vec<bool> = (0..7).map(|_| sampler.sample()).collect();
+
+        assert_eq!(logged, vec![true, false, false, true, false, false, true]);
+        assert_eq!(sampler.summary(), None);
+    }
+
+    #[test]
+    fn test_when_interval_passed_then_skipped_summarized() {
+        let mut sampler = Sampler::new(2, Duration::ZERO);
+
+        for _ in 0..5 {
+            sampler.sample();
+        }
+
+        assert_eq!(sampler.summary(), Some(2));
+        assert_eq!(sampler.summary(), None);
+    }
+
+    #[test]
+    fn test_when_rate_zero_then_every_event_sampled() {
+        let mut sampler = Sampler::new(0, Duration::ZERO);
+
+        assert!((0..3).all(|_| sampler.sample()));
+        assert_eq!(sampler.summary(), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use log::Record;
+use log4rs::append::Append;
+use log4rs::config::{Deserialize, Deserializers};
+
+use crate::facility::{severity, Facility};
+
+/// Sockets the local syslog daemon commonly listens on, in order of preference.
+const SYSLOG_SOCKETS: [&str; 2] = ["/dev/log", "/var/run/syslog"];
+
+/// An appender that sends records to the local syslog daemon over its unix
+/// socket.
+#[derive(Debug)]
+pub struct SyslogAppender {
+    path: PathBuf,
+    facility: Facility,
+    socket: Mutex<UnixDatagram>,
+}
+
+impl SyslogAppender {
+    /// Connects to the local syslog daemon.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if none of the known syslog sockets accept a
+    /// connection.
+    pub fn new(facility: Facility) -> io::Result<Self> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no syslog socket found");
+        for path in SYSLOG_SOCKETS {
+            match Self::with_path(path, facility) {
+                Ok(appender) => return Ok(appender),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Connects to a syslog daemon listening on `path`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the socket does not accept a connection.
+    pub fn with_path(path: impl AsRef<Path>, facility: Facility) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let socket = connect(&path)?;
+        Ok(Self {
+            path,
+            facility,
+            socket: Mutex::new(socket),
+        })
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let priority = self.facility.code() * 8 + severity(record.level());
+        format!(
+            "<{priority}>log-watchdog[{}]: {}",
+            std::process::id(),
+            record.args()
+        )
+    }
+}
+
+fn connect(path: &Path) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+impl Append for SyslogAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let message = self.format(record);
+        let mut socket = self
+            .socket
+            .lock()
+            .map_err(|_| anyhow::anyhow!("syslog socket lock poisoned"))?;
+
+        if socket.send(message.as_bytes()).is_err() {
+            // the syslog daemon may have restarted, reconnect once before giving up
+            *socket = connect(&self.path)?;
+            socket.send(message.as_bytes())?;
+        }
+        drop(socket);
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+/// Configuration of a `kind: syslog` appender in a log4rs config file.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyslogAppenderConfig {
+    facility: Option<String>,
+    path: Option<PathBuf>,
+}
+
+/// Deserializes `kind: syslog` appenders in a log4rs config file.
+#[derive(Debug)]
+pub struct SyslogAppenderDeserializer;
+
+impl Deserialize for SyslogAppenderDeserializer {
+    type Trait = dyn Append;
+    type Config = SyslogAppenderConfig;
+
+    fn deserialize(
+        &self,
+        config: SyslogAppenderConfig,
+        _: &Deserializers,
+    ) -> anyhow::Result<Box<dyn Append>> {
+        let facility = config
+            .facility
+            .as_deref()
+            .map(Facility::from_str)
+            .transpose()?
+            .unwrap_or_default();
+        let appender = match config.path {
+            Some(path) => SyslogAppender::with_path(path, facility)?,
+            None => SyslogAppender::new(facility)?,
+        };
+        Ok(Box::new(appender))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_when_record_appended_then_sent_with_priority() {
+        let dir = std::env::temp_dir().join(format!("syslog-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let appender = SyslogAppender::with_path(&path, Facility::Local0).unwrap();
+        appender
+            .append(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .args(format_args!("disk almost full"))
+                    .build(),
+            )
+            .unwrap();
+
+        let mut buf = [0; 256];
+        let n = server.recv(&mut buf).unwrap();
+        let message = String::from_utf8_lossy(&buf[..n]);
+
+        // local0 (16) * 8 + warning (4)
+        assert_eq!(
+            message,
+            format!(
+                "<132>log-watchdog[{}]: disk almost full",
+                std::process::id()
+            )
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use serde_yaml::Value;
+
+use crate::{SettingsError, Tls};
+
+/// The `resolve_after` of an `alertmanager` without one, in milliseconds:
+/// Alertmanager's own default `resolve_timeout` of 5 minutes.
+pub const DEFAULT_RESOLVE_AFTER: u64 = 300_000;
+
+/// A watchdog's `alertmanager`: where its matches are sent as alerts, in the
+/// Alertmanager v2 API's format, so that they go through its routing,
+/// silences and deduplication.
+///
+/// A watchdog's matches are the same alert, labeled with `alertname` (the
+/// watchdog's name unless it's one of the `labels`), `watchdog` and the
+/// `labels`. Every match fires it again, and it resolves once the watchdog
+/// hasn't matched for `resolve_after` milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alertmanager {
+    /// The Alertmanager's URL, e.g. `http://localhost:9093`, or an `https://`
+    /// one
+    pub url: String,
+    /// Labels of the alert, identifying it
+    pub labels: BTreeMap<String, String>,
+    /// Annotations of the alert, e.g. a `summary`; the matched line and log
+    /// file are added as `line` and `log_file`
+    pub annotations: BTreeMap<String, String>,
+    /// Time in milliseconds after the last match that the alert resolves
+    pub resolve_after: u64,
+    /// How an `https://` Alertmanager is verified and authenticated with
+    pub tls: Tls,
+}
+
+impl Alertmanager {
+    /// An Alertmanager at `url`, without labels or annotations of its own.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            labels: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+            resolve_after: DEFAULT_RESOLVE_AFTER,
+            tls: Tls::default(),
+        }
+    }
+
+    pub(crate) fn from_value(v: &Value) -> Result<Self, SettingsError> {
+        let invalid = |key: &str| SettingsError::InvalidValueType {
+            key: format!("alertmanager.{key}"),
+        };
+        let url = v
+            .get("url")
+            .ok_or(SettingsError::from("alertmanager.url"))?
+            .as_str()
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+            .ok_or_else(|| invalid("url"))?;
+        let strings = |key: &str| {
+            v.get(key)
+                .map(|strings| {
+                    strings
+                        .as_mapping()
+                        .ok_or_else(|| invalid(key))?
+                        .iter()
+                        .map(|(name, value)| match (name.as_str(), value.as_str()) {
+                            (Some(name), Some(value)) => Ok((name.to_string(), value.to_string())),
+                            _ => Err(invalid(key)),
+                        })
+                        .collect::<Result<BTreeMap<_, _>, _>>()
+                })
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        let labels = strings("labels")?;
+        if !labels.keys().all(|name| is_label_name(name)) {
+            return Err(invalid("labels"));
+        }
+        let resolve_after = v
+            .get("resolve_after")
+            .map(|after| {
+                after
+                    .as_u64()
+                    .filter(|after| *after > 0)
+                    .ok_or_else(|| invalid("resolve_after"))
+            })
+            .transpose()?
+            .unwrap_or(DEFAULT_RESOLVE_AFTER);
+
+        Ok(Self {
+            url: url.to_string(),
+            labels,
+            annotations: strings("annotations")?,
+            resolve_after,
+            tls: Tls::from_value(v, url, "alertmanager")?,
+        })
+    }
+}
+
+/// If true, `name` is a valid Prometheus label name.
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alertmanager(yaml: &str) -> Result<Alertmanager, SettingsError> {
+        Alertmanager::from_value(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn test_when_alertmanager_parsed_then_labels_and_annotations_kept() {
+        let alertmanager = alertmanager(
+            r"
+url: http://alertmanager:9093
+labels: {severity: page, team: dba}
+annotations: {summary: pgbouncer is refusing connections}
+resolve_after: 60000
+",
+        )
+        .unwrap();
+
+        assert_eq!(alertmanager.url, "http://alertmanager:9093");
+        assert_eq!(alertmanager.labels["severity"], "page");
+        assert_eq!(alertmanager.labels["team"], "dba");
+        assert_eq!(
+            alertmanager.annotations["summary"],
+            "pgbouncer is refusing connections"
+        );
+        assert_eq!(alertmanager.resolve_after, 60000);
+        assert_eq!(
+            self::alertmanager("url: http://alertmanager:9093").unwrap(),
+            Alertmanager::new("http://alertmanager:9093")
+        );
+    }
+
+    #[test]
+    fn test_when_alertmanager_invalid_then_error() {
+        assert!(alertmanager("labels: {severity: page}").is_err());
+        assert!(alertmanager("url: ftp://alertmanager:9093").is_err());
+        assert!(alertmanager("{url: 'http://alertmanager:9093', labels: {0team: dba}}").is_err());
+        assert!(alertmanager("{url: 'http://alertmanager:9093', labels: [dba]}").is_err());
+        assert!(alertmanager("{url: 'http://alertmanager:9093', resolve_after: 0}").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    fmt,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{sha256, Command, SettingsError};
+
+/// The top-level `allowed_commands`: if set, the only programs a watchdog may
+/// run, checked when the settings are loaded and again before each run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowedCommands(Vec<AllowedCommand>);
+
+/// An entry of `allowed_commands`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedCommand {
+    /// An absolute path, allowing the program there, or that it links to
+    Path(PathBuf),
+    /// `sha256:<hex>`: allowing any program with these contents, wherever it
+    /// is. The program is run by its path once it's hashed, not from what was
+    /// hashed, so this doesn't hold against whoever can replace it in between
+    Sha256([u8; 32]),
+    /// `<image>:<name>`: allowing a containerized command named `name` in
+    /// the `image`, spelled as in the command's settings
+    Container { image: String, name: String },
+}
+
+impl AllowedCommands {
+    pub const fn new(commands: Vec<AllowedCommand>) -> Self {
+        Self(commands)
+    }
+
+    pub fn commands(&self) -> &[AllowedCommand] {
+        &self.0
+    }
+
+    /// Checks the program the command runs on the host, like [`Self::check`],
+    /// and for a containerized command, that it's allowed in its image too,
+    /// since the runtime alone would run any program of any image.
+    pub fn check_command(&self, command: &Command) -> Result<PathBuf, SettingsError> {
+        if let Some(container) = &command.container {
+            if !self.allows_container(&container.image, &command.name) {
+                return Err(SettingsError::CommandNotAllowed(format!(
+                    "{}:{}",
+                    container.image, command.name
+                )));
+            }
+        }
+        self.check(command.program())
+    }
+
+    fn allows_container(&self, image: &str, name: &str) -> bool {
+        self.0.iter().any(|allowed| {
+            matches!(allowed, AllowedCommand::Container { image: allowed_image, name: allowed_name }
+                if allowed_image == image && allowed_name == name)
+        })
+    }
+
+    /// Finds the program a command named `name` runs, through `PATH` unless
+    /// the name has a `/` in it, like the shell does, and returns its path if
+    /// it's allowed. That path is what's run, so that changing `PATH` can't
+    /// swap the program after it was checked.
+    pub fn check(&self, name: &str) -> Result<PathBuf, SettingsError> {
+        let not_allowed = || SettingsError::CommandNotAllowed(name.to_string());
+        let path = resolve(name)
+            .and_then(|path| path.canonicalize().ok())
+            .ok_or_else(not_allowed)?;
+
+        let by_path = self.0.iter().any(|allowed| match allowed {
+            AllowedCommand::Path(allowed) => {
+                allowed.canonicalize().as_deref().unwrap_or(allowed) == path
+            }
+            AllowedCommand::Sha256(_) | AllowedCommand::Container { .. } => false,
+        });
+        if by_path {
+            return Ok(path);
+        }
+
+        let hashes: Vec<_> = self
+            .0
+            .iter()
+            .filter_map(|allowed| match allowed {
+                AllowedCommand::Sha256(hash) => Some(hash),
+                AllowedCommand::Path(_) | AllowedCommand::Container { .. } => None,
+            })
+            .collect();
+        if !hashes.is_empty() {
+            let hash = sha256::sha256(&std::fs::read(&path)?);
+            if hashes.contains(&&hash) {
+                return Ok(path);
+            }
+        }
+        Err(not_allowed())
+    }
+}
+
+/// The executable file the command runs, if there is one.
+fn resolve(name: &str) -> Option<PathBuf> {
+    let executable = |path: &Path| {
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    };
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return executable(&path).then_some(path);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| executable(path))
+}
+
+impl FromStr for AllowedCommand {
+    type Err = SettingsError;
+
+    /// Parses `sha256:<hex>`, an absolute path or `<image>:<name>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SettingsError::InvalidValueType {
+            key: "allowed_commands".into(),
+        };
+        if let Some(hex) = s.strip_prefix("sha256:") {
+            if hex.len() != 64 || !hex.is_ascii() {
+                return Err(invalid());
+            }
+            let mut hash = [0; 32];
+            for (byte, hex) in hash.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+                let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+                *byte = u8::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            }
+            return Ok(Self::Sha256(hash));
+        }
+        let path = PathBuf::from(s);
+        if path.is_absolute() {
+            return Ok(Self::Path(path));
+        }
+        // the image's tag or digest has colons of its own
+        match s.rsplit_once(':') {
+            Some((image, name)) if !image.is_empty() && !name.is_empty() => Ok(Self::Container {
+                image: image.to_string(),
+                name: name.to_string(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for AllowedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::Sha256(hash) => write!(f, "sha256:{}", sha256::to_hex(hash)),
+            Self::Container { image, name } => write!(f, "{image}:{name}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_when_command_allowed_by_path_or_hash_then_checked_path_returned() {
+        let dir = tempdir::TempDir::new("test_allowed").unwrap();
+        let restart = script(dir.path(), "restart", "#!/bin/sh\nexit 0\n");
+        let notify = script(dir.path(), "notify", "#!/bin/sh\necho notify\n");
+        let other = script(dir.path(), "other", "#!/bin/sh\necho other\n");
+        let hash = sha256::to_hex(&sha256::sha256(b"#!/bin/sh\necho notify\n"));
+        let allowed = AllowedCommands::new(vec![
+            restart.to_str().unwrap().parse().unwrap(),
+            format!("sha256:{hash}").parse().unwrap(),
+        ]);
+
+        let canonical = |path: &Path| path.canonicalize().unwrap();
+        assert_eq!(
+            allowed.check(restart.to_str().unwrap()).unwrap(),
+            canonical(&restart)
+        );
+        assert_eq!(
+            allowed.check(notify.to_str().unwrap()).unwrap(),
+            canonical(&notify)
+        );
+        assert!(matches!(
+            allowed.check(other.to_str().unwrap()),
+            Err(SettingsError::CommandNotAllowed(_))
+        ));
+        assert!(matches!(
+            allowed.check("no-such-command"),
+            Err(SettingsError::CommandNotAllowed(_))
+        ));
+    }
+
+    #[test]
+    fn test_when_container_command_not_allowed_in_image_then_refused_with_runtime_allowed() {
+        let allowed = AllowedCommands::new(vec![
+            "/usr/bin/docker".parse().unwrap(),
+            "registry.internal/pg-tools:16:pg_repair".parse().unwrap(),
+        ]);
+        let command = |name: &str, image: &str| Command {
+            name: name.into(),
+            args: Vec::new(),
+            sandbox: None,
+            container: Some(crate::Container {
+                runtime: crate::ContainerRuntime::Docker,
+                image: image.into(),
+                mounts: Vec::new(),
+            }),
+        };
+
+        assert!(matches!(
+            allowed.check_command(&command("sh", "registry.internal/pg-tools:16")),
+            Err(SettingsError::CommandNotAllowed(command))
+                if command == "registry.internal/pg-tools:16:sh"
+        ));
+        assert!(matches!(
+            allowed.check_command(&command("pg_repair", "alpine")),
+            Err(SettingsError::CommandNotAllowed(command)) if command == "alpine:pg_repair"
+        ));
+        assert!(allowed.allows_container("registry.internal/pg-tools:16", "pg_repair"));
+    }
+
+    #[test]
+    fn test_when_allowed_command_invalid_then_error() {
+        assert!("bin/restart".parse::<AllowedCommand>().is_err());
+        assert!(":pg_repair".parse::<AllowedCommand>().is_err());
+        assert_eq!(
+            "postgres@sha256:abc:pg_repair"
+                .parse::<AllowedCommand>()
+                .unwrap(),
+            AllowedCommand::Container {
+                image: "postgres@sha256:abc".into(),
+                name: "pg_repair".into()
+            }
+        );
+        assert!("sha256:abc".parse::<AllowedCommand>().is_err());
+        assert_eq!(
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                .parse::<AllowedCommand>()
+                .unwrap(),
+            AllowedCommand::Sha256(sha256::sha256(b""))
+        );
+    }
+}
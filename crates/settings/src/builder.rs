@@ -0,0 +1,462 @@
+use std::path::PathBuf;
+
+use logging::LevelFilter;
+use regex::Regex;
+
+use crate::{
+    Alertmanager, AllowedCommands, Command, DebounceMode, Encoding, FileMissing, FileOptions,
+    OutputFormat, OutputSettings, OutputTarget, PassiveCheck, Redactions, SettingsError, Tls,
+    Watchdog, WatcherBackend, Zabbix, DEFAULT_MAX_READ_BYTES, DEFAULT_POLL_INTERVAL,
+    DEFAULT_READ_BUFFER_SIZE,
+};
+
+/// Builds a [`Watchdog`] in code instead of parsing it from a settings file.
+///
+/// A name, log file and regex are required; everything else defaults like an
+/// unset settings key would, except that there are no outputs unless added.
+///
+/// ```
+/// let watchdog = settings::Watchdog::builder()
+///     .name("pgbouncer")
+///     .log_file("/var/log/pgbouncer/pgbouncer.log")
+///     .regex("FATAL")
+///     .command("systemctl", ["restart", "pgbouncer"])
+///     .output_file("/var/log/log-watchdog/pgbouncer.out")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(watchdog.commands.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WatchdogBuilder {
+    name: Option<String>,
+    log_file: Option<PathBuf>,
+    regex: Option<String>,
+    outputs: Vec<OutputSettings>,
+    file_options: FileOptions,
+    debounce: u64,
+    debounce_mode: DebounceMode,
+    oneshot: bool,
+    commands: Vec<Command>,
+    dry_run: bool,
+    log_level: Option<LevelFilter>,
+    log_sample_rate: u64,
+    max_read_bytes: u64,
+    read_buffer_size: usize,
+    partial_line_timeout: Option<u64>,
+    file_missing: FileMissing,
+    on_file_missing: Vec<Command>,
+    encoding: Encoding,
+    backend: Option<WatcherBackend>,
+    poll_interval: u64,
+    redact: Vec<String>,
+    alertmanager: Option<Alertmanager>,
+    passive_check: Option<PassiveCheck>,
+    zabbix: Option<Zabbix>,
+    allowed_commands: Option<AllowedCommands>,
+    audit_log: Option<PathBuf>,
+    executor: Option<PathBuf>,
+    max_concurrent_commands: Option<usize>,
+}
+
+impl Default for WatchdogBuilder {
+    fn default() -> Self {
+        Self {
+            name: None,
+            log_file: None,
+            regex: None,
+            outputs: Vec::new(),
+            file_options: FileOptions::default(),
+            debounce: 0,
+            debounce_mode: DebounceMode::default(),
+            oneshot: false,
+            commands: Vec::new(),
+            dry_run: false,
+            log_level: None,
+            log_sample_rate: 1,
+            max_read_bytes: DEFAULT_MAX_READ_BYTES,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            partial_line_timeout: None,
+            file_missing: FileMissing::default(),
+            on_file_missing: Vec::new(),
+            encoding: Encoding::default(),
+            backend: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            redact: Vec::new(),
+            alertmanager: None,
+            passive_check: None,
+            zabbix: None,
+            allowed_commands: None,
+            audit_log: None,
+            executor: None,
+            max_concurrent_commands: None,
+        }
+    }
+}
+
+impl Watchdog {
+    pub fn builder() -> WatchdogBuilder {
+        WatchdogBuilder::default()
+    }
+}
+
+impl WatchdogBuilder {
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    #[must_use]
+    pub fn log_file(mut self, log_file: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(log_file.into());
+        self
+    }
+
+    /// The regex to match, compiled by [`WatchdogBuilder::build`].
+    #[must_use]
+    pub fn regex(mut self, regex: impl Into<String>) -> Self {
+        self.regex = Some(regex.into());
+        self
+    }
+
+    /// Adds a command to run on a match, after the ones added before it.
+    #[must_use]
+    pub fn command<I, S>(mut self, name: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.commands.push(Command {
+            name: name.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            sandbox: None,
+            container: None,
+        });
+        self
+    }
+
+    /// Adds an output file, written in the default format.
+    #[must_use]
+    pub fn output_file(self, path: impl Into<PathBuf>) -> Self {
+        self.output(OutputSettings {
+            target: OutputTarget::File(path.into()),
+            format: OutputFormat::default(),
+            tls: Tls::default(),
+        })
+    }
+
+    #[must_use]
+    pub fn output(mut self, output: OutputSettings) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    #[must_use]
+    pub fn file_options(mut self, file_options: FileOptions) -> Self {
+        self.file_options = file_options;
+        self
+    }
+
+    /// Milliseconds to debounce the watchdog after a match.
+    #[must_use]
+    pub const fn debounce(mut self, debounce: u64) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    #[must_use]
+    pub const fn debounce_mode(mut self, debounce_mode: DebounceMode) -> Self {
+        self.debounce_mode = debounce_mode;
+        self
+    }
+
+    #[must_use]
+    pub const fn oneshot(mut self, oneshot: bool) -> Self {
+        self.oneshot = oneshot;
+        self
+    }
+
+    #[must_use]
+    pub const fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    #[must_use]
+    pub const fn log_level(mut self, log_level: LevelFilter) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    #[must_use]
+    pub const fn log_sample_rate(mut self, log_sample_rate: u64) -> Self {
+        self.log_sample_rate = log_sample_rate;
+        self
+    }
+
+    #[must_use]
+    pub const fn max_read_bytes(mut self, max_read_bytes: u64) -> Self {
+        self.max_read_bytes = max_read_bytes;
+        self
+    }
+
+    #[must_use]
+    pub const fn read_buffer_size(mut self, read_buffer_size: usize) -> Self {
+        self.read_buffer_size = read_buffer_size;
+        self
+    }
+
+    #[must_use]
+    pub const fn partial_line_timeout(mut self, partial_line_timeout: u64) -> Self {
+        self.partial_line_timeout = Some(partial_line_timeout);
+        self
+    }
+
+    #[must_use]
+    pub const fn file_missing(mut self, file_missing: FileMissing) -> Self {
+        self.file_missing = file_missing;
+        self
+    }
+
+    #[must_use]
+    pub const fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Watches the log file with this backend, instead of the platform's own
+    /// notifications.
+    #[must_use]
+    pub const fn backend(mut self, backend: WatcherBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    #[must_use]
+    pub const fn poll_interval(mut self, poll_interval: u64) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Adds a command to run when the log file is removed, after the ones
+    /// added before it.
+    #[must_use]
+    pub fn on_file_missing<I, S>(mut self, name: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.on_file_missing.push(Command {
+            name: name.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            sandbox: None,
+            container: None,
+        });
+        self
+    }
+
+    /// Adds a pattern of secrets to mask in what's logged and written,
+    /// compiled by [`WatchdogBuilder::build`].
+    #[must_use]
+    pub fn redact(mut self, pattern: impl Into<String>) -> Self {
+        self.redact.push(pattern.into());
+        self
+    }
+
+    /// Sends the matches as alerts to this Alertmanager.
+    #[must_use]
+    pub fn alertmanager(mut self, alertmanager: Alertmanager) -> Self {
+        self.alertmanager = Some(alertmanager);
+        self
+    }
+
+    /// Submits the matches as passive check results of this service check.
+    #[must_use]
+    pub fn passive_check(mut self, passive_check: PassiveCheck) -> Self {
+        self.passive_check = Some(passive_check);
+        self
+    }
+
+    /// Pushes the matches to this Zabbix server's trapper item.
+    #[must_use]
+    pub fn zabbix(mut self, zabbix: Zabbix) -> Self {
+        self.zabbix = Some(zabbix);
+        self
+    }
+
+    /// Restricts the programs the commands may run to these.
+    #[must_use]
+    pub fn allowed_commands(mut self, allowed_commands: AllowedCommands) -> Self {
+        self.allowed_commands = Some(allowed_commands);
+        self
+    }
+
+    /// Records every command run in the audit log at `path`.
+    #[must_use]
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_log = Some(path.into());
+        self
+    }
+
+    /// Sends the commands to the executor process listening on the socket at
+    /// `path`, instead of running them.
+    #[must_use]
+    pub fn executor(mut self, path: impl Into<PathBuf>) -> Self {
+        self.executor = Some(path.into());
+        self
+    }
+
+    /// Runs at most `max` commands at once, with those of every other
+    /// watchdog with the same cap, queueing the rest.
+    #[must_use]
+    pub const fn max_concurrent_commands(mut self, max: usize) -> Self {
+        self.max_concurrent_commands = Some(max);
+        self
+    }
+
+    /// # Errors
+    ///
+    /// If the name, log file or regex are missing, the regex or a redact
+    /// pattern doesn't compile,
+    /// the log sample rate, max read bytes or read buffer size are 0, an
+    /// output's format can't be written to its target, or a command isn't in
+    /// the allowed commands.
+    pub fn build(self) -> Result<Watchdog, SettingsError> {
+        let name = self.name.ok_or(SettingsError::from("name"))?;
+        let log_file = self.log_file.ok_or(SettingsError::from("log_file"))?;
+        let regex = Regex::new(&self.regex.ok_or(SettingsError::from("regex"))?)?;
+        if self.log_sample_rate == 0 {
+            return Err(SettingsError::InvalidValueType {
+                key: "log_sample_rate".into(),
+            });
+        }
+        if self.max_read_bytes == 0 {
+            return Err(SettingsError::InvalidValueType {
+                key: "max_read_bytes".into(),
+            });
+        }
+        if self.read_buffer_size == 0 {
+            return Err(SettingsError::InvalidValueType {
+                key: "read_buffer_size".into(),
+            });
+        }
+        if self.max_concurrent_commands == Some(0) {
+            return Err(SettingsError::InvalidValueType {
+                key: "max_concurrent_commands".into(),
+            });
+        }
+        for output in &self.outputs {
+            output.check()?;
+        }
+        let redact = self
+            .redact
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<_, _>>()?;
+        if let Some(allowed_commands) = &self.allowed_commands {
+            for command in self.commands.iter().chain(&self.on_file_missing) {
+                allowed_commands.check(command.program())?;
+            }
+        }
+
+        Ok(Watchdog {
+            name,
+            log_file,
+            outputs: self.outputs,
+            file_options: self.file_options,
+            debounce: self.debounce,
+            debounce_mode: self.debounce_mode,
+            oneshot: self.oneshot,
+            regex,
+            commands: self.commands,
+            dry_run: self.dry_run,
+            log_level: self.log_level,
+            log_sample_rate: self.log_sample_rate,
+            max_read_bytes: self.max_read_bytes,
+            read_buffer_size: self.read_buffer_size,
+            partial_line_timeout: self.partial_line_timeout,
+            file_missing: self.file_missing,
+            on_file_missing: self.on_file_missing,
+            encoding: self.encoding,
+            backend: self.backend,
+            poll_interval: self.poll_interval,
+            redact: Redactions::new(redact),
+            alertmanager: self.alertmanager,
+            passive_check: self.passive_check,
+            zabbix: self.zabbix,
+            allowed_commands: self.allowed_commands,
+            audit_log: self.audit_log,
+            executor: self.executor,
+            max_concurrent_commands: self.max_concurrent_commands,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_built_then_watchdog_has_settings() {
+        let watchdog = Watchdog::builder()
+            .name("pgbouncer")
+            .log_file("pgbouncer.log")
+            .regex("^FATAL")
+            .command("ls", ["-a"])
+            .command("df", Vec::<String>::new())
+            .output_file("pgbouncer.out")
+            .debounce(1000)
+            .oneshot(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(watchdog.name, "pgbouncer");
+        assert_eq!(watchdog.log_file, PathBuf::from("pgbouncer.log"));
+        assert_eq!(watchdog.regex.as_str(), "^FATAL");
+        assert_eq!(
+            watchdog.commands,
+            vec![
+                Command {
+                    name: "ls".into(),
+                    args: vec!["-a".into()],
+                    sandbox: None,
+                    container: None,
+                },
+                Command {
+                    name: "df".into(),
+                    args: Vec::new(),
+                    sandbox: None,
+                    container: None,
+                },
+            ]
+        );
+        assert_eq!(
+            watchdog.outputs,
+            vec![OutputSettings {
+                target: OutputTarget::File("pgbouncer.out".into()),
+                format: OutputFormat::default(),
+                tls: Tls::default(),
+            }]
+        );
+        assert_eq!(watchdog.debounce, 1000);
+        assert!(watchdog.oneshot);
+        assert_eq!(watchdog.log_sample_rate, 1);
+    }
+
+    #[test]
+    fn test_when_required_setting_missing_then_error() {
+        assert!(matches!(
+            Watchdog::builder().name("a").regex(".*").build(),
+            Err(SettingsError::MissingSettingKey { key: "log_file" })
+        ));
+        assert!(matches!(
+            Watchdog::builder()
+                .name("a")
+                .log_file("a.log")
+                .regex("(unclosed")
+                .build(),
+            Err(SettingsError::Regex(_))
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use serde_yaml::Value;
+
+use crate::SettingsError;
+
+/// A command's `runtime`, `image` and `mounts`: the container it's run in, so
+/// that remediation tooling doesn't have to be installed on the host, and
+/// can only touch what's mounted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    /// The container runtime, which must be installed on the host
+    pub runtime: ContainerRuntime,
+    /// The image the command is run in, its entrypoint replaced by the command
+    pub image: String,
+    /// The host paths the command can see, read-only unless marked `rw`
+    pub mounts: Vec<Mount>,
+}
+
+/// The program a containerized command is run with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    /// The program run on the host, resolved through `PATH`.
+    pub const fn program(self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+        }
+    }
+}
+
+impl FromStr for ContainerRuntime {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "docker" => Ok(Self::Docker),
+            "podman" => Ok(Self::Podman),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "runtime".into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ContainerRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.program())
+    }
+}
+
+/// A bind mount of a containerized command, `<host path>:<container path>`,
+/// with `:rw` appended for one the command may write to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub writable: bool,
+}
+
+impl FromStr for Mount {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SettingsError::InvalidValueType {
+            key: "mounts".into(),
+        };
+        let (paths, writable) = match s.rsplit_once(':') {
+            Some((paths, "rw")) => (paths, true),
+            Some((paths, "ro")) => (paths, false),
+            _ => (s, false),
+        };
+        let (source, target) = paths.split_once(':').ok_or_else(invalid)?;
+        // relative paths would be resolved against wherever the daemon runs,
+        // and a comma would end docker's mount option
+        let path = |path: &str| {
+            Some(PathBuf::from(path))
+                .filter(|path| path.is_absolute() && !path.to_string_lossy().contains([',', ':']))
+                .ok_or_else(invalid)
+        };
+
+        Ok(Self {
+            source: path(source)?,
+            target: path(target)?,
+            writable,
+        })
+    }
+}
+
+impl fmt::Display for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.source.display(), self.target.display())?;
+        if self.writable {
+            f.write_str(":rw")?;
+        }
+        Ok(())
+    }
+}
+
+impl Container {
+    /// Parses the container of a command's settings, if it has a `runtime`;
+    /// an `image` is required with it, and `mounts` may be left out for none.
+    pub(crate) fn from_command(v: &Value) -> Result<Option<Self>, SettingsError> {
+        let invalid = |key: &str| SettingsError::InvalidValueType { key: key.into() };
+        let Some(runtime) = v.get("runtime") else {
+            if v.get("image").is_some() || v.get("mounts").is_some() {
+                return Err(SettingsError::from("runtime"));
+            }
+            return Ok(None);
+        };
+        let runtime = runtime
+            .as_str()
+            .ok_or_else(|| invalid("runtime"))?
+            .parse()?;
+        let image = v
+            .get("image")
+            .ok_or(SettingsError::from("image"))?
+            .as_str()
+            .filter(|image| !image.is_empty())
+            .ok_or_else(|| invalid("image"))?
+            .to_string();
+        let mounts = v
+            .get("mounts")
+            .map(|mounts| {
+                mounts
+                    .as_sequence()
+                    .ok_or_else(|| invalid("mounts"))?
+                    .iter()
+                    .map(|mount| mount.as_str().ok_or_else(|| invalid("mounts"))?.parse())
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Some(Self {
+            runtime,
+            image,
+            mounts,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_mount_parsed_then_read_only_unless_rw() {
+        let mount: Mount = "/var/lib/pgbouncer:/data".parse().unwrap();
+        assert_eq!(
+            mount,
+            Mount {
+                source: "/var/lib/pgbouncer".into(),
+                target: "/data".into(),
+                writable: false,
+            }
+        );
+        assert_eq!(mount.to_string(), "/var/lib/pgbouncer:/data");
+
+        let mount: Mount = "/var/lib/pgbouncer:/data:rw".parse().unwrap();
+        assert!(mount.writable);
+        assert_eq!(mount.to_string(), "/var/lib/pgbouncer:/data:rw");
+        assert!(
+            !"/var/lib/pgbouncer:/data:ro"
+                .parse::<Mount>()
+                .unwrap()
+                .writable
+        );
+
+        assert!("/var/lib/pgbouncer".parse::<Mount>().is_err());
+        assert!("data:/data".parse::<Mount>().is_err());
+        assert!("/a,readonly=false:/data".parse::<Mount>().is_err());
+    }
+
+    #[test]
+    fn test_when_container_without_runtime_or_image_then_error() {
+        let container = |yaml: &str| {
+            let v: Value = serde_yaml::from_str(yaml).unwrap();
+            Container::from_command(&v)
+        };
+
+        assert_eq!(container("args: []").unwrap(), None);
+        assert_eq!(
+            container("{runtime: podman, image: tools:1.2}").unwrap(),
+            Some(Container {
+                runtime: ContainerRuntime::Podman,
+                image: "tools:1.2".into(),
+                mounts: Vec::new(),
+            })
+        );
+        assert!(container("{runtime: podman}").is_err());
+        assert!(container("{image: tools:1.2}").is_err());
+        assert!(container("{runtime: lxc, image: tools:1.2}").is_err());
+    }
+}
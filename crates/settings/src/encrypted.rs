@@ -0,0 +1,155 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde_yaml::Value;
+
+use crate::{RawSettings, SettingsError};
+
+/// Replaces every `!encrypted` value in the settings with its plaintext,
+/// decrypted with the age identity in the top-level `identity_file`, so that
+/// secrets like webhook tokens can be kept in version-controlled settings.
+///
+/// Values are decrypted with the `age` binary, which must be installed when
+/// there are any; an identity for an age plugin, e.g. for a KMS or a hardware
+/// key, is decrypted with that plugin.
+pub(crate) fn decrypt(settings: &mut RawSettings) -> Result<(), SettingsError> {
+    decrypt_with("age", settings)
+}
+
+fn decrypt_with(program: &str, settings: &mut RawSettings) -> Result<(), SettingsError> {
+    let identity_file = settings
+        .get("identity_file")
+        .map(|path| {
+            path.as_str()
+                .map(PathBuf::from)
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "identity_file".into(),
+                })
+        })
+        .transpose()?;
+    let mut decrypt = |ciphertext: &str| {
+        let identity_file = identity_file.as_deref().ok_or_else(|| {
+            SettingsError::Decryption("there's an !encrypted value, but no identity_file".into())
+        })?;
+        decrypt_value(program, identity_file, ciphertext)
+    };
+    settings
+        .values_mut()
+        .try_for_each(|value| decrypt_values(value, &mut decrypt))
+}
+
+fn decrypt_values(
+    value: &mut Value,
+    decrypt: &mut impl FnMut(&str) -> Result<String, SettingsError>,
+) -> Result<(), SettingsError> {
+    match value {
+        Value::Tagged(tagged) if tagged.tag == "encrypted" => {
+            let ciphertext = tagged.value.as_str().ok_or_else(|| {
+                SettingsError::Decryption("an !encrypted value must be a string".into())
+            })?;
+            *value = Value::String(decrypt(ciphertext)?);
+            Ok(())
+        }
+        Value::Tagged(tagged) => decrypt_values(&mut tagged.value, decrypt),
+        Value::Mapping(mapping) => mapping
+            .values_mut()
+            .try_for_each(|value| decrypt_values(value, decrypt)),
+        Value::Sequence(sequence) => sequence
+            .iter_mut()
+            .try_for_each(|value| decrypt_values(value, decrypt)),
+        _ => Ok(()),
+    }
+}
+
+/// The plaintext of an armored age ciphertext, without the newline it was
+/// likely encrypted with, e.g. by `echo $TOKEN | age -e -a`.
+fn decrypt_value(
+    program: &str,
+    identity_file: &Path,
+    ciphertext: &str,
+) -> Result<String, SettingsError> {
+    let failed = |reason: String| SettingsError::Decryption(reason);
+    let mut child = Command::new(program)
+        .args(["--decrypt", "--identity"])
+        .arg(identity_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| failed(format!("failed to run {program}: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(ciphertext.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    let mut plaintext = String::from_utf8(output.stdout)
+        .map_err(|_| failed("an !encrypted value isn't UTF-8".into()))?;
+    if plaintext.ends_with('\n') {
+        plaintext.pop();
+    }
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn settings(yaml: &str) -> RawSettings {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_when_encrypted_values_then_decrypted_with_identity_file() {
+        let dir = tempdir::TempDir::new("test_encrypted").unwrap();
+        let identity_file = dir.path().join("key.txt");
+        // "decrypts" what follows "ciphertext:", if given the identity file
+        let age = dir.path().join("age");
+        std::fs::write(
+            &age,
+            format!(
+                "#!/bin/sh\n[ \"$3\" = {} ] || {{ echo no identity >&2; exit 1; }}\nsed 's/^ciphertext://'\n",
+                identity_file.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&age, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut settings = settings(&format!(
+            r"
+identity_file: {}
+watchdogs:
+  pgbouncer:
+    commands:
+      curl:
+        args: [-H, !encrypted 'ciphertext:Authorization: Bearer s3cr3t']
+",
+            identity_file.display()
+        ));
+
+        decrypt_with(age.to_str().unwrap(), &mut settings).unwrap();
+
+        let args = &settings["watchdogs"]["pgbouncer"]["commands"]["curl"]["args"];
+        assert_eq!(args[1].as_str(), Some("Authorization: Bearer s3cr3t"));
+    }
+
+    #[test]
+    fn test_when_encrypted_value_without_identity_file_then_error() {
+        let mut encrypted = settings("token: !encrypted ciphertext");
+        assert!(matches!(
+            decrypt_with("age", &mut encrypted),
+            Err(SettingsError::Decryption(_))
+        ));
+
+        let mut plain = settings("token: plaintext");
+        decrypt_with("age", &mut plain).unwrap();
+        assert_eq!(plain["token"].as_str(), Some("plaintext"));
+    }
+}
@@ -1,13 +1,57 @@
 use std::{
     collections::HashMap,
+    ffi::OsString,
     fs::OpenOptions,
+    io::Read,
     path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
 };
 
+use logging::{Facility, LevelFilter, LogFormat, Rotation};
 use regex::Regex;
 use serde_yaml::Value;
 use thiserror::Error;
 
+mod alertmanager;
+mod allowed;
+mod builder;
+mod container;
+mod encrypted;
+mod output;
+mod passive_check;
+mod redact;
+mod sandbox;
+mod serialize;
+pub mod sha256;
+mod signature;
+mod tls;
+mod zabbix;
+
+pub use alertmanager::{Alertmanager, DEFAULT_RESOLVE_AFTER};
+pub use allowed::{AllowedCommand, AllowedCommands};
+pub use builder::WatchdogBuilder;
+pub use container::{Container, ContainerRuntime, Mount};
+pub use output::{
+    parse_mode, Column, Compression, CompressionAlgorithm, EmptyOutput, Field, FileOptions,
+    FlushPolicy, OutputFormat, OutputSettings, OutputTarget, Owner, Segment, Template,
+};
+pub use passive_check::{CheckStatus, CheckTarget, Icinga, PassiveCheck, DEFAULT_RECOVER_AFTER};
+pub use redact::Redactions;
+pub use sandbox::{Sandbox, SandboxWrapper};
+pub use signature::PublicKey;
+pub use tls::{Tls, TlsVersion};
+pub use zabbix::{Zabbix, DEFAULT_ZABBIX_PORT};
+
+/// The `max_read_bytes` of a watchdog without one: 1 MiB.
+pub const DEFAULT_MAX_READ_BYTES: u64 = 1 << 20;
+
+/// The `read_buffer_size` of a watchdog without one: 64 KiB.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 64 << 10;
+
+/// The `poll_interval` of a watchdog without one, in milliseconds.
+pub const DEFAULT_POLL_INTERVAL: u64 = 1000;
+
 #[derive(Error, Debug)]
 pub enum SettingsError {
     #[error("missing setting key: {key}")]
@@ -26,14 +70,85 @@ pub enum SettingsError {
     ParseBoolError(#[from] std::str::ParseBoolError),
     #[error(transparent)]
     TryFromIntError(#[from] std::num::TryFromIntError),
+    #[error(transparent)]
+    Logging(#[from] logging::ParseError),
+    #[error("invalid output format: {0}")]
+    InvalidOutputFormat(String),
+    #[error("no watchdog named {0}")]
+    UnknownWatchdog(String),
+    #[error("command {0} is not in allowed_commands")]
+    CommandNotAllowed(String),
+    #[error("settings file {path:?} isn't signed: {reason}")]
+    Signature { path: PathBuf, reason: String },
+    #[error("failed to decrypt settings: {0}")]
+    Decryption(String),
+    #[error(
+        "watchdogs {first} and {second} write {path:?} with different file_options or formats"
+    )]
+    SharedOutputFile {
+        path: PathBuf,
+        first: String,
+        second: String,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub struct Settings {
+    logging: LoggingSettings,
+    allowed_commands: Option<AllowedCommands>,
+    audit_log: Option<PathBuf>,
+    executor: Option<PathBuf>,
+    executor_group: Option<String>,
+    max_concurrent_commands: Option<usize>,
     watchdogs: Vec<Watchdog>,
 }
 
 impl Settings {
+    /// Settings with these watchdogs and the default logging, e.g. built with
+    /// [`Watchdog::builder`].
+    pub fn new(watchdogs: Vec<Watchdog>) -> Self {
+        Self {
+            logging: LoggingSettings::default(),
+            allowed_commands: None,
+            audit_log: None,
+            executor: None,
+            executor_group: None,
+            max_concurrent_commands: None,
+            watchdogs,
+        }
+    }
+
+    pub fn logging(&self) -> &LoggingSettings {
+        &self.logging
+    }
+
+    /// The only programs the watchdogs may run, if restricted.
+    pub fn allowed_commands(&self) -> Option<&AllowedCommands> {
+        self.allowed_commands.as_ref()
+    }
+
+    /// Where every command the watchdogs run is recorded, if anywhere.
+    pub fn audit_log(&self) -> Option<&Path> {
+        self.audit_log.as_deref()
+    }
+
+    /// The socket of the executor process that runs the watchdogs' commands,
+    /// if they aren't run by the process that reads the logs.
+    pub fn executor(&self) -> Option<&Path> {
+        self.executor.as_deref()
+    }
+
+    /// The group, by name or id, that the executor's socket belongs to, so
+    /// that the process reading the logs can connect to it as a member.
+    pub fn executor_group(&self) -> Option<&str> {
+        self.executor_group.as_deref()
+    }
+
+    /// The most commands the watchdogs run at once, all together, if capped.
+    pub fn max_concurrent_commands(&self) -> Option<usize> {
+        self.max_concurrent_commands
+    }
+
     pub fn watchdogs(&self) -> &[Watchdog] {
         &self.watchdogs
     }
@@ -41,6 +156,25 @@ impl Settings {
     pub fn into_watchdogs(self) -> Vec<Watchdog> {
         self.watchdogs
     }
+
+    /// Puts every watchdog in dry-run mode, whatever its `dry_run` setting.
+    pub fn set_dry_run(&mut self) {
+        for watchdog in &mut self.watchdogs {
+            watchdog.dry_run = true;
+        }
+    }
+
+    /// Keeps only the watchdogs with these names, all of which must exist.
+    pub fn retain_watchdogs(&mut self, names: &[String]) -> Result<(), SettingsError> {
+        if let Some(name) = names
+            .iter()
+            .find(|name| !self.watchdogs.iter().any(|w| &w.name == *name))
+        {
+            return Err(SettingsError::UnknownWatchdog(name.clone()));
+        }
+        self.watchdogs.retain(|w| names.contains(&w.name));
+        Ok(())
+    }
 }
 
 /// A watchdog will watch a log file for a regex match and run any commands when
@@ -51,16 +185,242 @@ pub struct Watchdog {
     pub name: String,
     /// Path to the log file to watch
     pub log_file: PathBuf,
-    /// Path to the output file to write to
-    pub output_file: PathBuf,
+    /// Where, and how, to write match records: `output_file`, or `output`,
+    /// which may be `stdout` or `stderr` as well as a path, or a list of these
+    pub outputs: Vec<OutputSettings>,
+    /// How output files are written
+    pub file_options: FileOptions,
     /// Time in milliseconds to debounce the watchdog after a positive match
     pub debounce: u64,
+    /// What's debounced
+    pub debounce_mode: DebounceMode,
     /// If true, only run the command once
     pub oneshot: bool,
     /// Regex to match in the log file
     pub regex: Regex,
     /// Commands to run when the regex matches
     pub commands: Vec<Command>,
+    /// If true, the commands are logged instead of run
+    pub dry_run: bool,
+    /// Level of this watchdog's own log records, overriding the daemon's
+    pub log_level: Option<LevelFilter>,
+    /// Log 1 in this many matches, with a periodic count of the rest
+    pub log_sample_rate: u64,
+    /// Most bytes read from the log file at once; a watchdog that's further
+    /// behind catches up in chunks of this size
+    pub max_read_bytes: u64,
+    /// Bytes of buffer kept between reads of the log file; a larger buffer
+    /// takes fewer reads for busy logs
+    pub read_buffer_size: usize,
+    /// Time in milliseconds to wait for the rest of a partially written line,
+    /// before matching what was written; waits indefinitely if unset
+    pub partial_line_timeout: Option<u64>,
+    /// What the watchdog does when its log file is removed
+    pub file_missing: FileMissing,
+    /// Commands to run when the log file is removed
+    pub on_file_missing: Vec<Command>,
+    /// How lines that aren't valid UTF-8 are read
+    pub encoding: Encoding,
+    /// What the log file is watched with; the platform's own notifications
+    /// if unset
+    pub backend: Option<WatcherBackend>,
+    /// Time in milliseconds between polls of the log file, when it's polled
+    pub poll_interval: u64,
+    /// Secrets masked in what's logged and written to the outputs
+    pub redact: Redactions,
+    /// Where matches are sent as alerts, if anywhere
+    pub alertmanager: Option<Alertmanager>,
+    /// The Nagios or Icinga service check that matches are submitted to as
+    /// passive check results, if any
+    pub passive_check: Option<PassiveCheck>,
+    /// The Zabbix server or proxy that matches are pushed to, if any
+    pub zabbix: Option<Zabbix>,
+    /// The only programs the commands may run, from the top-level
+    /// `allowed_commands`; any program if unset
+    pub allowed_commands: Option<AllowedCommands>,
+    /// The audit log every command run is recorded in, from the top-level
+    /// `audit_log`
+    pub audit_log: Option<PathBuf>,
+    /// The socket of the executor process the commands are sent to, from the
+    /// top-level `executor`; they're run by the watchdog itself if unset
+    pub executor: Option<PathBuf>,
+    /// The most commands run at once by every watchdog together, from the
+    /// top-level `max_concurrent_commands`; the rest wait for one to exit
+    pub max_concurrent_commands: Option<usize>,
+}
+
+/// What a watchdog's `debounce` holds back for that long after a match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DebounceMode {
+    /// `suppress_commands`: every line is matched, but matches aren't acted
+    /// on, only counted, until the debounce has passed since the last one
+    /// that was
+    #[default]
+    SuppressCommands,
+    /// `skip_lines`: lines aren't matched at all until the debounce has passed
+    /// since the last line that was
+    SkipLines,
+}
+
+impl FromStr for DebounceMode {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "suppress_commands" => Ok(Self::SuppressCommands),
+            "skip_lines" => Ok(Self::SkipLines),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "debounce_mode".into(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for DebounceMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::SuppressCommands => "suppress_commands",
+            Self::SkipLines => "skip_lines",
+        })
+    }
+}
+
+/// What a watchdog does when its log file is removed, after running its
+/// `on_file_missing` commands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileMissing {
+    /// `wait`: keeps waiting for the file to be recreated, and watches it from
+    /// its start once it is
+    #[default]
+    Wait,
+    /// `stop`: stops the watchdog, as failed
+    Stop,
+}
+
+impl FromStr for FileMissing {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wait" => Ok(Self::Wait),
+            "stop" => Ok(Self::Stop),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "file_missing".into(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for FileMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Wait => "wait",
+            Self::Stop => "stop",
+        })
+    }
+}
+
+/// How a watchdog reads its log file's lines as text, which decides what
+/// happens to lines that aren't valid UTF-8, e.g. binary garbage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// `strict`: lines that aren't valid UTF-8 are skipped, with a warning
+    Strict,
+    /// `lossy`: invalid UTF-8 is replaced with U+FFFD, `�`
+    #[default]
+    Lossy,
+    /// `latin1`: every byte is a character of ISO 8859-1, for logs that
+    /// aren't written in UTF-8 at all
+    Latin1,
+}
+
+impl FromStr for Encoding {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "lossy" => Ok(Self::Lossy),
+            "latin1" => Ok(Self::Latin1),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "encoding".into(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Strict => "strict",
+            Self::Lossy => "lossy",
+            Self::Latin1 => "latin1",
+        })
+    }
+}
+
+/// What a watchdog's log file is watched with, instead of the platform's own
+/// notifications, e.g. for a file on a network share that changes don't get
+/// notified for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WatcherBackend {
+    /// `inotify`: Linux's notifications
+    Inotify,
+    /// `kqueue`: the BSDs' notifications
+    Kqueue,
+    /// `poll`: the file's metadata, every `poll_interval`, which notices
+    /// changes to any file, at the cost of their latency
+    Poll,
+}
+
+impl FromStr for WatcherBackend {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inotify" => Ok(Self::Inotify),
+            "kqueue" => Ok(Self::Kqueue),
+            "poll" => Ok(Self::Poll),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "backend".into(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for WatcherBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Inotify => "inotify",
+            Self::Kqueue => "kqueue",
+            Self::Poll => "poll",
+        })
+    }
+}
+
+/// Settings for the daemon's own logs, from the optional `logging` section.
+///
+/// Any value left unset falls back to the command line or its default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoggingSettings {
+    /// Path to a rolling log file
+    pub file: Option<PathBuf>,
+    /// When to roll the log file, `daily` or a size like `10MB`
+    pub rotation: Option<Rotation>,
+    /// Number of rolled log files to keep
+    pub retention: Option<u32>,
+    /// If false, don't log to stdout
+    pub stdout: Option<bool>,
+    /// Format of the stdout logs, `json` or `pretty`
+    pub format: Option<LogFormat>,
+    /// Facility to send logs to the local syslog daemon with
+    pub syslog: Option<Facility>,
+    /// If true, send logs to journald with structured fields
+    pub journald: Option<bool>,
+    /// Seconds between summaries of repeated log records, 0 disables collapsing
+    pub repeat_interval: Option<u64>,
+    /// Number of log records queued for writing, 0 writes them synchronously
+    pub buffer_size: Option<usize>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -69,6 +429,20 @@ pub struct Command {
     pub name: String,
     /// Any arguments to pass to the program
     pub args: Vec<String>,
+    /// The restricted profile the program is run under, if any
+    pub sandbox: Option<Sandbox>,
+    /// The container the program is run in, instead of on the host, if any
+    pub container: Option<Container>,
+}
+
+impl Command {
+    /// The program run on the host: the container runtime of a containerized
+    /// command, and the command itself otherwise.
+    pub fn program(&self) -> &str {
+        self.container
+            .as_ref()
+            .map_or(&self.name, |container| container.runtime.program())
+    }
 }
 
 impl From<&'static str> for SettingsError {
@@ -77,57 +451,411 @@ impl From<&'static str> for SettingsError {
     }
 }
 
-impl TryFrom<HashMap<String, HashMap<String, Value>>> for Settings {
+impl TryFrom<RawSettings> for Settings {
     type Error = SettingsError;
 
-    fn try_from(value: HashMap<String, HashMap<String, Value>>) -> Result<Self, Self::Error> {
-        let m = value
-            .get("watchdogs")
-            .ok_or(SettingsError::from("watchdogs"))?;
+    fn try_from(mut value: RawSettings) -> Result<Self, Self::Error> {
+        encrypted::decrypt(&mut value)?;
+        let m = section(&value, "watchdogs")?.ok_or(SettingsError::from("watchdogs"))?;
 
-        let watchdogs = m
+        let allowed_commands = value
+            .get("allowed_commands")
+            .map(parse_allowed_commands_value)
+            .transpose()?;
+        let defaults = section(&value, "defaults")?;
+        let mut regexes = HashMap::new();
+        let mut watchdogs = m
             .iter()
             .map(|(name, v)| {
-                let name = name.clone();
-                let log_file: PathBuf = get_val_or_err(v, "log_file")?;
-                let output_file: PathBuf = get_val_or_err(v, "output_file")?;
-
-                let debounce: u64 = v
-                    .get("debounce")
-                    .ok_or(SettingsError::from("debounce"))?
-                    .as_i64()
+                parse_watchdog(
+                    name,
+                    &with_defaults(v, defaults.as_ref()),
+                    &mut regexes,
+                    allowed_commands.as_ref(),
+                )
+            })
+            .collect::<Result<Vec<Watchdog>, SettingsError>>()?;
+        check_shared_output_files(&watchdogs)?;
+        let path = |key: &str| {
+            value
+                .get(key)
+                .map(|path| {
+                    path.as_str()
+                        .map(PathBuf::from)
+                        .ok_or_else(|| SettingsError::InvalidValueType { key: key.into() })
+                })
+                .transpose()
+        };
+        let audit_log = path("audit_log")?;
+        let executor = path("executor")?;
+        let executor_group = value
+            .get("executor_group")
+            .map(|group| match group {
+                Value::String(group) if !group.is_empty() => Ok(group.clone()),
+                Value::Number(gid) if gid.is_u64() => Ok(gid.to_string()),
+                _ => Err(SettingsError::InvalidValueType {
+                    key: "executor_group".into(),
+                }),
+            })
+            .transpose()?;
+        let max_concurrent_commands = value
+            .get("max_concurrent_commands")
+            .map(|max| {
+                max.as_u64()
+                    .filter(|max| *max > 0)
+                    .and_then(|max| usize::try_from(max).ok())
                     .ok_or(SettingsError::InvalidValueType {
-                        key: "debounce".into(),
-                    })?
-                    .try_into()?;
+                        key: "max_concurrent_commands".into(),
+                    })
+            })
+            .transpose()?;
+        for watchdog in &mut watchdogs {
+            watchdog.audit_log.clone_from(&audit_log);
+            watchdog.executor.clone_from(&executor);
+            watchdog.max_concurrent_commands = max_concurrent_commands;
+        }
+
+        let logging = section(&value, "logging")?
+            .as_ref()
+            .map(LoggingSettings::try_from)
+            .transpose()?
+            .unwrap_or_default();
 
-                let oneshot: bool = v
-                    .get("oneshot")
-                    .ok_or(SettingsError::from("oneshot"))?
-                    .as_bool()
+        Ok(Settings {
+            logging,
+            allowed_commands,
+            audit_log,
+            executor,
+            executor_group,
+            max_concurrent_commands,
+            watchdogs,
+        })
+    }
+}
+
+impl TryFrom<HashMap<String, HashMap<String, Value>>> for Settings {
+    type Error = SettingsError;
+
+    fn try_from(value: HashMap<String, HashMap<String, Value>>) -> Result<Self, Self::Error> {
+        Settings::try_from(
+            value
+                .into_iter()
+                .map(|(key, section)| {
+                    let section = section
+                        .into_iter()
+                        .map(|(key, value)| (Value::from(key), value))
+                        .collect();
+                    (key, Value::Mapping(section))
+                })
+                .collect::<RawSettings>(),
+        )
+    }
+}
+
+impl TryFrom<&HashMap<String, Value>> for LoggingSettings {
+    type Error = SettingsError;
+
+    fn try_from(v: &HashMap<String, Value>) -> Result<Self, Self::Error> {
+        let file = v
+            .get("file")
+            .map(|file| {
+                file.as_str()
+                    .map(PathBuf::from)
                     .ok_or(SettingsError::InvalidValueType {
-                        key: "oneshot".into(),
-                    })?;
+                        key: "logging.file".into(),
+                    })
+            })
+            .transpose()?;
 
-                let regex = Regex::new(get_val_or_err::<String>(v, "regex")?.as_str())?;
+        let rotation = v
+            .get("rotation")
+            .map(|rotation| match rotation {
+                Value::Number(n) => Ok(n.to_string().parse::<Rotation>()?),
+                Value::String(s) => Ok(s.parse::<Rotation>()?),
+                _ => Err(SettingsError::InvalidValueType {
+                    key: "logging.rotation".into(),
+                }),
+            })
+            .transpose()?;
 
-                let commands = v.get("commands").ok_or(SettingsError::from("commands"))?;
+        let retention = v
+            .get("retention")
+            .map(|retention| -> Result<u32, SettingsError> {
+                Ok(retention
+                    .as_u64()
+                    .ok_or(SettingsError::InvalidValueType {
+                        key: "logging.retention".into(),
+                    })?
+                    .try_into()?)
+            })
+            .transpose()?;
 
-                let commands = parse_commands_value(commands)?;
+        let stdout = v
+            .get("stdout")
+            .map(|stdout| {
+                stdout.as_bool().ok_or(SettingsError::InvalidValueType {
+                    key: "logging.stdout".into(),
+                })
+            })
+            .transpose()?;
 
-                Ok(Watchdog {
-                    name,
-                    log_file,
-                    output_file,
-                    debounce,
-                    oneshot,
-                    regex,
-                    commands,
+        let format = v
+            .get("format")
+            .map(|format| -> Result<LogFormat, SettingsError> {
+                Ok(format
+                    .as_str()
+                    .ok_or(SettingsError::InvalidValueType {
+                        key: "logging.format".into(),
+                    })?
+                    .parse()?)
+            })
+            .transpose()?;
+
+        let syslog = v
+            .get("syslog")
+            .map(|facility| -> Result<Facility, SettingsError> {
+                Ok(facility
+                    .as_str()
+                    .ok_or(SettingsError::InvalidValueType {
+                        key: "logging.syslog".into(),
+                    })?
+                    .parse()?)
+            })
+            .transpose()?;
+
+        let journald = v
+            .get("journald")
+            .map(|journald| {
+                journald.as_bool().ok_or(SettingsError::InvalidValueType {
+                    key: "logging.journald".into(),
                 })
             })
-            .collect::<Result<Vec<Watchdog>, SettingsError>>()?;
+            .transpose()?;
+
+        let repeat_interval = v
+            .get("repeat_interval")
+            .map(|interval| {
+                interval.as_u64().ok_or(SettingsError::InvalidValueType {
+                    key: "logging.repeat_interval".into(),
+                })
+            })
+            .transpose()?;
+
+        let buffer_size = v
+            .get("buffer_size")
+            .map(|size| -> Result<usize, SettingsError> {
+                Ok(size
+                    .as_u64()
+                    .ok_or(SettingsError::InvalidValueType {
+                        key: "logging.buffer_size".into(),
+                    })?
+                    .try_into()?)
+            })
+            .transpose()?;
 
-        Ok(Settings { watchdogs })
+        Ok(LoggingSettings {
+            file,
+            rotation,
+            retention,
+            stdout,
+            format,
+            syslog,
+            journald,
+            repeat_interval,
+            buffer_size,
+        })
+    }
+}
+
+/// The problems found in a settings file by [`Settings::validate`].
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Problems with the file as a whole, or its `logging` section
+    pub errors: Vec<SettingsError>,
+    /// Every watchdog by name, with the first problem found in it, if any
+    pub watchdogs: Vec<(String, Option<SettingsError>)>,
+}
+
+impl Report {
+    /// If true, no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty() && self.watchdogs.iter().all(|(_, error)| error.is_none())
+    }
+}
+
+impl Settings {
+    /// Parses the settings files at `paths`, merged like
+    /// [`Settings::try_from`], compiling every watchdog's regex, and reports
+    /// every watchdog's problems instead of stopping at the first, along with
+    /// the files' signatures with the public key if there is one.
+    pub fn validate(paths: &[PathBuf], public_key: Option<&PublicKey>) -> Report {
+        let mut report = Report::default();
+        let contents = match read_all(paths) {
+            Ok(contents) => contents,
+            Err(e) => {
+                report.errors.push(e);
+                return report;
+            }
+        };
+        if let Some(key) = public_key {
+            report.errors.extend(
+                paths
+                    .iter()
+                    .zip(&contents)
+                    .filter_map(|(path, contents)| key.verify(path, contents).err()),
+            );
+        }
+        let settings = match merge_all(&contents).and_then(|mut settings| {
+            encrypted::decrypt(&mut settings)?;
+            Ok(settings)
+        }) {
+            Ok(settings) => settings,
+            Err(e) => {
+                report.errors.push(e);
+                return report;
+            }
+        };
+
+        let allowed_commands = match settings
+            .get("allowed_commands")
+            .map(parse_allowed_commands_value)
+            .transpose()
+        {
+            Ok(allowed_commands) => allowed_commands,
+            Err(e) => {
+                report.errors.push(e);
+                None
+            }
+        };
+        let defaults = section(&settings, "defaults").unwrap_or_else(|e| {
+            report.errors.push(e);
+            None
+        });
+        match section(&settings, "watchdogs") {
+            Ok(Some(watchdogs)) => {
+                let mut regexes = HashMap::new();
+                let mut parsed = Vec::new();
+                report.watchdogs = watchdogs
+                    .iter()
+                    .map(|(name, v)| {
+                        let v = with_defaults(v, defaults.as_ref());
+                        match parse_watchdog(name, &v, &mut regexes, allowed_commands.as_ref()) {
+                            Ok(watchdog) => {
+                                parsed.push(watchdog);
+                                (name.clone(), None)
+                            }
+                            Err(e) => (name.clone(), Some(e)),
+                        }
+                    })
+                    .collect();
+                report.watchdogs.sort_by(|(a, _), (b, _)| a.cmp(b));
+                if let Err(e) = check_shared_output_files(&parsed) {
+                    report.errors.push(e);
+                }
+            }
+            Ok(None) => report.errors.push(SettingsError::from("watchdogs")),
+            Err(e) => report.errors.push(e),
+        }
+        match section(&settings, "logging") {
+            Ok(Some(logging)) => {
+                if let Err(e) = LoggingSettings::try_from(&logging) {
+                    report.errors.push(e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => report.errors.push(e),
+        }
+
+        report
+    }
+}
+
+impl Settings {
+    /// Loads the settings files at `paths`, merged like
+    /// [`Settings::try_from`], once each file is verified with the public key
+    /// if there is one. Each file is read once, and its signature checked on
+    /// the contents that are parsed.
+    ///
+    /// # Errors
+    ///
+    /// If a file can't be read, isn't signed by the key, or the settings
+    /// aren't valid.
+    pub fn load(paths: &[PathBuf], public_key: Option<&PublicKey>) -> Result<Self, SettingsError> {
+        let contents = read_all(paths)?;
+        if let Some(key) = public_key {
+            for (path, contents) in paths.iter().zip(&contents) {
+                key.verify(path, contents)?;
+            }
+        }
+        Settings::try_from(merge_all(&contents)?)
+    }
+}
+
+impl Settings {
+    /// Where the settings are looked for when no file is given, in order:
+    /// `./log-watchdog.yml`, `$XDG_CONFIG_HOME/log-watchdog/config.yml`
+    /// (`~/.config` if unset) and `/etc/log-watchdog/config.yml`.
+    pub fn search_paths() -> Vec<PathBuf> {
+        search_paths(
+            std::env::var_os("XDG_CONFIG_HOME"),
+            std::env::var_os("HOME"),
+        )
+    }
+
+    /// The first of the [`Settings::search_paths`] that exists, if any.
+    pub fn find() -> Option<PathBuf> {
+        Self::search_paths().into_iter().find(|path| path.is_file())
+    }
+
+    /// The names of the watchdogs in the settings files, merged in order,
+    /// sorted, e.g. for shell completion: only read from the files that are
+    /// regular files, skipping stdin and any other, and without decrypting,
+    /// verifying or parsing the watchdogs.
+    ///
+    /// # Errors
+    ///
+    /// If a file can't be read or parsed, or its `watchdogs` isn't a mapping.
+    pub fn watchdog_names(paths: &[PathBuf]) -> Result<Vec<String>, SettingsError> {
+        let paths: Vec<_> = paths
+            .iter()
+            .filter(|path| *path != Path::new("-") && path.is_file())
+            .cloned()
+            .collect();
+        let settings = merge_all(&read_all(&paths)?)?;
+        let mut names: Vec<_> = section(&settings, "watchdogs")?
+            .unwrap_or_default()
+            .into_keys()
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+fn search_paths(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Vec<PathBuf> {
+    let config_home = xdg_config_home
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            home.map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .map(|home| home.join(".config"))
+        });
+
+    std::iter::once(PathBuf::from("./log-watchdog.yml"))
+        .chain(config_home.map(|path| path.join("log-watchdog/config.yml")))
+        .chain(std::iter::once(PathBuf::from(
+            "/etc/log-watchdog/config.yml",
+        )))
+        .collect()
+}
+
+/// Parses the settings from YAML, like a settings file.
+impl FromStr for Settings {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let settings: RawSettings = serde_yaml::from_str(s)?;
+        Settings::try_from(settings)
     }
 }
 
@@ -135,10 +863,424 @@ impl TryFrom<&Path> for Settings {
     type Error = SettingsError;
 
     fn try_from(value: &Path) -> Result<Self, Self::Error> {
-        let file = OpenOptions::new().read(true).open(value)?;
-        let settings: HashMap<String, HashMap<String, Value>> = serde_yaml::from_reader(file)?;
-        Settings::try_from(settings)
+        Settings::try_from(serde_yaml::from_slice::<RawSettings>(&read(value)?)?)
+    }
+}
+
+/// Merges the settings files in order, later files overriding or extending
+/// earlier ones: mappings, like a watchdog or its `commands`, are merged key
+/// by key, and any other value is replaced. E.g. fleet-wide defaults followed
+/// by a host's additions.
+impl TryFrom<&[PathBuf]> for Settings {
+    type Error = SettingsError;
+
+    fn try_from(value: &[PathBuf]) -> Result<Self, Self::Error> {
+        Settings::load(value, None)
+    }
+}
+
+/// The top-level keys of the settings: sections like `watchdogs`, which are
+/// mappings, and lists like `allowed_commands`.
+type RawSettings = HashMap<String, Value>;
+
+/// The section at `key`, if there is one.
+fn section(
+    settings: &RawSettings,
+    key: &str,
+) -> Result<Option<HashMap<String, Value>>, SettingsError> {
+    settings
+        .get(key)
+        .map(|section| {
+            serde_yaml::from_value(section.clone())
+                .map_err(|_| SettingsError::InvalidValueType { key: key.into() })
+        })
+        .transpose()
+}
+
+/// Reads a settings file, or stdin for `-`.
+fn read(path: &Path) -> Result<Vec<u8>, SettingsError> {
+    let mut contents = Vec::new();
+    if path == Path::new("-") {
+        std::io::stdin().lock().read_to_end(&mut contents)?;
+        return Ok(contents);
+    }
+    OpenOptions::new()
+        .read(true)
+        .open(path)
+        .and_then(|mut file| file.read_to_end(&mut contents))
+        .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    Ok(contents)
+}
+
+/// Reads the settings files, each once.
+fn read_all(paths: &[PathBuf]) -> Result<Vec<Vec<u8>>, SettingsError> {
+    paths.iter().map(|path| read(path)).collect()
+}
+
+/// Parses the settings files' contents, merged in order.
+fn merge_all(contents: &[Vec<u8>]) -> Result<RawSettings, SettingsError> {
+    let mut merged = RawSettings::new();
+    for contents in contents {
+        let settings: RawSettings = serde_yaml::from_slice(contents)?;
+        for (key, value) in settings {
+            match merged.get_mut(&key) {
+                Some(base) => merge(base, value),
+                None => {
+                    merged.insert(key, value);
+                }
+            }
+        }
     }
+    Ok(merged)
+}
+
+/// The watchdog's settings merged over the `defaults` section, like settings
+/// files are merged, e.g. for tunables that every watchdog shares.
+fn with_defaults(watchdog: &Value, defaults: Option<&HashMap<String, Value>>) -> Value {
+    let Some(defaults) = defaults else {
+        return watchdog.clone();
+    };
+    let mut merged = Value::Mapping(
+        defaults
+            .iter()
+            .map(|(key, value)| (key.as_str().into(), value.clone()))
+            .collect(),
+    );
+    merge(&mut merged, watchdog.clone());
+    merged
+}
+
+/// Checks that the watchdogs writing to the same output file write it alike,
+/// since the file is shared, and written as the watchdog that opened it says.
+fn check_shared_output_files(watchdogs: &[Watchdog]) -> Result<(), SettingsError> {
+    let mut files: Vec<(PathBuf, &Watchdog, &OutputFormat)> = Vec::new();
+    for watchdog in watchdogs {
+        for output in &watchdog.outputs {
+            let OutputTarget::File(path) = &output.target else {
+                continue;
+            };
+            let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+            match files.iter().find(|(file, ..)| *file == path) {
+                Some((_, first, format))
+                    if !first.file_options.same_file(&watchdog.file_options)
+                        || **format != output.format =>
+                {
+                    return Err(SettingsError::SharedOutputFile {
+                        path,
+                        first: first.name.clone(),
+                        second: watchdog.name.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => files.push((path, watchdog, &output.format)),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Mapping(base), Value::Mapping(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+/// `regexes` are the ones compiled for the watchdogs parsed before, by
+/// pattern: watchdogs with the same regex, e.g. from a templated config, share
+/// its compilation, as a `Regex`'s clones do.
+///
+/// The commands must be in `allowed_commands`, if it's set.
+fn parse_watchdog(
+    name: &str,
+    v: &Value,
+    regexes: &mut HashMap<String, Regex>,
+    allowed_commands: Option<&AllowedCommands>,
+) -> Result<Watchdog, SettingsError> {
+    let name = name.to_string();
+    let log_file: PathBuf = get_val_or_err(v, "log_file")?;
+    let output_format = v
+        .get("output_format")
+        .map(|format| {
+            format
+                .as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "output_format".into(),
+                })?
+                .parse()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let outputs = v
+        .get("output")
+        .or_else(|| v.get("output_file"))
+        .ok_or(SettingsError::from("output_file"))?;
+    let mut outputs = parse_outputs_value(outputs, &output_format)?;
+    if let Some(columns) = v.get("output_columns") {
+        let columns = parse_columns_value(columns)?;
+        for output in &mut outputs {
+            if let OutputFormat::Csv(c) = &mut output.format {
+                c.clone_from(&columns);
+            }
+        }
+    }
+    let file_options = parse_file_options(v)?;
+
+    let debounce: u64 = v
+        .get("debounce")
+        .ok_or(SettingsError::from("debounce"))?
+        .as_i64()
+        .ok_or(SettingsError::InvalidValueType {
+            key: "debounce".into(),
+        })?
+        .try_into()?;
+
+    let debounce_mode = v
+        .get("debounce_mode")
+        .map(|mode| {
+            mode.as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "debounce_mode".into(),
+                })?
+                .parse()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let oneshot: bool = v
+        .get("oneshot")
+        .ok_or(SettingsError::from("oneshot"))?
+        .as_bool()
+        .ok_or(SettingsError::InvalidValueType {
+            key: "oneshot".into(),
+        })?;
+
+    let pattern: String = get_val_or_err(v, "regex")?;
+    let regex = match regexes.get(&pattern) {
+        Some(regex) => regex.clone(),
+        None => {
+            let regex = Regex::new(&pattern)?;
+            regexes.insert(pattern, regex.clone());
+            regex
+        }
+    };
+
+    let commands = v.get("commands").ok_or(SettingsError::from("commands"))?;
+
+    let commands = parse_commands_value(commands)?;
+
+    let dry_run = v
+        .get("dry_run")
+        .map(|dry_run| {
+            dry_run.as_bool().ok_or(SettingsError::InvalidValueType {
+                key: "dry_run".into(),
+            })
+        })
+        .transpose()?
+        .unwrap_or(false);
+
+    let log_level = v
+        .get("log_level")
+        .map(|level| -> Result<LevelFilter, SettingsError> {
+            Ok(logging::parse_level_filter(level.as_str().ok_or(
+                SettingsError::InvalidValueType {
+                    key: "log_level".into(),
+                },
+            )?)?)
+        })
+        .transpose()?;
+
+    let log_sample_rate = v
+        .get("log_sample_rate")
+        .map(|rate| {
+            rate.as_u64()
+                .filter(|rate| *rate > 0)
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "log_sample_rate".into(),
+                })
+        })
+        .transpose()?
+        .unwrap_or(1);
+
+    let max_read_bytes = v
+        .get("max_read_bytes")
+        .map(|bytes| {
+            bytes
+                .as_u64()
+                .filter(|bytes| *bytes > 0)
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "max_read_bytes".into(),
+                })
+        })
+        .transpose()?
+        .unwrap_or(DEFAULT_MAX_READ_BYTES);
+
+    let read_buffer_size = v
+        .get("read_buffer_size")
+        .map(|size| {
+            size.as_u64()
+                .filter(|size| *size > 0)
+                .and_then(|size| usize::try_from(size).ok())
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "read_buffer_size".into(),
+                })
+        })
+        .transpose()?
+        .unwrap_or(DEFAULT_READ_BUFFER_SIZE);
+
+    let partial_line_timeout = v
+        .get("partial_line_timeout")
+        .map(|timeout| {
+            timeout.as_u64().ok_or(SettingsError::InvalidValueType {
+                key: "partial_line_timeout".into(),
+            })
+        })
+        .transpose()?;
+
+    let file_missing = v
+        .get("file_missing")
+        .map(|missing| {
+            missing
+                .as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "file_missing".into(),
+                })?
+                .parse()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let on_file_missing = v
+        .get("on_file_missing")
+        .map(parse_commands_value)
+        .transpose()?
+        .unwrap_or_default();
+
+    let encoding = v
+        .get("encoding")
+        .map(|encoding| {
+            encoding
+                .as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "encoding".into(),
+                })?
+                .parse()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let backend = v
+        .get("backend")
+        .map(|backend| {
+            backend
+                .as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "backend".into(),
+                })?
+                .parse()
+        })
+        .transpose()?;
+
+    let poll_interval = v
+        .get("poll_interval")
+        .map(|interval| {
+            interval.as_u64().filter(|interval| *interval > 0).ok_or(
+                SettingsError::InvalidValueType {
+                    key: "poll_interval".into(),
+                },
+            )
+        })
+        .transpose()?
+        .unwrap_or(DEFAULT_POLL_INTERVAL);
+
+    let redact = v
+        .get("redact")
+        .map(parse_redact_value)
+        .transpose()?
+        .unwrap_or_default();
+
+    let alertmanager = v
+        .get("alertmanager")
+        .map(Alertmanager::from_value)
+        .transpose()?;
+
+    let passive_check = v
+        .get("passive_check")
+        .map(PassiveCheck::from_value)
+        .transpose()?;
+
+    let zabbix = v.get("zabbix").map(Zabbix::from_value).transpose()?;
+
+    if let Some(allowed_commands) = allowed_commands {
+        for command in commands.iter().chain(&on_file_missing) {
+            allowed_commands.check_command(command)?;
+        }
+    }
+
+    Ok(Watchdog {
+        name,
+        log_file,
+        outputs,
+        file_options,
+        debounce,
+        debounce_mode,
+        oneshot,
+        regex,
+        commands,
+        dry_run,
+        log_level,
+        log_sample_rate,
+        max_read_bytes,
+        read_buffer_size,
+        partial_line_timeout,
+        file_missing,
+        on_file_missing,
+        encoding,
+        backend,
+        poll_interval,
+        redact,
+        alertmanager,
+        passive_check,
+        zabbix,
+        allowed_commands: allowed_commands.cloned(),
+        audit_log: None,
+        executor: None,
+        max_concurrent_commands: None,
+    })
+}
+
+fn parse_redact_value(v: &Value) -> Result<Redactions, SettingsError> {
+    let invalid = || SettingsError::InvalidValueType {
+        key: "redact".into(),
+    };
+    v.as_sequence()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|pattern| Ok(Regex::new(pattern.as_str().ok_or_else(invalid)?)?))
+        .collect::<Result<_, SettingsError>>()
+        .map(Redactions::new)
+}
+
+fn parse_allowed_commands_value(v: &Value) -> Result<AllowedCommands, SettingsError> {
+    let invalid = || SettingsError::InvalidValueType {
+        key: "allowed_commands".into(),
+    };
+    v.as_sequence()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|command| command.as_str().ok_or_else(invalid)?.parse())
+        .collect::<Result<_, _>>()
+        .map(AllowedCommands::new)
 }
 
 fn parse_commands_value(commands: &Value) -> Result<Vec<Command>, SettingsError> {
@@ -176,12 +1318,250 @@ fn parse_commands_value(commands: &Value) -> Result<Vec<Command>, SettingsError>
                         .map(|s| s.to_string())
                 })
                 .collect();
+            let sandbox = v.get("sandbox").map(Sandbox::try_from).transpose()?;
+            let container = Container::from_command(v)?;
+            // a container is a sandbox of its own
+            if sandbox.is_some() && container.is_some() {
+                return Err(SettingsError::InvalidValueType {
+                    key: "commands.named_command.sandbox".into(),
+                });
+            }
 
-            Ok(Command { name, args: args? })
+            Ok(Command {
+                name,
+                args: args?,
+                sandbox,
+                container,
+            })
         })
         .collect()
 }
 
+/// Parses a single output, or a list of them. Outputs are a target, or a
+/// mapping with a `target`, its own `format` and TLS options.
+fn parse_outputs_value(
+    outputs: &Value,
+    default_format: &OutputFormat,
+) -> Result<Vec<OutputSettings>, SettingsError> {
+    let parse_output = |output: &Value| -> Result<OutputSettings, SettingsError> {
+        let invalid = || SettingsError::InvalidValueType {
+            key: "output".into(),
+        };
+        let (target, format, mapping) = match output {
+            Value::String(target) => (target.as_str(), None, None),
+            Value::Mapping(mapping) => (
+                mapping
+                    .get("target")
+                    .ok_or(SettingsError::from("output.target"))?
+                    .as_str()
+                    .ok_or_else(invalid)?,
+                mapping
+                    .get("format")
+                    .map(|format| format.as_str().ok_or_else(invalid)?.parse())
+                    .transpose()?,
+                Some(output),
+            ),
+            _ => return Err(invalid()),
+        };
+
+        let target: OutputTarget = target.parse()?;
+        // only Loki and Elasticsearch targets have a URL to use TLS with
+        let url = match &target {
+            OutputTarget::Loki(url) | OutputTarget::Elasticsearch { url, .. } => url.as_str(),
+            _ => "",
+        };
+        let tls = mapping
+            .map(|mapping| Tls::from_value(mapping, url, "output"))
+            .transpose()?
+            .unwrap_or_default();
+        let format = format.unwrap_or_else(|| {
+            if target.is_socket() {
+                OutputFormat::Jsonl
+            } else {
+                default_format.clone()
+            }
+        });
+
+        let output = OutputSettings {
+            target,
+            format,
+            tls,
+        };
+        output.check()?;
+        Ok(output)
+    };
+
+    match outputs {
+        Value::Sequence(outputs) => outputs.iter().map(parse_output).collect(),
+        output => Ok(vec![parse_output(output)?]),
+    }
+}
+
+/// Parses `output_columns`, a list of the `csv` format's columns.
+fn parse_columns_value(columns: &Value) -> Result<Vec<Column>, SettingsError> {
+    let invalid = || SettingsError::InvalidValueType {
+        key: "output_columns".into(),
+    };
+    let columns = columns.as_sequence().ok_or_else(invalid)?;
+    if columns.is_empty() {
+        return Err(invalid());
+    }
+
+    columns
+        .iter()
+        .map(|column| match column {
+            Value::String(column) => column.parse(),
+            Value::Number(index) => index.to_string().parse(),
+            _ => Err(invalid()),
+        })
+        .collect()
+}
+
+fn parse_file_options(v: &Value) -> Result<FileOptions, SettingsError> {
+    let flush = v
+        .get("flush")
+        .map(|flush| match flush {
+            Value::Number(interval) => Ok(FlushPolicy::Interval(Duration::from_millis(
+                interval.as_u64().ok_or(SettingsError::InvalidValueType {
+                    key: "flush".into(),
+                })?,
+            ))),
+            Value::String(flush) => flush.parse(),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "flush".into(),
+            }),
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let fsync = v
+        .get("fsync")
+        .map(|fsync| {
+            fsync.as_bool().ok_or(SettingsError::InvalidValueType {
+                key: "fsync".into(),
+            })
+        })
+        .transpose()?
+        .unwrap_or(false);
+
+    let append = v
+        .get("output_append")
+        .map(|append| {
+            append.as_bool().ok_or(SettingsError::InvalidValueType {
+                key: "output_append".into(),
+            })
+        })
+        .transpose()?
+        .unwrap_or(true);
+
+    // an unquoted 0640 is read as the decimal 640, its digits are still octal
+    let mode = v
+        .get("output_mode")
+        .map(|mode| match mode {
+            Value::Number(mode) => parse_mode(&mode.to_string()),
+            Value::String(mode) => parse_mode(mode),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "output_mode".into(),
+            }),
+        })
+        .transpose()?;
+
+    let owner = v
+        .get("output_owner")
+        .map(|owner| match owner {
+            Value::Number(uid) => uid.to_string().parse(),
+            Value::String(owner) => owner.parse(),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "output_owner".into(),
+            }),
+        })
+        .transpose()?;
+
+    let rotation = v
+        .get("output_rotation")
+        .map(|rotation| -> Result<Rotation, SettingsError> {
+            Ok(rotation
+                .as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "output_rotation".into(),
+                })?
+                .parse()?)
+        })
+        .transpose()?;
+
+    let retention = v
+        .get("output_retention")
+        .map(|retention| -> Result<u32, SettingsError> {
+            Ok(retention
+                .as_u64()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "output_retention".into(),
+                })?
+                .try_into()?)
+        })
+        .transpose()?
+        .unwrap_or(7);
+
+    let compression = v
+        .get("output_compression")
+        .map(|compression| {
+            compression
+                .as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "output_compression".into(),
+                })?
+                .parse()
+        })
+        .transpose()?;
+
+    let dedup = v
+        .get("output_dedup")
+        .map(|dedup| {
+            dedup.as_bool().ok_or(SettingsError::InvalidValueType {
+                key: "output_dedup".into(),
+            })
+        })
+        .transpose()?
+        .unwrap_or(false);
+
+    let empty = v
+        .get("output_empty")
+        .map(|empty| {
+            empty
+                .as_str()
+                .ok_or(SettingsError::InvalidValueType {
+                    key: "output_empty".into(),
+                })?
+                .parse()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let suppressed = v
+        .get("output_suppressed")
+        .map(|suppressed| {
+            suppressed.as_bool().ok_or(SettingsError::InvalidValueType {
+                key: "output_suppressed".into(),
+            })
+        })
+        .transpose()?
+        .unwrap_or(false);
+
+    Ok(FileOptions {
+        append,
+        flush,
+        fsync,
+        mode,
+        owner,
+        rotation,
+        retention,
+        compression,
+        dedup,
+        empty,
+        suppressed,
+    })
+}
+
 fn get_val_or_err<T: From<String>>(v: &Value, key: &'static str) -> Result<T, SettingsError> {
     Ok(T::from(
         v.get(key)
@@ -196,7 +1576,7 @@ fn get_val_or_err<T: From<String>>(v: &Value, key: &'static str) -> Result<T, Se
 mod tests {
     use serde_yaml::Value;
 
-    use std::{collections::HashMap, path::PathBuf};
+    use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
 
     use super::*;
 
@@ -224,8 +1604,12 @@ mod tests {
             PathBuf::from("/var/log/pgbouncer/pgbouncer.log")
         );
         assert_eq!(
-            settings.watchdogs[0].output_file,
-            PathBuf::from("/var/log/pgbouncer/pgbouncer.out")
+            settings.watchdogs[0].outputs,
+            vec![OutputSettings {
+                target: OutputTarget::File(PathBuf::from("/var/log/pgbouncer/pgbouncer.out")),
+                format: OutputFormat::from_str("{timestamp} {watchdog}: {stdout}").unwrap(),
+                tls: Tls::default(),
+            }]
         );
         assert_eq!(settings.watchdogs[0].regex.as_str(), ".*");
         assert_eq!(settings.watchdogs[0].commands.len(), 1);
@@ -233,12 +1617,79 @@ mod tests {
             settings.watchdogs[0].commands[0],
             Command {
                 name: "ls".into(),
-                args: vec!["-a".into()]
+                args: vec!["-a".into()],
+                sandbox: Some(Sandbox {
+                    env: vec!["LANG".into()],
+                    wrapper: Some(SandboxWrapper::Bubblewrap),
+                    ..Sandbox::default()
+                }),
+                container: None,
             }
         );
 
         assert_eq!(settings.watchdogs[0].debounce, 5000);
+        assert_eq!(settings.watchdogs[0].debounce_mode, DebounceMode::SkipLines);
+        assert_eq!(settings.watchdogs[0].file_missing, FileMissing::Stop);
+        assert_eq!(settings.watchdogs[0].encoding, Encoding::Latin1);
+        assert_eq!(settings.watchdogs[0].backend, Some(WatcherBackend::Poll));
+        assert_eq!(settings.watchdogs[0].poll_interval, 5000);
+        assert_eq!(
+            settings.watchdogs[0].redact.redact("?token=s3cr3t"),
+            "?token=[REDACTED]"
+        );
+        assert_eq!(
+            settings.watchdogs[0].on_file_missing,
+            vec![Command {
+                name: "logger".into(),
+                args: vec!["pgbouncer.log is missing".into()],
+                sandbox: None,
+                container: None,
+            }]
+        );
         assert!(settings.watchdogs[0].oneshot);
+        assert_eq!(settings.watchdogs[0].log_level, Some(LevelFilter::Debug));
+        assert!(settings.watchdogs[0].dry_run);
+        assert_eq!(settings.watchdogs[0].log_sample_rate, 100);
+        assert_eq!(settings.watchdogs[0].max_read_bytes, 65536);
+        assert_eq!(settings.watchdogs[0].read_buffer_size, 4096);
+        assert_eq!(settings.watchdogs[0].partial_line_timeout, Some(2000));
+        assert_eq!(
+            settings.watchdogs[0].file_options,
+            FileOptions {
+                append: false,
+                flush: FlushPolicy::Interval(Duration::from_millis(1000)),
+                fsync: true,
+                mode: Some(0o640),
+                owner: Some(Owner {
+                    user: Some("postgres".into()),
+                    group: Some("adm".into()),
+                }),
+                rotation: Some(Rotation::Size(50 * 1024 * 1024)),
+                retention: 5,
+                compression: Some(Compression {
+                    algorithm: CompressionAlgorithm::Zstd,
+                    level: Some(19),
+                }),
+                dedup: true,
+                empty: EmptyOutput::Skip,
+                suppressed: true,
+            }
+        );
+
+        assert_eq!(
+            settings.logging,
+            LoggingSettings {
+                file: Some(PathBuf::from("/var/log/log-watchdog/log-watchdog.log")),
+                rotation: Some(Rotation::Size(10 * 1024 * 1024)),
+                retention: Some(3),
+                stdout: None,
+                format: Some(LogFormat::Json),
+                syslog: Some(Facility::Local0),
+                journald: Some(false),
+                repeat_interval: Some(60),
+                buffer_size: Some(1024),
+            }
+        );
     }
 
     #[test]
@@ -248,4 +1699,849 @@ mod tests {
         let settings = Settings::try_from(settings_path.as_path());
         assert!(settings.is_err());
     }
+
+    fn settings_from_yaml(yaml: &str) -> Result<Settings, SettingsError> {
+        yaml.parse()
+    }
+
+    #[test]
+    fn test_when_output_instead_of_output_file_then_target_parsed() {
+        let settings = settings_from_yaml(
+            r#"
+watchdogs:
+  dash:
+    log_file: a.log
+    output_file: "-"
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+  stderr:
+    log_file: a.log
+    output: stderr
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+"#,
+        )
+        .unwrap();
+
+        let output = |name| {
+            settings
+                .watchdogs()
+                .iter()
+                .find(|w| w.name == name)
+                .map(|w| w.outputs[0].target.clone())
+        };
+        assert_eq!(output("dash"), Some(OutputTarget::Stdout));
+        assert_eq!(output("stderr"), Some(OutputTarget::Stderr));
+    }
+
+    #[test]
+    fn test_when_defaults_then_used_by_watchdogs_without_their_own() {
+        let settings = settings_from_yaml(
+            r#"
+defaults:
+  read_buffer_size: 1048576
+  max_read_bytes: 8388608
+watchdogs:
+  busy:
+    log_file: a.log
+    output: stdout
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+  quiet:
+    log_file: b.log
+    output: stdout
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+    read_buffer_size: 4096
+"#,
+        )
+        .unwrap();
+
+        let watchdog = |name| {
+            settings
+                .watchdogs()
+                .iter()
+                .find(|w| w.name == name)
+                .unwrap()
+        };
+        assert_eq!(watchdog("busy").read_buffer_size, 1_048_576);
+        assert_eq!(watchdog("quiet").read_buffer_size, 4096);
+        assert_eq!(watchdog("quiet").max_read_bytes, 8_388_608);
+    }
+
+    #[test]
+    fn test_when_audit_log_then_every_watchdog_records_to_it() {
+        let settings = settings_from_yaml(
+            r#"
+audit_log: /var/log/log-watchdog/audit.jsonl
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output: stdout
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+"#,
+        )
+        .unwrap();
+
+        let audit_log = Path::new("/var/log/log-watchdog/audit.jsonl");
+        assert_eq!(settings.audit_log(), Some(audit_log));
+        assert_eq!(settings.watchdogs[0].audit_log.as_deref(), Some(audit_log));
+        let parsed = settings_from_yaml(&serde_yaml::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(parsed.audit_log(), Some(audit_log));
+    }
+
+    #[test]
+    fn test_when_executor_then_every_watchdog_sends_commands_to_it() {
+        let settings = settings_from_yaml(
+            r#"
+executor: /run/log-watchdog/executor.sock
+executor_group: log-watchdog
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output: stdout
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+"#,
+        )
+        .unwrap();
+
+        let executor = Path::new("/run/log-watchdog/executor.sock");
+        assert_eq!(settings.executor(), Some(executor));
+        assert_eq!(settings.executor_group(), Some("log-watchdog"));
+        assert_eq!(settings.watchdogs[0].executor.as_deref(), Some(executor));
+        let parsed = settings_from_yaml(&serde_yaml::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(parsed.executor(), Some(executor));
+        assert_eq!(parsed.executor_group(), Some("log-watchdog"));
+    }
+
+    #[test]
+    fn test_when_max_concurrent_commands_then_every_watchdog_shares_it() {
+        let yaml = |max: &str| {
+            format!(
+                r#"
+max_concurrent_commands: {max}
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output: stdout
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {{}}
+"#
+            )
+        };
+        let settings = settings_from_yaml(&yaml("4")).unwrap();
+
+        assert_eq!(settings.max_concurrent_commands(), Some(4));
+        assert_eq!(settings.watchdogs[0].max_concurrent_commands, Some(4));
+        let parsed = settings_from_yaml(&serde_yaml::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(parsed.max_concurrent_commands(), Some(4));
+        assert!(settings_from_yaml(&yaml("0")).is_err());
+    }
+
+    #[test]
+    fn test_when_allowed_commands_then_only_those_run() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir::TempDir::new("test_allowed_commands").unwrap();
+        let restart = dir.path().join("restart");
+        std::fs::write(&restart, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&restart, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let yaml = |command: &str| {
+            format!(
+                r#"
+allowed_commands:
+  - {}
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output: stdout
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands:
+      {command}:
+        args: []
+"#,
+                restart.display()
+            )
+        };
+
+        let settings = settings_from_yaml(&yaml(&restart.display().to_string())).unwrap();
+        assert_eq!(
+            settings.watchdogs[0].allowed_commands,
+            Some(AllowedCommands::new(vec![AllowedCommand::Path(
+                restart.clone()
+            )]))
+        );
+        let parsed = settings_from_yaml(&serde_yaml::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(parsed.allowed_commands, settings.allowed_commands);
+
+        assert!(matches!(
+            settings_from_yaml(&yaml("sh")),
+            Err(SettingsError::CommandNotAllowed(command)) if command == "sh"
+        ));
+    }
+
+    #[test]
+    fn test_when_watchdogs_have_same_regex_then_compiled_once() {
+        let watchdog = |regex: &str| {
+            serde_yaml::from_str::<Value>(&format!(
+                "{{log_file: a.log, output: stdout, debounce: 0, oneshot: false, regex: '{regex}', commands: {{}}}}"
+            ))
+            .unwrap()
+        };
+        let mut regexes = HashMap::new();
+
+        let a = parse_watchdog("a", &watchdog("^ERROR"), &mut regexes, None).unwrap();
+        let b = parse_watchdog("b", &watchdog("^ERROR"), &mut regexes, None).unwrap();
+        parse_watchdog("c", &watchdog("^WARN"), &mut regexes, None).unwrap();
+
+        assert_eq!(a.regex.as_str(), b.regex.as_str());
+        assert_eq!(regexes.len(), 2);
+    }
+
+    #[test]
+    fn test_when_output_list_then_every_output_parsed() {
+        let settings = settings_from_yaml(
+            r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output:
+      - /var/log/pgbouncer.out
+      - target: stdout
+        format: jsonl
+      - unix:/run/collector.sock
+    output_format: "{watchdog}: {stdout}"
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            settings.watchdogs()[0].outputs,
+            vec![
+                OutputSettings {
+                    target: OutputTarget::File(PathBuf::from("/var/log/pgbouncer.out")),
+                    format: OutputFormat::from_str("{watchdog}: {stdout}").unwrap(),
+                    tls: Tls::default(),
+                },
+                OutputSettings {
+                    target: OutputTarget::Stdout,
+                    format: OutputFormat::Jsonl,
+                    tls: Tls::default(),
+                },
+                OutputSettings {
+                    target: OutputTarget::Unix(PathBuf::from("/run/collector.sock")),
+                    format: OutputFormat::Jsonl,
+                    tls: Tls::default(),
+                },
+            ]
+        );
+        assert!(settings_from_yaml(
+            r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output:
+      - format: jsonl
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_when_output_tls_then_only_https_loki_or_elasticsearch() {
+        let settings = |output: &str| {
+            settings_from_yaml(&format!(
+                r"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output: {output}
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {{}}
+"
+            ))
+        };
+
+        let parsed = settings(
+            "{target: 'loki:https://loki:3100', client_cert: client.pem, client_key: client.key}",
+        )
+        .unwrap();
+        let output = &parsed.watchdogs()[0].outputs[0];
+        assert_eq!(output.format, OutputFormat::Jsonl);
+        assert_eq!(output.tls.client_key, Some(PathBuf::from("client.key")));
+        let yaml = serde_yaml::to_string(&parsed).unwrap();
+        assert_eq!(
+            settings_from_yaml(&yaml).unwrap().watchdogs()[0].outputs,
+            parsed.watchdogs()[0].outputs
+        );
+        assert!(settings("{target: 'loki:http://loki:3100', ca_file: ca.crt}").is_err());
+        assert!(settings("{target: b.out, ca_file: ca.crt}").is_err());
+    }
+
+    #[test]
+    fn test_when_elasticsearch_output_not_jsonl_then_error() {
+        let settings = |output: &str| {
+            settings_from_yaml(&format!(
+                r"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output: {output}
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {{}}
+"
+            ))
+        };
+
+        assert!(settings("elasticsearch:http://localhost:9200/watchdog").is_ok());
+        assert!(matches!(
+            settings("{target: 'elasticsearch:http://localhost:9200/watchdog', format: csv}"),
+            Err(SettingsError::InvalidValueType { key }) if key == "output.format"
+        ));
+    }
+
+    #[test]
+    fn test_when_output_columns_then_csv_outputs_use_them() {
+        let settings = settings_from_yaml(
+            r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output:
+      - target: /var/log/pgbouncer.csv
+        format: csv
+      - stdout
+    output_columns: [timestamp, watchdog, level, 1]
+    debounce: 0
+    oneshot: false
+    regex: (?<level>\w+) (\d+)
+    commands: {}
+"#,
+        )
+        .unwrap();
+
+        let outputs = &settings.watchdogs()[0].outputs;
+        assert_eq!(
+            outputs[0].format,
+            OutputFormat::Csv(vec![
+                Column::Field(Field::Timestamp),
+                Column::Field(Field::Watchdog),
+                Column::Capture("level".into()),
+                Column::Capture("1".into()),
+            ])
+        );
+        assert_eq!(outputs[1].format, OutputFormat::default());
+    }
+
+    #[test]
+    fn test_when_validated_then_every_watchdog_reported() {
+        let dir = tempdir::TempDir::new("settings_").unwrap();
+        let path = dir.path().join("settings.yml");
+        std::fs::write(
+            &path,
+            r#"
+logging:
+  rotation: sometimes
+watchdogs:
+  valid:
+    log_file: a.log
+    output_file: a.out
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+  bad_regex:
+    log_file: b.log
+    output_file: b.out
+    debounce: 0
+    oneshot: false
+    regex: (unclosed
+    commands: {}
+  missing_commands:
+    log_file: c.log
+    output_file: c.out
+    debounce: 0
+    oneshot: false
+    regex: .*
+"#,
+        )
+        .unwrap();
+
+        let report = Settings::validate(&[path], None);
+
+        assert!(!report.is_valid());
+        assert_eq!(report.errors.len(), 1);
+        let names: Vec<_> = report
+            .watchdogs
+            .iter()
+            .map(|(name, error)| (name.as_str(), error.is_some()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("bad_regex", true),
+                ("missing_commands", true),
+                ("valid", false)
+            ]
+        );
+        assert!(matches!(
+            report.watchdogs[0].1,
+            Some(SettingsError::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn test_when_watchdogs_share_output_file_differently_then_error() {
+        let yaml = |fsync: bool| {
+            format!(
+                r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output_file: shared.out
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {{}}
+  nginx:
+    log_file: b.log
+    output_file: ./shared.out
+    fsync: {fsync}
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {{}}
+"#
+            )
+        };
+
+        assert!(settings_from_yaml(&yaml(false)).is_ok());
+        assert!(matches!(
+            settings_from_yaml(&yaml(true)),
+            Err(SettingsError::SharedOutputFile { path, .. }) if path.ends_with("shared.out")
+        ));
+    }
+
+    #[test]
+    fn test_when_validated_file_missing_then_error() {
+        let report = Settings::validate(&["does/not/exist.yml".into()], None);
+
+        assert!(!report.is_valid());
+        assert!(matches!(report.errors[..], [SettingsError::Io(_)]));
+    }
+
+    #[test]
+    fn test_when_watchdogs_retained_then_others_removed() {
+        let yaml = r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output_file: a.out
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+  nginx:
+    log_file: b.log
+    output_file: b.out
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+"#;
+        let mut settings = settings_from_yaml(yaml).unwrap();
+
+        settings.retain_watchdogs(&["nginx".into()]).unwrap();
+        assert_eq!(settings.watchdogs().len(), 1);
+        assert_eq!(settings.watchdogs()[0].name, "nginx");
+
+        let mut settings = settings_from_yaml(yaml).unwrap();
+        assert!(matches!(
+            settings.retain_watchdogs(&["nginx".into(), "postgres".into()]),
+            Err(SettingsError::UnknownWatchdog(name)) if name == "postgres"
+        ));
+        assert_eq!(settings.watchdogs().len(), 2);
+    }
+
+    #[test]
+    fn test_when_multiple_files_then_later_ones_merged_over_earlier() {
+        let dir = tempdir::TempDir::new("settings_").unwrap();
+        let base = dir.path().join("base.yml");
+        let host = dir.path().join("host.yml");
+        std::fs::write(
+            &base,
+            r#"
+logging:
+  retention: 3
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output_file: a.out
+    debounce: 0
+    oneshot: false
+    regex: FATAL
+    commands:
+      ls:
+        args: [-a]
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &host,
+            r#"
+watchdogs:
+  pgbouncer:
+    regex: ERROR
+    commands:
+      ls:
+        args: [-l]
+      df:
+        args: [-h]
+  nginx:
+    log_file: b.log
+    output_file: b.out
+    debounce: 0
+    oneshot: false
+    regex: .*
+    commands: {}
+"#,
+        )
+        .unwrap();
+
+        let settings = Settings::try_from(&[base, host][..]).unwrap();
+
+        assert_eq!(settings.logging.retention, Some(3));
+        let mut watchdogs = settings.into_watchdogs();
+        watchdogs.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(watchdogs[0].name, "nginx");
+        let pgbouncer = &watchdogs[1];
+        assert_eq!(pgbouncer.log_file, PathBuf::from("a.log"));
+        assert_eq!(pgbouncer.regex.as_str(), "ERROR");
+        let mut commands = pgbouncer.commands.clone();
+        commands.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            commands,
+            vec![
+                Command {
+                    name: "df".into(),
+                    args: vec!["-h".into()],
+                    sandbox: None,
+                    container: None,
+                },
+                Command {
+                    name: "ls".into(),
+                    args: vec!["-l".into()],
+                    sandbox: None,
+                    container: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_watchdog_names_then_only_regular_files_read() {
+        let dir = tempdir::TempDir::new("test_watchdog_names").unwrap();
+        let base = dir.path().join("base.yml");
+        let host = dir.path().join("host.yml");
+        std::fs::write(&base, "watchdogs:\n  pgbouncer:\n    regex: '('\n").unwrap();
+        std::fs::write(&host, "watchdogs:\n  nginx: {}\n").unwrap();
+
+        let names = Settings::watchdog_names(&[
+            host,
+            "-".into(),
+            dir.path().to_path_buf(),
+            base,
+            dir.path().join("missing.yml"),
+        ])
+        .unwrap();
+
+        assert_eq!(names, vec!["nginx", "pgbouncer"]);
+    }
+
+    #[test]
+    fn test_when_searched_then_xdg_config_home_or_home_used() {
+        let paths = |xdg: Option<&str>, home: Option<&str>| {
+            search_paths(xdg.map(OsString::from), home.map(OsString::from))
+        };
+
+        assert_eq!(
+            paths(Some("/home/pg/.xdg"), Some("/home/pg")),
+            vec![
+                PathBuf::from("./log-watchdog.yml"),
+                PathBuf::from("/home/pg/.xdg/log-watchdog/config.yml"),
+                PathBuf::from("/etc/log-watchdog/config.yml"),
+            ]
+        );
+        assert_eq!(
+            paths(Some("relative"), Some("/home/pg"))[1],
+            PathBuf::from("/home/pg/.config/log-watchdog/config.yml"),
+            "a relative XDG_CONFIG_HOME is ignored"
+        );
+        assert_eq!(
+            paths(None, None),
+            vec![
+                PathBuf::from("./log-watchdog.yml"),
+                PathBuf::from("/etc/log-watchdog/config.yml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_new_then_default_logging() {
+        let watchdog = Watchdog::builder()
+            .name("pgbouncer")
+            .log_file("a.log")
+            .regex(".*")
+            .build()
+            .unwrap();
+
+        let settings = Settings::new(vec![watchdog]);
+
+        assert_eq!(settings.logging, LoggingSettings::default());
+        assert_eq!(settings.watchdogs()[0].name, "pgbouncer");
+    }
+
+    #[test]
+    fn test_when_serialized_then_parsed_back_to_same_settings() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/valid_settings.yml");
+        let settings = Settings::try_from(path.as_path()).unwrap();
+
+        let yaml = serde_yaml::to_string(&settings).unwrap();
+        let parsed = settings_from_yaml(&yaml).unwrap();
+
+        assert_eq!(parsed.logging, settings.logging);
+        let (watchdog, parsed) = (&settings.watchdogs[0], &parsed.watchdogs[0]);
+        assert_eq!(parsed.name, watchdog.name);
+        assert_eq!(parsed.log_file, watchdog.log_file);
+        assert_eq!(parsed.outputs, watchdog.outputs);
+        assert_eq!(parsed.file_options, watchdog.file_options);
+        assert_eq!(parsed.regex.as_str(), watchdog.regex.as_str());
+        assert_eq!(parsed.commands, watchdog.commands);
+        assert_eq!(parsed.dry_run, watchdog.dry_run);
+        assert_eq!(parsed.log_level, watchdog.log_level);
+        assert_eq!(parsed.log_sample_rate, watchdog.log_sample_rate);
+        assert_eq!(
+            serde_yaml::to_string(&parsed).unwrap(),
+            serde_yaml::to_string(watchdog).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_when_command_has_runtime_then_run_in_container() {
+        let settings = settings_from_yaml(
+            r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output_file: b.out
+    debounce: 0
+    oneshot: false
+    regex: ^aaa
+    commands:
+      pg_repair:
+        args: [--fix]
+        runtime: podman
+        image: registry.example.com/pg-tools:1.2
+        mounts:
+          - /var/lib/pgbouncer:/data:rw
+"#,
+        )
+        .unwrap();
+
+        let command = &settings.watchdogs[0].commands[0];
+        assert_eq!(command.program(), "podman");
+        assert_eq!(
+            command.container,
+            Some(Container {
+                runtime: ContainerRuntime::Podman,
+                image: "registry.example.com/pg-tools:1.2".into(),
+                mounts: vec![Mount {
+                    source: "/var/lib/pgbouncer".into(),
+                    target: "/data".into(),
+                    writable: true,
+                }],
+            })
+        );
+        let yaml = serde_yaml::to_string(&settings).unwrap();
+        assert_eq!(
+            settings_from_yaml(&yaml).unwrap().watchdogs[0].commands,
+            settings.watchdogs[0].commands
+        );
+
+        let sandboxed = r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    debounce: 0
+    oneshot: false
+    regex: ^aaa
+    commands:
+      pg_repair:
+        args: []
+        runtime: docker
+        image: pg-tools
+        sandbox: {}
+"#;
+        assert!(settings_from_yaml(sandboxed).is_err());
+    }
+
+    #[test]
+    fn test_when_alertmanager_then_parsed_back_from_yaml() {
+        let settings = settings_from_yaml(
+            r"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output_file: b.out
+    debounce: 0
+    oneshot: false
+    regex: ^aaa
+    commands: {}
+    alertmanager:
+      url: https://alertmanager:9093
+      labels: {severity: page}
+      resolve_after: 60000
+      ca_file: /etc/ssl/internal-ca.crt
+      client_cert: /etc/log-watchdog/client.pem
+      min_tls_version: 1.3
+",
+        )
+        .unwrap();
+
+        let alertmanager = settings.watchdogs[0].alertmanager.as_ref().unwrap();
+        assert_eq!(alertmanager.labels["severity"], "page");
+        assert_eq!(alertmanager.resolve_after, 60000);
+        assert_eq!(alertmanager.tls.min_version, Some(TlsVersion::V1_3));
+        let yaml = serde_yaml::to_string(&settings).unwrap();
+        assert_eq!(
+            settings_from_yaml(&yaml).unwrap().watchdogs[0].alertmanager,
+            settings.watchdogs[0].alertmanager
+        );
+    }
+
+    #[test]
+    fn test_when_passive_check_then_parsed_back_from_yaml() {
+        let settings = settings_from_yaml(
+            r"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output_file: b.out
+    debounce: 0
+    oneshot: false
+    regex: ^aaa
+    commands: {}
+    passive_check:
+      host: db1
+      service: pgbouncer-log
+      status: warning
+      output: '{line}'
+      icinga:
+        url: https://icinga:5665
+        username: log-watchdog
+        password: s3cr3t
+",
+        )
+        .unwrap();
+
+        let passive_check = settings.watchdogs[0].passive_check.as_ref().unwrap();
+        assert_eq!(passive_check.status, CheckStatus::Warning);
+        assert_eq!(passive_check.recover_after, DEFAULT_RECOVER_AFTER);
+        let yaml = serde_yaml::to_string(&settings).unwrap();
+        assert_eq!(
+            settings_from_yaml(&yaml).unwrap().watchdogs[0].passive_check,
+            settings.watchdogs[0].passive_check
+        );
+    }
+
+    #[test]
+    fn test_when_zabbix_then_parsed_back_from_yaml() {
+        let settings = settings_from_yaml(
+            r"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output_file: b.out
+    debounce: 0
+    oneshot: false
+    regex: ^aaa
+    commands: {}
+    zabbix:
+      server: zabbix-proxy
+      host: db1
+      key: pgbouncer.log.fatal
+      value: '{watchdog}: {line}'
+",
+        )
+        .unwrap();
+
+        let zabbix = settings.watchdogs[0].zabbix.as_ref().unwrap();
+        assert_eq!(zabbix.server, "zabbix-proxy:10051");
+        let yaml = serde_yaml::to_string(&settings).unwrap();
+        assert_eq!(
+            settings_from_yaml(&yaml).unwrap().watchdogs[0].zabbix,
+            settings.watchdogs[0].zabbix
+        );
+    }
+
+    #[test]
+    fn test_when_outputs_serialized_then_formats_kept() {
+        let settings = settings_from_yaml(
+            r#"
+watchdogs:
+  pgbouncer:
+    log_file: a.log
+    output:
+      - target: /var/log/pgbouncer.csv
+        format: csv
+      - unix:/run/collector.sock
+      - target: stdout
+        format: "{{ {line} }}"
+    output_columns: [timestamp, level]
+    debounce: 0
+    oneshot: false
+    regex: (?<level>\w+)
+    commands: {}
+"#,
+        )
+        .unwrap();
+
+        let yaml = serde_yaml::to_string(&settings).unwrap();
+
+        assert!(!yaml.contains("logging"));
+        assert_eq!(
+            settings_from_yaml(&yaml).unwrap().watchdogs[0].outputs,
+            settings.watchdogs[0].outputs
+        );
+    }
 }
@@ -0,0 +1,968 @@
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use logging::{Facility, Rotation};
+
+use crate::{SettingsError, Tls};
+
+/// Where a watchdog's match records are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// Appended to a file
+    File(PathBuf),
+    /// `-` or `stdout`
+    Stdout,
+    /// `stderr`
+    Stderr,
+    /// `syslog`, or `syslog:<facility>`: sent to the local syslog daemon, with
+    /// the `daemon` facility by default
+    Syslog(Facility),
+    /// `journald`: sent to journald, with the watchdog, matched line and
+    /// command as fields
+    Journald,
+    /// `unix:<path>`: written to a unix stream socket
+    Unix(PathBuf),
+    /// `tcp:<host>:<port>`: written to a TCP socket
+    Tcp(String),
+    /// `loki:<url>`: pushed to Grafana Loki's push API, at e.g.
+    /// `loki:http://localhost:3100` or an `https://` URL, with the watchdog
+    /// and host as labels
+    Loki(String),
+    /// `elasticsearch:<url>/<index>`: bulk-indexed into Elasticsearch or
+    /// OpenSearch, at e.g. `elasticsearch:http://localhost:9200/watchdog-{date}`,
+    /// where the index can have `{watchdog}` and `{date}` (UTC, `%Y.%m.%d`)
+    /// in it
+    Elasticsearch { url: String, index: String },
+}
+
+impl OutputTarget {
+    /// If true, records are sent to a socket or Loki, as JSON Lines unless
+    /// the output has a format of its own.
+    pub const fn is_socket(&self) -> bool {
+        matches!(
+            self,
+            Self::Unix(_) | Self::Tcp(_) | Self::Loki(_) | Self::Elasticsearch { .. }
+        )
+    }
+}
+
+impl FromStr for OutputTarget {
+    type Err = SettingsError;
+
+    /// Parses `-`, `stdout`, `stderr`, `syslog[:<facility>]`, `journald`,
+    /// `unix:<path>`, `tcp:<host>:<port>`, `loki:<url>` and
+    /// `elasticsearch:<url>/<index>`, anything else is a path; a file
+    /// named e.g. `stdout` can still be written to as `./stdout`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "-" | "stdout" => Self::Stdout,
+            "stderr" => Self::Stderr,
+            "syslog" => Self::Syslog(Facility::default()),
+            "journald" => Self::Journald,
+            s => {
+                if let Some(facility) = s.strip_prefix("syslog:") {
+                    Self::Syslog(facility.parse()?)
+                } else if let Some(path) = s.strip_prefix("unix:") {
+                    Self::Unix(PathBuf::from(path))
+                } else if let Some(address) = s.strip_prefix("tcp:") {
+                    if address
+                        .rsplit_once(':')
+                        .is_none_or(|(host, port)| host.is_empty() || port.parse::<u16>().is_err())
+                    {
+                        return Err(SettingsError::InvalidValueType {
+                            key: "output".into(),
+                        });
+                    }
+                    Self::Tcp(address.to_string())
+                } else if let Some(url) = s.strip_prefix("loki:") {
+                    if !url.starts_with("http://") && !url.starts_with("https://") {
+                        return Err(SettingsError::InvalidValueType {
+                            key: "output".into(),
+                        });
+                    }
+                    Self::Loki(url.to_string())
+                } else if let Some(url) = s.strip_prefix("elasticsearch:") {
+                    let invalid = || SettingsError::InvalidValueType {
+                        key: "output".into(),
+                    };
+                    let (scheme, authority) = url.split_once("://").ok_or_else(invalid)?;
+                    if scheme != "http" && scheme != "https" {
+                        return Err(invalid());
+                    }
+                    let (authority, index) = authority.split_once('/').ok_or_else(invalid)?;
+                    let placeholders = index.replace("{watchdog}", "").replace("{date}", "");
+                    if authority.is_empty() || index.is_empty() || placeholders.contains(['{', '}'])
+                    {
+                        return Err(invalid());
+                    }
+                    Self::Elasticsearch {
+                        url: format!("{scheme}://{authority}"),
+                        index: index.to_string(),
+                    }
+                } else {
+                    Self::File(PathBuf::from(s))
+                }
+            }
+        })
+    }
+}
+
+/// Formats the target like it's parsed; a file that would parse as another
+/// target, like `stdout`, is prefixed with `./`.
+impl fmt::Display for OutputTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => {
+                let path = path.to_string_lossy();
+                if matches!(path.parse(), Ok(Self::File(_))) {
+                    f.write_str(&path)
+                } else {
+                    write!(f, "./{path}")
+                }
+            }
+            Self::Stdout => f.write_str("stdout"),
+            Self::Stderr => f.write_str("stderr"),
+            Self::Syslog(facility) => write!(f, "syslog:{facility}"),
+            Self::Journald => f.write_str("journald"),
+            Self::Unix(path) => write!(f, "unix:{}", path.to_string_lossy()),
+            Self::Tcp(address) => write!(f, "tcp:{address}"),
+            Self::Loki(url) => write!(f, "loki:{url}"),
+            Self::Elasticsearch { url, index } => write!(f, "elasticsearch:{url}/{index}"),
+        }
+    }
+}
+
+/// One of the places a watchdog's match records are written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSettings {
+    pub target: OutputTarget,
+    pub format: OutputFormat,
+    /// How a Loki or Elasticsearch target's `https://` URL is verified and
+    /// authenticated with
+    pub tls: Tls,
+}
+
+impl OutputSettings {
+    /// Checks that the format can be written to the target: Elasticsearch
+    /// only indexes JSON documents. Only Loki and Elasticsearch targets,
+    /// requested over HTTPS, can have TLS options.
+    pub(crate) fn check(&self) -> Result<(), SettingsError> {
+        if matches!(self.target, OutputTarget::Elasticsearch { .. })
+            && self.format != OutputFormat::Jsonl
+        {
+            return Err(SettingsError::InvalidValueType {
+                key: "output.format".into(),
+            });
+        }
+        if !matches!(
+            self.target,
+            OutputTarget::Loki(_) | OutputTarget::Elasticsearch { .. }
+        ) && !self.tls.is_default()
+        {
+            return Err(SettingsError::InvalidValueType {
+                key: "output.target".into(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// How a watchdog's outputs, and output files in particular, are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOptions {
+    /// If false, output files are truncated when the watchdog starts
+    pub append: bool,
+    /// When buffered records are written to the file
+    pub flush: FlushPolicy,
+    /// If true, sync the file to disk on every flush
+    pub fsync: bool,
+    /// Permissions of files created by the watchdog, e.g. `0640`
+    pub mode: Option<u32>,
+    /// Owner of files created by the watchdog
+    pub owner: Option<Owner>,
+    /// When to roll output files over, if ever
+    pub rotation: Option<Rotation>,
+    /// Number of rolled output files to keep
+    pub retention: u32,
+    /// How rolled output files are compressed, if at all
+    pub compression: Option<Compression>,
+    /// If true, runs of records with the same command output are written
+    /// once, followed by a repeat count
+    pub dedup: bool,
+    /// What's written for commands without any stdout
+    pub empty: EmptyOutput,
+    /// If true, the matches a debounce suppressed are counted in a record
+    /// when it expires
+    pub suppressed: bool,
+}
+
+/// What's written for a command that printed nothing, or only whitespace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyOutput {
+    /// `write`: the empty stdout, e.g. a blank line with the default format
+    #[default]
+    Write,
+    /// `skip`: nothing, and no record at all if no command printed anything
+    Skip,
+    /// `mark`: `(no output)` as the stdout, or `null` in JSON Lines
+    Mark,
+}
+
+impl FromStr for EmptyOutput {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "write" => Ok(Self::Write),
+            "skip" => Ok(Self::Skip),
+            "mark" => Ok(Self::Mark),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "output_empty".into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for EmptyOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Write => "write",
+            Self::Skip => "skip",
+            Self::Mark => "mark",
+        })
+    }
+}
+
+/// `gzip` or `zstd`, optionally with a level, e.g. `zstd:19`; rolled files are
+/// compressed with the `gzip` or `zstd` binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub algorithm: CompressionAlgorithm,
+    pub level: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    Gzip,
+    Zstd,
+}
+
+impl CompressionAlgorithm {
+    /// The binary that compresses, and the extension of compressed files.
+    pub const fn program(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Gzip => ("gzip", ".gz"),
+            Self::Zstd => ("zstd", ".zst"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SettingsError::InvalidValueType {
+            key: "output_compression".into(),
+        };
+        let (algorithm, level) = match s.split_once(':') {
+            Some((algorithm, level)) => (algorithm, Some(level.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let algorithm = match algorithm {
+            "gzip" => CompressionAlgorithm::Gzip,
+            "zstd" => CompressionAlgorithm::Zstd,
+            _ => return Err(invalid()),
+        };
+        let max_level = match algorithm {
+            CompressionAlgorithm::Gzip => 9,
+            CompressionAlgorithm::Zstd => 19,
+        };
+        if level.is_some_and(|level| level == 0 || level > max_level) {
+            return Err(invalid());
+        }
+
+        Ok(Self { algorithm, level })
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.algorithm {
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Zstd => "zstd",
+        })?;
+        if let Some(level) = self.level {
+            write!(f, ":{level}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            append: true,
+            flush: FlushPolicy::default(),
+            fsync: false,
+            mode: None,
+            owner: None,
+            rotation: None,
+            retention: 7,
+            compression: None,
+            dedup: false,
+            empty: EmptyOutput::default(),
+            suppressed: false,
+        }
+    }
+}
+
+impl FileOptions {
+    /// If true, an output file is written the same with these options as
+    /// with `other`: the options of the file itself, rather than of the
+    /// watchdog's records, are equal.
+    pub fn same_file(&self, other: &Self) -> bool {
+        (
+            self.append,
+            self.flush,
+            self.fsync,
+            self.mode,
+            &self.owner,
+            self.rotation,
+            self.retention,
+            self.compression,
+        ) == (
+            other.append,
+            other.flush,
+            other.fsync,
+            other.mode,
+            &other.owner,
+            other.rotation,
+            other.retention,
+            other.compression,
+        )
+    }
+}
+
+/// `user`, `user:group` or `:group`, by name or id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Owner {
+    pub user: Option<String>,
+    pub group: Option<String>,
+}
+
+impl FromStr for Owner {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, group) = s.split_once(':').unwrap_or((s, ""));
+        let owner = Self {
+            user: Some(user.to_string()).filter(|user| !user.is_empty()),
+            group: Some(group.to_string()).filter(|group| !group.is_empty()),
+        };
+        if owner.user.is_none() && owner.group.is_none() {
+            return Err(SettingsError::InvalidValueType {
+                key: "output_owner".into(),
+            });
+        }
+        Ok(owner)
+    }
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.user.as_deref().unwrap_or_default())?;
+        if let Some(group) = &self.group {
+            write!(f, ":{group}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an octal file mode like `0640`, `640` or `0o640`.
+///
+/// # Errors
+///
+/// Will return an error if `s` isn't an octal number up to `7777`.
+pub fn parse_mode(s: &str) -> Result<u32, SettingsError> {
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or(SettingsError::InvalidValueType {
+            key: "output_mode".into(),
+        })
+}
+
+/// When records written to an output file are flushed from the buffer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// `every_write`: after every record
+    #[default]
+    EveryWrite,
+    /// A number of milliseconds: at most this long after a record is written
+    Interval(Duration),
+    /// `on_exit`: when the buffer is full, and when the watchdog stops
+    OnExit,
+}
+
+impl FromStr for FlushPolicy {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "every_write" => Ok(Self::EveryWrite),
+            "on_exit" => Ok(Self::OnExit),
+            interval => Ok(Self::Interval(Duration::from_millis(interval.parse()?))),
+        }
+    }
+}
+
+impl fmt::Display for FlushPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EveryWrite => f.write_str("every_write"),
+            Self::Interval(interval) => write!(f, "{}", interval.as_millis()),
+            Self::OnExit => f.write_str("on_exit"),
+        }
+    }
+}
+
+/// How a watchdog's match records are written to its output file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A line per command run, rendered from a template
+    Template(Template),
+    /// `jsonl`: a JSON object per match, with the regex's captures and the
+    /// output of every command run
+    Jsonl,
+    /// `csv`: a row per command run, with the given columns
+    Csv(Vec<Column>),
+}
+
+impl OutputFormat {
+    /// If true, records need the regex's captures.
+    pub fn needs_captures(&self) -> bool {
+        match self {
+            Self::Template(_) => false,
+            Self::Jsonl => true,
+            Self::Csv(columns) => columns
+                .iter()
+                .any(|column| matches!(column, Column::Capture(_))),
+        }
+    }
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self::Template(Template::default())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv(Column::defaults())),
+            template => Ok(Self::Template(template.parse()?)),
+        }
+    }
+}
+
+/// `jsonl`, `csv` without its columns, or the template.
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Template(template) => template.fmt(f),
+            Self::Jsonl => f.write_str("jsonl"),
+            Self::Csv(_) => f.write_str("csv"),
+        }
+    }
+}
+
+/// A value of a match record that can be used in an output template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// `{timestamp}`: when the match happened, in RFC 3339
+    Timestamp,
+    /// `{watchdog}`: the watchdog's name
+    Watchdog,
+    /// `{line}`: the matched log line
+    Line,
+    /// `{command}`: the name of the command that was run
+    Command,
+    /// `{stdout}`: the command's stdout, without its trailing newline
+    Stdout,
+    /// `{stderr}`: the command's stderr, truncated and without its trailing
+    /// newline
+    Stderr,
+    /// `{exit_code}`: the command's exit code, empty if it was killed by a
+    /// signal
+    ExitCode,
+    /// `{duration_ms}`: how long the command ran, in milliseconds
+    DurationMs,
+}
+
+impl Field {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Timestamp => "timestamp",
+            Self::Watchdog => "watchdog",
+            Self::Line => "line",
+            Self::Command => "command",
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+            Self::ExitCode => "exit_code",
+            Self::DurationMs => "duration_ms",
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timestamp" => Ok(Self::Timestamp),
+            "watchdog" => Ok(Self::Watchdog),
+            "line" => Ok(Self::Line),
+            "command" => Ok(Self::Command),
+            "stdout" => Ok(Self::Stdout),
+            "stderr" => Ok(Self::Stderr),
+            "exit_code" => Ok(Self::ExitCode),
+            "duration_ms" => Ok(Self::DurationMs),
+            _ => Err(SettingsError::InvalidOutputFormat(format!(
+                "unknown field {{{s}}}"
+            ))),
+        }
+    }
+}
+
+/// A column of the `csv` output format: a field, or else a capture group of
+/// the watchdog's regex by name or index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Field(Field),
+    Capture(String),
+}
+
+impl Column {
+    /// `timestamp,watchdog,line,command,exit_code,stdout`
+    pub fn defaults() -> Vec<Self> {
+        [
+            Field::Timestamp,
+            Field::Watchdog,
+            Field::Line,
+            Field::Command,
+            Field::ExitCode,
+            Field::Stdout,
+        ]
+        .into_iter()
+        .map(Self::Field)
+        .collect()
+    }
+
+    /// The column's name in the header row.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Field(field) => field.name(),
+            Self::Capture(name) => name,
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(SettingsError::InvalidOutputFormat(
+                "empty csv column".into(),
+            ));
+        }
+        Ok(s.parse()
+            .map_or_else(|_| Self::Capture(s.to_string()), Self::Field))
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// An output template like `{timestamp} {watchdog}: {stdout}`; `{{` and `}}`
+/// are literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Default for Template {
+    /// Just the command's stdout.
+    fn default() -> Self {
+        Self {
+            segments: vec![Segment::Field(Field::Stdout)],
+        }
+    }
+}
+
+impl Template {
+    /// Renders the template, with `value` supplying the value of each field.
+    pub fn render<'a>(&self, value: impl Fn(Field) -> &'a str) -> String {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Field(field) => rendered.push_str(value(*field)),
+            }
+        }
+        rendered
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// If true, the template only has the fields of the match itself, and
+    /// none of a command's, for what's rendered before any command runs.
+    pub(crate) fn has_match_fields_only(&self) -> bool {
+        self.segments.iter().all(|segment| match segment {
+            Segment::Field(field) => {
+                matches!(field, Field::Timestamp | Field::Watchdog | Field::Line)
+            }
+            Segment::Literal(_) => true,
+        })
+    }
+}
+
+impl FromStr for Template {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| {
+                        SettingsError::InvalidOutputFormat(format!("unclosed {{ in {s:?}"))
+                    })?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(rest[..end].trim().parse()?));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => {
+                    return Err(SettingsError::InvalidOutputFormat(format!(
+                        "unmatched }} in {s:?}"
+                    )))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => {
+                    f.write_str(&literal.replace('{', "{{").replace('}', "}}"))?;
+                }
+                Segment::Field(field) => write!(f, "{{{}}}", field.name())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_output_target_then_streams_or_path() {
+        assert_eq!(OutputTarget::from_str("-").unwrap(), OutputTarget::Stdout);
+        assert_eq!(
+            OutputTarget::from_str("stdout").unwrap(),
+            OutputTarget::Stdout
+        );
+        assert_eq!(
+            OutputTarget::from_str("stderr").unwrap(),
+            OutputTarget::Stderr
+        );
+        assert_eq!(
+            OutputTarget::from_str("./stdout").unwrap(),
+            OutputTarget::File(PathBuf::from("./stdout"))
+        );
+    }
+
+    #[test]
+    fn test_when_syslog_or_journald_then_parsed() {
+        assert_eq!(
+            OutputTarget::from_str("syslog").unwrap(),
+            OutputTarget::Syslog(Facility::Daemon)
+        );
+        assert_eq!(
+            OutputTarget::from_str("syslog:local3").unwrap(),
+            OutputTarget::Syslog(Facility::Local3)
+        );
+        assert_eq!(
+            OutputTarget::from_str("journald").unwrap(),
+            OutputTarget::Journald
+        );
+        assert!(OutputTarget::from_str("syslog:nope").is_err());
+    }
+
+    #[test]
+    fn test_when_socket_then_parsed() {
+        assert_eq!(
+            OutputTarget::from_str("unix:/run/collector.sock").unwrap(),
+            OutputTarget::Unix(PathBuf::from("/run/collector.sock"))
+        );
+        assert_eq!(
+            OutputTarget::from_str("tcp:localhost:5170").unwrap(),
+            OutputTarget::Tcp("localhost:5170".into())
+        );
+        assert!(OutputTarget::from_str("tcp:localhost").is_err());
+        assert!(OutputTarget::from_str("tcp::5170").is_err());
+        assert_eq!(
+            OutputTarget::from_str("loki:http://localhost:3100").unwrap(),
+            OutputTarget::Loki("http://localhost:3100".into())
+        );
+        assert!(OutputTarget::from_str("loki:localhost:3100").is_err());
+        assert_eq!(
+            OutputTarget::from_str("elasticsearch:http://localhost:9200/watchdog-{date}").unwrap(),
+            OutputTarget::Elasticsearch {
+                url: "http://localhost:9200".into(),
+                index: "watchdog-{date}".into()
+            }
+        );
+        assert_eq!(
+            OutputTarget::from_str("elasticsearch:https://localhost:9200/watchdog").unwrap(),
+            OutputTarget::Elasticsearch {
+                url: "https://localhost:9200".into(),
+                index: "watchdog".into()
+            }
+        );
+        assert!(OutputTarget::from_str("elasticsearch:ftp://localhost:9200/watchdog").is_err());
+        assert!(OutputTarget::from_str("elasticsearch:http://localhost:9200").is_err());
+        assert!(OutputTarget::from_str("elasticsearch:http://localhost:9200/{host}").is_err());
+    }
+
+    #[test]
+    fn test_when_flush_policy_then_parsed() {
+        assert_eq!(
+            FlushPolicy::from_str("every_write").unwrap(),
+            FlushPolicy::EveryWrite
+        );
+        assert_eq!(
+            FlushPolicy::from_str("on_exit").unwrap(),
+            FlushPolicy::OnExit
+        );
+        assert_eq!(
+            FlushPolicy::from_str("500").unwrap(),
+            FlushPolicy::Interval(Duration::from_millis(500))
+        );
+        assert!(FlushPolicy::from_str("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_when_owner_then_user_and_group_parsed() {
+        let owner = |user: Option<&str>, group: Option<&str>| Owner {
+            user: user.map(Into::into),
+            group: group.map(Into::into),
+        };
+
+        assert_eq!(
+            Owner::from_str("postgres").unwrap(),
+            owner(Some("postgres"), None)
+        );
+        assert_eq!(
+            Owner::from_str("postgres:adm").unwrap(),
+            owner(Some("postgres"), Some("adm"))
+        );
+        assert_eq!(Owner::from_str(":4").unwrap(), owner(None, Some("4")));
+        assert!(Owner::from_str(":").is_err());
+    }
+
+    #[test]
+    fn test_when_compression_then_algorithm_and_level_parsed() {
+        assert_eq!(
+            Compression::from_str("gzip").unwrap(),
+            Compression {
+                algorithm: CompressionAlgorithm::Gzip,
+                level: None
+            }
+        );
+        assert_eq!(
+            Compression::from_str("zstd:19").unwrap(),
+            Compression {
+                algorithm: CompressionAlgorithm::Zstd,
+                level: Some(19)
+            }
+        );
+        assert!(Compression::from_str("gzip:10").is_err());
+        assert!(Compression::from_str("zstd:0").is_err());
+        assert!(Compression::from_str("bzip2").is_err());
+    }
+
+    #[test]
+    fn test_when_mode_then_parsed_as_octal() {
+        assert_eq!(parse_mode("0640").unwrap(), 0o640);
+        assert_eq!(parse_mode("600").unwrap(), 0o600);
+        assert_eq!(parse_mode("0o2750").unwrap(), 0o2750);
+        assert!(parse_mode("0980").is_err());
+        assert!(parse_mode("17777").is_err());
+    }
+
+    #[test]
+    fn test_when_template_then_fields_and_literals_parsed() {
+        let template = Template::from_str("{timestamp} {{{watchdog}}}: {stdout}").unwrap();
+
+        assert_eq!(
+            template.segments(),
+            &[
+                Segment::Field(Field::Timestamp),
+                Segment::Literal(" {".into()),
+                Segment::Field(Field::Watchdog),
+                Segment::Literal("}: ".into()),
+                Segment::Field(Field::Stdout),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_template_rendered_then_fields_replaced() {
+        let template = Template::from_str(
+            "[{watchdog}] {command}: {stdout} ({line}) exited {exit_code} after {duration_ms}ms",
+        )
+        .unwrap();
+
+        let rendered = template.render(|field| match field {
+            Field::Timestamp => "2025-01-01T00:00:00.000Z",
+            Field::Watchdog => "pgbouncer",
+            Field::Line => "FATAL: too many connections",
+            Field::Command => "echo",
+            Field::Stdout => "restarted",
+            Field::Stderr => "",
+            Field::ExitCode => "0",
+            Field::DurationMs => "12",
+        });
+
+        assert_eq!(
+            rendered,
+            "[pgbouncer] echo: restarted (FATAL: too many connections) exited 0 after 12ms"
+        );
+    }
+
+    #[test]
+    fn test_when_jsonl_then_jsonl_format() {
+        assert_eq!(
+            OutputFormat::from_str("jsonl").unwrap(),
+            OutputFormat::Jsonl
+        );
+        assert!(matches!(
+            OutputFormat::from_str("{line}").unwrap(),
+            OutputFormat::Template(_)
+        ));
+    }
+
+    #[test]
+    fn test_when_csv_then_default_columns() {
+        assert_eq!(
+            OutputFormat::from_str("csv").unwrap(),
+            OutputFormat::Csv(Column::defaults())
+        );
+        assert!(!OutputFormat::from_str("csv").unwrap().needs_captures());
+    }
+
+    #[test]
+    fn test_when_column_then_field_or_capture() {
+        assert_eq!(
+            Column::from_str("exit_code").unwrap(),
+            Column::Field(Field::ExitCode)
+        );
+        assert_eq!(
+            Column::from_str("level").unwrap(),
+            Column::Capture("level".into())
+        );
+        assert_eq!(Column::from_str("1").unwrap().name(), "1");
+        assert!(Column::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_when_empty_output_then_parsed() {
+        assert_eq!(EmptyOutput::from_str("skip").unwrap(), EmptyOutput::Skip);
+        assert_eq!(EmptyOutput::from_str("mark").unwrap(), EmptyOutput::Mark);
+        assert!(EmptyOutput::from_str("drop").is_err());
+    }
+
+    #[test]
+    fn test_when_invalid_template_then_error() {
+        assert!(Template::from_str("{status}").is_err());
+        assert!(Template::from_str("{stdout").is_err());
+        assert!(Template::from_str("stdout}").is_err());
+    }
+
+    #[test]
+    fn test_when_displayed_then_parsed_back() {
+        for target in [
+            "/var/log/out.txt",
+            "./stdout",
+            "stderr",
+            "syslog:local3",
+            "journald",
+            "unix:/run/collector.sock",
+            "tcp:localhost:5170",
+            "loki:http://localhost:3100",
+            "elasticsearch:http://localhost:9200/{watchdog}-{date}",
+        ] {
+            assert_eq!(OutputTarget::from_str(target).unwrap().to_string(), target);
+        }
+        assert_eq!(
+            OutputTarget::File(PathBuf::from("stdout")).to_string(),
+            "./stdout"
+        );
+        for template in ["{timestamp} {watchdog}: {stdout}", "{{literal}} {line}"] {
+            assert_eq!(Template::from_str(template).unwrap().to_string(), template);
+        }
+        for flush in ["every_write", "on_exit", "500"] {
+            assert_eq!(FlushPolicy::from_str(flush).unwrap().to_string(), flush);
+        }
+        for compression in ["gzip", "zstd:19"] {
+            assert_eq!(
+                Compression::from_str(compression).unwrap().to_string(),
+                compression
+            );
+        }
+        for owner in ["www-data", "www-data:adm", ":adm"] {
+            assert_eq!(Owner::from_str(owner).unwrap().to_string(), owner);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use serde_yaml::Value;
+
+use crate::{SettingsError, Template, Tls};
+
+/// The `recover_after` of a `passive_check` without one, in milliseconds: 5
+/// minutes, like an `alertmanager`'s `resolve_after`.
+pub const DEFAULT_RECOVER_AFTER: u64 = 300_000;
+
+/// A watchdog's `passive_check`: the existing Nagios or Icinga service check
+/// that its matches are submitted to as passive check results.
+///
+/// Every match submits the check's `status` with the `output` rendered from
+/// the match, and once the watchdog hasn't matched for `recover_after`
+/// milliseconds, an OK result recovers the check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassiveCheck {
+    /// The name of the check's host, as Nagios or Icinga knows it
+    pub host: String,
+    /// The name of the service check on the host
+    pub service: String,
+    /// The state submitted for a match
+    pub status: CheckStatus,
+    /// The plugin output of a match's result, with `{timestamp}`,
+    /// `{watchdog}` and `{line}` fields
+    pub output: Template,
+    /// Time in milliseconds after the last match that the check recovers
+    pub recover_after: u64,
+    /// Where the results are submitted
+    pub target: CheckTarget,
+}
+
+impl PassiveCheck {
+    /// A check of `service` on `host`, submitted to `target`, that's
+    /// critical on a match.
+    pub fn new(host: impl Into<String>, service: impl Into<String>, target: CheckTarget) -> Self {
+        Self {
+            host: host.into(),
+            service: service.into(),
+            status: CheckStatus::Critical,
+            output: Self::default_output(),
+            recover_after: DEFAULT_RECOVER_AFTER,
+            target,
+        }
+    }
+
+    /// The `output` of a check without one: the watchdog's name and the
+    /// matched line.
+    pub fn default_output() -> Template {
+        "{watchdog}: {line}"
+            .parse()
+            .expect("the default output is a valid template")
+    }
+
+    pub(crate) fn from_value(v: &Value) -> Result<Self, SettingsError> {
+        let invalid = |key: &str| SettingsError::InvalidValueType {
+            key: format!("passive_check.{key}"),
+        };
+        // the fields of a command file's commands are separated by `;`
+        let name = |key: &str, missing: &'static str| {
+            v.get(key)
+                .ok_or(SettingsError::from(missing))?
+                .as_str()
+                .filter(|name| !name.is_empty() && !name.contains([';', '\n']))
+                .map(String::from)
+                .ok_or_else(|| invalid(key))
+        };
+        let host = name("host", "passive_check.host")?;
+        let service = name("service", "passive_check.service")?;
+
+        let status = v
+            .get("status")
+            .map(|status| status.as_str().ok_or_else(|| invalid("status"))?.parse())
+            .transpose()?
+            .unwrap_or(CheckStatus::Critical);
+        if status == CheckStatus::Ok {
+            return Err(invalid("status"));
+        }
+        let output = v
+            .get("output")
+            .map(|output| {
+                let output: Template = output
+                    .as_str()
+                    .ok_or_else(|| invalid("output"))?
+                    .parse()
+                    .map_err(|_| invalid("output"))?;
+                output
+                    .has_match_fields_only()
+                    .then_some(output)
+                    .ok_or_else(|| invalid("output"))
+            })
+            .transpose()?
+            .unwrap_or_else(Self::default_output);
+        let recover_after = v
+            .get("recover_after")
+            .map(|after| {
+                after
+                    .as_u64()
+                    .filter(|after| *after > 0)
+                    .ok_or_else(|| invalid("recover_after"))
+            })
+            .transpose()?
+            .unwrap_or(DEFAULT_RECOVER_AFTER);
+
+        let target = match (v.get("command_file"), v.get("icinga")) {
+            (Some(path), None) => CheckTarget::CommandFile(
+                path.as_str()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| invalid("command_file"))?,
+            ),
+            (None, Some(icinga)) => CheckTarget::Icinga(Icinga::from_value(icinga)?),
+            (None, None) => return Err(SettingsError::from("passive_check.command_file")),
+            (Some(_), Some(_)) => return Err(invalid("icinga")),
+        };
+
+        Ok(Self {
+            host,
+            service,
+            status,
+            output,
+            recover_after,
+            target,
+        })
+    }
+}
+
+/// The state of a passive check result, with the plugin exit code it's
+/// submitted as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+}
+
+impl FromStr for CheckStatus {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ok" => Ok(Self::Ok),
+            "warning" => Ok(Self::Warning),
+            "critical" => Ok(Self::Critical),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "passive_check.status".into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => f.write_str("ok"),
+            Self::Warning => f.write_str("warning"),
+            Self::Critical => f.write_str("critical"),
+        }
+    }
+}
+
+/// Where a watchdog's passive check results are submitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckTarget {
+    /// `command_file`: Nagios' external command file, or Icinga's, as
+    /// `PROCESS_SERVICE_CHECK_RESULT` commands
+    CommandFile(PathBuf),
+    /// `icinga`: the Icinga 2 API's `process-check-result` action
+    Icinga(Icinga),
+}
+
+/// An Icinga 2 API that passive check results are submitted to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icinga {
+    /// The API's URL, e.g. `https://icinga:5665`
+    pub url: String,
+    /// The API user, which needs the `actions/process-check-result`
+    /// permission
+    pub username: String,
+    pub password: String,
+    /// How an `https://` API is verified and authenticated with, e.g. with
+    /// Icinga's own `ca.crt` as the `ca_file`
+    pub tls: Tls,
+}
+
+impl Icinga {
+    fn from_value(v: &Value) -> Result<Self, SettingsError> {
+        let invalid = |key: &str| SettingsError::InvalidValueType {
+            key: format!("passive_check.icinga.{key}"),
+        };
+        let url = v
+            .get("url")
+            .ok_or(SettingsError::from("passive_check.icinga.url"))?
+            .as_str()
+            .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+            .ok_or_else(|| invalid("url"))?;
+        let username = v
+            .get("username")
+            .ok_or(SettingsError::from("passive_check.icinga.username"))?
+            .as_str()
+            .ok_or_else(|| invalid("username"))?;
+        let password = v
+            .get("password")
+            .ok_or(SettingsError::from("passive_check.icinga.password"))?
+            .as_str()
+            .ok_or_else(|| invalid("password"))?;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            tls: Tls::from_value(v, url, "passive_check.icinga")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passive_check(yaml: &str) -> Result<PassiveCheck, SettingsError> {
+        PassiveCheck::from_value(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn test_when_passive_check_parsed_then_status_output_and_target_kept() {
+        let check = passive_check(
+            r"
+host: db1
+service: pgbouncer-log
+status: warning
+output: 'pgbouncer: {line}'
+recover_after: 60000
+icinga:
+  url: https://icinga:5665/
+  username: log-watchdog
+  password: s3cr3t
+  ca_file: /etc/icinga2/pki/ca.crt
+",
+        )
+        .unwrap();
+
+        assert_eq!(check.host, "db1");
+        assert_eq!(check.service, "pgbouncer-log");
+        assert_eq!(check.status, CheckStatus::Warning);
+        assert_eq!(check.output.to_string(), "pgbouncer: {line}");
+        assert_eq!(check.recover_after, 60000);
+        assert_eq!(
+            check.target,
+            CheckTarget::Icinga(Icinga {
+                url: "https://icinga:5665".into(),
+                username: "log-watchdog".into(),
+                password: "s3cr3t".into(),
+                tls: Tls {
+                    ca_file: Some("/etc/icinga2/pki/ca.crt".into()),
+                    ..Tls::default()
+                },
+            })
+        );
+        assert_eq!(
+            passive_check(
+                "{host: db1, service: pgbouncer-log, command_file: /var/lib/nagios/rw/nagios.cmd}"
+            )
+            .unwrap(),
+            PassiveCheck::new(
+                "db1",
+                "pgbouncer-log",
+                CheckTarget::CommandFile("/var/lib/nagios/rw/nagios.cmd".into())
+            )
+        );
+    }
+
+    #[test]
+    fn test_when_passive_check_invalid_then_error() {
+        let check = |fields: &str| {
+            passive_check(&format!(
+                "{{host: db1, command_file: nagios.cmd, {fields}}}"
+            ))
+        };
+        assert!(check("service: pgbouncer-log").is_ok());
+        assert!(check("service: pgbouncer-log, status: ok").is_err());
+        assert!(check("service: pgbouncer-log, status: unknown").is_err());
+        assert!(check("service: pgbouncer-log, output: '{stdout}'").is_err());
+        assert!(check("service: pgbouncer-log, recover_after: 0").is_err());
+        assert!(check("service: 'pgbouncer;log'").is_err());
+        assert!(check("").is_err());
+        assert!(check(
+            "service: pgbouncer-log, icinga: {url: 'https://icinga:5665', username: a, password: b}"
+        )
+        .is_err());
+        assert!(passive_check("{host: db1, service: pgbouncer-log}").is_err());
+        assert!(passive_check(
+            "{host: db1, service: pgbouncer-log, icinga: {url: 'icinga:5665', username: a, password: b}}"
+        )
+        .is_err());
+        assert!(passive_check(
+            "{host: db1, service: pgbouncer-log, icinga: {url: 'http://icinga:5665', username: a, password: b, ca_file: ca.crt}}"
+        )
+        .is_err());
+    }
+}
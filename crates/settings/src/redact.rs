@@ -0,0 +1,129 @@
+use std::{borrow::Cow, ops::Range};
+
+use regex::Regex;
+
+/// A watchdog's `redact` patterns: the secrets masked in the matched lines,
+/// command lines and command output it logs and writes to its outputs, e.g.
+/// tokens in URLs.
+#[derive(Debug, Clone, Default)]
+pub struct Redactions(Vec<Regex>);
+
+impl Redactions {
+    /// What a secret is replaced with.
+    pub const MASK: &'static str = "[REDACTED]";
+
+    pub const fn new(patterns: Vec<Regex>) -> Self {
+        Self(patterns)
+    }
+
+    pub fn patterns(&self) -> &[Regex] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `s` with its secrets masked: every match of a pattern, or only its
+    /// capture groups for a pattern with any, like the token of
+    /// `token=([^&\s]+)`.
+    pub fn redact<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let spans = self.spans(s);
+        if spans.is_empty() {
+            return Cow::Borrowed(s);
+        }
+        let mut redacted = String::with_capacity(s.len());
+        let mut last = 0;
+        for span in spans {
+            redacted.push_str(&s[last..span.start]);
+            redacted.push_str(Self::MASK);
+            last = span.end;
+        }
+        redacted.push_str(&s[last..]);
+        Cow::Owned(redacted)
+    }
+
+    /// `part` of `s`, like a capture group of the line, masked whole if it's
+    /// within a secret of `s`, and with the secrets of `s` in it masked
+    /// otherwise, as the patterns may not match it on its own.
+    pub fn redact_part<'a>(&self, s: &str, part: &'a str) -> Cow<'a, str> {
+        let secrets: Vec<_> = self.spans(s).into_iter().map(|span| &s[span]).collect();
+        if !part.is_empty() && secrets.iter().any(|secret| secret.contains(part)) {
+            return Cow::Borrowed(Self::MASK);
+        }
+        let mut redacted = self.redact(part);
+        for secret in secrets {
+            if redacted.contains(secret) {
+                redacted = Cow::Owned(redacted.replace(secret, Self::MASK));
+            }
+        }
+        redacted
+    }
+
+    /// Where the secrets in `s` are, in order, overlapping ones merged.
+    fn spans(&self, s: &str) -> Vec<Range<usize>> {
+        let mut spans: Vec<Range<usize>> = self
+            .0
+            .iter()
+            .flat_map(|regex| {
+                let groups = regex.captures_len() > 1;
+                regex.captures_iter(s).flat_map(move |captures| {
+                    let spans: Vec<_> = captures
+                        .iter()
+                        .skip(usize::from(groups))
+                        .flatten()
+                        .map(|m| m.range())
+                        .collect();
+                    spans
+                })
+            })
+            .filter(|span| !span.is_empty())
+            .collect();
+        spans.sort_by_key(|span| span.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(spans.len());
+        for span in spans {
+            match merged.last_mut() {
+                Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+                _ => merged.push(span),
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactions(patterns: &[&str]) -> Redactions {
+        Redactions::new(patterns.iter().map(|p| Regex::new(p).unwrap()).collect())
+    }
+
+    #[test]
+    fn test_when_redacted_then_matches_or_their_groups_masked() {
+        let redactions = redactions(&[r"token=([^&\s]+)", r"Bearer \S+"]);
+
+        assert_eq!(
+            redactions.redact("GET /hook?token=s3cr3t&x=1 Authorization: Bearer abc.def"),
+            "GET /hook?token=[REDACTED]&x=1 Authorization: [REDACTED]"
+        );
+        assert!(matches!(
+            redactions.redact("nothing to hide"),
+            Cow::Borrowed("nothing to hide")
+        ));
+    }
+
+    #[test]
+    fn test_when_part_of_secret_then_masked_whole() {
+        let redactions = redactions(&[r"token=([^&\s]+)"]);
+        let line = "GET /hook?token=s3cr3t&x=1";
+
+        assert_eq!(redactions.redact_part(line, "cr3"), Redactions::MASK);
+        assert_eq!(
+            redactions.redact_part(line, "/hook?token=s3cr3t"),
+            "/hook?token=[REDACTED]"
+        );
+        assert_eq!(redactions.redact_part(line, "x=1"), "x=1");
+    }
+}
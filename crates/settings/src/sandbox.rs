@@ -0,0 +1,162 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use serde_yaml::Value;
+
+use crate::SettingsError;
+
+/// A command's `sandbox`: the restricted profile it's run under, for commands
+/// that handle untrusted log content, e.g. its captures as arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    /// If true, the command, and anything it runs, can't gain privileges, e.g.
+    /// through a setuid program; run through `setpriv --no-new-privs` unless
+    /// it's wrapped, as `bwrap` and `nsjail` always do this
+    pub no_new_privileges: bool,
+    /// Environment variables passed on to the command; the rest are cleared,
+    /// and `PATH` is set to [`Sandbox::DEFAULT_PATH`] unless it's passed on
+    pub env: Vec<String>,
+    /// The sandbox the command is run in, if any
+    pub wrapper: Option<SandboxWrapper>,
+    /// An nsjail seccomp policy file, restricting the command's system calls
+    pub seccomp_policy: Option<PathBuf>,
+}
+
+impl Sandbox {
+    /// The `PATH` of a sandboxed command that isn't passed the daemon's.
+    pub const DEFAULT_PATH: &'static str = "/usr/local/bin:/usr/bin:/bin";
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            no_new_privileges: true,
+            env: Vec::new(),
+            wrapper: None,
+            seccomp_policy: None,
+        }
+    }
+}
+
+/// The program a sandboxed command is run in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxWrapper {
+    /// `bwrap`: bubblewrap, with the filesystem read-only but for a private
+    /// `/tmp`, and every namespace but the network's unshared
+    Bubblewrap,
+    /// `nsjail`, likewise
+    Nsjail,
+}
+
+impl FromStr for SandboxWrapper {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bwrap" => Ok(Self::Bubblewrap),
+            "nsjail" => Ok(Self::Nsjail),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "sandbox.wrapper".into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for SandboxWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bubblewrap => "bwrap",
+            Self::Nsjail => "nsjail",
+        })
+    }
+}
+
+/// Parses a `sandbox` mapping, any key of which may be left out for its
+/// default.
+impl TryFrom<&Value> for Sandbox {
+    type Error = SettingsError;
+
+    fn try_from(v: &Value) -> Result<Self, Self::Error> {
+        let invalid = |key: &str| SettingsError::InvalidValueType {
+            key: format!("sandbox.{key}"),
+        };
+        if !v.is_mapping() {
+            return Err(SettingsError::InvalidValueType {
+                key: "sandbox".into(),
+            });
+        }
+
+        let no_new_privileges = v
+            .get("no_new_privileges")
+            .map(|value| value.as_bool().ok_or_else(|| invalid("no_new_privileges")))
+            .transpose()?
+            .unwrap_or(true);
+        let env = v
+            .get("env")
+            .map(|env| {
+                env.as_sequence()
+                    .ok_or_else(|| invalid("env"))?
+                    .iter()
+                    .map(|name| {
+                        name.as_str()
+                            .map(String::from)
+                            .ok_or_else(|| invalid("env"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let wrapper = v
+            .get("wrapper")
+            .map(|wrapper| wrapper.as_str().ok_or_else(|| invalid("wrapper"))?.parse())
+            .transpose()?;
+        let seccomp_policy = v
+            .get("seccomp_policy")
+            .map(|path| {
+                path.as_str()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| invalid("seccomp_policy"))
+            })
+            .transpose()?;
+        // bubblewrap only takes compiled filters, from a file descriptor
+        if seccomp_policy.is_some() && wrapper != Some(SandboxWrapper::Nsjail) {
+            return Err(invalid("seccomp_policy"));
+        }
+
+        Ok(Self {
+            no_new_privileges,
+            env,
+            wrapper,
+            seccomp_policy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_sandbox_parsed_then_unset_keys_default() {
+        let sandbox: Value = serde_yaml::from_str("env: [LANG]").unwrap();
+        assert_eq!(
+            Sandbox::try_from(&sandbox).unwrap(),
+            Sandbox {
+                env: vec!["LANG".into()],
+                ..Sandbox::default()
+            }
+        );
+
+        let sandbox: Value =
+            serde_yaml::from_str("{wrapper: nsjail, seccomp_policy: /etc/curl.policy}").unwrap();
+        let sandbox = Sandbox::try_from(&sandbox).unwrap();
+        assert_eq!(sandbox.wrapper, Some(SandboxWrapper::Nsjail));
+        assert_eq!(sandbox.seccomp_policy, Some("/etc/curl.policy".into()));
+    }
+
+    #[test]
+    fn test_when_seccomp_policy_without_nsjail_then_error() {
+        let sandbox: Value =
+            serde_yaml::from_str("{wrapper: bwrap, seccomp_policy: /etc/curl.policy}").unwrap();
+        assert!(Sandbox::try_from(&sandbox).is_err());
+    }
+}
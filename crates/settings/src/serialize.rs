@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    Alertmanager, CheckStatus, CheckTarget, Column, Command, DebounceMode, Encoding, FileMissing,
+    FileOptions, FlushPolicy, LoggingSettings, OutputFormat, OutputSettings, PassiveCheck, Sandbox,
+    Settings, Tls, Watchdog, Zabbix, DEFAULT_MAX_READ_BYTES, DEFAULT_POLL_INTERVAL,
+    DEFAULT_READ_BUFFER_SIZE, DEFAULT_RECOVER_AFTER, DEFAULT_RESOLVE_AFTER,
+};
+
+/// Serializes like a settings file, which parses back to the same settings.
+impl Serialize for Settings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut settings = Mapping::new();
+        if self.logging != LoggingSettings::default() {
+            settings.insert("logging".into(), logging_value(&self.logging));
+        }
+        if let Some(allowed_commands) = &self.allowed_commands {
+            let commands = allowed_commands
+                .commands()
+                .iter()
+                .map(|command| command.to_string().into())
+                .collect();
+            settings.insert("allowed_commands".into(), Value::Sequence(commands));
+        }
+        if let Some(audit_log) = &self.audit_log {
+            let audit_log = audit_log.to_string_lossy().into_owned();
+            settings.insert("audit_log".into(), audit_log.into());
+        }
+        if let Some(executor) = &self.executor {
+            let executor = executor.to_string_lossy().into_owned();
+            settings.insert("executor".into(), executor.into());
+        }
+        if let Some(group) = &self.executor_group {
+            settings.insert("executor_group".into(), group.clone().into());
+        }
+        if let Some(max) = self.max_concurrent_commands {
+            settings.insert("max_concurrent_commands".into(), max.into());
+        }
+        let watchdogs = self
+            .watchdogs
+            .iter()
+            .map(|watchdog| (watchdog.name.clone().into(), watchdog_value(watchdog)))
+            .collect();
+        settings.insert("watchdogs".into(), Value::Mapping(watchdogs));
+        settings.serialize(serializer)
+    }
+}
+
+/// Serializes like the watchdog's settings in a settings file, without its
+/// name, which is its key under `watchdogs`.
+impl Serialize for Watchdog {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        watchdog_value(self).serialize(serializer)
+    }
+}
+
+fn logging_value(logging: &LoggingSettings) -> Value {
+    let mut v = Mapping::new();
+    if let Some(file) = &logging.file {
+        v.insert("file".into(), file.to_string_lossy().into_owned().into());
+    }
+    if let Some(rotation) = logging.rotation {
+        v.insert("rotation".into(), rotation.to_string().into());
+    }
+    if let Some(retention) = logging.retention {
+        v.insert("retention".into(), retention.into());
+    }
+    if let Some(stdout) = logging.stdout {
+        v.insert("stdout".into(), stdout.into());
+    }
+    if let Some(format) = logging.format {
+        v.insert("format".into(), format.to_string().into());
+    }
+    if let Some(syslog) = logging.syslog {
+        v.insert("syslog".into(), syslog.to_string().into());
+    }
+    if let Some(journald) = logging.journald {
+        v.insert("journald".into(), journald.into());
+    }
+    if let Some(interval) = logging.repeat_interval {
+        v.insert("repeat_interval".into(), interval.into());
+    }
+    if let Some(size) = logging.buffer_size {
+        v.insert("buffer_size".into(), size.into());
+    }
+    Value::Mapping(v)
+}
+
+fn watchdog_value(watchdog: &Watchdog) -> Value {
+    let mut v = Mapping::new();
+    v.insert(
+        "log_file".into(),
+        watchdog.log_file.to_string_lossy().into_owned().into(),
+    );
+    v.insert("output".into(), outputs_value(&watchdog.outputs));
+    // the parser gives every csv output the same columns
+    let columns = watchdog
+        .outputs
+        .iter()
+        .find_map(|output| match &output.format {
+            OutputFormat::Csv(columns) if *columns != Column::defaults() => Some(columns),
+            _ => None,
+        });
+    if let Some(columns) = columns {
+        let columns = columns.iter().map(|column| column.to_string().into());
+        v.insert("output_columns".into(), Value::Sequence(columns.collect()));
+    }
+    insert_file_options(&mut v, &watchdog.file_options);
+    v.insert("debounce".into(), watchdog.debounce.into());
+    if watchdog.debounce_mode != DebounceMode::default() {
+        v.insert(
+            "debounce_mode".into(),
+            watchdog.debounce_mode.to_string().into(),
+        );
+    }
+    v.insert("oneshot".into(), watchdog.oneshot.into());
+    v.insert("regex".into(), watchdog.regex.as_str().into());
+
+    v.insert("commands".into(), commands_value(&watchdog.commands));
+
+    if watchdog.dry_run {
+        v.insert("dry_run".into(), true.into());
+    }
+    if let Some(level) = watchdog.log_level {
+        v.insert(
+            "log_level".into(),
+            level.as_str().to_ascii_lowercase().into(),
+        );
+    }
+    if watchdog.log_sample_rate != 1 {
+        v.insert("log_sample_rate".into(), watchdog.log_sample_rate.into());
+    }
+    if watchdog.max_read_bytes != DEFAULT_MAX_READ_BYTES {
+        v.insert("max_read_bytes".into(), watchdog.max_read_bytes.into());
+    }
+    if watchdog.read_buffer_size != DEFAULT_READ_BUFFER_SIZE {
+        v.insert("read_buffer_size".into(), watchdog.read_buffer_size.into());
+    }
+    if let Some(timeout) = watchdog.partial_line_timeout {
+        v.insert("partial_line_timeout".into(), timeout.into());
+    }
+    if watchdog.file_missing != FileMissing::default() {
+        v.insert(
+            "file_missing".into(),
+            watchdog.file_missing.to_string().into(),
+        );
+    }
+    if !watchdog.on_file_missing.is_empty() {
+        v.insert(
+            "on_file_missing".into(),
+            commands_value(&watchdog.on_file_missing),
+        );
+    }
+    if watchdog.encoding != Encoding::default() {
+        v.insert("encoding".into(), watchdog.encoding.to_string().into());
+    }
+    if let Some(backend) = watchdog.backend {
+        v.insert("backend".into(), backend.to_string().into());
+    }
+    if watchdog.poll_interval != DEFAULT_POLL_INTERVAL {
+        v.insert("poll_interval".into(), watchdog.poll_interval.into());
+    }
+    if !watchdog.redact.is_empty() {
+        let patterns = watchdog.redact.patterns().iter();
+        v.insert(
+            "redact".into(),
+            Value::Sequence(patterns.map(|regex| regex.as_str().into()).collect()),
+        );
+    }
+    if let Some(alertmanager) = &watchdog.alertmanager {
+        v.insert("alertmanager".into(), alertmanager_value(alertmanager));
+    }
+    if let Some(passive_check) = &watchdog.passive_check {
+        v.insert("passive_check".into(), passive_check_value(passive_check));
+    }
+    if let Some(zabbix) = &watchdog.zabbix {
+        v.insert("zabbix".into(), zabbix_value(zabbix));
+    }
+    Value::Mapping(v)
+}
+
+fn alertmanager_value(alertmanager: &Alertmanager) -> Value {
+    let strings = |strings: &BTreeMap<String, String>| {
+        Value::Mapping(
+            strings
+                .iter()
+                .map(|(name, value)| (name.as_str().into(), value.as_str().into()))
+                .collect(),
+        )
+    };
+    let mut v = Mapping::new();
+    v.insert("url".into(), alertmanager.url.as_str().into());
+    if !alertmanager.labels.is_empty() {
+        v.insert("labels".into(), strings(&alertmanager.labels));
+    }
+    if !alertmanager.annotations.is_empty() {
+        v.insert("annotations".into(), strings(&alertmanager.annotations));
+    }
+    if alertmanager.resolve_after != DEFAULT_RESOLVE_AFTER {
+        v.insert("resolve_after".into(), alertmanager.resolve_after.into());
+    }
+    insert_tls(&mut v, &alertmanager.tls);
+    Value::Mapping(v)
+}
+
+fn passive_check_value(passive_check: &PassiveCheck) -> Value {
+    let mut v = Mapping::new();
+    v.insert("host".into(), passive_check.host.as_str().into());
+    v.insert("service".into(), passive_check.service.as_str().into());
+    if passive_check.status != CheckStatus::Critical {
+        v.insert("status".into(), passive_check.status.to_string().into());
+    }
+    if passive_check.output != PassiveCheck::default_output() {
+        v.insert("output".into(), passive_check.output.to_string().into());
+    }
+    if passive_check.recover_after != DEFAULT_RECOVER_AFTER {
+        v.insert("recover_after".into(), passive_check.recover_after.into());
+    }
+    match &passive_check.target {
+        CheckTarget::CommandFile(path) => {
+            v.insert(
+                "command_file".into(),
+                path.to_string_lossy().as_ref().into(),
+            );
+        }
+        CheckTarget::Icinga(icinga) => {
+            let mut api = Mapping::new();
+            api.insert("url".into(), icinga.url.as_str().into());
+            api.insert("username".into(), icinga.username.as_str().into());
+            api.insert("password".into(), icinga.password.as_str().into());
+            insert_tls(&mut api, &icinga.tls);
+            v.insert("icinga".into(), Value::Mapping(api));
+        }
+    }
+    Value::Mapping(v)
+}
+
+fn zabbix_value(zabbix: &Zabbix) -> Value {
+    let mut v = Mapping::new();
+    v.insert("server".into(), zabbix.server.as_str().into());
+    v.insert("host".into(), zabbix.host.as_str().into());
+    v.insert("key".into(), zabbix.key.as_str().into());
+    if zabbix.value != Zabbix::default_value() {
+        v.insert("value".into(), zabbix.value.to_string().into());
+    }
+    Value::Mapping(v)
+}
+
+fn commands_value(commands: &[Command]) -> Value {
+    let commands = commands
+        .iter()
+        .map(|command| {
+            let mut args = Mapping::new();
+            let values = command.args.iter().map(|arg| arg.as_str().into());
+            args.insert("args".into(), Value::Sequence(values.collect()));
+            if let Some(sandbox) = &command.sandbox {
+                args.insert("sandbox".into(), sandbox_value(sandbox));
+            }
+            if let Some(container) = &command.container {
+                args.insert("runtime".into(), container.runtime.to_string().into());
+                args.insert("image".into(), container.image.as_str().into());
+                if !container.mounts.is_empty() {
+                    let mounts = container
+                        .mounts
+                        .iter()
+                        .map(|mount| mount.to_string().into());
+                    args.insert("mounts".into(), Value::Sequence(mounts.collect()));
+                }
+            }
+            (command.name.clone().into(), Value::Mapping(args))
+        })
+        .collect();
+    Value::Mapping(commands)
+}
+
+fn sandbox_value(sandbox: &Sandbox) -> Value {
+    let mut v = Mapping::new();
+    if !sandbox.no_new_privileges {
+        v.insert("no_new_privileges".into(), false.into());
+    }
+    if !sandbox.env.is_empty() {
+        let env = sandbox.env.iter().map(|name| name.as_str().into());
+        v.insert("env".into(), Value::Sequence(env.collect()));
+    }
+    if let Some(wrapper) = sandbox.wrapper {
+        v.insert("wrapper".into(), wrapper.to_string().into());
+    }
+    if let Some(policy) = &sandbox.seccomp_policy {
+        v.insert(
+            "seccomp_policy".into(),
+            policy.to_string_lossy().into_owned().into(),
+        );
+    }
+    Value::Mapping(v)
+}
+
+/// A target for every output in the format it would be parsed with anyway,
+/// and a mapping with a `target` and `format` for the others.
+fn outputs_value(outputs: &[OutputSettings]) -> Value {
+    let output_value = |output: &OutputSettings| {
+        let parsed_format = if output.target.is_socket() {
+            OutputFormat::Jsonl
+        } else {
+            OutputFormat::default()
+        };
+        if output.format == parsed_format && output.tls.is_default() {
+            return output.target.to_string().into();
+        }
+        let mut v = Mapping::new();
+        v.insert("target".into(), output.target.to_string().into());
+        if output.format != parsed_format {
+            v.insert("format".into(), output.format.to_string().into());
+        }
+        insert_tls(&mut v, &output.tls);
+        Value::Mapping(v)
+    };
+
+    match outputs {
+        [output] => output_value(output),
+        outputs => Value::Sequence(outputs.iter().map(output_value).collect()),
+    }
+}
+
+/// Inserts the TLS options that are given.
+fn insert_tls(v: &mut Mapping, tls: &Tls) {
+    let paths = [
+        ("ca_file", &tls.ca_file),
+        ("client_cert", &tls.client_cert),
+        ("client_key", &tls.client_key),
+    ];
+    for (key, path) in paths {
+        if let Some(path) = path {
+            v.insert(key.into(), path.to_string_lossy().as_ref().into());
+        }
+    }
+    if let Some(version) = tls.min_version {
+        v.insert("min_tls_version".into(), version.to_string().into());
+    }
+}
+
+/// Inserts the file options that aren't their defaults.
+fn insert_file_options(v: &mut Mapping, options: &FileOptions) {
+    let defaults = FileOptions::default();
+    if options.append != defaults.append {
+        v.insert("output_append".into(), options.append.into());
+    }
+    if options.flush != defaults.flush {
+        let flush = match options.flush {
+            FlushPolicy::Interval(interval) => u64::try_from(interval.as_millis())
+                .unwrap_or(u64::MAX)
+                .into(),
+            flush => flush.to_string().into(),
+        };
+        v.insert("flush".into(), flush);
+    }
+    if options.fsync != defaults.fsync {
+        v.insert("fsync".into(), options.fsync.into());
+    }
+    if let Some(mode) = options.mode {
+        v.insert("output_mode".into(), format!("{mode:04o}").into());
+    }
+    if let Some(owner) = &options.owner {
+        v.insert("output_owner".into(), owner.to_string().into());
+    }
+    if let Some(rotation) = options.rotation {
+        v.insert("output_rotation".into(), rotation.to_string().into());
+    }
+    if options.retention != defaults.retention {
+        v.insert("output_retention".into(), options.retention.into());
+    }
+    if let Some(compression) = options.compression {
+        v.insert("output_compression".into(), compression.to_string().into());
+    }
+    if options.dedup != defaults.dedup {
+        v.insert("output_dedup".into(), options.dedup.into());
+    }
+    if options.empty != defaults.empty {
+        v.insert("output_empty".into(), options.empty.to_string().into());
+    }
+    if options.suppressed != defaults.suppressed {
+        v.insert("output_suppressed".into(), options.suppressed.into());
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    ffi::OsString,
+    fmt,
+    fs::{DirBuilder, OpenOptions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::SettingsError;
+
+/// A minisign public key, that settings files must have a detached signature
+/// from before they're loaded, so that a host only runs the commands of
+/// settings that the release process signed.
+///
+/// Signatures are checked with the `minisign` binary, which must be
+/// installed; a settings file's signature is `<file>.minisig`, as
+/// `minisign -Sm <file>` writes it.
+///
+/// What's checked is the contents the settings are parsed from, rather than
+/// the file, which could be replaced between being checked and being read:
+/// minisign is given a private copy of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey(String);
+
+impl PublicKey {
+    /// Checks the signature of `contents`, read from the settings file at
+    /// `path`.
+    ///
+    /// # Errors
+    ///
+    /// If the file is stdin, which can't be checked, or its signature is
+    /// missing, invalid or not from this key, or `minisign` can't be run.
+    pub fn verify(&self, path: &Path, contents: &[u8]) -> Result<(), SettingsError> {
+        self.verify_with("minisign", path, contents)
+    }
+
+    fn verify_with(
+        &self,
+        program: &str,
+        path: &Path,
+        contents: &[u8],
+    ) -> Result<(), SettingsError> {
+        let unsigned = |reason: String| SettingsError::Signature {
+            path: path.to_path_buf(),
+            reason,
+        };
+        if path == Path::new("-") {
+            return Err(unsigned("stdin can't be verified".into()));
+        }
+        let mut signature = OsString::from(path);
+        signature.push(".minisig");
+        let signature = PathBuf::from(signature);
+
+        let copy = PrivateCopy::new(contents)
+            .map_err(|e| unsigned(format!("failed to copy the settings to verify: {e}")))?;
+        let output = Command::new(program)
+            .args(["-V", "-q", "-P", &self.0, "-m"])
+            .arg(copy.path())
+            .arg("-x")
+            .arg(&signature)
+            .output()
+            .map_err(|e| unsigned(format!("failed to run {program}: {e}")))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(unsigned(match stderr.trim() {
+                "" => format!("{} doesn't verify", signature.display()),
+                stderr => stderr.to_string(),
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// A copy of a settings file's contents in a new directory only the user can
+/// read, removed when it's dropped.
+struct PrivateCopy(PathBuf);
+
+impl PrivateCopy {
+    fn new(contents: &[u8]) -> std::io::Result<Self> {
+        static COPIES: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "log-watchdog-verify-{}-{}",
+            std::process::id(),
+            COPIES.fetch_add(1, Ordering::Relaxed)
+        ));
+        // fails if the directory exists, so that it can't be someone else's
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let copy = Self(dir);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(copy.path())?
+            .write_all(contents)?;
+        Ok(copy)
+    }
+
+    fn path(&self) -> PathBuf {
+        self.0.join("settings")
+    }
+}
+
+impl Drop for PrivateCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = SettingsError;
+
+    /// Parses a public key as minisign prints it, e.g. the last line of its
+    /// `.pub` file: 56 base64 characters, starting with `RW`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let base64 = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=';
+        let s = s.trim();
+        if s.len() != 56 || !s.starts_with("RW") || !s.chars().all(base64) {
+            return Err(SettingsError::InvalidValueType {
+                key: "public key".into(),
+            });
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    const KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+
+    #[test]
+    fn test_when_signature_checked_then_minisign_given_key_and_signature() {
+        let dir = tempdir::TempDir::new("test_signature").unwrap();
+        let settings = dir.path().join("config.yml");
+        std::fs::write(&settings, "watchdogs: {}\n").unwrap();
+        // verifies if it's given the key and an existing signature
+        let minisign = dir.path().join("minisign");
+        std::fs::write(
+            &minisign,
+            format!(
+                "#!/bin/sh\n[ \"$4\" = {KEY} ] && [ -f \"$8\" ] || {{ echo bad >&2; exit 1; }}\n"
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&minisign, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let key: PublicKey = KEY.parse().unwrap();
+        let verify = |path: &Path| key.verify_with(minisign.to_str().unwrap(), path, b"");
+
+        assert!(matches!(
+            verify(&settings),
+            Err(SettingsError::Signature { reason, .. }) if reason == "bad"
+        ));
+        std::fs::write(dir.path().join("config.yml.minisig"), "").unwrap();
+        verify(&settings).unwrap();
+        assert!(verify(Path::new("-")).is_err());
+        assert!(key
+            .verify_with("no-such-minisign", &settings, b"")
+            .is_err_and(|e| e.to_string().contains("no-such-minisign")));
+    }
+
+    #[test]
+    fn test_when_signature_checked_then_contents_read_verified_not_file() {
+        let dir = tempdir::TempDir::new("test_signature_contents").unwrap();
+        let settings = dir.path().join("config.yml");
+        std::fs::write(&settings, "watchdogs: {}\n").unwrap();
+        std::fs::write(dir.path().join("config.yml.minisig"), "").unwrap();
+        // verifies the file it's given if it's the signed contents, and keeps
+        // its path to check that it's removed
+        let given = dir.path().join("given");
+        let minisign = dir.path().join("minisign");
+        std::fs::write(
+            &minisign,
+            format!(
+                "#!/bin/sh\necho \"$6\" > {}\n[ \"$(cat \"$6\")\" = signed ] || exit 1\n",
+                given.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&minisign, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let key: PublicKey = KEY.parse().unwrap();
+
+        key.verify_with(minisign.to_str().unwrap(), &settings, b"signed")
+            .unwrap();
+        let copy = std::fs::read_to_string(&given).unwrap();
+        assert_ne!(Path::new(copy.trim()), settings);
+        assert!(!Path::new(copy.trim()).exists());
+        assert!(key
+            .verify_with(minisign.to_str().unwrap(), &settings, b"swapped")
+            .is_err());
+    }
+
+    #[test]
+    fn test_when_public_key_invalid_then_error() {
+        assert!("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3"
+            .parse::<PublicKey>()
+            .is_ok());
+        assert!("RWQf6LRCGA9i53ml".parse::<PublicKey>().is_err());
+        assert!("untrusted comment: minisign public key"
+            .parse::<PublicKey>()
+            .is_err());
+    }
+}
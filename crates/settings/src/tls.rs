@@ -0,0 +1,143 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use serde_yaml::Value;
+
+use crate::SettingsError;
+
+/// How an `https://` URL's server is verified and the client authenticated:
+/// the keys `ca_file`, `client_cert`, `client_key` and `min_tls_version`
+/// next to its `url`, e.g. for an internal endpoint requiring mutual TLS.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tls {
+    /// The CA certificates the server's certificate is verified with,
+    /// instead of the system's
+    pub ca_file: Option<PathBuf>,
+    /// The PEM client certificate presented to the server, with its key
+    /// unless there's a `client_key`
+    pub client_cert: Option<PathBuf>,
+    /// The PEM key of the client certificate
+    pub client_key: Option<PathBuf>,
+    /// The oldest TLS version that's negotiated
+    pub min_version: Option<TlsVersion>,
+}
+
+impl Tls {
+    /// If true, the system's CAs verify the server, without a client
+    /// certificate, at any TLS version.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Parses the TLS keys of the mapping `v`, with `url` its URL, and
+    /// `section` the key of the mapping, for errors: they can only be given
+    /// for an `https://` URL, and a `client_key` only with a `client_cert`.
+    pub(crate) fn from_value(v: &Value, url: &str, section: &str) -> Result<Self, SettingsError> {
+        let invalid = |key: &str| SettingsError::InvalidValueType {
+            key: format!("{section}.{key}"),
+        };
+        let path = |key: &str| {
+            v.get(key)
+                .map(|path| {
+                    path.as_str()
+                        .filter(|path| !path.is_empty())
+                        .map(PathBuf::from)
+                        .ok_or_else(|| invalid(key))
+                })
+                .transpose()
+        };
+        let min_version = v
+            .get("min_tls_version")
+            .map(|version| match version {
+                Value::String(version) => version.parse().ok(),
+                Value::Number(version) => version.to_string().parse().ok(),
+                _ => None,
+            })
+            .map(|version| version.ok_or_else(|| invalid("min_tls_version")))
+            .transpose()?;
+        let tls = Self {
+            ca_file: path("ca_file")?,
+            client_cert: path("client_cert")?,
+            client_key: path("client_key")?,
+            min_version,
+        };
+
+        if tls.client_key.is_some() && tls.client_cert.is_none() {
+            return Err(invalid("client_key"));
+        }
+        if !tls.is_default() && !url.starts_with("https://") {
+            return Err(invalid("url"));
+        }
+        Ok(tls)
+    }
+}
+
+/// A version of TLS, given as `1.2` or `1.3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    V1_2,
+    V1_3,
+}
+
+impl FromStr for TlsVersion {
+    type Err = SettingsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.2" => Ok(Self::V1_2),
+            "1.3" => Ok(Self::V1_3),
+            _ => Err(SettingsError::InvalidValueType {
+                key: "min_tls_version".into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V1_2 => f.write_str("1.2"),
+            Self::V1_3 => f.write_str("1.3"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tls(url: &str, yaml: &str) -> Result<Tls, SettingsError> {
+        Tls::from_value(&serde_yaml::from_str(yaml).unwrap(), url, "alertmanager")
+    }
+
+    #[test]
+    fn test_when_tls_keys_then_parsed() {
+        assert_eq!(
+            tls(
+                "https://alerts.internal",
+                "{ca_file: ca.crt, client_cert: client.crt, client_key: client.key, min_tls_version: 1.3}"
+            )
+            .unwrap(),
+            Tls {
+                ca_file: Some("ca.crt".into()),
+                client_cert: Some("client.crt".into()),
+                client_key: Some("client.key".into()),
+                min_version: Some(TlsVersion::V1_3),
+            }
+        );
+        assert_eq!(
+            tls("https://alerts.internal", "{min_tls_version: '1.2'}")
+                .unwrap()
+                .min_version,
+            Some(TlsVersion::V1_2)
+        );
+        assert!(tls("http://alerts.internal", "{}").unwrap().is_default());
+    }
+
+    #[test]
+    fn test_when_tls_keys_invalid_then_error() {
+        assert!(tls("https://alerts.internal", "{min_tls_version: 1.1}").is_err());
+        assert!(tls("https://alerts.internal", "{client_key: client.key}").is_err());
+        assert!(tls("https://alerts.internal", "{ca_file: ''}").is_err());
+        assert!(tls("http://alerts.internal", "{ca_file: ca.crt}").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use serde_yaml::Value;
+
+use crate::{SettingsError, Template};
+
+/// The port of a `zabbix` server without one: the Zabbix trapper's.
+pub const DEFAULT_ZABBIX_PORT: u16 = 10051;
+
+/// A watchdog's `zabbix`: the Zabbix server or proxy that its matches are
+/// pushed to with the trapper protocol, like `zabbix_sender` does, as values
+/// of a trapper item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zabbix {
+    /// The server's or proxy's `host:port`, with the trapper port by default
+    pub server: String,
+    /// The name of the host the item is on, as Zabbix knows it
+    pub host: String,
+    /// The key of the trapper item
+    pub key: String,
+    /// The value pushed for a match, with `{timestamp}`, `{watchdog}` and
+    /// `{line}` fields
+    pub value: Template,
+}
+
+impl Zabbix {
+    /// Pushes the matched lines to the `key` item of `host` on `server`.
+    pub fn new(server: impl Into<String>, host: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            server: server.into(),
+            host: host.into(),
+            key: key.into(),
+            value: Self::default_value(),
+        }
+    }
+
+    /// The `value` of a `zabbix` without one: the matched line.
+    pub fn default_value() -> Template {
+        "{line}"
+            .parse()
+            .expect("the default value is a valid template")
+    }
+
+    pub(crate) fn from_value(v: &Value) -> Result<Self, SettingsError> {
+        let invalid = |key: &str| SettingsError::InvalidValueType {
+            key: format!("zabbix.{key}"),
+        };
+        let string = |key: &str, missing: &'static str| {
+            v.get(key)
+                .ok_or(SettingsError::from(missing))?
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| invalid(key))
+        };
+        let server = string("server", "zabbix.server")?;
+        let server = match server.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_err() => return Err(invalid("server")),
+            Some(_) => server.to_string(),
+            None => format!("{server}:{DEFAULT_ZABBIX_PORT}"),
+        };
+        let value = v
+            .get("value")
+            .map(|value| {
+                let value: Template = value
+                    .as_str()
+                    .ok_or_else(|| invalid("value"))?
+                    .parse()
+                    .map_err(|_| invalid("value"))?;
+                value
+                    .has_match_fields_only()
+                    .then_some(value)
+                    .ok_or_else(|| invalid("value"))
+            })
+            .transpose()?
+            .unwrap_or_else(Self::default_value);
+
+        Ok(Self {
+            server,
+            host: string("host", "zabbix.host")?.to_string(),
+            key: string("key", "zabbix.key")?.to_string(),
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zabbix(yaml: &str) -> Result<Zabbix, SettingsError> {
+        Zabbix::from_value(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn test_when_zabbix_parsed_then_trapper_port_by_default() {
+        let zabbix = zabbix(
+            r"
+server: zabbix-proxy
+host: db1
+key: pgbouncer.log.fatal
+value: '{timestamp} {line}'
+",
+        )
+        .unwrap();
+
+        assert_eq!(zabbix.server, "zabbix-proxy:10051");
+        assert_eq!(zabbix.host, "db1");
+        assert_eq!(zabbix.key, "pgbouncer.log.fatal");
+        assert_eq!(zabbix.value.to_string(), "{timestamp} {line}");
+        assert_eq!(
+            self::zabbix("{server: 'zabbix:10052', host: db1, key: pgbouncer.log.fatal}").unwrap(),
+            Zabbix::new("zabbix:10052", "db1", "pgbouncer.log.fatal")
+        );
+    }
+
+    #[test]
+    fn test_when_zabbix_invalid_then_error() {
+        assert!(zabbix("{host: db1, key: pgbouncer.log.fatal}").is_err());
+        assert!(zabbix("{server: zabbix, key: pgbouncer.log.fatal}").is_err());
+        assert!(zabbix("{server: zabbix, host: db1}").is_err());
+        assert!(zabbix("{server: 'zabbix:trapper', host: db1, key: a}").is_err());
+        assert!(zabbix("{server: zabbix, host: db1, key: a, value: '{stdout}'}").is_err());
+    }
+}
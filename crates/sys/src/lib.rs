@@ -0,0 +1,147 @@
+//! Safe wrappers of the syscalls that `--init` makes and std has no wrappers
+//! for: signalling and reaping processes, becoming a subreaper, and waiting
+//! for blocked signals. The rest of the workspace forbids unsafe code, so
+//! it's confined to this crate, every block with the reason it's sound.
+
+use std::{io, time::Duration};
+
+pub use libc::{c_int, pid_t};
+
+/// Sends the signal to the process.
+///
+/// # Errors
+///
+/// If the process doesn't exist, or can't be signalled.
+pub fn kill(pid: pid_t, signal: c_int) -> io::Result<()> {
+    // SAFETY: kill has no memory safety requirements
+    match unsafe { libc::kill(pid, signal) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// The pid of a child that has exited and not been waited for yet, if there
+/// is one, which is left to be waited for.
+pub fn exited_child() -> Option<pid_t> {
+    // SAFETY: siginfo_t is a plain C struct, for which zeroes are valid
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: info is valid for writes
+    let peeked = unsafe {
+        libc::waitid(
+            libc::P_ALL,
+            0,
+            &raw mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    // SAFETY: waitid filled in info, with a pid of 0 if none has exited
+    let pid = unsafe { info.si_pid() };
+    (peeked == 0 && pid != 0).then_some(pid)
+}
+
+/// Reaps the child, if it has exited, without its exit status.
+pub fn reap(pid: pid_t) {
+    // SAFETY: a null status is allowed, for a status that isn't wanted
+    unsafe {
+        libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG);
+    }
+}
+
+/// Makes orphaned descendants children of this process instead of PID 1.
+///
+/// # Errors
+///
+/// If the kernel doesn't support subreapers.
+pub fn become_subreaper() -> io::Result<()> {
+    // SAFETY: PR_SET_CHILD_SUBREAPER only takes an integer argument
+    match unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Sends the signal to this thread.
+///
+/// # Errors
+///
+/// If the signal isn't valid.
+pub fn raise(signal: c_int) -> io::Result<()> {
+    // SAFETY: raise has no memory safety requirements
+    match unsafe { libc::raise(signal) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// A set of signals, to be received by waiting for them rather than by a
+/// handler.
+pub struct SignalSet(libc::sigset_t);
+
+impl SignalSet {
+    pub fn new(signals: &[c_int]) -> Self {
+        // SAFETY: sigset_t is a plain C struct, for which zeroes are valid
+        let mut set = unsafe { std::mem::zeroed() };
+        // SAFETY: sigemptyset initializes the set, and sigaddset only adds
+        // signals to it, failing for invalid ones
+        unsafe {
+            libc::sigemptyset(&raw mut set);
+            for signal in signals {
+                libc::sigaddset(&raw mut set, *signal);
+            }
+        }
+        Self(set)
+    }
+
+    /// Blocks the signals for this thread, and the threads it starts, so that
+    /// they're only received by waiting for them.
+    ///
+    /// # Errors
+    ///
+    /// If the thread's signal mask can't be changed.
+    pub fn block(&self) -> io::Result<()> {
+        // SAFETY: the set is initialized, and the old mask isn't wanted
+        match unsafe {
+            libc::pthread_sigmask(libc::SIG_BLOCK, &raw const self.0, std::ptr::null_mut())
+        } {
+            0 => Ok(()),
+            e => Err(io::Error::from_raw_os_error(e)),
+        }
+    }
+
+    /// Waits for one of the signals, returning none after the timeout.
+    pub fn wait(&self, timeout: Duration) -> Option<c_int> {
+        let timeout = libc::timespec {
+            tv_sec: libc::time_t::try_from(timeout.as_secs()).unwrap_or(libc::time_t::MAX),
+            tv_nsec: libc::c_long::from(timeout.subsec_nanos().cast_signed()),
+        };
+        // SAFETY: the set and timeout are initialized, and the signal's info
+        // isn't wanted
+        let signal = unsafe {
+            libc::sigtimedwait(&raw const self.0, std::ptr::null_mut(), &raw const timeout)
+        };
+        (signal > 0).then_some(signal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_blocked_signal_raised_then_waited_for() {
+        // blocked for this test's thread only
+        let signals = SignalSet::new(&[libc::SIGUSR2]);
+        signals.block().unwrap();
+        assert_eq!(signals.wait(Duration::from_millis(10)), None);
+
+        raise(libc::SIGUSR2).unwrap();
+
+        assert_eq!(signals.wait(Duration::from_secs(1)), Some(libc::SIGUSR2));
+    }
+
+    #[test]
+    fn test_when_no_child_exited_then_none() {
+        assert_eq!(exited_child(), None);
+        assert!(kill(pid_t::MAX, 0).is_err());
+    }
+}
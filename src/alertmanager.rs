@@ -0,0 +1,203 @@
+//! Alerts sent to a watchdog's `alertmanager`, with the Alertmanager v2 API.
+
+use std::{
+    io,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+use settings::{Alertmanager, Redactions};
+
+use crate::{
+    http::{self, Url},
+    MatchEvent,
+};
+
+/// How long sending an alert may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A watchdog's alert, fired on every match.
+pub(crate) struct Alerts {
+    url: Url,
+    labels: Map<String, Value>,
+    annotations: Map<String, Value>,
+    resolve_after: Duration,
+    /// When the alert started firing, and when it resolves unless fired again
+    firing: Option<(SystemTime, SystemTime)>,
+}
+
+impl Alerts {
+    pub(crate) fn new(watchdog: &str, alertmanager: &Alertmanager) -> io::Result<Self> {
+        let url = Url::from_str(&alertmanager.url)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .with_tls(&alertmanager.tls);
+        let mut labels = Map::new();
+        labels.insert("alertname".into(), watchdog.into());
+        labels.insert("watchdog".into(), watchdog.into());
+        for (name, value) in &alertmanager.labels {
+            labels.insert(name.clone(), value.as_str().into());
+        }
+        let annotations = alertmanager
+            .annotations
+            .iter()
+            .map(|(name, value)| (name.clone(), value.as_str().into()))
+            .collect();
+
+        Ok(Self {
+            url: url.join("api/v2/alerts"),
+            labels,
+            annotations,
+            resolve_after: Duration::from_millis(alertmanager.resolve_after),
+            firing: None,
+        })
+    }
+
+    /// Fires the alert for the match, resolving `resolve_after` from now
+    /// unless it's fired again. An alert that's still firing keeps the time
+    /// it started.
+    pub(crate) fn fire(&mut self, event: &MatchEvent, redact: &Redactions) -> io::Result<()> {
+        let starts_at = match self.firing {
+            Some((starts_at, ends_at)) if ends_at > event.timestamp => starts_at,
+            _ => event.timestamp,
+        };
+        let ends_at = event.timestamp + self.resolve_after;
+
+        let mut annotations = self.annotations.clone();
+        annotations.insert("line".into(), redact.redact(&event.line).into());
+        annotations.insert("log_file".into(), event.log_file.to_string_lossy().into());
+        let alert = json!([{
+            "labels": self.labels,
+            "annotations": annotations,
+            "startsAt": rfc3339(starts_at),
+            "endsAt": rfc3339(ends_at),
+        }]);
+        let status = http::post(
+            &self.url,
+            &[],
+            "application/json",
+            alert.to_string().as_bytes(),
+            TIMEOUT,
+        )?;
+        if !(200..300).contains(&status) {
+            return Err(io::Error::other(format!(
+                "alertmanager at {:?} refused the alert with status {status}",
+                self.url
+            )));
+        }
+        self.firing = Some((starts_at, ends_at));
+        Ok(())
+    }
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        path::PathBuf,
+    };
+
+    /// Answers `requests` requests with 200, returning their bodies.
+    fn alertmanager(requests: usize) -> (String, std::thread::JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut alerts = Vec::new();
+            for _ in 0..requests {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                assert_eq!(request, "POST /api/v2/alerts HTTP/1.1\r\n");
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(length) = header.strip_prefix("Content-Length: ") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                (&stream).write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+                alerts.push(serde_json::from_slice(&body).unwrap());
+            }
+            alerts
+        });
+        (url, server)
+    }
+
+    fn event(line: &str, timestamp: SystemTime) -> MatchEvent {
+        MatchEvent {
+            watchdog: "pgbouncer".into(),
+            log_file: PathBuf::from("/var/log/pgbouncer.log"),
+            line: line.into(),
+            captures: Vec::new(),
+            timestamp,
+            match_id: 1,
+            suppressed: 0,
+        }
+    }
+
+    #[test]
+    fn test_when_fired_then_alert_labeled_with_watchdog_and_resolves_later() {
+        let (url, server) = alertmanager(1);
+        let mut settings = Alertmanager::new(url);
+        settings.labels.insert("severity".into(), "page".into());
+        settings
+            .annotations
+            .insert("summary".into(), "pgbouncer is down".into());
+        settings.resolve_after = 60_000;
+        let mut alerts = Alerts::new("pgbouncer", &settings).unwrap();
+
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(1_736_767_800);
+        alerts
+            .fire(
+                &event("FATAL password=hunter2", timestamp),
+                &Redactions::new(vec![regex::Regex::new("hunter2").unwrap()]),
+            )
+            .unwrap();
+
+        let alert = &server.join().unwrap()[0][0];
+        assert_eq!(
+            alert["labels"],
+            json!({ "alertname": "pgbouncer", "watchdog": "pgbouncer", "severity": "page" })
+        );
+        assert_eq!(alert["annotations"]["summary"], "pgbouncer is down");
+        assert_eq!(alert["annotations"]["line"], "FATAL password=[REDACTED]");
+        assert_eq!(alert["startsAt"], "2025-01-13T11:30:00.000Z");
+        assert_eq!(alert["endsAt"], "2025-01-13T11:31:00.000Z");
+    }
+
+    #[test]
+    fn test_when_fired_while_firing_then_start_kept_until_resolved() {
+        let (url, server) = alertmanager(3);
+        let mut settings = Alertmanager::new(url);
+        settings.resolve_after = 60_000;
+        let mut alerts = Alerts::new("pgbouncer", &settings).unwrap();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_736_767_800);
+
+        for after in [0, 30, 120] {
+            let timestamp = start + Duration::from_secs(after);
+            alerts
+                .fire(&event("FATAL", timestamp), &Redactions::default())
+                .unwrap();
+        }
+
+        let alerts = server.join().unwrap();
+        assert_eq!(alerts[1][0]["startsAt"], "2025-01-13T11:30:00.000Z");
+        assert_eq!(alerts[1][0]["endsAt"], "2025-01-13T11:31:30.000Z");
+        // resolved a minute after the second match, so this one fires anew
+        assert_eq!(alerts[2][0]["startsAt"], "2025-01-13T11:32:00.000Z");
+    }
+}
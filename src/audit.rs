@@ -0,0 +1,205 @@
+//! The audit log: every command a watchdog ran, with a hash chain so that
+//! an entry that was changed, removed or inserted afterwards is found by
+//! [`verify`].
+//!
+//! Every entry is a JSON object on a line of its own, with the `timestamp`,
+//! `watchdog`, the command's `argv`, its `exit_code` (null if it was killed
+//! by a signal), the `line_sha256` of the line that triggered it (null for
+//! `on_file_missing` commands), the `prev` entry's hash and its own `hash`:
+//! the SHA-256 of the entry without it, as written.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use serde_json::{json, Map, Value};
+use settings::sha256::{sha256, to_hex};
+
+use crate::output::rfc3339;
+
+/// The `prev` of the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A command that ran, for the audit log.
+pub(crate) struct Execution<'a> {
+    pub(crate) watchdog: &'a str,
+    pub(crate) argv: Vec<String>,
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) line: Option<&'a str>,
+}
+
+struct AuditLog {
+    path: PathBuf,
+    file: File,
+    last_hash: String,
+}
+
+/// The audit logs that are open, by path, kept open for the life of the
+/// process so that the watchdogs sharing one extend the same chain.
+static AUDIT_LOGS: Mutex<Vec<AuditLog>> = Mutex::new(Vec::new());
+
+/// Appends the execution to the audit log at `path`, chained to its last
+/// entry, and syncs it to disk.
+pub(crate) fn record(path: &Path, execution: &Execution) -> io::Result<()> {
+    let absolute = std::path::absolute(path)?;
+    let mut logs = AUDIT_LOGS.lock().unwrap_or_else(PoisonError::into_inner);
+    let log = match logs.iter().position(|log| log.path == absolute) {
+        Some(i) => &mut logs[i],
+        None => {
+            let log = AuditLog::open(absolute)?;
+            logs.push(log);
+            logs.last_mut().expect("just pushed")
+        }
+    };
+
+    let entry = json!({
+        "timestamp": rfc3339(SystemTime::now()),
+        "watchdog": execution.watchdog,
+        "argv": execution.argv,
+        "exit_code": execution.exit_code,
+        "line_sha256": execution.line.map(|line| to_hex(&sha256(line.as_bytes()))),
+        "prev": log.last_hash,
+    });
+    let Value::Object(mut entry) = entry else {
+        unreachable!("an object literal");
+    };
+    let hash = hash(&entry);
+    entry.insert("hash".into(), hash.clone().into());
+
+    let mut line = Value::Object(entry).to_string();
+    line.push('\n');
+    log.file.write_all(line.as_bytes())?;
+    log.file.sync_data()?;
+    log.last_hash = hash;
+    Ok(())
+}
+
+impl AuditLog {
+    /// Opens the audit log for appending, continuing the chain of its last
+    /// entry if it has any.
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .read(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        let mut last_hash = GENESIS.to_string();
+        for line in BufReader::new(&file).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            last_hash = serde_json::from_str::<Map<String, Value>>(&line)
+                .ok()
+                .and_then(|entry| entry.get("hash")?.as_str().map(String::from))
+                .ok_or_else(|| invalid(&path, "its last entry has no hash"))?;
+        }
+        Ok(Self {
+            path,
+            file,
+            last_hash,
+        })
+    }
+}
+
+fn hash(entry: &Map<String, Value>) -> String {
+    to_hex(&sha256(Value::Object(entry.clone()).to_string().as_bytes()))
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {message}", path.display()),
+    )
+}
+
+/// Checks the audit log at `path`: that every entry's hash is that of its
+/// contents, and its `prev` the hash of the entry before it. Returns how many
+/// entries there are.
+///
+/// # Errors
+///
+/// If the file can't be read, or with [`io::ErrorKind::InvalidData`] naming
+/// the first line that doesn't check out.
+pub fn verify(path: &Path) -> io::Result<u64> {
+    let file = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let mut prev = GENESIS.to_string();
+    let mut entries = 0;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |message: &str| invalid(path, &format!("line {}: {message}", i + 1));
+        let mut entry: Map<String, Value> =
+            serde_json::from_str(&line).map_err(|_| invalid("not a JSON object"))?;
+        let hash = match entry.remove("hash") {
+            Some(Value::String(hash)) => hash,
+            _ => return Err(invalid("no hash")),
+        };
+        if entry.get("prev").and_then(Value::as_str) != Some(&prev) {
+            return Err(invalid("not chained to the entry before it"));
+        }
+        if self::hash(&entry) != hash {
+            return Err(invalid("hash doesn't match its contents"));
+        }
+        prev = hash;
+        entries += 1;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execution(exit_code: i32) -> Execution<'static> {
+        Execution {
+            watchdog: "pgbouncer",
+            argv: vec!["systemctl".into(), "restart".into(), "pgbouncer".into()],
+            exit_code: Some(exit_code),
+            line: Some("FATAL: too many connections"),
+        }
+    }
+
+    #[test]
+    fn test_when_recorded_then_chain_verified_across_reopening() {
+        let dir = tempdir::TempDir::new("test_audit").unwrap();
+        let path = dir.path().join("audit.jsonl");
+
+        record(&path, &execution(0)).unwrap();
+        record(&path, &execution(1)).unwrap();
+        // as a restarted daemon would
+        AUDIT_LOGS.lock().unwrap().clear();
+        record(&path, &execution(0)).unwrap();
+
+        assert_eq!(verify(&path).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_when_entry_changed_or_removed_then_not_verified() {
+        let dir = tempdir::TempDir::new("test_audit").unwrap();
+        let path = dir.path().join("audit.jsonl");
+        for exit_code in 0..3 {
+            record(&path, &execution(exit_code)).unwrap();
+        }
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+
+        std::fs::write(&path, log.replacen("\"exit_code\":1", "\"exit_code\":0", 1)).unwrap();
+        let changed = verify(&path).unwrap_err();
+        assert!(changed.to_string().contains("line 2"), "{changed}");
+
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        let removed = verify(&path).unwrap_err();
+        assert!(removed.to_string().contains("line 2"), "{removed}");
+    }
+}
@@ -0,0 +1,415 @@
+use std::fmt::Write;
+
+use clap::{Arg, ArgAction, Command, ValueHint};
+
+/// A shell to generate a completion script for.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Options that take a watchdog name, completed with the names of the
+/// watchdogs in the `--settings` file given on the same command line, by the
+/// hidden `complete-watchdogs` subcommand, which only reads plain files.
+const WATCHDOG_OPTION: &str = "watchdog";
+
+/// Generates a completion script for the command's options and subcommands.
+pub fn generate(command: &mut Command, shell: Shell) -> String {
+    command.build();
+    match shell {
+        Shell::Bash => bash(command),
+        Shell::Zsh => zsh(command),
+        Shell::Fish => fish(command),
+    }
+}
+
+/// The options of a command, which for subcommands include the global ones.
+fn options(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+}
+
+/// The possible values of a command's positional arguments, e.g. shells.
+fn positional_values(command: &Command) -> Vec<String> {
+    command
+        .get_positionals()
+        .flat_map(possible_values)
+        .collect()
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_num_args().is_some_and(|n| n.takes_values())
+}
+
+/// If true, the option's value is completed with file names.
+fn is_path(arg: &Arg) -> bool {
+    matches!(
+        arg.get_value_hint(),
+        ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+    )
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+/// `-s --settings`
+fn flags(arg: &Arg) -> Vec<String> {
+    let short = arg.get_short().map(|short| format!("-{short}"));
+    let long = arg.get_long().map(|long| format!("--{long}"));
+    short.into_iter().chain(long).collect()
+}
+
+/// The first line of the argument's help, for shells that show it.
+fn summary(help: Option<&clap::builder::StyledStr>) -> String {
+    let help = help.map(ToString::to_string).unwrap_or_default();
+    help.lines()
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn subcommands(command: &Command) -> impl Iterator<Item = &Command> {
+    command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+fn bash(command: &Command) -> String {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut script = String::new();
+
+    let _ = writeln!(
+        script,
+        r#"{function}_watchdogs() {{
+    local i settings=()
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${{COMP_WORDS[i]}}" in
+            -s | --settings) settings+=(--settings "${{COMP_WORDS[i + 1]}}") ;;
+        esac
+    done
+    ((${{#settings[@]}})) && {name} complete-watchdogs "${{settings[@]}}" 2>/dev/null
+}}
+
+{function}() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD - 1]}}"
+    local i subcommand=""
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${{COMP_WORDS[i]}}" in"#
+    );
+    let names: Vec<_> = subcommands(command).map(Command::get_name).collect();
+    let _ = writeln!(
+        script,
+        r#"            {}) subcommand="${{COMP_WORDS[i]}}" && break ;;
+        esac
+    done
+"#,
+        names.join(" | ")
+    );
+
+    // values of options, the same for every subcommand they're in
+    let mut values = Vec::new();
+    for arg in std::iter::once(command)
+        .chain(subcommands(command))
+        .flat_map(options)
+        .filter(|arg| takes_value(arg))
+    {
+        let case = flags(arg).join(" | ");
+        if values.iter().any(|(existing, _)| *existing == case) {
+            continue;
+        }
+        let reply = if arg.get_id() == WATCHDOG_OPTION {
+            format!(r#"$(compgen -W "$({function}_watchdogs)" -- "$cur")"#)
+        } else {
+            match possible_values(arg).as_slice() {
+                [] if is_path(arg) => r#"$(compgen -f -- "$cur")"#.to_string(),
+                [] => String::new(),
+                values => format!(r#"$(compgen -W "{}" -- "$cur")"#, values.join(" ")),
+            }
+        };
+        values.push((case, reply));
+    }
+    script.push_str("    case \"$prev\" in\n");
+    for (case, reply) in values {
+        let _ = writeln!(
+            script,
+            "        {case})\n            COMPREPLY=({reply})\n            return\n            ;;"
+        );
+    }
+    script.push_str("    esac\n\n    local words\n    case \"$subcommand\" in\n");
+
+    let words = |command: &Command, with_subcommands: bool| {
+        let mut words = positional_values(command);
+        if with_subcommands {
+            words.extend(subcommands(command).map(|s| s.get_name().to_string()));
+        }
+        words.extend(options(command).flat_map(flags));
+        words.join(" ")
+    };
+    let _ = writeln!(
+        script,
+        "        \"\") words=\"{}\" ;;",
+        words(command, true)
+    );
+    for subcommand in subcommands(command) {
+        let _ = writeln!(
+            script,
+            "        {}) words=\"{}\" ;;",
+            subcommand.get_name(),
+            words(subcommand, false)
+        );
+    }
+    let _ = write!(
+        script,
+        r#"    esac
+    COMPREPLY=($(compgen -W "$words" -- "$cur"))
+}}
+
+complete -F {function} {name}
+"#
+    );
+
+    script
+}
+
+fn zsh(command: &Command) -> String {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let escape = |s: &str| {
+        s.replace('\'', r"'\''")
+            .replace('[', r"\[")
+            .replace(']', r"\]")
+            .replace(':', r"\:")
+    };
+    let specs = |command: &Command| {
+        let mut specs = Vec::new();
+        for (i, arg) in command.get_positionals().enumerate() {
+            let values = possible_values(arg);
+            if !values.is_empty() {
+                specs.push(format!(
+                    "        '{}:{}:({})'",
+                    i + 1,
+                    arg.get_id(),
+                    values.join(" ")
+                ));
+            }
+        }
+        for arg in options(command) {
+            let help = escape(&summary(arg.get_help()));
+            let action = if !takes_value(arg) {
+                String::new()
+            } else if arg.get_id() == WATCHDOG_OPTION {
+                format!(":watchdog:{function}_watchdogs")
+            } else {
+                match possible_values(arg).as_slice() {
+                    [] if is_path(arg) => format!(":{}:_files", arg.get_id()),
+                    [] => format!(":{}: ", arg.get_id()),
+                    values => format!(":{}:({})", arg.get_id(), values.join(" ")),
+                }
+            };
+            let repeat = if matches!(arg.get_action(), ArgAction::Append) {
+                "*"
+            } else {
+                ""
+            };
+            for flag in flags(arg) {
+                specs.push(format!("        '{repeat}{flag}[{help}]{action}'"));
+            }
+        }
+        specs.join(" \\\n")
+    };
+
+    let mut script = format!(
+        r#"#compdef {name}
+
+{function}_watchdogs() {{
+    local i
+    local -a settings watchdogs
+    for ((i = 1; i < $#words; i++)); do
+        [[ $words[i] == (-s|--settings) ]] && settings+=(--settings $words[i + 1])
+    done
+    (( $#settings )) || return
+    watchdogs=(${{(f)"$({name} complete-watchdogs $settings 2>/dev/null)"}})
+    _describe watchdog watchdogs
+}}
+
+{function}() {{
+    local curcontext="$curcontext" state line
+    _arguments -C \
+{} \
+        '1: :->subcommand' \
+        '*:: :->args'
+
+    case $state in
+    subcommand)
+        local -a subcommands
+        subcommands=(
+"#,
+        specs(command)
+    );
+    for subcommand in subcommands(command) {
+        let _ = writeln!(
+            script,
+            "            '{}:{}'",
+            subcommand.get_name(),
+            escape(&summary(subcommand.get_about()))
+        );
+    }
+    script.push_str(
+        "        )\n        _describe subcommand subcommands\n        ;;\n    args)\n        case $line[1] in\n",
+    );
+    for subcommand in subcommands(command) {
+        let _ = writeln!(
+            script,
+            "        {})\n            _arguments \\\n{}\n            ;;",
+            subcommand.get_name(),
+            specs(subcommand)
+        );
+    }
+    let _ = write!(
+        script,
+        "        esac\n        ;;\n    esac\n}}\n\n{function} \"$@\"\n"
+    );
+
+    script
+}
+
+fn fish(command: &Command) -> String {
+    let name = command.get_name();
+    let function = format!("__{}", name.replace('-', "_"));
+    let escape = |s: &str| s.replace('\\', r"\\").replace('\'', r"\'");
+    let mut script = format!(
+        r"function {function}_watchdogs
+    set -l tokens (commandline -opc)
+    for i in (seq (count $tokens))
+        if contains -- $tokens[$i] -s --settings
+            set -a settings --settings $tokens[(math $i + 1)]
+        end
+    end
+    set -q settings[1]; and {name} complete-watchdogs $settings 2>/dev/null
+end
+
+"
+    );
+
+    let complete = |script: &mut String, condition: &str, arg: &Arg| {
+        let _ = write!(script, "complete -c {name} -n '{condition}'");
+        if let Some(short) = arg.get_short() {
+            let _ = write!(script, " -s {short}");
+        }
+        if let Some(long) = arg.get_long() {
+            let _ = write!(script, " -l {long}");
+        }
+        if takes_value(arg) {
+            if arg.get_id() == WATCHDOG_OPTION {
+                let _ = write!(script, " -x -a '({function}_watchdogs)'");
+            } else {
+                match possible_values(arg).as_slice() {
+                    [] if is_path(arg) => script.push_str(" -r -F"),
+                    [] => script.push_str(" -x"),
+                    values => {
+                        let _ = write!(script, " -x -a '{}'", values.join(" "));
+                    }
+                }
+            }
+        }
+        let _ = writeln!(script, " -d '{}'", escape(&summary(arg.get_help())));
+    };
+
+    let top_level = "__fish_use_subcommand";
+    for arg in options(command) {
+        complete(&mut script, top_level, arg);
+    }
+    for subcommand in subcommands(command) {
+        let _ = writeln!(
+            script,
+            "complete -c {name} -n '{top_level}' -f -a {} -d '{}'",
+            subcommand.get_name(),
+            escape(&summary(subcommand.get_about()))
+        );
+    }
+    for subcommand in subcommands(command) {
+        let condition = format!("__fish_seen_subcommand_from {}", subcommand.get_name());
+        for arg in options(subcommand) {
+            complete(&mut script, &condition, arg);
+        }
+        let values = positional_values(subcommand);
+        if !values.is_empty() {
+            let _ = writeln!(
+                script,
+                "complete -c {name} -n '{condition}' -f -a '{}'",
+                values.join(" ")
+            );
+        }
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command as Process;
+
+    use clap::CommandFactory;
+
+    use super::*;
+    use crate::Args;
+
+    /// Checks the script's syntax with the shell, if it's installed.
+    fn check_syntax(shell: &str, script: &str) {
+        let dir = tempdir::TempDir::new("completions_").unwrap();
+        let path = dir.path().join("completions");
+        std::fs::write(&path, script).unwrap();
+
+        let Ok(output) = Process::new(shell).arg("-n").arg(&path).output() else {
+            return;
+        };
+        assert!(
+            output.status.success(),
+            "{shell}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn test_when_bash_then_subcommands_and_watchdogs_completed() {
+        let script = generate(&mut Args::command(), Shell::Bash);
+
+        assert!(script.contains("complete -F _log_watchdog log-watchdog"));
+        assert!(script.contains("validate) words=\""));
+        assert!(script.contains("-w | --watchdog)"));
+        assert!(script.contains("completions) words=\"bash zsh fish"));
+        assert!(script.contains("log-watchdog complete-watchdogs \"${settings[@]}\""));
+        check_syntax("bash", &script);
+    }
+
+    #[test]
+    fn test_when_zsh_then_subcommands_and_watchdogs_completed() {
+        let script = generate(&mut Args::command(), Shell::Zsh);
+
+        assert!(script.starts_with("#compdef log-watchdog\n"));
+        assert!(script.contains("'*--watchdog[Only run the watchdog with this name"));
+        assert!(script.contains(":watchdog:_log_watchdog_watchdogs'"));
+        check_syntax("zsh", &script);
+    }
+
+    #[test]
+    fn test_when_fish_then_subcommands_and_watchdogs_completed() {
+        let script = generate(&mut Args::command(), Shell::Fish);
+
+        assert!(script.contains("complete -c log-watchdog -n '__fish_use_subcommand' -f -a run"));
+        assert!(script
+            .contains("-n '__fish_seen_subcommand_from validate' -l format -x -a 'text json'"));
+        assert!(script.contains("-l watchdog -x -a '(__log_watchdog_watchdogs)'"));
+        check_syntax("fish", &script);
+    }
+}
@@ -0,0 +1,140 @@
+//! The cap on the commands running at once, across every watchdog, so that a
+//! flood of log lines matching many watchdogs can't fork-bomb the host: with
+//! `max_concurrent_commands`, a command waits in a queue until one exits.
+//! Likewise, the servers cap the connections they handle at once.
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+struct Commands {
+    running: usize,
+    queued: usize,
+}
+
+static COMMANDS: Mutex<Commands> = Mutex::new(Commands {
+    running: 0,
+    queued: 0,
+});
+
+/// Notified whenever a command exits.
+static EXITED: Condvar = Condvar::new();
+
+/// A running command's slot, given back when it's dropped.
+pub(crate) struct Slot(());
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        commands().running -= 1;
+        EXITED.notify_all();
+    }
+}
+
+/// Waits for fewer than `max` commands to be running, if there's a cap, and
+/// takes a slot for one more.
+pub(crate) fn acquire(max: Option<usize>, target: &str) -> Slot {
+    let mut commands = commands();
+    if let Some(max) = max.filter(|max| commands.running >= *max) {
+        commands.queued += 1;
+        log::debug!(
+            target: target,
+            "waiting for one of {max} running commands to exit, {} queued",
+            commands.queued
+        );
+        #[cfg(feature = "otel")]
+        crate::otel::record_queued_commands(commands.queued);
+        commands = EXITED
+            .wait_while(commands, |commands| commands.running >= max)
+            .unwrap_or_else(PoisonError::into_inner);
+        commands.queued -= 1;
+        #[cfg(feature = "otel")]
+        crate::otel::record_queued_commands(commands.queued);
+    }
+    commands.running += 1;
+    Slot(())
+}
+
+/// How many commands are waiting for others to exit, because
+/// `max_concurrent_commands` are already running.
+pub fn queued_commands() -> usize {
+    commands().queued
+}
+
+fn commands() -> MutexGuard<'static, Commands> {
+    COMMANDS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The connections a server handles at once, each on a thread of its own, so
+/// that clients connecting faster than they're served can't make it spawn
+/// threads without bounds.
+pub(crate) struct Connections {
+    max: usize,
+    open: Mutex<usize>,
+    closed: Condvar,
+}
+
+/// An open connection, closed when it's dropped.
+pub(crate) struct Connection(Arc<Connections>);
+
+impl Connections {
+    pub(crate) fn new(max: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max,
+            open: Mutex::new(0),
+            closed: Condvar::new(),
+        })
+    }
+
+    /// Waits for fewer than `max` connections to be open, and opens one more.
+    pub(crate) fn open(self: &Arc<Self>) -> Connection {
+        let mut open = self
+            .closed
+            .wait_while(
+                self.open.lock().unwrap_or_else(PoisonError::into_inner),
+                |open| *open >= self.max,
+            )
+            .unwrap_or_else(PoisonError::into_inner);
+        *open += 1;
+        Connection(Arc::clone(self))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        *self.0.open.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+        self.0.closed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_when_max_commands_running_then_others_queued_until_one_exits() {
+        let running = acquire(Some(1), "test");
+        let queued = std::thread::spawn(|| drop(acquire(Some(1), "test")));
+        while queued_commands() == 0 {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(!queued.is_finished());
+        drop(running);
+        queued.join().unwrap();
+        assert_eq!(queued_commands(), 0);
+    }
+
+    #[test]
+    fn test_when_max_connections_open_then_next_opened_once_one_closes() {
+        let connections = Connections::new(1);
+        let open = connections.open();
+        let next = std::thread::spawn({
+            let connections = Arc::clone(&connections);
+            move || drop(connections.open())
+        });
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert!(!next.is_finished());
+        drop(open);
+        next.join().unwrap();
+    }
+}
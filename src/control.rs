@@ -0,0 +1,127 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use log::{error, info};
+use logging::{Handle, LogConfig, LogLevel};
+
+/// A command sent, one per line, over the control socket.
+#[derive(Debug, PartialEq, Eq)]
+enum ControlCommand {
+    /// `log-level`: reply with the current log level
+    GetLogLevel,
+    /// `log-level <level>`: change the log level, e.g. `log-level debug,notify=warn`
+    SetLogLevel(LogLevel),
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("log-level"), None, None) => Ok(Self::GetLogLevel),
+            (Some("log-level"), Some(level), None) => LogLevel::from_str(level)
+                .map(Self::SetLogLevel)
+                .map_err(|e| e.to_string()),
+            _ => Err(format!("unknown command: {s}")),
+        }
+    }
+}
+
+/// Listens on a unix socket for commands that change the running daemon, such
+/// as its log level, from a separate thread.
+///
+/// Any stale socket left at `path` by a previous run is replaced.
+pub fn listen(path: &Path, handle: Handle, config: LogConfig) -> std::io::Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("control socket listening on {path:?}");
+
+    let config = Arc::new(Mutex::new(config));
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = serve(stream, &handle, &config) {
+                        error!("control socket connection failed: {e}");
+                    }
+                }
+                Err(e) => error!("control socket failed to accept connection: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(stream: UnixStream, handle: &Handle, config: &Mutex<LogConfig>) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut config = config.lock().unwrap();
+        match ControlCommand::from_str(&line) {
+            Ok(ControlCommand::GetLogLevel) => writeln!(writer, "{}", config.level)?,
+            Ok(ControlCommand::SetLogLevel(_)) if config.config_file.is_some() => writeln!(
+                writer,
+                "error: the log level is set by the logging config file {:?}",
+                config.config_file.as_ref().unwrap()
+            )?,
+            Ok(ControlCommand::SetLogLevel(level)) => {
+                info!(
+                    "control socket: changing log level from {} to {level}",
+                    config.level
+                );
+                let previous = std::mem::replace(&mut config.level, level);
+                match logging::reconfigure(handle, &config) {
+                    Ok(()) => writeln!(writer, "ok")?,
+                    Err(e) => {
+                        config.level = previous;
+                        writeln!(writer, "error: {e}")?;
+                    }
+                }
+            }
+            Err(e) => writeln!(writer, "error: {e}")?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_log_level_without_argument_then_get() {
+        assert_eq!(
+            ControlCommand::from_str("log-level").unwrap(),
+            ControlCommand::GetLogLevel
+        );
+    }
+
+    #[test]
+    fn test_when_log_level_with_argument_then_set() {
+        assert_eq!(
+            ControlCommand::from_str("log-level debug,notify=warn").unwrap(),
+            ControlCommand::SetLogLevel(LogLevel::from_str("debug,notify=warn").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_when_unknown_or_invalid_command_then_error() {
+        assert!(ControlCommand::from_str("restart").is_err());
+        assert!(ControlCommand::from_str("log-level loud").is_err());
+        assert!(ControlCommand::from_str("log-level debug extra").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! `elasticsearch:<url>/<index>` outputs, bulk-indexing match records into
+//! Elasticsearch or OpenSearch, so that they can be searched alongside the
+//! logs that triggered them.
+
+use std::{io, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use settings::Tls;
+
+use crate::http::{self, Url};
+
+/// How long a bulk request may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Indexes a watchdog's records with the bulk API, into the index its
+/// template names.
+pub(crate) struct ElasticsearchBulk {
+    url: Url,
+    index: String,
+}
+
+impl ElasticsearchBulk {
+    /// A watchdog's bulk indexing into `index`, with its `{watchdog}`
+    /// placeholders replaced; `{date}` is replaced for every request.
+    pub(crate) fn new(url: &str, tls: &Tls, index: &str, watchdog: &str) -> io::Result<Self> {
+        let url = Url::from_str(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Self {
+            url: url.join("_bulk").with_tls(tls),
+            index: index.replace("{watchdog}", watchdog),
+        })
+    }
+
+    /// Indexes every message, a JSON record, as a document, with a single
+    /// bulk request.
+    pub(crate) fn index(
+        &self,
+        messages: &[(Option<&str>, String)],
+        timestamp: DateTime<Utc>,
+    ) -> io::Result<()> {
+        let action = json!({
+            "index": {
+                "_index": self.index.replace("{date}", &timestamp.format("%Y.%m.%d").to_string())
+            }
+        })
+        .to_string();
+        let mut body = String::new();
+        for (_, message) in messages {
+            body.push_str(&action);
+            body.push('\n');
+            body.push_str(message);
+            body.push('\n');
+        }
+
+        let (status, response) = http::post_for_body(
+            &self.url,
+            &[],
+            "application/x-ndjson",
+            body.as_bytes(),
+            TIMEOUT,
+        )?;
+        if !(200..300).contains(&status) {
+            return Err(io::Error::other(format!(
+                "elasticsearch bulk request to {:?} failed with status {status}: {}",
+                self.url,
+                String::from_utf8_lossy(&response).trim()
+            )));
+        }
+        // the bulk API reports documents that weren't indexed in the response
+        let response: Value = serde_json::from_slice(&response)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if response.get("errors").and_then(Value::as_bool) == Some(true) {
+            let reason = response
+                .get("items")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .find_map(|item| item.get("index")?.get("error"))
+                .map_or_else(String::new, Value::to_string);
+            return Err(io::Error::other(format!(
+                "elasticsearch failed to index records into {}: {reason}",
+                self.index
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    /// Answers one request with `response`, returning the request's line and body.
+    fn serve_once(response: &'static str) -> (String, std::thread::JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(length) = header.strip_prefix("Content-Length: ") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream).write_all(response.as_bytes()).unwrap();
+            (request, String::from_utf8(body).unwrap())
+        });
+        (url, server)
+    }
+
+    fn timestamp() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2025-01-13T11:30:00Z")
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn test_when_indexed_then_records_sent_to_templated_index() {
+        let (url, server) = serve_once("HTTP/1.1 200 OK\r\n\r\n{\"errors\":false,\"items\":[]}");
+
+        ElasticsearchBulk::new(
+            &url,
+            &Tls::default(),
+            "watchdog-{watchdog}-{date}",
+            "pgbouncer",
+        )
+        .unwrap()
+        .index(
+            &[
+                (None, r#"{"line":"a"}"#.into()),
+                (None, r#"{"line":"b"}"#.into()),
+            ],
+            timestamp(),
+        )
+        .unwrap();
+
+        let (request, body) = server.join().unwrap();
+        assert_eq!(request, "POST /_bulk HTTP/1.1\r\n");
+        assert_eq!(
+            body,
+            concat!(
+                r#"{"index":{"_index":"watchdog-pgbouncer-2025.01.13"}}"#,
+                "\n",
+                r#"{"line":"a"}"#,
+                "\n",
+                r#"{"index":{"_index":"watchdog-pgbouncer-2025.01.13"}}"#,
+                "\n",
+                r#"{"line":"b"}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_when_documents_not_indexed_then_error() {
+        let (url, server) = serve_once(concat!(
+            "HTTP/1.1 200 OK\r\n\r\n",
+            r#"{"errors":true,"items":[{"index":{"status":400,"error":{"type":"mapper_parsing_exception"}}}]}"#,
+        ));
+
+        let indexed = ElasticsearchBulk::new(&url, &Tls::default(), "watchdog", "pgbouncer")
+            .unwrap()
+            .index(&[(None, r#"{"line":"a"}"#.into())], timestamp());
+
+        server.join().unwrap();
+        assert!(indexed
+            .unwrap_err()
+            .to_string()
+            .contains("mapper_parsing_exception"));
+    }
+}
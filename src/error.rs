@@ -0,0 +1,138 @@
+use std::{io, path::PathBuf, sync::Arc};
+
+use thiserror::Error;
+
+/// Why a watchdog, or a scan of the log files, failed.
+///
+/// Sources are shared, so that the error can be kept in the watchdog's
+/// [`crate::WatchdogStatus`] as well as returned.
+#[derive(Error, Debug, Clone)]
+pub enum WatchdogError {
+    /// The log file couldn't be watched for changes
+    #[error("watchdog {watchdog}: failed to watch {path:?}: {source}")]
+    Watch {
+        watchdog: String,
+        path: PathBuf,
+        #[source]
+        source: Arc<notify::Error>,
+    },
+    /// Reading the log file, or writing the output, failed
+    #[error("watchdog {watchdog}: {source}")]
+    Io {
+        watchdog: String,
+        #[source]
+        source: Arc<io::Error>,
+    },
+    /// A command exited unsuccessfully, with no exit code if it was killed by
+    /// a signal
+    #[error(
+        "watchdog {watchdog}: command {command} failed with exit code {exit_code:?}: {stderr}"
+    )]
+    Command {
+        watchdog: String,
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    /// A command's program isn't in `allowed_commands`, or no longer is
+    #[error("watchdog {watchdog}: command {command} is not in allowed_commands")]
+    NotAllowed { watchdog: String, command: String },
+    /// The positions saved between scans couldn't be loaded or saved
+    #[error("positions in {path:?}: {source}")]
+    Positions {
+        path: PathBuf,
+        #[source]
+        source: Arc<io::Error>,
+    },
+}
+
+impl WatchdogError {
+    /// The name of the watchdog that failed, if it was one.
+    pub fn watchdog(&self) -> Option<&str> {
+        match self {
+            Self::Watch { watchdog, .. }
+            | Self::Io { watchdog, .. }
+            | Self::Command { watchdog, .. }
+            | Self::NotAllowed { watchdog, .. } => Some(watchdog),
+            Self::Positions { .. } => None,
+        }
+    }
+}
+
+/// What the process exits with, so that wrapper scripts and e.g. systemd's
+/// `SuccessExitStatus` can tell why it stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// 0: the watchdogs were stopped, or completed, e.g. oneshot ones after
+    /// their match
+    Success,
+    /// 1: any other failure, e.g. the daemon's own logging couldn't be set up
+    Failure,
+    /// 2: the command line or settings are invalid
+    Config,
+    /// 3: a log file couldn't be watched
+    Watch,
+    /// 4: a command failed, or wasn't allowed to run
+    Command,
+    /// 5: a log file couldn't be read, an output written or the positions
+    /// between scans saved
+    Io,
+}
+
+impl ExitCode {
+    pub const fn code(self) -> i32 {
+        match self {
+            Self::Success => 0,
+            Self::Failure => 1,
+            Self::Config => 2,
+            Self::Watch => 3,
+            Self::Command => 4,
+            Self::Io => 5,
+        }
+    }
+
+    /// Exits the process with the code.
+    pub fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+impl From<&WatchdogError> for ExitCode {
+    fn from(error: &WatchdogError) -> Self {
+        match error {
+            WatchdogError::Watch { .. } => Self::Watch,
+            WatchdogError::Command { .. } | WatchdogError::NotAllowed { .. } => Self::Command,
+            WatchdogError::Io { .. } | WatchdogError::Positions { .. } => Self::Io,
+        }
+    }
+}
+
+/// The exit code of a run: success, or why the first watchdog that failed did.
+impl<T> From<&Result<T, WatchdogError>> for ExitCode {
+    fn from(result: &Result<T, WatchdogError>) -> Self {
+        result.as_ref().map_or_else(Self::from, |_| Self::Success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_watchdog_failed_then_exit_code_says_why() {
+        let command = WatchdogError::Command {
+            watchdog: "pgbouncer".into(),
+            command: "false".into(),
+            exit_code: Some(1),
+            stderr: String::new(),
+        };
+        let io = WatchdogError::Io {
+            watchdog: "pgbouncer".into(),
+            source: Arc::new(io::Error::other("disk full")),
+        };
+
+        assert_eq!(ExitCode::from(&Ok::<(), _>(())).code(), 0);
+        assert_eq!(ExitCode::from(&Err::<(), _>(command)).code(), 4);
+        assert_eq!(ExitCode::from(&io).code(), 5);
+    }
+}
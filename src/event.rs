@@ -0,0 +1,18 @@
+use std::{path::PathBuf, time::SystemTime};
+
+/// A line that a watchdog matched, passed to the callback of
+/// [`crate::run_with`] and to [`crate::Observer::matched`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchEvent {
+    pub watchdog: String,
+    pub log_file: PathBuf,
+    pub line: String,
+    /// The regex's capture groups that matched, by name or index
+    pub captures: Vec<(String, String)>,
+    pub timestamp: SystemTime,
+    /// Identifies the match in the daemon's logs, as `match_id`
+    pub match_id: u64,
+    /// The matches since the previous event that weren't acted on because
+    /// of the watchdog's debounce
+    pub suppressed: u64,
+}
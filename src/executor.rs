@@ -0,0 +1,392 @@
+//! The executor: the process that runs the watchdogs' commands for the
+//! process reading their logs, when the settings have an `executor` socket,
+//! so that the process parsing untrusted log content doesn't need the
+//! privileges the commands do, e.g. to restart services.
+//!
+//! The reader connects for every match, and sends a JSON object on a line:
+//! the `watchdog` and the `line` it matched, or the `watchdog` and
+//! `"file_missing": true` for its `on_file_missing` commands. The executor
+//! only runs the commands of its own settings' watchdogs, for a line their
+//! regex matches, or a log file that's missing, and refuses anything else.
+//! It replies with a JSON object on a line: the `outputs` of the commands it
+//! ran, and the `error` that stopped them, if any.
+
+use std::{
+    fs::Permissions,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use settings::{Settings, Watchdog};
+
+use crate::{concurrency::Connections, output::lookup_id, CommandOutput, Error};
+
+/// The longest request read, so that a reader can't make the executor buffer
+/// without bounds.
+const MAX_REQUEST_LEN: u64 = 16 << 20;
+
+/// The most requests served at once; more wait to be accepted.
+const MAX_CONNECTIONS: usize = 64;
+
+/// What the reader asks the executor to run.
+#[derive(Debug, PartialEq, Eq)]
+enum Request {
+    /// The watchdog's `commands`, for the line it matched
+    Matched { watchdog: String, line: String },
+    /// The watchdog's `on_file_missing` commands
+    FileMissing { watchdog: String },
+}
+
+impl Request {
+    fn parse(s: &str) -> Result<Self, String> {
+        let Ok(Value::Object(request)) = serde_json::from_str(s) else {
+            return Err("a request is a JSON object".into());
+        };
+        if let Some(key) = request
+            .keys()
+            .find(|key| !matches!(key.as_str(), "watchdog" | "line" | "file_missing"))
+        {
+            return Err(format!("unknown request key {key}"));
+        }
+        let watchdog = request
+            .get("watchdog")
+            .and_then(Value::as_str)
+            .ok_or("a request needs a watchdog")?
+            .to_string();
+        match (request.get("line"), request.get("file_missing")) {
+            (Some(Value::String(line)), None) => Ok(Self::Matched {
+                watchdog,
+                line: line.clone(),
+            }),
+            (None, Some(Value::Bool(true))) => Ok(Self::FileMissing { watchdog }),
+            _ => Err("a request needs either a line or file_missing: true".into()),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Self::Matched { watchdog, line } => json!({ "watchdog": watchdog, "line": line }),
+            Self::FileMissing { watchdog } => json!({ "watchdog": watchdog, "file_missing": true }),
+        }
+        .to_string()
+    }
+}
+
+/// Runs the watchdogs' commands for the readers that connect to the socket
+/// of the settings' `executor`, until the process is killed.
+///
+/// Any stale socket left by a previous run is replaced. Only the executor's
+/// user and the members of its group, the settings' `executor_group` if
+/// they have one, may connect to the socket.
+///
+/// # Errors
+///
+/// If the settings have no `executor`, its socket can't be listened on, or
+/// given to the `executor_group`.
+pub fn serve(settings: Settings) -> io::Result<()> {
+    let path = settings
+        .executor()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the settings have no executor socket",
+            )
+        })?
+        .to_path_buf();
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    if let Some(group) = settings.executor_group() {
+        let gid = lookup_id(Path::new("/etc/group"), group)?;
+        std::os::unix::fs::chown(&path, None, Some(gid))?;
+    }
+    std::fs::set_permissions(&path, Permissions::from_mode(0o660))?;
+    info!("executor listening on {path:?}");
+
+    listen(&listener, settings.into_watchdogs());
+    Ok(())
+}
+
+fn listen(listener: &UnixListener, watchdogs: Vec<Watchdog>) {
+    // the executor runs the commands itself
+    let watchdogs: Arc<[Watchdog]> = watchdogs
+        .into_iter()
+        .map(|mut watchdog| {
+            watchdog.executor = None;
+            watchdog
+        })
+        .collect();
+    let connections = Connections::new(MAX_CONNECTIONS);
+    loop {
+        let connection = connections.open();
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let watchdogs = Arc::clone(&watchdogs);
+                std::thread::spawn(move || {
+                    if let Err(e) = serve_connection(stream, &watchdogs) {
+                        error!("executor connection failed: {e}");
+                    }
+                    drop(connection);
+                });
+            }
+            Err(e) => error!("executor failed to accept connection: {e}"),
+        }
+    }
+}
+
+fn serve_connection(stream: UnixStream, watchdogs: &[Watchdog]) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut request = String::new();
+    BufReader::new(stream.take(MAX_REQUEST_LEN)).read_line(&mut request)?;
+
+    let request =
+        Request::parse(&request).and_then(|request| Ok((checked(&request, watchdogs)?, request)));
+    let reply = match request {
+        Ok((watchdog, request)) => {
+            let (commands, line) = match &request {
+                Request::Matched { line, .. } => (&watchdog.commands, Some(line.as_str())),
+                Request::FileMissing { .. } => (&watchdog.on_file_missing, None),
+            };
+            let target = logging::watchdog_target(&watchdog.name);
+            let mut outputs = Vec::new();
+            let result = crate::run_commands(watchdog, commands, line, &target, None, &mut outputs);
+            let outputs: Vec<_> = outputs.iter().map(output_value).collect();
+            json!({ "outputs": outputs, "error": result.err().as_ref().map(error_value) })
+        }
+        Err(message) => {
+            warn!("executor refused request: {message}");
+            json!({ "outputs": [], "error": { "kind": "refused", "message": message } })
+        }
+    };
+    writeln!(writer, "{reply}")
+}
+
+/// The watchdog the request is for, if the executor may run its commands.
+fn checked<'a>(request: &Request, watchdogs: &'a [Watchdog]) -> Result<&'a Watchdog, String> {
+    let (Request::Matched { watchdog: name, .. } | Request::FileMissing { watchdog: name }) =
+        request;
+    let watchdog = watchdogs
+        .iter()
+        .find(|watchdog| watchdog.name == *name)
+        .ok_or_else(|| format!("no watchdog named {name}"))?;
+    match request {
+        Request::Matched { line, .. } if !watchdog.regex.is_match(line) => {
+            Err(format!("watchdog {name} doesn't match the line"))
+        }
+        Request::FileMissing { .. } if watchdog.log_file.exists() => {
+            Err(format!("the log file of watchdog {name} isn't missing"))
+        }
+        _ => Ok(watchdog),
+    }
+}
+
+/// Sends the watchdog's commands for the line it matched, or its
+/// `on_file_missing` commands without one, to the executor listening on
+/// `socket`, adding the outputs of those it ran to `outputs`.
+pub(crate) fn run_commands(
+    socket: &Path,
+    watchdog: &Watchdog,
+    line: Option<&str>,
+    outputs: &mut Vec<CommandOutput>,
+) -> Result<(), Error> {
+    let executor_error = |message: &str| {
+        Error::Io(io::Error::other(format!(
+            "executor {}: {message}",
+            socket.display()
+        )))
+    };
+    let request = match line {
+        Some(line) => Request::Matched {
+            watchdog: watchdog.name.clone(),
+            line: line.to_string(),
+        },
+        None => Request::FileMissing {
+            watchdog: watchdog.name.clone(),
+        },
+    };
+
+    let mut stream = UnixStream::connect(socket)
+        .map_err(|e| executor_error(&format!("failed to connect: {e}")))?;
+    writeln!(stream, "{}", request.to_json())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let Ok(Value::Object(reply)) = serde_json::from_str(&reply) else {
+        return Err(executor_error("invalid reply"));
+    };
+
+    for output in reply
+        .get("outputs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        outputs.push(parse_output(output).ok_or_else(|| executor_error("invalid output"))?);
+    }
+    let Some(Value::Object(error)) = reply.get("error") else {
+        return Ok(());
+    };
+    let field = |key: &str| error.get(key).and_then(Value::as_str).unwrap_or_default();
+    Err(match field("kind") {
+        "command" => Error::Command(
+            field("command").to_string(),
+            error
+                .get("exit_code")
+                .and_then(Value::as_i64)
+                .and_then(|code| i32::try_from(code).ok()),
+            field("stderr").to_string(),
+        ),
+        "not_allowed" => Error::NotAllowed(field("command").to_string()),
+        _ => executor_error(field("message")),
+    })
+}
+
+fn output_value(output: &CommandOutput) -> Value {
+    json!({
+        "command": output.command,
+        "stdout": output.stdout,
+        "stderr": output.stderr,
+        "exit_code": output.exit_code,
+        "duration": output.duration.as_secs_f64(),
+    })
+}
+
+fn parse_output(v: &Value) -> Option<CommandOutput> {
+    let field = |key: &str| v.get(key)?.as_str().map(String::from);
+    Some(CommandOutput {
+        command: field("command")?,
+        stdout: field("stdout")?,
+        stderr: field("stderr")?,
+        exit_code: v
+            .get("exit_code")?
+            .as_i64()
+            .and_then(|code| i32::try_from(code).ok()),
+        duration: Duration::try_from_secs_f64(v.get("duration")?.as_f64()?).ok()?,
+    })
+}
+
+fn error_value(e: &Error) -> Value {
+    match e {
+        Error::Command(command, exit_code, stderr) => json!({
+            "kind": "command",
+            "command": command,
+            "exit_code": exit_code,
+            "stderr": stderr,
+        }),
+        Error::NotAllowed(command) => json!({ "kind": "not_allowed", "command": command }),
+        e => json!({ "kind": "failed", "message": e.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    fn executor(dir: &Path, watchdogs: Vec<Watchdog>) -> std::path::PathBuf {
+        let socket = dir.join("executor.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        std::thread::spawn(move || listen(&listener, watchdogs));
+        socket
+    }
+
+    #[test]
+    fn test_when_served_then_socket_only_for_user_and_group() {
+        let dir = tempdir::TempDir::new("test_executor").unwrap();
+        let socket = dir.path().join("executor.sock");
+        // a group the user is in, whoever runs the tests
+        let gid = std::fs::metadata(dir.path()).unwrap().gid();
+        let settings: Settings = format!(
+            "executor: {}\nexecutor_group: {gid}\nwatchdogs: {{}}",
+            socket.display()
+        )
+        .parse()
+        .unwrap();
+        std::thread::spawn(move || serve(settings));
+        while !socket.exists() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // the permissions are set right after the socket is bound
+        std::thread::sleep(Duration::from_millis(50));
+
+        let metadata = std::fs::metadata(&socket).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o660);
+        assert_eq!(metadata.gid(), gid);
+    }
+
+    #[test]
+    fn test_when_request_parsed_then_only_known_requests_accepted() {
+        assert_eq!(
+            Request::parse(r#"{"watchdog": "pgbouncer", "line": "aaa"}"#),
+            Ok(Request::Matched {
+                watchdog: "pgbouncer".into(),
+                line: "aaa".into()
+            })
+        );
+        assert_eq!(
+            Request::parse(r#"{"watchdog": "pgbouncer", "file_missing": true}"#),
+            Ok(Request::FileMissing {
+                watchdog: "pgbouncer".into()
+            })
+        );
+        assert!(Request::parse(r#"{"watchdog": "pgbouncer"}"#).is_err());
+        assert!(Request::parse(r#"{"watchdog": "pgbouncer", "line": "a", "args": []}"#).is_err());
+        assert!(Request::parse("run rm -rf /").is_err());
+    }
+
+    #[test]
+    fn test_when_line_matched_then_executor_runs_commands() {
+        let dir = tempdir::TempDir::new("test_executor").unwrap();
+        let watchdog = Watchdog::builder()
+            .name("pgbouncer")
+            .log_file(dir.path().join("log.txt"))
+            .regex("^aaa")
+            .command("echo", ["hello"])
+            .build()
+            .unwrap();
+        let socket = executor(dir.path(), vec![watchdog.clone()]);
+
+        let mut outputs = Vec::new();
+        run_commands(&socket, &watchdog, Some("aaa"), &mut outputs).unwrap();
+
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].command, "echo");
+        assert_eq!(outputs[0].stdout, "hello\n");
+        assert_eq!(outputs[0].exit_code, Some(0));
+    }
+
+    #[test]
+    fn test_when_request_not_for_configured_watchdog_then_refused() {
+        let dir = tempdir::TempDir::new("test_executor").unwrap();
+        let log_file = dir.path().join("log.txt");
+        std::fs::write(&log_file, "").unwrap();
+        let watchdog = |name: &str| {
+            Watchdog::builder()
+                .name(name)
+                .log_file(&log_file)
+                .regex("^aaa")
+                .command("echo", ["hello"])
+                .build()
+                .unwrap()
+        };
+        let socket = executor(dir.path(), vec![watchdog("pgbouncer")]);
+        let refused = |watchdog: &Watchdog, line| {
+            let mut outputs = Vec::new();
+            let result = run_commands(&socket, watchdog, line, &mut outputs);
+            assert!(outputs.is_empty());
+            matches!(result, Err(Error::Io(_)))
+        };
+
+        assert!(refused(&watchdog("other"), Some("aaa")));
+        assert!(refused(&watchdog("pgbouncer"), Some("bbb")));
+        assert!(refused(&watchdog("pgbouncer"), None));
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{CancellationToken, MatchEvent, WatchdogError};
+
+/// Values sent from the watchdogs' threads to a future, which is woken for
+/// each of them.
+struct Channel<T> {
+    values: VecDeque<T>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// Sends to a [`Channel`], closing it when dropped.
+pub(crate) struct Sender<T>(Arc<Mutex<Channel<T>>>);
+
+impl<T> Sender<T> {
+    pub(crate) fn send(&self, value: T) {
+        let mut channel = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        channel.values.push_back(value);
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut channel = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        channel.closed = true;
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Receiver<T>(Arc<Mutex<Channel<T>>>);
+
+impl<T> Receiver<T> {
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut channel = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(value) = channel.values.pop_front() {
+            Poll::Ready(Some(value))
+        } else if channel.closed {
+            Poll::Ready(None)
+        } else {
+            channel.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Mutex::new(Channel {
+        values: VecDeque::new(),
+        closed: false,
+        waker: None,
+    }));
+    (Sender(Arc::clone(&channel)), Receiver(channel))
+}
+
+/// The watchdogs started by [`crate::run_async`], which completes like
+/// [`crate::run_blocking`] returns. Dropping it stops the watchdogs.
+pub struct RunFuture {
+    token: CancellationToken,
+    result: Receiver<Result<(), WatchdogError>>,
+}
+
+impl RunFuture {
+    pub(crate) fn new(token: CancellationToken) -> (Self, Sender<Result<(), WatchdogError>>) {
+        let (tx, result) = channel();
+        (Self { token, result }, tx)
+    }
+}
+
+impl Future for RunFuture {
+    type Output = Result<(), WatchdogError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.result
+            .poll_recv(cx)
+            .map(|result| result.unwrap_or(Ok(())))
+    }
+}
+
+impl Drop for RunFuture {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+/// The matches of the watchdogs started by [`crate::events_async`], which end
+/// when every watchdog has stopped.
+///
+/// `poll_next` has the signature of `futures::Stream::poll_next`, to wrap it in
+/// the `Stream` of whichever runtime is used.
+pub struct EventStream(Receiver<MatchEvent>);
+
+impl EventStream {
+    pub(crate) fn new() -> (Self, Sender<MatchEvent>) {
+        let (tx, rx) = channel();
+        (Self(rx), tx)
+    }
+
+    pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<MatchEvent>> {
+        self.0.poll_recv(cx)
+    }
+
+    /// The next match, or `None` once every watchdog has stopped.
+    pub async fn next(&mut self) -> Option<MatchEvent> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use crate::{CancellationToken, WatchdogError};
+
+/// What a watchdog started by [`crate::run`] is doing.
+#[derive(Debug, Clone)]
+pub enum WatchdogStatus {
+    /// Setting up the watcher on its log file; lines appended before it's
+    /// running aren't read
+    Starting,
+    Running,
+    /// It stopped by itself, e.g. a oneshot watchdog after its match
+    Completed,
+    /// It was stopped with [`WatchdogHandle::stop`], or its cancellation
+    /// token
+    Stopped,
+    /// It stopped on this error
+    Failed(WatchdogError),
+}
+
+/// The watchdogs started by [`crate::run`], which run until they complete,
+/// fail or are stopped.
+pub struct WatchdogHandle {
+    pub(crate) token: CancellationToken,
+    pub(crate) statuses: Arc<Mutex<Vec<(String, WatchdogStatus)>>>,
+    pub(crate) threads: Vec<JoinHandle<()>>,
+    /// Receives once for every watchdog that stops
+    pub(crate) stopped: Receiver<()>,
+}
+
+impl WatchdogHandle {
+    /// Asks every watchdog to stop, without waiting for them to.
+    pub fn stop(&self) {
+        self.token.cancel();
+    }
+
+    /// The token that stops the watchdogs, e.g. to hand to the code that
+    /// shuts the host application down.
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Every watchdog by name, with what it's doing.
+    pub fn statuses(&self) -> Vec<(String, WatchdogStatus)> {
+        self.statuses
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Blocks until the next watchdog stops, returning false if they all have
+    /// already.
+    pub fn wait(&self) -> bool {
+        self.stopped.recv().is_ok()
+    }
+
+    /// Waits for every watchdog to stop, returning their final statuses.
+    pub fn join(self) -> Vec<(String, WatchdogStatus)> {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        let statuses = self
+            .statuses
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+        statuses
+    }
+}
@@ -0,0 +1,596 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    process::Command,
+    str::FromStr,
+    time::Duration,
+};
+
+use settings::{Tls, TlsVersion};
+
+/// The longest response body read, like the executor's requests, so that a
+/// server can't make a client buffer without bounds.
+const MAX_BODY_LEN: usize = 16 << 20;
+
+/// curl's exit code when the response is larger than `--max-filesize`.
+const CURL_FILESIZE_EXCEEDED: i32 = 63;
+
+pub(crate) const BASE64: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// An `http://` URL, or an `https://` one, which is requested with `curl`
+/// and its TLS options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub https: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+    pub tls: Tls,
+}
+
+impl Url {
+    /// Returns this URL with the TLS options, which settings only allow
+    /// for `https://` URLs.
+    pub fn with_tls(self, tls: &Tls) -> Self {
+        Self {
+            tls: tls.clone(),
+            ..self
+        }
+    }
+
+    /// Returns this URL with `path` appended to its path.
+    pub fn join(&self, path: &str) -> Self {
+        Self {
+            https: self.https,
+            host: self.host.clone(),
+            port: self.port,
+            path: format!(
+                "{}/{}",
+                self.path.trim_end_matches('/'),
+                path.trim_start_matches('/')
+            ),
+            tls: self.tls.clone(),
+        }
+    }
+}
+
+impl FromStr for Url {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (https, rest) = match (s.strip_prefix("http://"), s.strip_prefix("https://")) {
+            (Some(rest), _) => (false, rest),
+            (_, Some(rest)) => (true, rest),
+            _ => {
+                return Err(format!(
+                    "unsupported url {s}, only http:// and https:// are supported"
+                ))
+            }
+        };
+        let (authority, path) = rest
+            .find('/')
+            .map_or((rest, "/"), |i| (&rest[..i], &rest[i..]));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("invalid port in url {s}"))?,
+            ),
+            None => (authority, if https { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in url {s}"));
+        }
+
+        Ok(Self {
+            https,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            tls: Tls::default(),
+        })
+    }
+}
+
+/// Sends a GET request for `path_and_query` on the URL's host, e.g. with a
+/// query string the caller encoded, and returns the response status code and
+/// body.
+#[cfg(any(feature = "loki", feature = "remote-settings"))]
+pub fn get(
+    url: &Url,
+    path_and_query: &str,
+    headers: &[(String, String)],
+    timeout: Duration,
+) -> io::Result<(u16, Vec<u8>)> {
+    if url.https {
+        return curl(url, path_and_query, headers, None, timeout);
+    }
+    let mut stream = connect((url.host.as_str(), url.port), timeout)?;
+    write!(
+        stream,
+        "GET {path_and_query} HTTP/1.1\r\nHost: {}:{}\r\nAccept: application/json\r\nConnection: close\r\n",
+        url.host, url.port,
+    )?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    stream.write_all(b"\r\n")?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let status = status(&mut reader)?;
+    Ok((status, body(&mut reader)?))
+}
+
+/// Sends a POST request and returns the response status code.
+pub fn post(
+    url: &Url,
+    headers: &[(String, String)],
+    content_type: &str,
+    body: &[u8],
+    timeout: Duration,
+) -> io::Result<u16> {
+    if url.https {
+        let (status, _) = curl(url, &url.path, headers, Some((content_type, body)), timeout)?;
+        return Ok(status);
+    }
+    status(&mut send_post(url, headers, content_type, body, timeout)?)
+}
+
+/// Like [`post`], also returning the response body.
+#[cfg(any(feature = "elasticsearch", feature = "remote-settings"))]
+pub fn post_for_body(
+    url: &Url,
+    headers: &[(String, String)],
+    content_type: &str,
+    body: &[u8],
+    timeout: Duration,
+) -> io::Result<(u16, Vec<u8>)> {
+    if url.https {
+        return curl(url, &url.path, headers, Some((content_type, body)), timeout);
+    }
+    let mut reader = send_post(url, headers, content_type, body, timeout)?;
+    let status = status(&mut reader)?;
+    Ok((status, self::body(&mut reader)?))
+}
+
+fn send_post(
+    url: &Url,
+    headers: &[(String, String)],
+    content_type: &str,
+    body: &[u8],
+    timeout: Duration,
+) -> io::Result<BufReader<TcpStream>> {
+    let mut stream = connect((url.host.as_str(), url.port), timeout)?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.host,
+        url.port,
+        body.len()
+    )?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    stream.write_all(b"\r\n")?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(BufReader::new(stream))
+}
+
+/// Reads the headers and body of a response, after its status line: a body
+/// with a `Content-Length`, a chunked one, or one that ends with the
+/// connection, of at most [`MAX_BODY_LEN`].
+#[cfg(any(
+    feature = "loki",
+    feature = "elasticsearch",
+    feature = "remote-settings"
+))]
+fn body(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size)?;
+            let size = size.trim_end().split(';').next().unwrap_or_default();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| invalid_data(format!("invalid http chunk size: {size:?}")))?;
+            if size == 0 {
+                break;
+            }
+            if size > MAX_BODY_LEN - body.len() {
+                return Err(too_long());
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            reader.read_line(&mut String::new())?;
+        }
+    } else if let Some(length) = content_length {
+        if length > MAX_BODY_LEN {
+            return Err(too_long());
+        }
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        reader
+            .by_ref()
+            .take(MAX_BODY_LEN as u64 + 1)
+            .read_to_end(&mut body)?;
+        if body.len() > MAX_BODY_LEN {
+            return Err(too_long());
+        }
+    }
+    Ok(body)
+}
+
+/// Sends the request for an `https://` URL with `curl`, and returns the
+/// response status code and body.
+fn curl(
+    url: &Url,
+    path_and_query: &str,
+    headers: &[(String, String)],
+    body: Option<(&str, &[u8])>,
+    timeout: Duration,
+) -> io::Result<(u16, Vec<u8>)> {
+    let mut command = Command::new("curl");
+    command
+        .args(["--silent", "--show-error", "--max-time"])
+        .arg(timeout.as_secs_f64().to_string())
+        .arg("--max-filesize")
+        .arg(MAX_BODY_LEN.to_string())
+        // the status code on a line of its own after the body
+        .args(["--write-out", "\\n%{http_code}", "--config", "-"]);
+    let config = curl_config(url, path_and_query, headers, body);
+    let output = crate::init::output_with_input(&mut command, Some(config.as_bytes()))
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run curl: {e}")))?;
+    if output.status.code() == Some(CURL_FILESIZE_EXCEEDED) {
+        return Err(too_long());
+    }
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let mut body = output.stdout;
+    let status = body
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .and_then(|i| {
+            let status = std::str::from_utf8(&body[i + 1..]).ok()?.parse().ok()?;
+            body.truncate(i);
+            Some(status)
+        })
+        .ok_or_else(|| invalid_data("curl wrote no http status".into()))?;
+    // curl can only tell a body is too large before reading it by its length
+    if body.len() > MAX_BODY_LEN {
+        return Err(too_long());
+    }
+    Ok((status, body))
+}
+
+/// The request in a curl config, which curl is given on stdin rather than as
+/// arguments, so that other users can't see the credentials in its headers.
+fn curl_config(
+    url: &Url,
+    path_and_query: &str,
+    headers: &[(String, String)],
+    body: Option<(&str, &[u8])>,
+) -> String {
+    let mut config = format!(
+        "url = {}\n",
+        quoted(&format!(
+            "https://{}:{}{path_and_query}",
+            url.host, url.port
+        ))
+    );
+    match body {
+        Some((content_type, body)) => {
+            config.push_str(&format!(
+                "header = {}\ndata-raw = {}\n",
+                quoted(&format!("Content-Type: {content_type}")),
+                quoted(&String::from_utf8_lossy(body))
+            ));
+        }
+        None => config.push_str("header = \"Accept: application/json\"\n"),
+    }
+    for (name, value) in headers {
+        config.push_str(&format!(
+            "header = {}\n",
+            quoted(&format!("{name}: {value}"))
+        ));
+    }
+    let paths = [
+        ("cacert", &url.tls.ca_file),
+        ("cert", &url.tls.client_cert),
+        ("key", &url.tls.client_key),
+    ];
+    for (option, path) in paths {
+        if let Some(path) = path {
+            config.push_str(&format!("{option} = {}\n", quoted(&path.to_string_lossy())));
+        }
+    }
+    match url.tls.min_version {
+        Some(TlsVersion::V1_2) => config.push_str("tlsv1.2\n"),
+        Some(TlsVersion::V1_3) => config.push_str("tlsv1.3\n"),
+        None => {}
+    }
+    config
+}
+
+/// Quotes the value for a curl config.
+fn quoted(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Encodes bytes as padded base64, as etcd's JSON gateway expects keys and
+/// basic authentication credentials.
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | u32::from(*byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(BASE64[(n >> (18 - 6 * i)) as usize & 63]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Connects to the address, with the timeout for connecting and for every
+/// read and write after.
+pub(crate) fn connect(addr: impl ToSocketAddrs, timeout: Duration) -> io::Result<TcpStream> {
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host did not resolve"))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+/// Reads the status line of a response, returning its status code.
+fn status(reader: &mut impl BufRead) -> io::Result<u16> {
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid_data(format!("invalid http status line: {status_line:?}")))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn too_long() -> io::Error {
+    invalid_data(format!(
+        "http response body longer than {MAX_BODY_LEN} bytes"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net::TcpListener};
+
+    use super::*;
+
+    #[test]
+    fn test_when_url_then_host_port_and_path_are_parsed() {
+        assert_eq!(
+            Url::from_str("http://collector:4318/v1/traces").unwrap(),
+            Url {
+                https: false,
+                host: "collector".into(),
+                port: 4318,
+                path: "/v1/traces".into(),
+                tls: Tls::default(),
+            }
+        );
+        assert_eq!(
+            Url::from_str("http://localhost")
+                .unwrap()
+                .join("v1/metrics"),
+            Url::from_str("http://localhost:80/v1/metrics").unwrap()
+        );
+        assert_eq!(Url::from_str("https://localhost").unwrap().port, 443);
+        assert!(Url::from_str("ftp://localhost").is_err());
+        assert!(Url::from_str("http://:80").is_err());
+    }
+
+    #[test]
+    fn test_when_https_then_request_in_curl_config() {
+        let url = Url::from_str("https://loki:3100/loki/api/v1/push").unwrap();
+
+        assert_eq!(
+            curl_config(
+                &url,
+                &url.path,
+                &[("Authorization".into(), "Bearer \"token\"".into())],
+                Some(("application/json", b"{\"line\":\"a\\nb\"}")),
+            ),
+            r#"url = "https://loki:3100/loki/api/v1/push"
+header = "Content-Type: application/json"
+data-raw = "{\"line\":\"a\\nb\"}"
+header = "Authorization: Bearer \"token\""
+"#
+        );
+        assert_eq!(
+            curl_config(&url, "/ready", &[], None),
+            "url = \"https://loki:3100/ready\"\nheader = \"Accept: application/json\"\n"
+        );
+
+        let url = url.with_tls(&Tls {
+            ca_file: Some("/etc/ssl/internal-ca.crt".into()),
+            client_cert: Some("client.crt".into()),
+            client_key: Some("client.key".into()),
+            min_version: Some(TlsVersion::V1_3),
+        });
+        assert!(curl_config(&url, "/ready", &[], None).ends_with(
+            "cacert = \"/etc/ssl/internal-ca.crt\"\ncert = \"client.crt\"\nkey = \"client.key\"\ntlsv1.3\n"
+        ));
+    }
+
+    #[test]
+    #[cfg(any(feature = "loki", feature = "remote-settings"))]
+    fn test_when_response_body_too_long_then_invalid_data() {
+        for response in [
+            "HTTP/1.1 200 OK\r\nContent-Length: 16777217\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1000001\r\n",
+        ] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let server = std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+                stream
+            });
+
+            let url = Url::from_str(&format!("http://127.0.0.1:{port}")).unwrap();
+            let error = get(&url, "/", &[], Duration::from_secs(1)).unwrap_err();
+
+            drop(server.join().unwrap());
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "loki", feature = "remote-settings"))]
+    fn test_when_get_and_response_chunked_then_body_returned() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut request)
+                .unwrap();
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+                )
+                .unwrap();
+            request
+        });
+
+        let url = Url::from_str(&format!("http://127.0.0.1:{port}")).unwrap();
+        let (status, body) = get(&url, "/ready?now=1", &[], Duration::from_secs(1)).unwrap();
+
+        assert_eq!(server.join().unwrap(), "GET /ready?now=1 HTTP/1.1\r\n");
+        assert_eq!(status, 200);
+        assert_eq!(body, b"hello world");
+    }
+
+    #[test]
+    #[cfg(any(feature = "elasticsearch", feature = "remote-settings"))]
+    fn test_when_post_for_body_then_body_of_content_length_returned() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n{}") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            // the connection is left open, the body ends with its length
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 16\r\n\r\n{\"errors\":false}")
+                .unwrap();
+            stream
+        });
+
+        let url = Url::from_str(&format!("http://127.0.0.1:{port}/_bulk")).unwrap();
+        let (status, body) = post_for_body(
+            &url,
+            &[],
+            "application/x-ndjson",
+            b"{}",
+            Duration::from_secs(1),
+        )
+        .unwrap();
+
+        drop(server.join().unwrap());
+        assert_eq!(status, 200);
+        assert_eq!(body, br#"{"errors":false}"#);
+    }
+
+    #[test]
+    fn test_when_post_then_request_is_sent_and_status_returned() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n{}") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(b"HTTP/1.1 202 Accepted\r\n\r\n").unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        let url = Url::from_str(&format!("http://127.0.0.1:{port}/v1/traces")).unwrap();
+        let status = post(
+            &url,
+            &[("Authorization".into(), "Bearer token".into())],
+            "application/json",
+            b"{}",
+            Duration::from_secs(1),
+        )
+        .unwrap();
+
+        let request = server.join().unwrap();
+        assert_eq!(status, 202);
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(request.contains("Authorization: Bearer token\r\n"));
+        assert!(request.ends_with("\r\n\r\n{}"));
+    }
+}
@@ -0,0 +1,177 @@
+//! `--init`: running as PID 1, e.g. as a container's entrypoint without an
+//! init process.
+//!
+//! PID 1 gets no signal it hasn't a handler for, so the container runtime's
+//! SIGTERM would be ignored, and it inherits every orphaned process, so the
+//! zombies of the commands' own children would accumulate. Instead, SIGTERM
+//! and SIGINT stop the watchdogs and are forwarded to the commands that are
+//! running, which are killed if they're still running after the grace
+//! period, and orphans are reaped as they exit. Out of PID 1, the daemon is
+//! made a subreaper, so that it inherits its commands' orphans all the same.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+    process::{Child, Command, Output, Stdio},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use sys::SignalSet;
+
+use crate::{CancellationToken, ExitCode};
+
+/// How often orphans are reaped, besides when a child exits.
+const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a shutdown checks whether the commands have exited.
+const EXIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The pids of the commands that are running, that signals are forwarded to
+/// and that the reaper leaves for [`output`] to wait for.
+static RUNNING: Mutex<BTreeSet<libc::pid_t>> = Mutex::new(BTreeSet::new());
+
+fn running() -> MutexGuard<'static, BTreeSet<libc::pid_t>> {
+    RUNNING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs the command like [`Command::output`], as one of the commands that
+/// `--init` forwards signals to.
+pub(crate) fn output(command: &mut Command) -> io::Result<Output> {
+    output_with_input(command.stdin(Stdio::null()), None)
+}
+
+/// Runs the command like [`output`], writing `input` to its stdin.
+pub(crate) fn output_with_input(command: &mut Command, input: Option<&[u8]>) -> io::Result<Output> {
+    if input.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    let mut child = {
+        // held until the pid is known, so it's never reaped in between
+        let mut running = running();
+        let child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        running.insert(pid(&child));
+        child
+    };
+    let pid = pid(&child);
+    // closed once it's written, so the command sees the end of its input
+    let written = match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => stdin.write_all(input),
+        _ => Ok(()),
+    };
+    let output = child.wait_with_output();
+    running().remove(&pid);
+    written.and(output)
+}
+
+fn pid(child: &Child) -> libc::pid_t {
+    libc::pid_t::try_from(child.id()).unwrap_or(libc::pid_t::MAX)
+}
+
+/// Handles SIGTERM, SIGINT and SIGCHLD from a separate thread, returning the
+/// token that a SIGTERM or SIGINT cancels. The process exits once
+/// `grace_period` has passed since, if the watchdogs haven't stopped by then.
+///
+/// It must be called before any other thread is started, since they inherit
+/// the signals it blocks, to be waited for.
+///
+/// # Errors
+///
+/// If the signals can't be blocked.
+pub fn start(grace_period: Duration) -> io::Result<CancellationToken> {
+    let signals = SignalSet::new(&[libc::SIGTERM, libc::SIGINT, libc::SIGCHLD]);
+    signals.block()?;
+    if std::process::id() != 1 {
+        if let Err(e) = sys::become_subreaper() {
+            warn!("failed to become a subreaper, orphans won't be reaped: {e}");
+        }
+    }
+
+    let token = CancellationToken::new();
+    let stopping = token.clone();
+    std::thread::spawn(move || loop {
+        match signals.wait(REAP_INTERVAL) {
+            Some(signal @ (libc::SIGTERM | libc::SIGINT)) if !stopping.is_cancelled() => {
+                let name = if signal == libc::SIGTERM {
+                    "SIGTERM"
+                } else {
+                    "SIGINT"
+                };
+                info!("received {name}, stopping the watchdogs");
+                stopping.cancel();
+                std::thread::spawn(move || shut_down(signal, grace_period));
+            }
+            _ => (),
+        }
+        reap();
+    });
+    Ok(token)
+}
+
+/// Forwards the signal to the running commands, kills those still running
+/// after the grace period, and then exits, unless the watchdogs stopped and
+/// the process exited first.
+fn shut_down(signal: libc::c_int, grace_period: Duration) {
+    let deadline = Instant::now() + grace_period;
+    kill_running(signal);
+    while !running().is_empty() && Instant::now() < deadline {
+        std::thread::sleep(EXIT_INTERVAL);
+    }
+    if !running().is_empty() {
+        warn!(
+            "commands still running after the grace period of {}s, killing them",
+            grace_period.as_secs()
+        );
+        kill_running(libc::SIGKILL);
+    }
+    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+
+    warn!("the watchdogs didn't stop within the grace period, exiting");
+    log::logger().flush();
+    ExitCode::Success.exit();
+}
+
+fn kill_running(signal: libc::c_int) {
+    for pid in running().iter() {
+        debug!("sending signal {signal} to command {pid}");
+        // the pid is a command that hasn't been waited for, so it isn't
+        // reused yet
+        if let Err(e) = sys::kill(*pid, signal) {
+            debug!("failed to send signal {signal} to command {pid}: {e}");
+        }
+    }
+}
+
+/// Reaps every exited child, but for the commands that are waited for.
+fn reap() {
+    let running = running();
+    // looked at before it's reaped, so that the commands are left alone
+    while let Some(pid) = sys::exited_child().filter(|pid| !running.contains(pid)) {
+        sys::reap(pid);
+        debug!("reaped orphaned process {pid}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_command_output_then_captured_like_std() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo running; echo failed >&2; exit 3"]);
+
+        let output = output(&mut command).unwrap();
+
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, b"running\n");
+        assert_eq!(output.stderr, b"failed\n");
+
+        let output = output_with_input(&mut Command::new("cat"), Some(b"input")).unwrap();
+        assert_eq!(output.stdout, b"input");
+    }
+}
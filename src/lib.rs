@@ -1,239 +1,1308 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{BufRead, BufReader, Seek, SeekFrom, Write},
-    process::Command,
-    sync::mpsc::{Receiver, Sender, TryRecvError},
-    time::{Duration, Instant},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, TryRecvError},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
-use log::{error, info};
-use notify::{Config, RecommendedWatcher, Watcher};
-use settings::{Settings, Watchdog};
+use log::{debug, error, info, warn};
+use logging::Sampler;
+use output::{MatchRecord, Output};
+use pipeline::{Action, Commands, DefaultMatchers, FileSource, Matcher, Source, WatchdogPipeline};
+use position::Positions;
+use settings::{DebounceMode, FileMissing, Settings, SettingsError, Watchdog, WatcherBackend};
 use thiserror::Error;
 
+pub use cancel::CancellationToken;
+pub use concurrency::queued_commands;
+pub use error::{ExitCode, WatchdogError};
+pub use event::MatchEvent;
+pub use future::{EventStream, RunFuture};
+pub use handle::{WatchdogHandle, WatchdogStatus};
+#[cfg(feature = "loki")]
+pub use loki::LokiSource;
+pub use observer::Observer;
+use observer::OnMatch;
+pub use output::CommandOutput;
+pub use tailer::{Lines, Tailer};
+
+#[cfg(feature = "alertmanager")]
+mod alertmanager;
+pub mod audit;
+mod cancel;
+mod concurrency;
+pub mod control;
+#[cfg(feature = "elasticsearch")]
+mod elasticsearch;
+mod error;
+mod event;
+pub mod executor;
+mod future;
+mod handle;
+mod http;
+pub mod init;
+#[cfg(feature = "loki")]
+mod loki;
+mod observer;
+#[cfg(feature = "otel")]
+pub mod otel;
+mod output;
+mod passive_check;
+pub mod pipeline;
+mod position;
+pub mod reload;
+#[cfg(feature = "remote-settings")]
+pub mod remote_settings;
+pub mod replay;
+mod rotation;
+mod sandbox;
+pub mod scan_server;
+mod tailer;
+pub mod testing;
+mod watcher;
+#[cfg(feature = "zabbix")]
+mod zabbix;
+
+/// How often a watchdog waiting for its log file to change checks whether
+/// it's been stopped.
+const STOP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the number of matches left out by `log_sample_rate` is logged.
+const MATCH_SUMMARY_INTERVAL: Duration = Duration::from_mins(1);
+
 #[derive(Error, Debug)]
 enum Error {
     #[error(transparent)]
     Io(#[from] std::io::Error),
-    #[error(transparent)]
-    Send(std::sync::mpsc::SendError<String>),
     #[error("watcher {0 }error: {1}")]
     Watcher(String, notify::Error),
     #[error("command {0} failed with exit code {1:?}: {2}")]
     Command(String, Option<i32>, String),
+    #[error("command {0} is not in allowed_commands")]
+    NotAllowed(String),
+    #[error(transparent)]
+    Watchdog(#[from] WatchdogError),
+}
+
+impl Error {
+    fn into_watchdog_error(self, watchdog: &Watchdog) -> WatchdogError {
+        let name = watchdog.name.clone();
+        match self {
+            Self::Io(e) => WatchdogError::Io {
+                watchdog: name,
+                source: Arc::new(e),
+            },
+            Self::Watcher(_, e) => WatchdogError::Watch {
+                watchdog: name,
+                path: watchdog.log_file.clone(),
+                source: Arc::new(e),
+            },
+            Self::Command(command, exit_code, stderr) => WatchdogError::Command {
+                watchdog: name,
+                command,
+                exit_code,
+                stderr,
+            },
+            Self::NotAllowed(command) => WatchdogError::NotAllowed {
+                watchdog: name,
+                command,
+            },
+            Self::Watchdog(e) => e,
+        }
+    }
+}
+
+/// Starts every watchdog on a thread of its own, returning a handle to stop
+/// them, wait for them and see what they're doing.
+pub fn run(settings: Settings) -> WatchdogHandle {
+    start(pipelines(settings), None, CancellationToken::new())
+}
+
+/// Like [`run`], but with pipelines whose source, matcher or action may be
+/// custom, e.g. to act on a watchdog's matches in-process.
+pub fn run_pipelines(pipelines: Vec<WatchdogPipeline>) -> WatchdogHandle {
+    start(pipelines, None, CancellationToken::new())
+}
+
+/// The watchdogs' own pipelines.
+fn pipelines(settings: Settings) -> Vec<WatchdogPipeline> {
+    settings
+        .into_watchdogs()
+        .into_iter()
+        .map(WatchdogPipeline::new)
+        .collect()
+}
+
+/// Like [`run`], but also calls `observer` with what the watchdogs do, e.g.
+/// to wire them into an application's own metrics.
+pub fn run_with_observer(settings: Settings, observer: impl Observer + 'static) -> WatchdogHandle {
+    start(
+        pipelines(settings),
+        Some(Arc::new(observer)),
+        CancellationToken::new(),
+    )
+}
+
+/// Like [`run`], but the watchdogs also stop when `token` is cancelled, e.g.
+/// by a host application shutting down.
+pub fn run_until(settings: Settings, token: CancellationToken) -> WatchdogHandle {
+    start(pipelines(settings), None, token)
+}
+
+/// Like [`run`], but also calls `on_match` with every match, before the
+/// watchdog's commands are run, e.g. for an application embedding the
+/// watchdogs to act on matches itself. Watchdogs without commands only call
+/// it.
+///
+/// The watchdogs share `on_match`, so it's called for one match at a time.
+pub fn run_with(
+    settings: Settings,
+    on_match: impl FnMut(MatchEvent) + Send + 'static,
+) -> WatchdogHandle {
+    start(
+        pipelines(settings),
+        Some(Arc::new(OnMatch(Mutex::new(on_match)))),
+        CancellationToken::new(),
+    )
+}
+
+/// Like [`run_with`], but delivers the matches as a stream of events, which
+/// ends when every watchdog has stopped, e.g. to use the watchdogs as a
+/// tailing and matching library.
+pub fn events(settings: Settings) -> (WatchdogHandle, Receiver<MatchEvent>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let handle = run_with(settings, move |event| {
+        // nobody's listening anymore, the watchdogs are still running
+        let _ = tx.send(event);
+    });
+    (handle, rx)
 }
 
-pub fn run(settings: Settings) {
+/// Runs the watchdogs for `duration` without running their commands or
+/// writing their outputs, and returns what they matched, e.g. to assert on
+/// the logs of the code under test in an integration test.
+///
+/// The file is tailed from when the watchdogs start, so the lines must be
+/// written during `duration`, e.g. from another thread.
+pub fn run_collect(settings: Settings, duration: Duration) -> Vec<MatchEvent> {
+    let watchdogs = settings
+        .into_watchdogs()
+        .into_iter()
+        .map(|mut watchdog| {
+            watchdog.commands.clear();
+            watchdog.outputs.clear();
+            watchdog
+        })
+        .collect();
+    let (handle, events) = events(Settings::new(watchdogs));
+
+    std::thread::sleep(duration);
+    handle.stop();
+    handle.join();
+    events.into_iter().collect()
+}
+
+fn start(
+    pipelines: Vec<WatchdogPipeline>,
+    observer: Option<Arc<dyn Observer>>,
+    token: CancellationToken,
+) -> WatchdogHandle {
     info!("starting log-watchdog");
     let (tx, rx) = std::sync::mpsc::channel::<()>();
+    let statuses = Arc::new(Mutex::new(Vec::new()));
 
-    for watchdog in settings.into_watchdogs() {
-        let tx = tx.clone();
-        std::thread::spawn(move || match watch(watchdog, tx) {
-            Ok(name) => info!("watchdog::{name}: completed"),
+    let mut matchers = DefaultMatchers::default();
+    let threads = pipelines
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut pipeline)| {
+            let watchdog = Arc::clone(&pipeline.watchdog);
+            if pipeline.matcher.is_none() {
+                pipeline.matcher = Some(matchers.get(&watchdog));
+            }
+            statuses
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((watchdog.name.clone(), WatchdogStatus::Starting));
+            let tx = tx.clone();
+            let token = token.clone();
+            let statuses = Arc::clone(&statuses);
+            let observer = observer.clone();
+            let target = logging::watchdog_target(&watchdog.name);
+            std::thread::spawn(move || {
+                let failed = |e: Error| {
+                    error!(target: &target, "watchdog failed: {e}");
+                    let e = e.into_watchdog_error(&watchdog);
+                    if let Some(observer) = &observer {
+                        observer.error(&e);
+                    }
+                    WatchdogStatus::Failed(e)
+                };
+                let watching = || {
+                    statuses.lock().unwrap_or_else(PoisonError::into_inner)[i].1 =
+                        WatchdogStatus::Running;
+                };
+                let status = match watch(pipeline, &target, &token, observer.clone(), watching) {
+                    Ok(()) => {
+                        info!(target: &target, "watchdog::{}: completed", watchdog.name);
+                        if token.is_cancelled() {
+                            WatchdogStatus::Stopped
+                        } else {
+                            WatchdogStatus::Completed
+                        }
+                    }
+                    Err(e) => failed(e),
+                };
+                statuses.lock().unwrap_or_else(PoisonError::into_inner)[i].1 = status;
+                let _ = tx.send(());
+            })
+        })
+        .collect();
+
+    WatchdogHandle {
+        token,
+        statuses,
+        threads,
+        stopped: rx,
+    }
+}
+
+/// Runs every watchdog until they've all completed, or until one fails.
+///
+/// # Errors
+///
+/// The error of the first watchdog to fail, leaving the others running.
+pub fn run_blocking(settings: Settings) -> Result<(), WatchdogError> {
+    run_blocking_until(settings, CancellationToken::new())
+}
+
+/// Like [`run_blocking`], but the watchdogs also stop when `token` is
+/// cancelled, e.g. by a signal.
+///
+/// # Errors
+///
+/// The error of the first watchdog to fail, leaving the others running.
+pub fn run_blocking_until(
+    settings: Settings,
+    token: CancellationToken,
+) -> Result<(), WatchdogError> {
+    let result = first_failure(&run_until(settings, token));
+
+    #[cfg(feature = "otel")]
+    otel::shutdown();
+    log::logger().flush();
+    result
+}
+
+/// Like [`run_blocking`], but restarts the watchdogs with every settings
+/// received from `reloads`, e.g. when they change where they're kept. The
+/// running watchdogs are stopped before the new ones start, so lines appended
+/// in between aren't read.
+///
+/// # Errors
+///
+/// The error of the first watchdog to fail, stopping the others.
+pub fn run_reloading(
+    settings: Settings,
+    reloads: &Receiver<Settings>,
+) -> Result<(), WatchdogError> {
+    run_reloading_until(settings, reloads, &CancellationToken::new())
+}
+
+/// Like [`run_reloading`], but the watchdogs also stop, and aren't restarted,
+/// when `token` is cancelled, e.g. by a signal.
+///
+/// # Errors
+///
+/// The error of the first watchdog to fail, stopping the others.
+pub fn run_reloading_until(
+    mut settings: Settings,
+    reloads: &Receiver<Settings>,
+    token: &CancellationToken,
+) -> Result<(), WatchdogError> {
+    let result = loop {
+        // not `token`, which stopping them before a restart would cancel
+        let handle = run(settings);
+        match next_reload(&handle, reloads, token) {
+            Ok(Some(reloaded)) => {
+                info!("settings changed, restarting the watchdogs");
+                handle.stop();
+                handle.join();
+                settings = reloaded;
+            }
+            Ok(None) => break Ok(()),
             Err(e) => {
-                error!("watchdog failed: {e}");
-                std::process::exit(1);
+                handle.stop();
+                break Err(e);
             }
-        });
+        }
+    };
+
+    #[cfg(feature = "otel")]
+    otel::shutdown();
+    log::logger().flush();
+    result
+}
+
+/// Waits for settings from `reloads`, returning none if every watchdog
+/// stops first, or is stopped by `token`, or the error of the first one to
+/// fail.
+fn next_reload(
+    handle: &WatchdogHandle,
+    reloads: &Receiver<Settings>,
+    token: &CancellationToken,
+) -> Result<Option<Settings>, WatchdogError> {
+    loop {
+        if token.is_cancelled() {
+            handle.stop();
+            return first_failure(handle).map(|()| None);
+        }
+        match reloads.recv_timeout(STOP_INTERVAL) {
+            Ok(settings) => return Ok(Some(settings)),
+            Err(RecvTimeoutError::Disconnected) => return first_failure(handle).map(|()| None),
+            Err(RecvTimeoutError::Timeout) => (),
+        }
+        match handle.stopped.try_recv() {
+            Ok(()) => {
+                if let Some(e) = failure(handle) {
+                    return Err(e);
+                }
+            }
+            Err(TryRecvError::Disconnected) => return Ok(None),
+            Err(TryRecvError::Empty) => (),
+        }
     }
+}
 
-    // drop the last one so that we know when to exit
-    drop(tx);
+/// Like [`run_blocking`], but as a future, for applications on an async
+/// runtime. It doesn't depend on any runtime; the watchdogs still run on
+/// threads of their own.
+///
+/// Dropping the future stops the watchdogs.
+pub fn run_async(settings: Settings) -> RunFuture {
+    let token = CancellationToken::new();
+    let handle = run_until(settings, token.clone());
+    let (future, result) = RunFuture::new(token);
+    std::thread::spawn(move || result.send(first_failure(&handle)));
+    future
+}
 
-    for _ in rx.iter() {}
+/// Like [`events`], but as an [`EventStream`] of matches for async
+/// applications.
+pub fn events_async(settings: Settings) -> (WatchdogHandle, EventStream) {
+    let (stream, tx) = EventStream::new();
+    let handle = run_with(settings, move |event| tx.send(event));
+    (handle, stream)
 }
 
-fn watch(watchdog: Watchdog, _: Sender<()>) -> Result<String, Error> {
-    let watchdog_name = watchdog.name.clone();
-    info!("watchdog::{watchdog_name}: starting");
+/// Waits until every watchdog has stopped, or one has failed, returning its
+/// error.
+fn first_failure(handle: &WatchdogHandle) -> Result<(), WatchdogError> {
+    while handle.wait() {
+        if let Some(e) = failure(handle) {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
 
-    let (tx, rx) = std::sync::mpsc::channel();
+/// The error of a watchdog that failed, if any has.
+fn failure(handle: &WatchdogHandle) -> Option<WatchdogError> {
+    handle
+        .statuses()
+        .into_iter()
+        .find_map(|(_, status)| match status {
+            WatchdogStatus::Failed(e) => Some(e),
+            _ => None,
+        })
+}
+
+/// Scans every watchdog's log file once instead of tailing it, running the
+/// commands of every match, and returns when all are done.
+///
+/// Files are read from the beginning, or with a `state_file` from where the
+/// previous scan stopped, e.g. for batch checks from cron on hosts where a
+/// daemon isn't wanted.
+///
+/// # Errors
+///
+/// The first error of the watchdogs that failed, after every watchdog is
+/// done; the positions of the others are still saved.
+pub fn run_once(settings: Settings, state_file: Option<&Path>) -> Result<(), WatchdogError> {
+    run_once_until(settings, state_file, &CancellationToken::new())
+}
+
+/// Like [`run_once`], but the scans also stop when `token` is cancelled, e.g.
+/// by a signal, at the end of the chunk they're reading; the positions they
+/// stopped at are saved.
+///
+/// # Errors
+///
+/// The first error of the watchdogs that failed, after every watchdog is
+/// done; the positions of the others are still saved.
+pub fn run_once_until(
+    settings: Settings,
+    state_file: Option<&Path>,
+    token: &CancellationToken,
+) -> Result<(), WatchdogError> {
+    info!("starting log-watchdog, scanning log files once");
+    let mut positions = match state_file.map(Positions::load).transpose() {
+        Ok(positions) => positions.unwrap_or_default(),
+        Err(e) => {
+            error!("failed to load positions from {state_file:?}: {e}");
+            return Err(WatchdogError::Positions {
+                path: state_file.unwrap_or(Path::new("")).to_path_buf(),
+                source: Arc::new(e),
+            });
+        }
+    };
+
+    let mut matchers = DefaultMatchers::default();
+    let scans: Vec<_> = settings
+        .into_watchdogs()
+        .into_iter()
+        .map(|watchdog| {
+            let position = positions.get(&watchdog.name, &watchdog.log_file);
+            let watchdog = Arc::new(watchdog);
+            let scanned = Arc::clone(&watchdog);
+            let matcher = matchers.get(&watchdog);
+            let token = token.clone();
+            (
+                watchdog,
+                std::thread::spawn(move || {
+                    scan(&scanned, matcher.as_ref(), position, None, &token)
+                }),
+            )
+        })
+        .collect();
+
+    let mut result = Ok(());
+    for (watchdog, scan) in scans {
+        let (name, file) = (&watchdog.name, &watchdog.log_file);
+        let target = logging::watchdog_target(name);
+        let e = match scan.join() {
+            Ok(Ok(position)) => {
+                info!(target: &target, "watchdog::{name}: scanned {file:?} up to {position}");
+                positions.set(name, file, position);
+                continue;
+            }
+            Ok(Err(e)) => {
+                error!(target: &target, "watchdog failed: {e}");
+                e
+            }
+            Err(_) => Error::Io(std::io::Error::other("scan panicked")),
+        };
+        if result.is_ok() {
+            result = Err(e.into_watchdog_error(&watchdog));
+        }
+    }
+
+    if let Some(state_file) = state_file {
+        if let Err(e) = positions.save(state_file) {
+            error!("failed to save positions to {state_file:?}: {e}");
+            result = result.and(Err(WatchdogError::Positions {
+                path: state_file.to_path_buf(),
+                source: Arc::new(e),
+            }));
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    otel::shutdown();
+    log::logger().flush();
+    result
+}
 
-    let mut watcher = RecommendedWatcher::new(tx, Config::default())
-        .map_err(|e| Error::Watcher(watchdog.name.clone(), e))?;
+/// Matches the lines of the watchdog's log file from `position` on, returning
+/// the position it read up to.
+fn scan(
+    watchdog: &Arc<Watchdog>,
+    matcher: &dyn Matcher,
+    position: u64,
+    observer: Option<&dyn Observer>,
+    token: &CancellationToken,
+) -> Result<u64, Error> {
+    insert_mdc(watchdog);
+    let target = logging::watchdog_target(&watchdog.name);
 
-    watcher
-        .watch(&watchdog.log_file, notify::RecursiveMode::NonRecursive)
-        .map_err(|e| Error::Watcher(watchdog.name.clone(), e))?;
+    let mut tailer = Tailer::open_at(&watchdog.log_file, position)?;
+    // the file was truncated, or rotated, since the previous scan
+    if tailer.position() < position {
+        tailer = Tailer::open_at(&watchdog.log_file, 0)?;
+    }
+    let mut tailer = tailer
+        .read_buffer_size(watchdog.read_buffer_size)
+        .max_read_bytes(watchdog.max_read_bytes)
+        .encoding(watchdog.encoding);
 
+    let mut commands = Commands {
+        watchdog: Arc::clone(watchdog),
+        target: target.clone(),
+        observer: None,
+    };
+    // a chunk at a time, until it has caught up or is stopped
+    let mut read_error = None;
+    let mut more = true;
+    let lines = std::iter::from_fn(|| {
+        if !more || token.is_cancelled() {
+            return None;
+        }
+        match tailer.poll_lines() {
+            Ok(lines) => {
+                more = tailer.is_behind();
+                Some(lines)
+            }
+            Err(e) => {
+                read_error = Some(e);
+                None
+            }
+        }
+    });
+    match_log_entries(watchdog, &target, lines, matcher, &mut commands, observer)?;
+    if let Some(e) = read_error {
+        return Err(e.into());
+    }
+
+    Ok(tailer.position())
+}
+
+/// Calls `line` with every line appended to the file from now on, without
+/// matching or running anything, e.g. for `tail -f`, until `token` is
+/// cancelled.
+///
+/// # Errors
+///
+/// If the file can't be watched or read.
+pub fn tail(
+    path: &Path,
+    token: &CancellationToken,
+    mut line: impl FnMut(String),
+) -> std::io::Result<()> {
+    let backend = watcher::polled_filesystem(path).map(|filesystem| {
+        warn!(
+            "{:?} is on {filesystem}, which may not notify of changes, polling it instead",
+            path.as_os_str()
+        );
+        WatcherBackend::Poll
+    });
+    let poll_interval = Duration::from_millis(settings::DEFAULT_POLL_INTERVAL);
+    let mut source = FileSource::open_with_backend(path, backend, poll_interval)
+        .map_err(std::io::Error::other)?;
+    while !token.is_cancelled() {
+        match source.read(STOP_INTERVAL) {
+            Ok(Some(lines)) => lines
+                .iter()
+                .for_each(|read| line(String::from_utf8_lossy(read).into_owned())),
+            Ok(None) => break,
+            // like `tail -F`, the file is followed once it's recreated
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// The watchdog's `backend`, or else polling for a log file on a filesystem
+/// that may not notify of its changes, which is logged, since it's read with
+/// latency.
+fn backend(watchdog: &Watchdog, target: &str) -> Option<WatcherBackend> {
+    watchdog.backend.or_else(|| {
+        let filesystem = watcher::polled_filesystem(&watchdog.log_file)?;
+        warn!(
+            target: target,
+            "watchdog::{}: {:?} is on {filesystem}, which may not notify of changes, polling it every {}ms instead; set its backend to keep notifications",
+            watchdog.name,
+            watchdog.log_file.as_os_str(),
+            watchdog.poll_interval
+        );
+        Some(WatcherBackend::Poll)
+    })
+}
+
+/// Matches the lines appended to the watchdog's log file until the watchdog
+/// completes, fails or `token` is cancelled. `watching` is called once the
+/// file is watched.
+fn watch(
+    pipeline: WatchdogPipeline,
+    target: &str,
+    token: &CancellationToken,
+    observer: Option<Arc<dyn Observer>>,
+    watching: impl FnOnce(),
+) -> Result<(), Error> {
+    let WatchdogPipeline {
+        watchdog,
+        source,
+        matcher,
+        action,
+    } = pipeline;
+    insert_mdc(&watchdog);
+    info!(target: target, "watchdog::{}: starting", watchdog.name);
+
+    let mut source = match source {
+        Some(source) => source,
+        None => Box::new(
+            FileSource::open_with_backend(
+                &watchdog.log_file,
+                backend(&watchdog, target),
+                Duration::from_millis(watchdog.poll_interval),
+            )
+            .map(|source| match watchdog.partial_line_timeout {
+                Some(timeout) => source.partial_line_timeout(Duration::from_millis(timeout)),
+                None => source,
+            })
+            .map_err(|e| Error::Watcher(watchdog.name.clone(), e))?
+            .read_buffer_size(watchdog.read_buffer_size)
+            .max_read_bytes(watchdog.max_read_bytes)
+            .encoding(watchdog.encoding),
+        ),
+    };
     info!(
-        "watchdog::{watchdog_name}: watching {:?}",
+        target: target,
+        "watchdog::{}: watching {:?}",
+        watchdog.name,
         &watchdog.log_file.as_os_str()
     );
-    let mut log_file = File::open(&watchdog.log_file).unwrap();
-    let mut position = log_file.seek(SeekFrom::End(0)).unwrap();
+    watching();
 
     /*
-        The linesender is used whenever there's a Modify event on our log file
-        The linereceiver parses each line and acts on it. It handles eventual
-        debouncing or one-shot functionality.
-
-        To track whether the current thread should complete (outside of errors, ofc),
-        we give the linereceiver a close flag -- because the linereceiver knows
-        when to exit.
+        The source's new lines are matched and acted on as they're read, in
+        batches of what it read at once, on this thread: a watchdog is a
+        single loop, and the file events of every watchdog come from one
+        shared watcher, so that hundreds of watchdogs don't cost hundreds of
+        threads. Matching handles eventual debouncing or one-shot
+        functionality, and returns when the watchdog is done (a oneshot match,
+        or an error).
     */
-    let (linesender, linereceiver) = std::sync::mpsc::channel::<String>();
-    let (close_flag, close_receiver) = std::sync::mpsc::channel::<()>();
-    std::thread::spawn(
-        move || match match_log_entries(watchdog, linereceiver, close_flag) {
-            Ok(name) => info!("watchdog::{name}: match_log_entries completed"),
-            Err(e) => error!("match_log_entries failed: {e}"),
-        },
-    );
-
-    for res in rx {
-        if is_closed(&close_receiver) {
-            break;
-        }
-        match res {
-            Ok(event) => match event.kind {
-                notify::EventKind::Modify(_) => {
-                    read_new_lines(&mut log_file, &mut position, linesender.clone())?;
+    let matcher = matcher.unwrap_or_else(|| DefaultMatchers::default().get(&watchdog));
+    let mut action = action.unwrap_or_else(|| {
+        Box::new(Commands {
+            watchdog: Arc::clone(&watchdog),
+            target: target.to_string(),
+            observer: observer.clone(),
+        })
+    });
+    let mut read_error = None;
+    let lines = std::iter::from_fn(|| {
+        while !token.is_cancelled() {
+            match source.read(STOP_INTERVAL) {
+                // none too, for a debounce to expire while the log is quiet
+                Ok(lines) => return lines,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    if !file_missing(&watchdog, target, observer.as_deref(), &e) {
+                        read_error = Some(e);
+                        return None;
+                    }
+                }
+                Err(e) => {
+                    read_error = Some(e);
+                    return None;
                 }
-                notify::EventKind::Any
-                | notify::EventKind::Access(_)
-                | notify::EventKind::Create(_)
-                | notify::EventKind::Remove(_)
-                | notify::EventKind::Other => (), // do nothing on these events for now,
-            },
-            Err(e) => {
-                return Err(Error::Watcher(watchdog_name, e));
             }
         }
-    }
+        None
+    });
 
-    Ok(watchdog_name)
+    let matched = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        match_log_entries(
+            &watchdog,
+            target,
+            lines,
+            matcher.as_ref(),
+            action.as_mut(),
+            observer.as_deref(),
+        )
+    }))
+    .unwrap_or_else(|_| Err(Error::Io(std::io::Error::other("matching panicked"))));
+    matched?;
+    read_error.map_or(Ok(()), |e| Err(e.into()))
+}
+
+/// Runs the watchdog's `on_file_missing` commands for its removed log file,
+/// returning whether it waits for the file to be recreated.
+fn file_missing(
+    watchdog: &Watchdog,
+    target: &str,
+    observer: Option<&dyn Observer>,
+    missing: &std::io::Error,
+) -> bool {
+    warn!(target: target, "watchdog::{}: {missing}", watchdog.name);
+    if let Err(e) = run_commands(
+        watchdog,
+        &watchdog.on_file_missing,
+        None,
+        target,
+        observer,
+        &mut Vec::new(),
+    ) {
+        error!(target: target, "watchdog::{}: on_file_missing: {e}", watchdog.name);
+    }
+    match watchdog.file_missing {
+        FileMissing::Wait => {
+            info!(
+                target: target,
+                "watchdog::{}: waiting for {:?} to be recreated",
+                watchdog.name,
+                watchdog.log_file.as_os_str()
+            );
+            true
+        }
+        FileMissing::Stop => {
+            info!(target: target, "watchdog::{}: stopping, its log file is missing", watchdog.name);
+            false
+        }
+    }
 }
 
 fn match_log_entries(
-    watchdog: Watchdog,
-    linereceiver: Receiver<String>,
-    _close_flag: Sender<()>,
-) -> Result<String, Error> {
+    watchdog: &Watchdog,
+    target: &str,
+    lines: impl IntoIterator<Item = Lines>,
+    matcher: &dyn Matcher,
+    action: &mut dyn Action,
+    observer: Option<&dyn Observer>,
+) -> Result<(), Error> {
     let mut last_match = Instant::now();
     let debounce_duration = Duration::from_millis(watchdog.debounce);
+    // when the last match was acted on, and the matches since that weren't:
+    // for the next event, and for the summary once the debounce expires
+    let mut last_acted: Option<Instant> = None;
+    let mut suppressed = 0;
+    let mut unsummarized = 0;
+    let mut sampler = Sampler::new(watchdog.log_sample_rate, MATCH_SUMMARY_INTERVAL);
+
+    let mut output = Output::open(watchdog)?;
+    #[cfg(feature = "alertmanager")]
+    let mut alerts = watchdog
+        .alertmanager
+        .as_ref()
+        .map(|alertmanager| alertmanager::Alerts::new(&watchdog.name, alertmanager))
+        .transpose()?;
+    #[cfg(not(feature = "alertmanager"))]
+    if watchdog.alertmanager.is_some() {
+        return Err(Error::Io(std::io::Error::other(
+            "alertmanager needs log-watchdog built with the alertmanager feature",
+        )));
+    }
+    #[cfg(not(feature = "zabbix"))]
+    if watchdog.zabbix.is_some() {
+        return Err(Error::Io(std::io::Error::other(
+            "zabbix needs log-watchdog built with the zabbix feature",
+        )));
+    }
+
+    let mut check_results = watchdog
+        .passive_check
+        .as_ref()
+        .map(passive_check::CheckResults::new);
 
-    let mut out_file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(&watchdog.output_file)?;
+    let debounce_expired = |last_acted: Option<Instant>| {
+        last_acted.is_none_or(|acted| acted.elapsed() >= debounce_duration)
+    };
 
-    for line in linereceiver.iter() {
-        if last_match.elapsed() >= debounce_duration {
-            last_match = Instant::now();
-            if watchdog.regex.is_match(&line) {
-                execute_commands(&watchdog.commands, &mut out_file)?;
+    for lines in lines {
+        if let Some(check_results) = &mut check_results {
+            recover_check(watchdog, target, check_results);
+        }
+        if unsummarized > 0 && debounce_expired(last_acted) {
+            summarize_suppressed(
+                watchdog,
+                target,
+                &mut output,
+                std::mem::take(&mut unsummarized),
+            )?;
+        }
+        for line in lines.iter() {
+            if let Some(observer) = observer {
+                observer.line_read(&watchdog.name, &String::from_utf8_lossy(line));
+            }
+            if watchdog.debounce_mode == DebounceMode::SkipLines {
+                if last_match.elapsed() < debounce_duration {
+                    continue;
+                }
+                last_match = Instant::now();
+            }
+            if let Some(captures) = matcher.captures_bytes(line) {
+                if watchdog.debounce_mode == DebounceMode::SuppressCommands
+                    && !debounce_expired(last_acted)
+                {
+                    suppressed += 1;
+                    unsummarized += 1;
+                    debug!(
+                        target: target,
+                        "watchdog::{}: match suppressed by debounce: {}",
+                        watchdog.name,
+                        watchdog.redact.redact(&String::from_utf8_lossy(line))
+                    );
+                    continue;
+                }
+                if unsummarized > 0 {
+                    summarize_suppressed(
+                        watchdog,
+                        target,
+                        &mut output,
+                        std::mem::take(&mut unsummarized),
+                    )?;
+                }
+                last_acted = Some(Instant::now());
+
+                // only matching lines are converted, the others stay in the read buffer
+                let line = String::from_utf8_lossy(line).into_owned();
+                let match_id = next_match_id();
+                let _match = log_mdc::insert_scoped("match_id", match_id.to_string());
+                if sampler.sample() {
+                    info!(
+                        target: target,
+                        "watchdog::{}: match {match_id}: {}",
+                        watchdog.name,
+                        watchdog.redact.redact(&line)
+                    );
+                }
+                if let Some(skipped) = sampler.summary() {
+                    info!(
+                        target: target,
+                        "watchdog::{}: {skipped} more matches not logged, 1 in {} is",
+                        watchdog.name,
+                        watchdog.log_sample_rate
+                    );
+                }
+                #[cfg(feature = "otel")]
+                otel::start_match(&watchdog.name, &watchdog.log_file, match_id);
+
+                let event = MatchEvent {
+                    watchdog: watchdog.name.clone(),
+                    log_file: watchdog.log_file.clone(),
+                    line,
+                    captures,
+                    timestamp: SystemTime::now(),
+                    match_id,
+                    suppressed: std::mem::take(&mut suppressed),
+                };
+                if let Some(observer) = observer {
+                    observer.matched(&event);
+                }
+                #[cfg(feature = "alertmanager")]
+                if let Some(alerts) = &mut alerts {
+                    fire_alert(watchdog, target, alerts, &event);
+                }
+                if let Some(check_results) = &mut check_results {
+                    submit_check_result(watchdog, target, check_results, &event);
+                }
+                #[cfg(feature = "zabbix")]
+                if let Some(zabbix) = &watchdog.zabbix {
+                    push_to_zabbix(watchdog, target, zabbix, &event);
+                }
+                let mut command_outputs = Vec::new();
+                let result = action.run(&event, &mut command_outputs);
+                #[cfg(feature = "otel")]
+                otel::end_match(result.is_err());
+                // the output of the commands that ran before a failing one is still kept
+                output.write(&MatchRecord {
+                    timestamp: event.timestamp,
+                    watchdog: &watchdog.name,
+                    line: &event.line,
+                    captures: if output.needs_captures() {
+                        event.captures
+                    } else {
+                        Vec::new()
+                    },
+                    commands: &command_outputs,
+                })?;
+                result?;
 
                 if watchdog.oneshot {
-                    break;
+                    return Ok(());
                 }
             }
         }
     }
 
-    Ok(watchdog.name)
+    // stopped before the debounce expired
+    if unsummarized > 0 {
+        summarize_suppressed(watchdog, target, &mut output, unsummarized)?;
+    }
+    Ok(())
+}
+
+/// Sends the watchdog's alert for the match, unless it's a dry run. An alert
+/// that can't be sent is logged, and doesn't stop the watchdog.
+#[cfg(feature = "alertmanager")]
+fn fire_alert(
+    watchdog: &Watchdog,
+    target: &str,
+    alerts: &mut alertmanager::Alerts,
+    event: &MatchEvent,
+) {
+    if watchdog.dry_run {
+        info!(target: target, "watchdog::{}: dry run, not sending alert", watchdog.name);
+    } else if let Err(e) = alerts.fire(event, &watchdog.redact) {
+        error!(target: target, "watchdog::{}: alertmanager: {e}", watchdog.name);
+    }
+}
+
+/// Submits the watchdog's passive check result for the match, unless it's a
+/// dry run. A result that can't be submitted is logged, and doesn't stop the
+/// watchdog.
+fn submit_check_result(
+    watchdog: &Watchdog,
+    target: &str,
+    check_results: &mut passive_check::CheckResults,
+    event: &MatchEvent,
+) {
+    if watchdog.dry_run {
+        info!(target: target, "watchdog::{}: dry run, not submitting check result", watchdog.name);
+    } else if let Err(e) = check_results.submit(event, &watchdog.redact) {
+        error!(target: target, "watchdog::{}: passive_check: {e}", watchdog.name);
+    }
 }
 
-fn execute_commands(commands: &[settings::Command], out_file: &mut File) -> Result<(), Error> {
+/// Pushes the match to the watchdog's Zabbix trapper item, unless it's a dry
+/// run. A value that can't be pushed is logged, and doesn't stop the watchdog.
+#[cfg(feature = "zabbix")]
+fn push_to_zabbix(
+    watchdog: &Watchdog,
+    target: &str,
+    zabbix: &settings::Zabbix,
+    event: &MatchEvent,
+) {
+    if watchdog.dry_run {
+        info!(target: target, "watchdog::{}: dry run, not pushing to zabbix", watchdog.name);
+    } else if let Err(e) = zabbix::send(zabbix, event, &watchdog.redact) {
+        error!(target: target, "watchdog::{}: zabbix: {e}", watchdog.name);
+    }
+}
+
+/// Recovers the watchdog's passive check, if it hasn't matched for long
+/// enough since its last result.
+fn recover_check(
+    watchdog: &Watchdog,
+    target: &str,
+    check_results: &mut passive_check::CheckResults,
+) {
+    match check_results.recover_if_due(&watchdog.name) {
+        Ok(true) => info!(target: target, "watchdog::{}: passive check recovered", watchdog.name),
+        Ok(false) => (),
+        Err(e) => error!(target: target, "watchdog::{}: passive_check: {e}", watchdog.name),
+    }
+}
+
+/// Logs how many matches the debounce that expired suppressed, and writes it
+/// to the outputs with `output_suppressed`.
+fn summarize_suppressed(
+    watchdog: &Watchdog,
+    target: &str,
+    output: &mut Output,
+    suppressed: u64,
+) -> Result<(), Error> {
+    info!(
+        target: target,
+        "watchdog::{}: {suppressed} matches suppressed by debounce",
+        watchdog.name
+    );
+    Ok(output.write_suppressed(SystemTime::now(), suppressed)?)
+}
+
+fn execute_commands(
+    watchdog: &Watchdog,
+    line: &str,
+    target: &str,
+    observer: Option<&dyn Observer>,
+    outputs: &mut Vec<CommandOutput>,
+) -> Result<(), Error> {
+    run_commands(
+        watchdog,
+        &watchdog.commands,
+        Some(line),
+        target,
+        observer,
+        outputs,
+    )
+}
+
+/// Runs the commands in order, like [`execute_commands`] does the watchdog's
+/// for the line that matched, recording each in the audit log if there is
+/// one. The watchdog's commands are sent to its executor instead, if it has
+/// one, for the line, and its `on_file_missing` commands without one.
+fn run_commands(
+    watchdog: &Watchdog,
+    commands: &[settings::Command],
+    line: Option<&str>,
+    target: &str,
+    observer: Option<&dyn Observer>,
+    outputs: &mut Vec<CommandOutput>,
+) -> Result<(), Error> {
+    if let Some(executor) = watchdog.executor.as_deref().filter(|_| !watchdog.dry_run) {
+        debug!(target: target, "sending commands to the executor at {executor:?}");
+        let sent = outputs.len();
+        let result = executor::run_commands(executor, watchdog, line, outputs);
+        if let Some(observer) = observer {
+            for (command, output) in commands.iter().zip(&outputs[sent..]) {
+                observer.command_started(&watchdog.name, command);
+                observer.command_finished(
+                    &watchdog.name,
+                    command,
+                    output.exit_code,
+                    output.duration,
+                );
+            }
+        }
+        return result;
+    }
     for command in commands {
-        let output = Command::new(&command.name).args(&command.args).output()?;
+        let _command = log_mdc::insert_scoped("command", &command.name);
+        if watchdog.dry_run {
+            info!(
+                target: target,
+                "dry run, would run: {}",
+                watchdog.redact.redact(&replay::command_line(command))
+            );
+            continue;
+        }
+        // checked again, in case the program was replaced since the settings
+        // were loaded
+        let program = match &watchdog.allowed_commands {
+            Some(allowed_commands) => allowed_commands.check_command(command).map_err(|e| {
+                Error::NotAllowed(match e {
+                    SettingsError::CommandNotAllowed(denied) => denied,
+                    _ => command.program().to_string(),
+                })
+            })?,
+            None => PathBuf::from(command.program()),
+        };
+        debug!(
+            target: target,
+            "running command {}",
+            watchdog.redact.redact(&replay::command_line(command))
+        );
+        #[cfg(feature = "otel")]
+        let start = std::time::SystemTime::now();
+        if let Some(observer) = observer {
+            observer.command_started(&watchdog.name, command);
+        }
+        let started = Instant::now();
+        let mut process = match &command.container {
+            Some(container) => {
+                sandbox::container(&program, &command.name, &command.args, container)
+            }
+            None => sandbox::command(&program, &command.args, command.sandbox.as_ref()),
+        };
+        let output = {
+            let _slot = concurrency::acquire(watchdog.max_concurrent_commands, target);
+            init::output(&mut process)?
+        };
+        debug!(target: target, "command {} exited with {}", command.name, output.status);
+        if let Some(observer) = observer {
+            observer.command_finished(
+                &watchdog.name,
+                command,
+                output.status.code(),
+                started.elapsed(),
+            );
+        }
+        #[cfg(feature = "otel")]
+        otel::record_command(
+            &command.name,
+            start,
+            output.status.code(),
+            output.status.success(),
+        );
+
+        if let Some(audit_log) = &watchdog.audit_log {
+            // what was run on the host, e.g. the container runtime's command
+            // line
+            let argv = std::iter::once(process.get_program())
+                .chain(process.get_args())
+                .map(|arg| watchdog.redact.redact(&arg.to_string_lossy()).into_owned())
+                .collect();
+            audit::record(
+                audit_log,
+                &audit::Execution {
+                    watchdog: &watchdog.name,
+                    argv,
+                    exit_code: output.status.code(),
+                    line,
+                },
+            )?;
+        }
+
+        // a failing command is recorded too, so the output shows how far
+        // remediation got
+        outputs.push(CommandOutput::new(
+            &command.name,
+            &output,
+            started.elapsed(),
+        ));
 
         if !output.status.success() {
             let error = String::from_utf8_lossy(&output.stderr);
             return Err(Error::Command(
                 command.name.clone(),
                 output.status.code(),
-                error.to_string(),
+                watchdog.redact.redact(&error).into_owned(),
             ));
         }
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        writeln!(out_file, "{}", stdout)?;
     }
 
     Ok(())
 }
 
-fn is_closed(chan: &Receiver<()>) -> bool {
-    match chan.try_recv() {
-        Ok(_) => false,
-        Err(TryRecvError::Disconnected) => true,
-        Err(TryRecvError::Empty) => false,
-    }
-}
-
-fn read_new_lines(file: &mut File, position: &mut u64, tx: Sender<String>) -> Result<(), Error> {
-    let mut reader = BufReader::new(file);
-
-    reader.seek(SeekFrom::Start(*position))?;
+/// Identifies a single match, so the logs of the commands it ran can be
+/// correlated across concurrent watchdogs.
+static MATCH_ID: AtomicU64 = AtomicU64::new(1);
 
-    for line in reader.lines() {
-        let line = line.map_err(Error::Io)?;
-        *position += line.len() as u64 + 1;
-        tx.send(line).map_err(Error::Send)?;
-    }
+fn next_match_id() -> u64 {
+    MATCH_ID.fetch_add(1, Ordering::Relaxed)
+}
 
-    Ok(())
+/// Tags every log record from the current thread with the watchdog's name and
+/// log file, so they can be filtered on as structured fields. Matches and
+/// command executions add scoped `match_id` and `command` fields on top.
+///
+/// Records are also logged with the watchdog's own target, see
+/// [`logging::watchdog_target`].
+fn insert_mdc(watchdog: &Watchdog) {
+    log_mdc::insert("watchdog", &watchdog.name);
+    log_mdc::insert("file", watchdog.log_file.to_string_lossy());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::{Seek, SeekFrom, Write};
 
     #[test]
-    fn test_read_new_lines_from_0() {
-        let dir = tempdir::TempDir::new("test_read_new_lines").unwrap();
-        let mut file = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(dir.path().join("test_read_new_lines.txt"))
+    fn test_when_dry_run_then_commands_not_run() {
+        let dir = tempdir::TempDir::new("test_dry_run").unwrap();
+        let touched = dir.path().join("touched");
+        let mut watchdog = Watchdog::builder()
+            .name("test")
+            .log_file("test.log")
+            .regex(".*")
+            .command("touch", [touched.to_str().unwrap()])
+            .dry_run(true)
+            .build()
             .unwrap();
 
-        writeln!(file, "Hello, world!").unwrap();
-        writeln!(file, "Goodbye, world!").unwrap();
-
-        let mut position = 0;
-        file.seek(SeekFrom::Start(position)).unwrap();
+        let mut outputs = Vec::new();
+        execute_commands(&watchdog, "line", "test", None, &mut outputs).unwrap();
+        assert!(outputs.is_empty());
+        assert!(!touched.exists());
 
-        let expected_lines = vec!["Hello, world!", "Goodbye, world!"];
-        let expected_position = file.seek(SeekFrom::End(0)).unwrap();
+        watchdog.dry_run = false;
+        execute_commands(&watchdog, "line", "test", None, &mut outputs).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert!(touched.exists());
+    }
 
-        let (tx, rx) = std::sync::mpsc::channel();
-        read_new_lines(&mut file, &mut position, tx).unwrap();
+    struct Recorded(Vec<MatchEvent>);
 
-        let actual_lines = rx.iter().collect::<Vec<String>>();
+    impl Action for Recorded {
+        fn run(
+            &mut self,
+            event: &MatchEvent,
+            _outputs: &mut Vec<CommandOutput>,
+        ) -> Result<(), WatchdogError> {
+            self.0.push(event.clone());
+            Ok(())
+        }
+    }
 
-        assert_eq!(actual_lines, expected_lines);
-        assert_eq!(position, expected_position);
+    fn debounced(mode: DebounceMode, lines: Vec<Vec<&str>>) -> Vec<MatchEvent> {
+        let watchdog = Watchdog::builder()
+            .name("debounced")
+            .log_file("debounced.log")
+            .regex("^aaa")
+            .debounce(50)
+            .debounce_mode(mode)
+            .build()
+            .unwrap();
+        let batches = lines.into_iter().enumerate().map(|(i, batch)| {
+            if i > 0 {
+                std::thread::sleep(Duration::from_millis(60));
+            }
+            Lines::from(batch.into_iter().map(String::from).collect::<Vec<_>>())
+        });
+        let mut recorded = Recorded(Vec::new());
+        match_log_entries(
+            &watchdog,
+            "test",
+            batches,
+            &watchdog.regex,
+            &mut recorded,
+            None,
+        )
+        .unwrap();
+        recorded.0
     }
 
     #[test]
-    fn test_read_new_lines_from_position() {
-        let dir = tempdir::TempDir::new("test_read_new_lines").unwrap();
-        let mut file = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(dir.path().join("test_read_new_lines.txt"))
-            .unwrap();
+    fn test_when_debounced_then_matches_counted_but_not_acted_on() {
+        let events = debounced(
+            DebounceMode::SuppressCommands,
+            vec![vec!["aaa 1", "bbb", "aaa 2", "aaa 3"], vec!["aaa 4"]],
+        );
 
-        writeln!(file, "Hello, world!").unwrap();
+        let lines: Vec<_> = events
+            .iter()
+            .map(|e| (e.line.as_str(), e.suppressed))
+            .collect();
+        assert_eq!(lines, [("aaa 1", 0), ("aaa 4", 2)]);
+    }
 
-        let mut position = file.seek(SeekFrom::End(0)).unwrap();
+    #[test]
+    fn test_when_debounce_expires_then_suppressed_matches_summarized() {
+        let dir = tempdir::TempDir::new("test_suppressed").unwrap();
+        let out = dir.path().join("suppressed.out");
+        let watchdog = Watchdog::builder()
+            .name("suppressed")
+            .log_file("suppressed.log")
+            .regex("^aaa")
+            .debounce(50)
+            .output_file(&out)
+            .file_options(settings::FileOptions {
+                suppressed: true,
+                ..settings::FileOptions::default()
+            })
+            .build()
+            .unwrap();
+        // the log is quiet once the debounce expires
+        let batches = [vec!["aaa 1", "aaa 2", "aaa 3"], vec![]]
+            .into_iter()
+            .enumerate()
+            .map(|(i, batch)| {
+                if i > 0 {
+                    std::thread::sleep(Duration::from_millis(60));
+                }
+                Lines::from(batch.into_iter().map(String::from).collect::<Vec<_>>())
+            });
 
-        writeln!(file, "Goodbye, world!").unwrap();
-        let (tx, rx) = std::sync::mpsc::channel();
+        match_log_entries(
+            &watchdog,
+            "test",
+            batches,
+            &watchdog.regex,
+            &mut Recorded(Vec::new()),
+            None,
+        )
+        .unwrap();
 
-        let expected_lines = vec!["Goodbye, world!"];
-        let expected_position = file.seek(SeekFrom::End(0)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "2 matches suppressed by debounce\n"
+        );
+    }
 
-        read_new_lines(&mut file, &mut position, tx).unwrap();
-        let actual_lines = rx.iter().collect::<Vec<String>>();
+    #[test]
+    fn test_when_debounce_skips_lines_then_lines_not_matched() {
+        let events = debounced(
+            DebounceMode::SkipLines,
+            vec![vec!["aaa 1"], vec!["aaa 2", "aaa 3"]],
+        );
 
-        assert_eq!(actual_lines, expected_lines);
-        assert_eq!(position, expected_position);
+        // the first line is within the debounce of the start, too
+        let lines: Vec<_> = events.iter().map(|e| e.line.as_str()).collect();
+        assert_eq!(lines, ["aaa 2"]);
     }
 }
@@ -0,0 +1,285 @@
+//! Grafana Loki: `loki:<url>` outputs push match records to its push API,
+//! and [`LokiSource`] reads the lines of a LogQL query as a pipeline source.
+
+use std::{
+    io,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use settings::Tls;
+
+use crate::{
+    http::{self, Url},
+    pipeline::Source,
+    Lines,
+};
+
+/// How long a request to Loki may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most lines a query returns at once, Loki's default `max_entries_limit_per_query`.
+const QUERY_LIMIT: usize = 5000;
+
+fn url(url: &str) -> io::Result<Url> {
+    Url::from_str(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+/// The host's name, for the `host` label of pushed records.
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|hostname| hostname.trim().to_string())
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "localhost".into())
+}
+
+/// Pushes a watchdog's records to Loki, as a stream labeled with the
+/// watchdog and host.
+pub(crate) struct LokiPush {
+    url: Url,
+    labels: Value,
+}
+
+impl LokiPush {
+    pub(crate) fn new(url: &str, tls: &Tls, watchdog: &str) -> io::Result<Self> {
+        Ok(Self {
+            url: self::url(url)?.join("loki/api/v1/push").with_tls(tls),
+            labels: json!({ "watchdog": watchdog, "host": hostname() }),
+        })
+    }
+
+    /// Pushes every message as an entry of the stream, timestamped now.
+    pub(crate) fn push(&self, messages: &[(Option<&str>, String)]) -> io::Result<()> {
+        let timestamp = unix_nanos(SystemTime::now()).to_string();
+        let values: Vec<_> = messages
+            .iter()
+            .map(|(_, message)| json!([timestamp, message]))
+            .collect();
+        let body = json!({ "streams": [{ "stream": self.labels, "values": values }] });
+        match http::post(
+            &self.url,
+            &[],
+            "application/json",
+            body.to_string().as_bytes(),
+            TIMEOUT,
+        )? {
+            status if (200..300).contains(&status) => Ok(()),
+            status => Err(io::Error::other(format!(
+                "loki push to {:?} failed with status {status}",
+                self.url
+            ))),
+        }
+    }
+}
+
+/// The lines of a LogQL query, e.g. `{app="pgbouncer"} |= "error"`, read by
+/// polling Loki's `query_range` API for those logged since the last poll.
+///
+/// Reading starts with the lines logged after the source is created. Lines
+/// are read in the order of their timestamps, across the query's streams.
+pub struct LokiSource {
+    url: Url,
+    query: String,
+    start: u128,
+    poll_interval: Duration,
+    polled: Option<Instant>,
+}
+
+impl LokiSource {
+    /// A source querying the Loki at `url`, e.g. `http://localhost:3100`,
+    /// every second.
+    ///
+    /// # Errors
+    ///
+    /// If the URL isn't an `http://` or `https://` URL.
+    pub fn new(url: &str, query: impl Into<String>) -> io::Result<Self> {
+        Ok(Self {
+            url: self::url(url)?.join("loki/api/v1/query_range"),
+            query: query.into(),
+            start: unix_nanos(SystemTime::now()),
+            poll_interval: Duration::from_secs(1),
+            polled: None,
+        })
+    }
+
+    /// Queries an `https://` Loki with the TLS options, e.g. a client
+    /// certificate.
+    #[must_use]
+    pub fn tls(mut self, tls: &Tls) -> Self {
+        self.url = self.url.with_tls(tls);
+        self
+    }
+
+    /// Queries Loki this often, instead of every second.
+    #[must_use]
+    pub const fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    fn query(&mut self) -> io::Result<Vec<String>> {
+        let path = format!(
+            "{}?query={}&start={}&direction=forward&limit={QUERY_LIMIT}",
+            self.url.path,
+            percent_encode(&self.query),
+            self.start,
+        );
+        let (status, body) = http::get(&self.url, &path, &[], TIMEOUT)?;
+        if !(200..300).contains(&status) {
+            return Err(io::Error::other(format!(
+                "loki query failed with status {status}: {}",
+                String::from_utf8_lossy(&body).trim()
+            )));
+        }
+        let response: Value = serde_json::from_slice(&body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut entries = parse_entries(&response).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid loki query response")
+        })?;
+        entries.sort_by_key(|(timestamp, _)| *timestamp);
+        if let Some((last, _)) = entries.last() {
+            self.start = last + 1;
+        }
+        Ok(entries.into_iter().map(|(_, line)| line).collect())
+    }
+}
+
+impl Source for LokiSource {
+    fn read(&mut self, timeout: Duration) -> io::Result<Option<Lines>> {
+        if let Some(polled) = self.polled {
+            let next = self.poll_interval.saturating_sub(polled.elapsed());
+            if !next.is_zero() {
+                std::thread::sleep(next.min(timeout));
+                return Ok(Some(Lines::default()));
+            }
+        }
+        self.polled = Some(Instant::now());
+        self.query().map(|lines| Some(Lines::from(lines)))
+    }
+}
+
+/// The `[timestamp, line]` values of every stream of a `streams` result.
+fn parse_entries(response: &Value) -> Option<Vec<(u128, String)>> {
+    let mut entries = Vec::new();
+    for stream in response.get("data")?.get("result")?.as_array()? {
+        for value in stream.get("values")?.as_array()? {
+            let timestamp = value.get(0)?.as_str()?.parse().ok()?;
+            let line = value.get(1)?.as_str()?;
+            entries.push((timestamp, line.to_string()));
+        }
+    }
+    Some(entries)
+}
+
+/// Encodes a query parameter, leaving only unreserved characters as they are.
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    /// Answers one request with `response`, returning the request's line and body.
+    fn serve_once(response: &'static str) -> (String, std::thread::JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(length) = header.strip_prefix("Content-Length: ") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream).write_all(response.as_bytes()).unwrap();
+            (request, String::from_utf8(body).unwrap())
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_when_pushed_then_records_sent_as_stream_of_watchdog() {
+        let (url, server) = serve_once("HTTP/1.1 204 No Content\r\n\r\n");
+
+        LokiPush::new(&url, &Tls::default(), "pgbouncer")
+            .unwrap()
+            .push(&[(None, r#"{"watchdog":"pgbouncer"}"#.into())])
+            .unwrap();
+
+        let (request, body) = server.join().unwrap();
+        assert_eq!(request, "POST /loki/api/v1/push HTTP/1.1\r\n");
+        let body: Value = serde_json::from_str(&body).unwrap();
+        let stream = &body["streams"][0];
+        assert_eq!(stream["stream"]["watchdog"], "pgbouncer");
+        assert!(stream["stream"]["host"].is_string());
+        assert_eq!(stream["values"][0][1], r#"{"watchdog":"pgbouncer"}"#);
+    }
+
+    #[test]
+    fn test_when_push_rejected_then_error() {
+        let (url, server) = serve_once("HTTP/1.1 400 Bad Request\r\n\r\n");
+
+        let pushed = LokiPush::new(&url, &Tls::default(), "pgbouncer")
+            .unwrap()
+            .push(&[(None, "record".into())]);
+
+        server.join().unwrap();
+        assert!(pushed.is_err());
+    }
+
+    #[test]
+    fn test_when_queried_then_lines_read_in_timestamp_order() {
+        let (url, server) = serve_once(concat!(
+            "HTTP/1.1 200 OK\r\n\r\n",
+            r#"{"status":"success","data":{"resultType":"streams","result":["#,
+            r#"{"stream":{"app":"a"},"values":[["30","third"],["10","first"]]},"#,
+            r#"{"stream":{"app":"b"},"values":[["20","second"]]}]}}"#,
+        ));
+        let mut source = LokiSource::new(&url, r#"{app=~"a|b"} |= "error""#).unwrap();
+
+        let lines = source.read(Duration::from_secs(1)).unwrap().unwrap();
+
+        let (request, _) = server.join().unwrap();
+        assert!(request.starts_with(
+            "GET /loki/api/v1/query_range?query=%7Bapp%3D~%22a%7Cb%22%7D%20%7C%3D%20%22error%22&start="
+        ));
+        assert_eq!(
+            lines.iter().collect::<Vec<_>>(),
+            [b"first".as_slice(), b"second", b"third"]
+        );
+        assert_eq!(source.start, 31);
+    }
+}
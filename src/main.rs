@@ -1,16 +1,45 @@
-use clap::Parser;
-use log_watchdog::run;
-use settings::Settings;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{CommandFactory, FromArgMatches, ValueHint};
+use completions::Shell;
+#[cfg(feature = "remote-settings")]
+use log_watchdog::remote_settings::{self, RemoteSettings};
+use log_watchdog::{
+    init, reload, replay, run_blocking_until, run_once_until, run_reloading_until,
+    scan_server::{self, Scannable},
+    CancellationToken, ExitCode,
+};
+use logging::{Facility, LevelFilter, LogConfig, LogFile, LogFormat, LogLevel, Rotation};
+use serde_json::json;
+use settings::{PublicKey, Settings};
+
+mod completions;
+mod man;
+mod systemd;
 
 #[derive(clap::Parser, Debug)]
+#[clap(version)]
 struct Args {
-    /// The settings file used to configure the watchdogs.
+    #[clap(subcommand)]
+    command: Option<Subcommand>,
+
+    /// The settings file used to configure the watchdogs, or `-` for stdin.
+    /// May be given more than once, e.g. fleet-wide defaults and a host's
+    /// additions: later files override or extend the watchdogs of earlier
+    /// ones. Subcommands load these before their own. Defaults to the first
+    /// of `./log-watchdog.yml`, `$XDG_CONFIG_HOME/log-watchdog/config.yml`
+    /// and `/etc/log-watchdog/config.yml` that exists.
     ///
     /// The file should be in YAML format, and has the following schema:
     ///   watchdogs:
     ///     watchdog_name:
     ///     log_file: path/to/log/file.log
-    ///     output_file: path/to/output/file.txt
+    ///     output_file: path/to/output/file.txt # or - for stdout
     ///     debounce: 1000
     ///     oneshot: false
     ///     regex: .*
@@ -19,18 +48,957 @@ struct Args {
     ///         args:
     ///          - https://example.com
     ///          - -v
-    #[clap(short, long, verbatim_doc_comment, value_parser = settings_from_path)]
-    settings: Settings,
+    #[clap(short, long, verbatim_doc_comment, value_hint = ValueHint::FilePath)]
+    settings: Vec<PathBuf>,
+
+    /// Load the settings from this Consul or etcd key instead of a file, e.g.
+    /// `consul://localhost:8500/log-watchdog/web` or
+    /// `etcd://localhost:2379/log-watchdog/web`, and restart the watchdogs
+    /// whenever they change. The key is checked every 10 seconds.
+    #[cfg(feature = "remote-settings")]
+    #[clap(long, conflicts_with_all = ["settings", "public_key", "reload"])]
+    settings_from: Option<RemoteSettings>,
+
+    /// The log level of the daemon itself: `error`, `warn`, `info`, `debug`
+    /// or `trace`.
+    ///
+    /// Accepts a default level and optional per-module overrides, e.g.
+    /// `info,notify=warn`.
+    #[clap(long, global = true, env = "LOG_WATCHDOG_LOG", default_value = "info")]
+    log_level: LogLevel,
+
+    /// Only log errors, e.g. for runs from cron or CI. Overrides
+    /// `--log-level` and the watchdogs' `log_level`.
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    /// Format of the daemon's stdout logs: `json`, or `pretty` for a colored
+    /// single-line format when debugging interactively. Defaults to `json`.
+    #[clap(long, global = true)]
+    log_format: Option<LogFormat>,
+
+    /// Also write the daemon's logs to this file, rolling it over according to
+    /// `--log-rotation`. Overrides `logging.file` in the settings file.
+    #[clap(long, global = true, value_hint = ValueHint::FilePath)]
+    log_file: Option<PathBuf>,
+
+    /// When to roll the log file over: `daily`, or a size like `10MB`.
+    /// Defaults to `daily`.
+    #[clap(long, global = true)]
+    log_rotation: Option<Rotation>,
+
+    /// Number of rolled log files to keep. Defaults to 7.
+    #[clap(long, global = true)]
+    log_retention: Option<u32>,
+
+    /// Also send the daemon's logs to the local syslog daemon, with this
+    /// facility (e.g. `daemon`, `local0`). Overrides `logging.syslog`.
+    #[clap(long, global = true)]
+    log_syslog: Option<Facility>,
+
+    /// Also send the daemon's logs to journald, with the watchdog name and
+    /// watched file as `WATCHDOG` and `FILE` fields.
+    #[clap(long, global = true)]
+    log_journald: bool,
+
+    /// Collapse runs of identical log records, summarizing them as "last
+    /// message repeated N times" at most every this many seconds. 0 logs every
+    /// record. Defaults to 30.
+    #[clap(long, global = true)]
+    log_repeat_interval: Option<u64>,
+
+    /// Queue at most this many log records for writing from a background
+    /// thread, dropping records when the queue is full. 0 writes every record
+    /// synchronously. Defaults to 8192.
+    #[clap(long, global = true)]
+    log_buffer_size: Option<usize>,
+
+    /// Don't write the daemon's logs to stdout.
+    #[clap(long, global = true)]
+    no_log_stdout: bool,
+
+    /// Configure the daemon's logging with this log4rs config file instead,
+    /// ignoring all other logging flags and settings. Besides the log4rs
+    /// appenders, `kind: syslog` (`facility`, `path`) and `kind: journald`
+    /// (`path`) are available.
+    #[clap(long, global = true, value_hint = ValueHint::FilePath)]
+    logging_config: Option<PathBuf>,
+
+    /// Scan every watchdog's log file once, from the beginning, and exit
+    /// instead of tailing it, e.g. for batch checks from cron.
+    #[clap(long, global = true)]
+    once: bool,
+
+    /// With `--once`, save where every watchdog stopped reading to this file,
+    /// and start the next scan from there.
+    #[clap(long, global = true, requires = "once", value_hint = ValueHint::FilePath)]
+    state_file: Option<PathBuf>,
+
+    /// Log the commands every watchdog would run instead of running them,
+    /// whatever the watchdogs' `dry_run` settings, e.g. to trial a settings
+    /// file on a production host.
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Only load settings files with a valid minisign signature from this
+    /// public key, in `<file>.minisig` next to each, e.g. so that a host only
+    /// runs commands that the release process approved. The `minisign`
+    /// binary must be installed, and settings can't be read from stdin.
+    #[clap(long, global = true, env = "LOG_WATCHDOG_PUBLIC_KEY")]
+    public_key: Option<PublicKey>,
+
+    /// Listen for control commands on this unix socket, e.g. `log-level debug`
+    /// to change the log level without restarting.
+    #[clap(long, global = true, value_hint = ValueHint::FilePath)]
+    control_socket: Option<PathBuf>,
+
+    /// Listen for on-demand scans on this address, e.g. `127.0.0.1:9090`:
+    /// `POST /scan/<watchdog>` scans the watchdog's log file for the lines
+    /// written since its previous scan, or with `?full=true` the whole file.
+    /// Only a loopback address can be listened on without a `--scan-token`.
+    #[clap(long, global = true)]
+    scan_address: Option<String>,
+
+    /// Only scan on requests with this token, as an `Authorization: Bearer`
+    /// header.
+    #[clap(
+        long,
+        global = true,
+        env = "LOG_WATCHDOG_SCAN_TOKEN",
+        requires = "scan_address"
+    )]
+    scan_token: Option<String>,
+
+    /// Restart the watchdogs with the settings whenever the settings files
+    /// change, including Kubernetes ConfigMap volumes, whose updates swap a
+    /// symlink instead of writing the files. Settings that aren't valid are
+    /// logged, and the watchdogs keep running with the current ones.
+    #[clap(long, global = true, conflicts_with_all = ["public_key", "once"])]
+    reload: bool,
+
+    /// Run as PID 1, e.g. as a container's entrypoint: reap the zombies of
+    /// orphaned processes, and on SIGTERM or SIGINT stop the watchdogs, or
+    /// the scans of `--once`, and forward the signal to the commands that are
+    /// running. Other subcommands exit after the grace period.
+    #[clap(long, global = true)]
+    init: bool,
+
+    /// With `--init`, seconds that the commands, and the watchdogs, have to
+    /// exit after SIGTERM or SIGINT before they're killed.
+    #[clap(long, global = true, default_value_t = 10, requires = "init")]
+    grace_period: u64,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    /// Run the watchdogs, optionally only some of them. Running without a
+    /// subcommand runs them all.
+    Run {
+        /// The settings file used to configure the watchdogs, or `-` for
+        /// stdin. May be given more than once, merged in order.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Vec<PathBuf>,
+
+        /// Only run the watchdog with this name, e.g. to debug it or to split
+        /// a shared settings file across systemd template units. May be given
+        /// more than once.
+        #[clap(short, long)]
+        watchdog: Vec<String>,
+    },
+    /// Run the watchdogs' commands for the daemon reading their logs, as a
+    /// separate, privileged process.
+    ///
+    /// Listens on the settings' `executor` socket, and runs the commands of
+    /// the watchdogs in the settings when the daemon asks, for a line their
+    /// regex matches, or a log file that's missing. The daemon then needs no
+    /// privileges but to read the logs.
+    Executor {
+        /// The settings file with the watchdogs whose commands may be run.
+        /// May be given more than once, merged in order.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Vec<PathBuf>,
+    },
+    /// Check a settings file without starting any watchers, e.g. in CI.
+    ///
+    /// Parses the file and compiles every watchdog's regex, printing the
+    /// problems found in each watchdog. Exits with 2 if there are any.
+    Validate {
+        /// The settings file to check, or `-` for stdin. May be given more
+        /// than once, to check the files merged in order.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Vec<PathBuf>,
+
+        /// Print the report as `text`, or as `json` for other tools.
+        #[clap(long, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Replay a sample log against the watchdogs, to develop their regexes.
+    ///
+    /// Prints every line a watchdog would have triggered on, along with the
+    /// regex's captures and the commands it would have run. Nothing is run,
+    /// and no output is written.
+    Test {
+        /// The settings file with the watchdogs to test, or `-` for stdin.
+        /// May be given more than once, merged in order.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Vec<PathBuf>,
+
+        /// The sample log to replay, or `-` for stdin.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+
+        /// Only test this watchdog.
+        #[clap(short, long)]
+        watchdog: Option<String>,
+    },
+    /// Print the lines appended to the watchdogs' log files that they match,
+    /// without running any commands.
+    ///
+    /// Like `tail -f | grep` with the watchdogs' regexes, highlighting their
+    /// capture groups on a terminal.
+    Tail {
+        /// The settings file with the watchdogs to tail, or `-` for stdin.
+        /// May be given more than once, merged in order.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Vec<PathBuf>,
+
+        /// Only tail the watchdog with this name. May be given more than
+        /// once.
+        #[clap(short, long)]
+        watchdog: Vec<String>,
+    },
+    /// Match lines typed on stdin, or piped to it, against the watchdogs as
+    /// they're read, to develop their regexes interactively.
+    ///
+    /// Prints the watchdogs that would have triggered on every line, along
+    /// with the regex's captures and the commands they would have run.
+    /// Nothing is run, and no output is written.
+    Simulate {
+        /// The settings file with the watchdogs to simulate. May be given more
+        /// than once, merged in order.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Vec<PathBuf>,
+
+        /// Only simulate this watchdog.
+        #[clap(short, long)]
+        watchdog: Option<String>,
+    },
+    /// Print a table of the configured watchdogs.
+    List {
+        /// The settings file with the watchdogs to list, or `-` for stdin.
+        /// May be given more than once, merged in order.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Vec<PathBuf>,
+
+        /// Only print the watchdogs' names, one per line.
+        #[clap(long)]
+        names: bool,
+    },
+    /// Print the names of the watchdogs in the settings files, for the
+    /// completion scripts: only from regular files, without decrypting or
+    /// verifying them, and nothing if that takes longer than a second.
+    #[clap(hide = true)]
+    CompleteWatchdogs {
+        #[clap(short, long)]
+        settings: Vec<PathBuf>,
+    },
+    /// Check the hash chain of an audit log, e.g. in a security review.
+    ///
+    /// Prints how many command executions it records, or the first line that
+    /// was changed, removed or inserted since it was written, exiting with 1.
+    VerifyAudit {
+        /// The audit log to check.
+        #[clap(value_hint = ValueHint::FilePath)]
+        file: PathBuf,
+    },
+    /// Print a completion script for a shell.
+    ///
+    /// Besides subcommands and options, `--watchdog` is completed with the
+    /// names of the watchdogs in the `--settings` file on the command line.
+    /// For bash, e.g.:
+    ///   log-watchdog completions bash > /etc/bash_completion.d/log-watchdog
+    #[clap(verbatim_doc_comment)]
+    Completions { shell: Shell },
+    /// Print a man page for the command line and the settings file.
+    ///
+    /// E.g. for packaging:
+    ///   log-watchdog man > /usr/share/man/man1/log-watchdog.1
+    #[clap(verbatim_doc_comment)]
+    Man,
+    /// Print a hardened systemd unit that runs the watchdogs.
+    ///
+    /// The unit validates the settings before starting the daemon, and only
+    /// lets it write to the directories of the settings' output files, audit
+    /// logs and log file. E.g. for packaging:
+    ///   log-watchdog systemd > /etc/systemd/system/log-watchdog.service
+    ///   log-watchdog systemd --template > /etc/systemd/system/log-watchdog@.service
+    #[clap(verbatim_doc_comment)]
+    Systemd {
+        /// The settings file the unit runs the watchdogs of. Defaults to
+        /// `/etc/log-watchdog/config.yml`.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        settings: Option<PathBuf>,
+
+        /// Print a `log-watchdog@.service` template unit instead, which runs
+        /// the watchdog its instance names, e.g. `log-watchdog@pgbouncer`.
+        #[clap(long)]
+        template: bool,
+
+        /// The log-watchdog binary the unit runs.
+        #[clap(
+            long,
+            default_value = "/usr/bin/log-watchdog",
+            value_hint = ValueHint::ExecutablePath
+        )]
+        executable: PathBuf,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl Args {
+    /// Combines the logging flags with the settings file's `logging` section,
+    /// with flags taking precedence.
+    fn log_config(&self, settings: &Settings) -> LogConfig {
+        let logging = settings.logging();
+
+        LogConfig {
+            level: if self.quiet {
+                LogLevel::from(LevelFilter::Error)
+            } else {
+                self.log_level.clone()
+            },
+            stdout: !self.no_log_stdout && logging.stdout.unwrap_or(true),
+            format: self.log_format.or(logging.format).unwrap_or_default(),
+            file: self
+                .log_file
+                .clone()
+                .or_else(|| logging.file.clone())
+                .map(|path| LogFile {
+                    path,
+                    rotation: self.log_rotation.or(logging.rotation).unwrap_or_default(),
+                    retention: self.log_retention.or(logging.retention).unwrap_or(7),
+                }),
+            syslog: self.log_syslog.or(logging.syslog),
+            journald: self.log_journald || logging.journald.unwrap_or(false),
+            repeat_interval: match self.log_repeat_interval.or(logging.repeat_interval) {
+                Some(0) => None,
+                Some(seconds) => Some(Duration::from_secs(seconds)),
+                None => Some(logging::DEFAULT_REPEAT_INTERVAL),
+            },
+            watchdog_levels: if self.quiet {
+                Vec::new()
+            } else {
+                settings
+                    .watchdogs()
+                    .iter()
+                    .filter_map(|w| Some((w.name.clone(), w.log_level?)))
+                    .collect()
+            },
+            buffer_size: match self.log_buffer_size.or(logging.buffer_size) {
+                Some(0) => None,
+                Some(size) => Some(size),
+                None => Some(logging::DEFAULT_BUFFER_SIZE),
+            },
+            config_file: self.logging_config.clone(),
+        }
+    }
+}
+
+/// The settings files given with `--settings` before the subcommand, followed
+/// by the subcommand's own, or else the first settings file found in the
+/// search path.
+fn settings_paths(mut global: Vec<PathBuf>, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    global.extend(paths);
+    if !global.is_empty() {
+        return global;
+    }
+    match Settings::find() {
+        Some(path) => vec![path],
+        None => {
+            let searched: Vec<_> = Settings::search_paths()
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    format!(
+                        "no --settings given, and none of {} exist",
+                        searched.join(", ")
+                    ),
+                )
+                .exit()
+        }
+    }
 }
 
-fn settings_from_path(path: &str) -> Result<Settings, settings::SettingsError> {
-    let path = std::path::Path::new(path);
-    Settings::try_from(path)
+/// Loads the settings files merged in order, once they're verified with the
+/// public key if there is one, exiting like clap does for any other invalid
+/// argument if they're not valid or not signed.
+fn load_settings(paths: &[PathBuf], public_key: Option<&PublicKey>) -> Settings {
+    Settings::load(paths, public_key).unwrap_or_else(|e| {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                format!("invalid settings: {e}"),
+            )
+            .exit()
+    })
+}
+
+/// Prints the problems found in a settings file, and its signature with a
+/// public key, returning false if there are any.
+fn validate(paths: &[PathBuf], format: ReportFormat, public_key: Option<&PublicKey>) -> bool {
+    let report = Settings::validate(paths, public_key);
+
+    match format {
+        ReportFormat::Text => {
+            for error in &report.errors {
+                println!("{error}");
+            }
+            for (name, error) in &report.watchdogs {
+                match error {
+                    Some(error) => println!("{name}: {error}"),
+                    None => println!("{name}: ok"),
+                }
+            }
+        }
+        ReportFormat::Json => {
+            let watchdogs: serde_json::Map<_, _> = report
+                .watchdogs
+                .iter()
+                .map(|(name, error)| {
+                    let errors: Vec<_> = error.iter().map(ToString::to_string).collect();
+                    (
+                        name.clone(),
+                        json!({ "valid": error.is_none(), "errors": errors }),
+                    )
+                })
+                .collect();
+            let errors: Vec<_> = report.errors.iter().map(ToString::to_string).collect();
+            println!(
+                "{}",
+                json!({
+                    "settings": paths,
+                    "valid": report.is_valid(),
+                    "errors": errors,
+                    "watchdogs": watchdogs,
+                })
+            );
+        }
+    }
+
+    report.is_valid()
+}
+
+/// Prints the lines of `input` the watchdogs would have triggered on.
+fn test(mut settings: Settings, input: &Path, watchdog: Option<String>) -> io::Result<()> {
+    if let Some(name) = watchdog {
+        settings
+            .retain_watchdogs(&[name])
+            .map_err(io::Error::other)?;
+    }
+    let mut watchdogs = settings.into_watchdogs();
+    watchdogs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let input: Box<dyn BufRead> = if input == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = File::open(input)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", input.display())))?;
+        Box::new(BufReader::new(file))
+    };
+
+    let mut hits = 0;
+    let lines = replay::replay(&watchdogs, input, |hit| {
+        hits += 1;
+        print_hit(&hit);
+    })?;
+    println!("{hits} matches in {lines} lines");
+
+    Ok(())
+}
+
+/// Prints the watchdogs that would have triggered on every line of stdin as
+/// it's read, prompting for lines if it's a terminal.
+fn simulate(mut settings: Settings, watchdog: Option<String>) -> io::Result<()> {
+    if let Some(name) = watchdog {
+        settings
+            .retain_watchdogs(&[name])
+            .map_err(io::Error::other)?;
+    }
+    let mut watchdogs = settings.into_watchdogs();
+    watchdogs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    if interactive {
+        eprintln!("Type lines to match against the watchdogs, Ctrl-D to exit.");
+    }
+
+    let mut replay = replay::Replay::new(&watchdogs);
+    let mut lines = stdin.lock().split(b'\n');
+    for line_number in 1.. {
+        if interactive {
+            eprint!("> ");
+            io::stderr().flush()?;
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let hits = replay.hits_bytes(line_number, &line);
+        if hits.is_empty() && interactive {
+            println!("no match");
+        }
+        for hit in &hits {
+            print_hit(hit);
+        }
+    }
+    if interactive {
+        eprintln!();
+    }
+
+    Ok(())
+}
+
+/// Prints the lines appended to the watchdogs' log files that they match,
+/// prefixed with the watchdog's name if there are several, until it's
+/// stopped.
+fn tail(settings: Settings) {
+    let watchdogs = settings.into_watchdogs();
+    let prefix = watchdogs.len() > 1;
+    let terminal = io::stdout().is_terminal();
+    // never cancelled: tail runs until it's interrupted
+    let token = CancellationToken::new();
+
+    let tails: Vec<_> = watchdogs
+        .into_iter()
+        .map(|watchdog| {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                let result = log_watchdog::tail(&watchdog.log_file, &token, |line| {
+                    if !watchdog.regex.is_match(&line) {
+                        return;
+                    }
+                    let line = if terminal {
+                        replay::highlight(&watchdog.regex, &line)
+                    } else {
+                        line
+                    };
+                    let written = if prefix {
+                        writeln!(io::stdout().lock(), "{}: {line}", watchdog.name)
+                    } else {
+                        writeln!(io::stdout().lock(), "{line}")
+                    };
+                    // e.g. piped to head, which has exited
+                    if written.is_err() {
+                        ExitCode::Success.exit();
+                    }
+                });
+                if let Err(e) = result {
+                    eprintln!("{}: {e}", watchdog.log_file.display());
+                    ExitCode::Io.exit();
+                }
+            })
+        })
+        .collect();
+    for tail in tails {
+        let _ = tail.join();
+    }
+}
+
+/// `<line number>: <watchdog>: <line>`, followed by the captures and the
+/// commands that would have run.
+fn print_hit(hit: &replay::Hit) {
+    println!("{}: {}: {}", hit.line_number, hit.watchdog.name, hit.line);
+    if !hit.captures.is_empty() {
+        let captures: Vec<_> = hit
+            .captures
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        println!("    captures: {}", captures.join(" "));
+    }
+    for command in &hit.watchdog.commands {
+        println!("    would run: {}", replay::command_line(command));
+    }
+}
+
+/// How long completing a watchdog name waits for the settings files, e.g. on
+/// a hung network mount, before completing nothing.
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Prints the names of the watchdogs in the settings files, one per line, for
+/// the completion scripts, which ignore errors.
+fn complete_watchdogs(paths: Vec<PathBuf>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    // left behind if it times out, until the process exits
+    std::thread::spawn(move || tx.send(Settings::watchdog_names(&paths)));
+    match rx.recv_timeout(COMPLETION_TIMEOUT) {
+        Ok(Ok(names)) => {
+            for name in names {
+                println!("{name}");
+            }
+        }
+        Ok(Err(e)) => eprintln!("{e}"),
+        Err(_) => eprintln!("timed out reading the settings"),
+    }
+}
+
+/// Prints the watchdogs' names, files, regexes, debounce, oneshot and number
+/// of commands as a table.
+fn list(settings: &Settings, names: bool) {
+    let mut watchdogs: Vec<_> = settings.watchdogs().iter().collect();
+    watchdogs.sort_by(|a, b| a.name.cmp(&b.name));
+    if names {
+        for watchdog in watchdogs {
+            println!("{}", watchdog.name);
+        }
+        return;
+    }
+
+    let header = ["NAME", "FILE", "REGEX", "DEBOUNCE", "ONESHOT", "COMMANDS"].map(String::from);
+    let rows: Vec<[String; 6]> = std::iter::once(header)
+        .chain(watchdogs.iter().map(|w| {
+            [
+                w.name.clone(),
+                w.log_file.display().to_string(),
+                w.regex.to_string(),
+                format!("{}ms", w.debounce),
+                w.oneshot.to_string(),
+                w.commands.len().to_string(),
+            ]
+        }))
+        .collect();
+
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let cells: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+/// The version, followed by the build's commit, date, features and notify
+/// backend for `--version`, so that bug reports include them.
+fn long_version() -> String {
+    let features = match env!("LOG_WATCHDOG_FEATURES") {
+        "" => "none",
+        features => features,
+    };
+    // the watcher's module, e.g. inotify for notify::inotify::INotifyWatcher
+    let backend = std::any::type_name::<notify::RecommendedWatcher>()
+        .rsplit("::")
+        .nth(1)
+        .unwrap_or("unknown");
+
+    format!(
+        "{}\ncommit: {}\nbuilt: {}\nfeatures: {features}\nnotify backend: {backend}",
+        env!("CARGO_PKG_VERSION"),
+        env!("LOG_WATCHDOG_COMMIT"),
+        env!("LOG_WATCHDOG_BUILD_DATE"),
+    )
 }
 
 fn main() {
-    let args = Args::parse();
-    let _logging = logging::init_logging();
+    let matches = Args::command().long_version(long_version()).get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // before any other thread is started, so that they leave the signals to it
+    let stop = if args.init {
+        init::start(Duration::from_secs(args.grace_period)).unwrap_or_else(|e| {
+            eprintln!("failed to set up signal handling for --init: {e}");
+            ExitCode::Failure.exit();
+        })
+    } else {
+        CancellationToken::new()
+    };
+    let public_key = args.public_key.clone();
+    let public_key = public_key.as_ref();
+    let mut executor = false;
+    #[cfg(feature = "remote-settings")]
+    if args.settings_from.is_some() && args.command.is_some() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--settings-from only runs the watchdogs, without a subcommand",
+            )
+            .exit();
+    }
+    // the watchdogs `run --watchdog` is limited to, also after a reload
+    let mut only = Vec::new();
+    let (mut settings, paths) = match args.command.take() {
+        Some(Subcommand::Validate { settings, format }) => {
+            let paths = settings_paths(std::mem::take(&mut args.settings), settings);
+            if !validate(&paths, format, public_key) {
+                ExitCode::Config.exit();
+            }
+            return;
+        }
+        Some(Subcommand::Test {
+            settings,
+            input,
+            watchdog,
+        }) => {
+            let settings = settings_paths(std::mem::take(&mut args.settings), settings);
+            if input == Path::new("-") && settings.iter().any(|path| path == Path::new("-")) {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "--settings and --input can't both be read from stdin",
+                    )
+                    .exit();
+            }
+            if let Err(e) = test(load_settings(&settings, public_key), &input, watchdog) {
+                eprintln!("{e}");
+                ExitCode::Failure.exit();
+            }
+            return;
+        }
+        Some(Subcommand::Tail { settings, watchdog }) => {
+            let mut settings = load_settings(
+                &settings_paths(std::mem::take(&mut args.settings), settings),
+                public_key,
+            );
+            if !watchdog.is_empty() {
+                if let Err(e) = settings.retain_watchdogs(&watchdog) {
+                    eprintln!("{e}");
+                    ExitCode::Config.exit();
+                }
+            }
+            tail(settings);
+            return;
+        }
+        Some(Subcommand::Simulate { settings, watchdog }) => {
+            let settings = settings_paths(std::mem::take(&mut args.settings), settings);
+            if settings.iter().any(|path| path == Path::new("-")) {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "simulate reads lines from stdin, so --settings can't",
+                    )
+                    .exit();
+            }
+            if let Err(e) = simulate(load_settings(&settings, public_key), watchdog) {
+                eprintln!("{e}");
+                ExitCode::Failure.exit();
+            }
+            return;
+        }
+        Some(Subcommand::List { settings, names }) => {
+            list(
+                &load_settings(
+                    &settings_paths(std::mem::take(&mut args.settings), settings),
+                    public_key,
+                ),
+                names,
+            );
+            return;
+        }
+        Some(Subcommand::CompleteWatchdogs { mut settings }) => {
+            let mut paths = std::mem::take(&mut args.settings);
+            paths.append(&mut settings);
+            complete_watchdogs(paths);
+            return;
+        }
+        Some(Subcommand::VerifyAudit { file }) => {
+            match log_watchdog::audit::verify(&file) {
+                Ok(entries) => println!("{entries} entries verified"),
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::Failure.exit();
+                }
+            }
+            return;
+        }
+        Some(Subcommand::Man) => {
+            print!(
+                "{}",
+                man::generate(&Args::command(), env!("CARGO_PKG_VERSION"))
+            );
+            return;
+        }
+        Some(Subcommand::Systemd {
+            settings,
+            template,
+            executable,
+        }) => {
+            let settings = match (settings, args.settings.as_slice()) {
+                (Some(settings), []) => settings,
+                (None, [settings]) => settings.clone(),
+                (None, []) => PathBuf::from("/etc/log-watchdog/config.yml"),
+                _ => Args::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "a systemd unit runs a single --settings file",
+                    )
+                    .exit(),
+            };
+            // the unit is still useful before the settings file is written
+            let loaded = Settings::try_from(settings.as_path()).ok();
+            print!(
+                "{}",
+                systemd::generate(&executable, &settings, loaded.as_ref(), template)
+            );
+            return;
+        }
+        Some(Subcommand::Completions { shell }) => {
+            print!("{}", completions::generate(&mut Args::command(), shell));
+            return;
+        }
+        Some(Subcommand::Executor { settings }) => {
+            let paths = settings_paths(std::mem::take(&mut args.settings), settings);
+            executor = true;
+            (load_settings(&paths, public_key), paths)
+        }
+        Some(Subcommand::Run { settings, watchdog }) => {
+            let paths = settings_paths(std::mem::take(&mut args.settings), settings);
+            let mut settings = load_settings(&paths, public_key);
+            if !watchdog.is_empty() {
+                if let Err(e) = settings.retain_watchdogs(&watchdog) {
+                    eprintln!("{e}");
+                    ExitCode::Config.exit();
+                }
+            }
+            only = watchdog;
+            (settings, paths)
+        }
+        #[cfg(feature = "remote-settings")]
+        None if args.settings_from.is_some() => {
+            let remote = args.settings_from.as_mut().expect("checked by the guard");
+            let settings = remote.load().unwrap_or_else(|e| {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ValueValidation,
+                        format!("invalid settings in {remote}: {e}"),
+                    )
+                    .exit()
+            });
+            (settings, vec![PathBuf::from(remote.to_string())])
+        }
+        None => {
+            let paths = settings_paths(std::mem::take(&mut args.settings), Vec::new());
+            (load_settings(&paths, public_key), paths)
+        }
+    };
+    if args.reload && paths.iter().any(|path| path == Path::new("-")) {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "settings read from stdin can't be reloaded",
+            )
+            .exit();
+    }
+    let log_config = args.log_config(&settings);
+    let logging = match logging::init_logging(&log_config) {
+        Ok(handle) => handle,
+        Err(e) => {
+            eprintln!("failed to initialize logging: {e}");
+            ExitCode::Failure.exit();
+        }
+    };
+    let loaded: Vec<_> = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    log::info!("loaded settings from {}", loaded.join(", "));
+    if args.dry_run {
+        log::info!("dry run, commands are logged instead of run");
+        settings.set_dry_run();
+    }
+
+    if let Some(path) = &args.control_socket {
+        if let Err(e) = log_watchdog::control::listen(path, logging, log_config) {
+            log::error!("failed to listen on control socket {path:?}: {e}");
+            log::logger().flush();
+            ExitCode::Failure.exit();
+        }
+    }
+
+    if executor {
+        if let Err(e) = log_watchdog::executor::serve(settings) {
+            log::error!("executor failed: {e}");
+            log::logger().flush();
+            ExitCode::Failure.exit();
+        }
+        return;
+    }
+
+    // replaced on every reload, so that scans are of the watchdogs running
+    let scannable = args
+        .scan_address
+        .as_ref()
+        .filter(|_| !args.once)
+        .map(|_| Scannable::new(settings.watchdogs()));
+    if let (Some(address), Some(watchdogs)) = (args.scan_address.as_deref(), &scannable) {
+        if let Err(e) = scan_server::listen(address, args.scan_token.take(), watchdogs.clone()) {
+            log::error!("failed to listen for scans on {address}: {e}");
+            log::logger().flush();
+            ExitCode::Failure.exit();
+        }
+    }
 
-    run(args.settings);
+    // the watchdogs log their own errors
+    let result = if args.once {
+        run_once_until(settings, args.state_file.as_deref(), &stop)
+    } else {
+        #[cfg(feature = "remote-settings")]
+        if let Some(remote) = args.settings_from.take() {
+            let dry_run = args.dry_run;
+            let scannable = scannable.clone();
+            let reloads = remote.watch(remote_settings::DEFAULT_POLL_INTERVAL, move |settings| {
+                if dry_run {
+                    settings.set_dry_run();
+                }
+                if let Some(scannable) = &scannable {
+                    scannable.replace(settings.watchdogs());
+                }
+                Ok(())
+            });
+            ExitCode::from(&run_reloading_until(settings, &reloads, &stop)).exit();
+        }
+        if args.reload {
+            let dry_run = args.dry_run;
+            let reloads = reload::watch_files(paths, move |settings| {
+                if dry_run {
+                    settings.set_dry_run();
+                }
+                if !only.is_empty() {
+                    settings.retain_watchdogs(&only)?;
+                }
+                if let Some(scannable) = &scannable {
+                    scannable.replace(settings.watchdogs());
+                }
+                Ok(())
+            });
+            match reloads {
+                Ok(reloads) => {
+                    ExitCode::from(&run_reloading_until(settings, &reloads, &stop)).exit()
+                }
+                Err(e) => {
+                    log::error!("failed to watch the settings files for changes: {e}");
+                    log::logger().flush();
+                    ExitCode::Failure.exit();
+                }
+            }
+        }
+        run_blocking_until(settings, stop)
+    };
+    ExitCode::from(&result).exit();
 }
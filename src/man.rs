@@ -0,0 +1,318 @@
+use std::fmt::Write;
+
+use clap::{Arg, Command};
+
+/// The settings file's keys, which aren't part of the command line.
+const SETTINGS: &str = r#"Without \fB\-\-settings\fR, the first of \fI./log\-watchdog.yml\fR, \fI$XDG_CONFIG_HOME/log\-watchdog/config.yml\fR and \fI/etc/log\-watchdog/config.yml\fR that exists is used.
+.PP
+The settings file is YAML, with a \fBwatchdogs\fR mapping of watchdog names to their settings and an optional \fBlogging\fR section:
+.PP
+.nf
+.RS
+watchdogs:
+  pgbouncer:
+    log_file: /var/log/pgbouncer/pgbouncer.log
+    output_file: /var/log/log-watchdog/pgbouncer.out
+    debounce: 5000
+    oneshot: false
+    regex: FATAL
+    commands:
+      systemctl:
+        args: [restart, pgbouncer]
+.RE
+.fi
+.SS Watchdogs
+.TP
+\fBlog_file\fR
+The log file to watch.
+.TP
+\fBregex\fR
+The regex to match every new line against.
+.TP
+\fBcommands\fR
+The commands to run on a match, by name, each with a list of \fBargs\fR, and optionally a \fBsandbox\fR: \fBno_new_privileges\fR (true by default), the \fBenv\fR variables passed on (the rest are cleared), a \fBwrapper\fR, \fBbwrap\fR or \fBnsjail\fR, and an nsjail \fBseccomp_policy\fR file. Instead of a \fBsandbox\fR, a command may have a container \fBruntime\fR, \fBdocker\fR or \fBpodman\fR, to run it in an \fBimage\fR, seeing only the host paths in \fBmounts\fR, \fIhost\fB:\fIcontainer\fR, read-only unless \fB:rw\fR is appended.
+.TP
+\fBdebounce\fR, \fBdebounce_mode\fR
+Milliseconds after a match during which further matches aren't acted on, only counted (\fBsuppress_commands\fR, the default), or lines aren't matched at all (\fBskip_lines\fR).
+.TP
+\fBoneshot\fR
+If true, the watchdog stops after its first match.
+.TP
+\fBdry_run\fR
+If true, the commands are logged instead of run.
+.TP
+\fBoutput_file\fR, \fBoutput\fR
+Where match records are written: a file, \fB\-\fR or \fBstdout\fR, \fBstderr\fR, \fBsyslog\fR[\fB:\fR\fIfacility\fR], \fBjournald\fR, \fBunix:\fR\fIpath\fR, \fBtcp:\fR\fIhost\fR\fB:\fR\fIport\fR or, when built with the \fBloki\fR feature, \fBloki:\fR\fIurl\fR to push to Grafana Loki, and with the \fBelasticsearch\fR feature, \fBelasticsearch:\fR\fIurl\fR\fB/\fR\fIindex\fR to bulk-index into Elasticsearch or OpenSearch, where \fB{watchdog}\fR and \fB{date}\fR in \fIindex\fR are replaced, with \fIurl\fR an \fBhttp://\fR or, requested with \fBcurl\fR(1), \fBhttps://\fR URL; or a list of these, where entries may be a mapping with a \fBtarget\fR, its own \fBformat\fR and TLS options.
+.TP
+\fBoutput_format\fR
+A template like \fB{timestamp} {watchdog}: {stdout}\fR, \fBjsonl\fR or \fBcsv\fR. Templates have \fB{timestamp}\fR, \fB{watchdog}\fR, \fB{line}\fR, \fB{command}\fR, \fB{stdout}\fR, \fB{stderr}\fR, \fB{exit_code}\fR and \fB{duration_ms}\fR fields.
+.TP
+\fBoutput_columns\fR
+The columns of the \fBcsv\fR format: fields, or the regex's capture groups.
+.TP
+\fBoutput_empty\fR
+What's written for commands without stdout: \fBwrite\fR, \fBskip\fR or \fBmark\fR.
+.TP
+\fBoutput_append\fR
+If false, output files are truncated when the watchdog starts.
+.TP
+\fBflush\fR, \fBfsync\fR
+When output files are flushed: \fBevery_write\fR, a number of milliseconds or \fBon_exit\fR; and whether every flush is synced to disk.
+.TP
+\fBoutput_mode\fR, \fBoutput_owner\fR
+Permissions and \fIuser\fR[\fB:\fR\fIgroup\fR] of output files the watchdog creates.
+.TP
+\fBoutput_rotation\fR, \fBoutput_retention\fR, \fBoutput_compression\fR
+When output files are rolled over (\fBdaily\fR or a size like \fB10MB\fR), how many rolled files are kept, and whether they are compressed with \fBgzip\fR or \fBzstd\fR.
+.TP
+\fBoutput_dedup\fR
+If true, runs of records with the same command output are written once, with a repeat count.
+.TP
+\fBoutput_suppressed\fR
+If true, the matches a debounce suppressed are counted in a record when it expires.
+.TP
+\fBlog_level\fR, \fBlog_sample_rate\fR
+The level of the watchdog's own logs, and how many of its matches are logged (1 in this many).
+.TP
+\fBmax_read_bytes\fR
+The most bytes read from the log file at once, 1 MiB by default. A watchdog that has fallen further behind catches up in chunks of this size.
+.TP
+\fBread_buffer_size\fR
+Bytes of buffer kept between reads of the log file, 64 KiB by default. A larger buffer takes fewer reads for busy logs, at the cost of memory per watchdog.
+.TP
+\fBpartial_line_timeout\fR
+Milliseconds to wait for the rest of a partially written line before matching what was written; the rest is then matched as a line of its own. Without it, lines are only matched once their newline is written.
+.TP
+\fBbackend\fR, \fBpoll_interval\fR
+What the log file is watched with, instead of the platform's own change notifications: \fBinotify\fR, \fBkqueue\fR, or \fBpoll\fR, which checks the file every \fBpoll_interval\fR milliseconds (1000 by default), e.g. for a file on a CIFS/SMB or NFS share, which gets no notifications. Without a \fBbackend\fR, files on NFS, CIFS/SMB and overlayfs are polled, with a warning.
+.TP
+\fBencoding\fR
+How lines that aren't valid UTF-8 are read: \fBlossy\fR (the default) replaces invalid UTF-8 with U+FFFD, \fBstrict\fR skips such lines with a warning, and \fBlatin1\fR reads every byte as an ISO 8859-1 character.
+.TP
+\fBredact\fR
+Regexes of secrets masked as \fB[REDACTED]\fR in the matched lines, command lines and command output that are logged and written to the outputs; only their capture groups are masked, for regexes with any.
+.TP
+\fBalertmanager\fR
+With the \fBalertmanager\fR feature, the Prometheus Alertmanager the matches are sent to as one alert: its \fBurl\fR, the alert's \fBlabels\fR and \fBannotations\fR, and \fBresolve_after\fR, the milliseconds after the last match that it resolves (300000 by default). An \fBhttps://\fR \fBurl\fR of an \fBalertmanager\fR, an \fBicinga\fR API or a \fBloki:\fR or \fBelasticsearch:\fR output entry can have TLS options next to it: \fBca_file\fR, the CA certificates its server is verified with, \fBclient_cert\fR and \fBclient_key\fR, the client certificate and its key for mutual TLS, and \fBmin_tls_version\fR, \fB1.2\fR or \fB1.3\fR.
+.TP
+\fBpassive_check\fR
+The Nagios or Icinga service check the matches are submitted to as passive check results: its \fBhost\fR and \fBservice\fR, the \fBstatus\fR of a match, \fBcritical\fR (the default) or \fBwarning\fR, its plugin \fBoutput\fR, a template with the \fB{timestamp}\fR, \fB{watchdog}\fR and \fB{line}\fR fields, and \fBrecover_after\fR, the milliseconds after the last match that an OK result is submitted (300000 by default). Results are written to the external \fBcommand_file\fR, or posted to the Icinga 2 API given as \fBicinga\fR, with its \fBurl\fR, \fBusername\fR and \fBpassword\fR.
+.TP
+\fBzabbix\fR
+With the \fBzabbix\fR feature, the Zabbix server or proxy the matches are pushed to with the trapper protocol: its \fBserver\fR, a \fIhost\fR[:\fIport\fR] with port 10051 by default, the \fBhost\fR and \fBkey\fR of the trapper item, and the \fBvalue\fR, a template like a passive check's \fBoutput\fR (\fB{line}\fR by default).
+.TP
+\fBfile_missing\fR, \fBon_file_missing\fR
+What the watchdog does when its log file is removed, after running the \fBon_file_missing\fR commands, given like \fBcommands\fR: \fBwait\fR (the default) for it to be recreated and watch it from its start, or \fBstop\fR as failed.
+.SS Logging
+.TP
+\fBfile\fR, \fBrotation\fR, \fBretention\fR
+A rolling file for the daemon's logs, when it's rolled over, and how many rolled files are kept.
+.TP
+\fBstdout\fR, \fBformat\fR
+Whether the daemon logs to stdout, as \fBjson\fR or \fBpretty\fR.
+.TP
+\fBsyslog\fR, \fBjournald\fR
+The facility to log to the local syslog daemon with, and whether to log to journald.
+.TP
+\fBrepeat_interval\fR, \fBbuffer_size\fR
+Seconds between summaries of repeated log records, and the number of log records queued for writing.
+.SS Allowed commands
+The optional top-level \fBallowed_commands\fR list restricts the programs the commands may run to absolute paths, symlinks resolved, \fBsha256:\fIhex\fR hashes of their contents, and \fIimage\fB:\fIname\fR for a command run in a container, whose runtime must be allowed too. Commands are resolved through \fBPATH\fR and checked when the settings are loaded and again before every run. A hashed program is run by its path after it's hashed, so it must be where nobody else can replace it in between.
+.SS Audit log
+The optional top-level \fBaudit_log\fR is a file every command run is appended to, as a JSON object per line with its \fBtimestamp\fR, \fBwatchdog\fR, \fBargv\fR, \fBexit_code\fR and the \fBline_sha256\fR of the line that triggered it, hash chained so that \fBverify-audit\fR finds entries changed, removed or inserted since.
+.SS Concurrent commands
+The optional top-level \fBmax_concurrent_commands\fR caps the commands running at once across every watchdog; the others wait in a queue until one exits.
+.SS Executor
+The optional top-level \fBexecutor\fR is the unix socket of a separate, privileged \fBexecutor\fR process that runs the commands, so that the daemon reading the logs needs no privileges. The executor only runs the commands of its own settings' watchdogs, for a line the watchdog's regex matches, or a log file that's missing. Only its user, and the members of the group \fBexecutor_group\fR names, may connect to the socket.
+.SS Encrypted values
+A value tagged \fB!encrypted\fR is an armored age ciphertext, decrypted with the \fBage\fR binary when the settings are loaded, with the identity in the optional top-level \fBidentity_file\fR.
+.SS Defaults
+The optional \fBdefaults\fR section takes any watchdog setting, e.g. \fBread_buffer_size\fR, for every watchdog that doesn't set it itself.
+"#;
+
+/// The exit codes, as [`log_watchdog::ExitCode`] has them.
+const EXIT_STATUS: &str = r".TP
+\fB0\fR
+The watchdogs were stopped, or completed, e.g. oneshot watchdogs after their match.
+.TP
+\fB1\fR
+Any other failure.
+.TP
+\fB2\fR
+The command line or settings are invalid, including the problems \fBvalidate\fR finds.
+.TP
+\fB3\fR
+A log file couldn't be watched.
+.TP
+\fB4\fR
+A command failed, or wasn't in \fBallowed_commands\fR.
+.TP
+\fB5\fR
+A log file couldn't be read, an output written, or the positions of \fB\-\-state\-file\fR saved.
+";
+
+/// Generates a roff man page for the command, its subcommands and the
+/// settings file.
+pub fn generate(command: &Command, version: &str) -> String {
+    let name = command.get_name();
+    let mut page = String::new();
+
+    let _ = writeln!(
+        page,
+        ".TH {} 1 \"\" \"{name} {version}\"",
+        escape(&name.to_uppercase())
+    );
+    let _ = writeln!(
+        page,
+        ".SH NAME\n{} \\- watch log files and run commands when they match",
+        escape(name)
+    );
+    let _ = writeln!(
+        page,
+        ".SH SYNOPSIS\n\\fB{0}\\fR [\\fIOPTIONS\\fR]\n.br\n\\fB{0}\\fR \\fICOMMAND\\fR [\\fIOPTIONS\\fR]",
+        escape(name)
+    );
+    page.push_str(
+        ".SH DESCRIPTION\nlog\\-watchdog watches log files, and runs commands when a new line matches a watchdog's regex. Without a command, it runs every watchdog in the settings file until it's stopped.\n",
+    );
+
+    page.push_str(".SH OPTIONS\n");
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        option(&mut page, arg);
+    }
+
+    page.push_str(".SH COMMANDS\n");
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+    {
+        let _ = writeln!(
+            page,
+            ".SS \"{} {}\"",
+            escape(name),
+            escape(subcommand.get_name())
+        );
+        paragraphs(
+            &mut page,
+            &subcommand
+                .get_long_about()
+                .or_else(|| subcommand.get_about())
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        );
+        for arg in subcommand
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set() && !arg.is_global_set())
+        {
+            option(&mut page, arg);
+        }
+    }
+
+    page.push_str(".SH SETTINGS\n");
+    page.push_str(SETTINGS);
+    page.push_str(".SH \"EXIT STATUS\"\n");
+    page.push_str(EXIT_STATUS);
+    page
+}
+
+/// `-s, --settings SETTINGS` and its help.
+fn option(page: &mut String, arg: &Arg) {
+    let value = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map_or_else(
+            || arg.get_id().to_string().to_uppercase(),
+            ToString::to_string,
+        );
+    let takes_value = arg.get_action().takes_values();
+
+    page.push_str(".TP\n");
+    if arg.is_positional() {
+        let _ = writeln!(page, "\\fI{}\\fR", escape(&value));
+    } else {
+        let short = arg.get_short().map(|short| format!("\\fB\\-{short}\\fR"));
+        let long = arg
+            .get_long()
+            .map(|long| format!("\\fB\\-\\-{}\\fR", escape(long)));
+        let flags: Vec<_> = short.into_iter().chain(long).collect();
+        page.push_str(&flags.join(", "));
+        if takes_value {
+            let _ = write!(page, " \\fI{}\\fR", escape(&value));
+        }
+        page.push('\n');
+    }
+
+    let help = arg
+        .get_long_help()
+        .or_else(|| arg.get_help())
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    paragraphs(page, &help);
+    let values: Vec<_> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| format!("\\fB{}\\fR", escape(value.get_name())))
+        .collect();
+    if takes_value && !values.is_empty() {
+        let _ = writeln!(page, ".IP\nOne of {}.", values.join(", "));
+    }
+}
+
+/// Help text as roff paragraphs, keeping indented lines, like the settings
+/// schema, as they are.
+fn paragraphs(page: &mut String, help: &str) {
+    for (i, paragraph) in help.split("\n\n").enumerate() {
+        if i > 0 {
+            page.push_str(".IP\n");
+        }
+        let verbatim = paragraph.lines().skip(1).any(|line| line.starts_with(' '));
+        if verbatim {
+            page.push_str(".nf\n");
+        }
+        for line in paragraph.lines() {
+            let line = escape(line);
+            if line.starts_with(['.', '\'']) {
+                page.push_str("\\&");
+            }
+            page.push_str(&line);
+            page.push('\n');
+        }
+        if verbatim {
+            page.push_str(".fi\n");
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+    use crate::Args;
+
+    #[test]
+    fn test_when_generated_then_options_commands_and_settings_documented() {
+        let page = generate(&Args::command(), "0.1.0");
+
+        assert!(page.starts_with(".TH LOG\\-WATCHDOG 1 \"\" \"log-watchdog 0.1.0\"\n"));
+        assert!(page.contains(".TP\n\\fB\\-s\\fR, \\fB\\-\\-settings\\fR \\fISETTINGS\\fR\n"));
+        assert!(page.contains(".SS \"log\\-watchdog validate\"\n"));
+        assert!(page.contains("One of \\fBtext\\fR, \\fBjson\\fR."));
+        assert!(page.contains(".SH SETTINGS\n"));
+        assert!(page.contains(".SH \"EXIT STATUS\"\n.TP\n\\fB0\\fR\n"));
+    }
+
+    #[test]
+    fn test_when_help_has_dots_or_backslashes_then_escaped() {
+        let mut page = String::new();
+
+        paragraphs(&mut page, ".hidden\\path\n'quoted");
+
+        assert_eq!(page, "\\&.hidden\\epath\n\\&'quoted\n");
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use settings::Command;
+
+use crate::{MatchEvent, WatchdogError};
+
+/// Callbacks for what the watchdogs started by [`crate::run_with_observer`]
+/// do, e.g. to count lines, matches and commands in an application's own
+/// metrics. Every method does nothing unless it's implemented.
+///
+/// The watchdogs share the observer, and call it from their own threads.
+pub trait Observer: Send + Sync {
+    /// A line was appended to the watchdog's log file.
+    fn line_read(&self, _watchdog: &str, _line: &str) {}
+
+    /// The watchdog matched a line, before its commands are run.
+    fn matched(&self, _event: &MatchEvent) {}
+
+    /// A command is about to run. Commands aren't run, and this isn't
+    /// called, in a dry run.
+    fn command_started(&self, _watchdog: &str, _command: &Command) {}
+
+    /// A command exited, with its exit code unless it was killed by a
+    /// signal.
+    fn command_finished(
+        &self,
+        _watchdog: &str,
+        _command: &Command,
+        _exit_code: Option<i32>,
+        _duration: Duration,
+    ) {
+    }
+
+    /// The watchdog stopped on this error.
+    fn error(&self, _error: &WatchdogError) {}
+}
+
+/// Lets the application keep a reference to the observer, e.g. to read the
+/// metrics it collects.
+impl<O: Observer + ?Sized> Observer for Arc<O> {
+    fn line_read(&self, watchdog: &str, line: &str) {
+        self.as_ref().line_read(watchdog, line);
+    }
+
+    fn matched(&self, event: &MatchEvent) {
+        self.as_ref().matched(event);
+    }
+
+    fn command_started(&self, watchdog: &str, command: &Command) {
+        self.as_ref().command_started(watchdog, command);
+    }
+
+    fn command_finished(
+        &self,
+        watchdog: &str,
+        command: &Command,
+        exit_code: Option<i32>,
+        duration: Duration,
+    ) {
+        self.as_ref()
+            .command_finished(watchdog, command, exit_code, duration);
+    }
+
+    fn error(&self, error: &WatchdogError) {
+        self.as_ref().error(error);
+    }
+}
+
+/// The callback of [`crate::run_with`], called for one match at a time.
+pub(crate) struct OnMatch<F>(pub(crate) Mutex<F>);
+
+impl<F: FnMut(MatchEvent) + Send> Observer for OnMatch<F> {
+    fn matched(&self, event: &MatchEvent) {
+        let mut on_match = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        on_match(event.clone());
+    }
+}
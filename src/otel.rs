@@ -0,0 +1,577 @@
+//! OpenTelemetry export of match events and command executions.
+//!
+//! Each match becomes a `match` span with a child `command` span per executed
+//! command, and `log_watchdog.matches`/`log_watchdog.commands` counters are
+//! exported as cumulative sums, along with a `log_watchdog.commands.queued`
+//! gauge of the commands waiting for `max_concurrent_commands`. Data is sent over OTLP/HTTP with JSON encoding,
+//! configured through the standard environment variables:
+//!
+//! - `OTEL_SDK_DISABLED`: `true` disables export
+//! - `OTEL_EXPORTER_OTLP_ENDPOINT`: base endpoint, defaults to `http://localhost:4318`
+//! - `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`/`OTEL_EXPORTER_OTLP_METRICS_ENDPOINT`:
+//!   full per-signal endpoints, override the base endpoint
+//! - `OTEL_EXPORTER_OTLP_HEADERS`: `key=value` pairs separated by commas
+//! - `OTEL_EXPORTER_OTLP_CERTIFICATE`, `OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE`,
+//!   `OTEL_EXPORTER_OTLP_CLIENT_KEY`: the CA certificates an `https://`
+//!   endpoint is verified with, and the client certificate and key for mutual TLS
+//! - `OTEL_EXPORTER_OTLP_TIMEOUT`: export timeout in milliseconds
+//! - `OTEL_SERVICE_NAME`, `OTEL_RESOURCE_ATTRIBUTES`: resource attributes
+//! - `OTEL_BSP_SCHEDULE_DELAY`, `OTEL_METRIC_EXPORT_INTERVAL`: export intervals in milliseconds
+//!
+//! `https://` endpoints are exported to with `curl`, which must be installed.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, SyncSender},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, warn};
+use serde_json::{json, Value};
+use settings::Tls;
+
+use crate::http::{self, Url};
+
+/// Spans are exported once this many are queued, or every schedule delay.
+const MAX_EXPORT_BATCH_SIZE: usize = 512;
+/// Spans are dropped if this many are waiting to be exported.
+const MAX_QUEUE_SIZE: usize = 2048;
+
+static EXPORTER: OnceLock<Option<Exporter>> = OnceLock::new();
+
+thread_local! {
+    static CURRENT_MATCH: RefCell<Option<Span>> = const { RefCell::new(None) };
+}
+
+/// Starts a `match` span on the current thread, ended by [`end_match`].
+pub fn start_match(watchdog: &str, file: &Path, match_id: u64) {
+    let Some(exporter) = exporter() else {
+        return;
+    };
+    exporter.count("log_watchdog.matches", &[("watchdog", watchdog)]);
+
+    let span = Span {
+        trace_id: rand::random(),
+        span_id: rand::random(),
+        parent_span_id: None,
+        name: "match",
+        start: SystemTime::now(),
+        end: SystemTime::now(),
+        attributes: vec![
+            ("watchdog", watchdog.to_string()),
+            ("file", file.to_string_lossy().to_string()),
+            ("match_id", match_id.to_string()),
+        ],
+        error: false,
+    };
+    CURRENT_MATCH.with(|current| *current.borrow_mut() = Some(span));
+}
+
+/// Records a `command` span as a child of the current match.
+pub fn record_command(name: &str, start: SystemTime, exit_code: Option<i32>, success: bool) {
+    let Some(exporter) = exporter() else {
+        return;
+    };
+
+    CURRENT_MATCH.with(|current| {
+        let current = current.borrow();
+        let Some(parent) = current.as_ref() else {
+            return;
+        };
+        let watchdog = &parent.attributes[0].1;
+        exporter.count(
+            "log_watchdog.commands",
+            &[
+                ("watchdog", watchdog),
+                ("command", name),
+                ("success", if success { "true" } else { "false" }),
+            ],
+        );
+
+        let mut attributes = vec![
+            ("watchdog", watchdog.clone()),
+            ("command", name.to_string()),
+        ];
+        if let Some(code) = exit_code {
+            attributes.push(("exit_code", code.to_string()));
+        }
+        exporter.export(Span {
+            trace_id: parent.trace_id,
+            span_id: rand::random(),
+            parent_span_id: Some(parent.span_id),
+            name: "command",
+            start,
+            end: SystemTime::now(),
+            attributes,
+            error: !success,
+        });
+    });
+}
+
+/// Records how many commands are waiting for others to exit.
+pub fn record_queued_commands(queued: usize) {
+    if let Some(exporter) = exporter() {
+        exporter.gauge("log_watchdog.commands.queued", queued);
+    }
+}
+
+/// Ends the current match span, marking it as failed if `error` is set.
+pub fn end_match(error: bool) {
+    let Some(span) = CURRENT_MATCH.with(|current| current.borrow_mut().take()) else {
+        return;
+    };
+    if let Some(exporter) = exporter() {
+        exporter.export(Span {
+            end: SystemTime::now(),
+            error,
+            ..span
+        });
+    }
+}
+
+/// Exports everything that is still queued, e.g. before the process exits.
+pub fn shutdown() {
+    if let Some(exporter) = exporter() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        if exporter.queue.send(Message::Flush(tx)).is_ok() {
+            let _ = rx.recv_timeout(exporter.config.timeout * 2);
+        }
+    }
+}
+
+fn exporter() -> Option<&'static Exporter> {
+    EXPORTER
+        .get_or_init(
+            || match OtelConfig::from_env(|key| std::env::var(key).ok()) {
+                Ok(Some(config)) => Some(Exporter::start(config)),
+                Ok(None) => None,
+                Err(e) => {
+                    error!("opentelemetry export disabled: {e}");
+                    None
+                }
+            },
+        )
+        .as_ref()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct OtelConfig {
+    traces: Url,
+    metrics: Url,
+    headers: Vec<(String, String)>,
+    resource: Vec<(String, String)>,
+    timeout: Duration,
+    schedule_delay: Duration,
+    metric_interval: Duration,
+}
+
+impl OtelConfig {
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, String> {
+        if var("OTEL_SDK_DISABLED").is_some_and(|v| v.eq_ignore_ascii_case("true")) {
+            return Ok(None);
+        }
+
+        let endpoint = Url::from_str(
+            &var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or_else(|| "http://localhost:4318".into()),
+        )?;
+        let traces = var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+            .map_or_else(|| Ok(endpoint.join("v1/traces")), |url| Url::from_str(&url))?;
+        let metrics = var("OTEL_EXPORTER_OTLP_METRICS_ENDPOINT").map_or_else(
+            || Ok(endpoint.join("v1/metrics")),
+            |url| Url::from_str(&url),
+        )?;
+        let tls = Tls {
+            ca_file: var("OTEL_EXPORTER_OTLP_CERTIFICATE").map(PathBuf::from),
+            client_cert: var("OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE").map(PathBuf::from),
+            client_key: var("OTEL_EXPORTER_OTLP_CLIENT_KEY").map(PathBuf::from),
+            min_version: None,
+        };
+
+        let mut resource = key_values(var("OTEL_RESOURCE_ATTRIBUTES").as_deref());
+        let service_name = var("OTEL_SERVICE_NAME")
+            .or_else(|| {
+                resource
+                    .iter()
+                    .find(|(key, _)| key == "service.name")
+                    .map(|(_, value)| value.clone())
+            })
+            .unwrap_or_else(|| "log-watchdog".into());
+        resource.retain(|(key, _)| key != "service.name");
+        resource.insert(0, ("service.name".into(), service_name));
+
+        let millis = |key: &str, default: u64| -> Result<Duration, String> {
+            var(key).map_or(Ok(Duration::from_millis(default)), |v| {
+                v.parse()
+                    .map(Duration::from_millis)
+                    .map_err(|_| format!("invalid {key}: {v}"))
+            })
+        };
+
+        Ok(Some(Self {
+            traces: traces.with_tls(&tls),
+            metrics: metrics.with_tls(&tls),
+            headers: key_values(var("OTEL_EXPORTER_OTLP_HEADERS").as_deref()),
+            resource,
+            timeout: millis("OTEL_EXPORTER_OTLP_TIMEOUT", 10_000)?,
+            schedule_delay: millis("OTEL_BSP_SCHEDULE_DELAY", 5_000)?,
+            metric_interval: millis("OTEL_METRIC_EXPORT_INTERVAL", 60_000)?,
+        }))
+    }
+}
+
+/// Parses `key1=value1,key2=value2` lists as used by the `OTEL_` variables.
+fn key_values(s: Option<&str>) -> Vec<(String, String)> {
+    s.unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct Span {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    name: &'static str,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+    error: bool,
+}
+
+impl Span {
+    fn to_json(&self) -> Value {
+        let mut span = json!({
+            "traceId": hex(&self.trace_id),
+            "spanId": hex(&self.span_id),
+            "name": self.name,
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(self.start),
+            "endTimeUnixNano": unix_nanos(self.end),
+            "attributes": attributes(self.attributes.iter().map(|(k, v)| (*k, v.as_str()))),
+            "status": { "code": if self.error { 2 } else { 1 } },
+        });
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = json!(hex(parent));
+        }
+        span
+    }
+}
+
+enum Message {
+    Span(Span),
+    Flush(std::sync::mpsc::Sender<()>),
+}
+
+/// Counter values keyed by metric name and attributes.
+type Counters = BTreeMap<(&'static str, Vec<(String, String)>), u64>;
+
+struct Exporter {
+    config: OtelConfig,
+    queue: SyncSender<Message>,
+    counters: Mutex<Counters>,
+    gauges: Mutex<BTreeMap<&'static str, usize>>,
+    start: SystemTime,
+}
+
+impl Exporter {
+    fn start(config: OtelConfig) -> Self {
+        let (queue, rx) = std::sync::mpsc::sync_channel(MAX_QUEUE_SIZE);
+        std::thread::spawn(move || export_loop(&rx));
+        Self {
+            config,
+            queue,
+            counters: Mutex::new(BTreeMap::new()),
+            gauges: Mutex::new(BTreeMap::new()),
+            start: SystemTime::now(),
+        }
+    }
+
+    fn export(&self, span: Span) {
+        if self.queue.try_send(Message::Span(span)).is_err() {
+            warn!("opentelemetry export queue is full, dropping span");
+        }
+    }
+
+    fn count(&self, name: &'static str, attributes: &[(&str, &str)]) {
+        let key = attributes
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry((name, key))
+            .or_default() += 1;
+    }
+
+    fn gauge(&self, name: &'static str, value: usize) {
+        self.gauges.lock().unwrap().insert(name, value);
+    }
+
+    fn resource(&self) -> Value {
+        json!({
+            "attributes": attributes(
+                self.config
+                    .resource
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+            )
+        })
+    }
+
+    fn traces_body(&self, spans: &[Span]) -> Value {
+        json!({
+            "resourceSpans": [{
+                "resource": self.resource(),
+                "scopeSpans": [{
+                    "scope": { "name": "log-watchdog" },
+                    "spans": spans.iter().map(Span::to_json).collect::<Vec<_>>(),
+                }],
+            }],
+        })
+    }
+
+    fn metrics_body(&self) -> Value {
+        let now = unix_nanos(SystemTime::now());
+        let start = unix_nanos(self.start);
+        let mut metrics: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+        for ((name, attrs), value) in self.counters.lock().unwrap().iter() {
+            metrics.entry(name).or_default().push(json!({
+                "attributes": attributes(attrs.iter().map(|(k, v)| (k.as_str(), v.as_str()))),
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "asInt": value.to_string(),
+            }));
+        }
+
+        json!({
+            "resourceMetrics": [{
+                "resource": self.resource(),
+                "scopeMetrics": [{
+                    "scope": { "name": "log-watchdog" },
+                    "metrics": metrics.into_iter().map(|(name, data_points)| json!({
+                        "name": name,
+                        "sum": {
+                            "aggregationTemporality": 2,
+                            "isMonotonic": true,
+                            "dataPoints": data_points,
+                        },
+                    })).chain(self.gauges.lock().unwrap().iter().map(|(name, value)| json!({
+                        "name": name,
+                        "gauge": {
+                            "dataPoints": [{
+                                "timeUnixNano": now,
+                                "asInt": value.to_string(),
+                            }],
+                        },
+                    }))).collect::<Vec<_>>(),
+                }],
+            }],
+        })
+    }
+
+    fn send(&self, url: &Url, body: &Value) {
+        let result = http::post(
+            url,
+            &self.config.headers,
+            "application/json",
+            body.to_string().as_bytes(),
+            self.config.timeout,
+        );
+        match result {
+            Ok(status) if (200..300).contains(&status) => (),
+            Ok(status) => warn!("opentelemetry export to {url:?} failed with status {status}"),
+            Err(e) => warn!("opentelemetry export to {url:?} failed: {e}"),
+        }
+    }
+}
+
+fn export_loop(rx: &Receiver<Message>) {
+    // the exporter is stored in the OnceLock before any span is queued
+    let Some(exporter) = EXPORTER.wait() else {
+        return;
+    };
+    let mut batch = Vec::new();
+    let mut last_spans = Instant::now();
+    let mut last_metrics = Instant::now();
+
+    loop {
+        let flush = match rx.recv_timeout(exporter.config.schedule_delay) {
+            Ok(Message::Span(span)) => {
+                batch.push(span);
+                None
+            }
+            Ok(Message::Flush(ack)) => Some(ack),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        if !batch.is_empty()
+            && (flush.is_some()
+                || batch.len() >= MAX_EXPORT_BATCH_SIZE
+                || last_spans.elapsed() >= exporter.config.schedule_delay)
+        {
+            exporter.send(&exporter.config.traces, &exporter.traces_body(&batch));
+            batch.clear();
+            last_spans = Instant::now();
+        }
+
+        if flush.is_some() || last_metrics.elapsed() >= exporter.config.metric_interval {
+            exporter.send(&exporter.config.metrics, &exporter.metrics_body());
+            last_metrics = Instant::now();
+        }
+
+        if let Some(ack) = flush {
+            let _ = ack.send(());
+        }
+    }
+}
+
+fn attributes<'a>(attributes: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<Value> {
+    attributes
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect()
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Option<OtelConfig>, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+        OtelConfig::from_env(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn test_when_no_env_then_defaults() {
+        let config = config(&[]).unwrap().unwrap();
+
+        assert_eq!(
+            config.traces,
+            Url::from_str("http://localhost:4318/v1/traces").unwrap()
+        );
+        assert_eq!(
+            config.metrics,
+            Url::from_str("http://localhost:4318/v1/metrics").unwrap()
+        );
+        assert_eq!(
+            config.resource,
+            vec![("service.name".to_string(), "log-watchdog".to_string())]
+        );
+        assert_eq!(config.schedule_delay, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_when_env_then_endpoints_headers_and_resource_are_read() {
+        let config = config(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"),
+            (
+                "OTEL_EXPORTER_OTLP_METRICS_ENDPOINT",
+                "http://metrics:9090/otlp",
+            ),
+            ("OTEL_EXPORTER_OTLP_HEADERS", "x-api-key=secret, x-team=db"),
+            ("OTEL_RESOURCE_ATTRIBUTES", "service.name=wd,host.name=db1"),
+        ])
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            config.traces,
+            Url::from_str("http://collector:4318/v1/traces").unwrap()
+        );
+        assert_eq!(
+            config.metrics,
+            Url::from_str("http://metrics:9090/otlp").unwrap()
+        );
+        assert_eq!(
+            config.headers,
+            vec![
+                ("x-api-key".to_string(), "secret".to_string()),
+                ("x-team".to_string(), "db".to_string())
+            ]
+        );
+        assert_eq!(
+            config.resource,
+            vec![
+                ("service.name".to_string(), "wd".to_string()),
+                ("host.name".to_string(), "db1".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_https_certificates_then_endpoints_use_them() {
+        let config = config(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "https://collector:4318"),
+            (
+                "OTEL_EXPORTER_OTLP_CERTIFICATE",
+                "/etc/ssl/collector-ca.crt",
+            ),
+            ("OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE", "client.crt"),
+            ("OTEL_EXPORTER_OTLP_CLIENT_KEY", "client.key"),
+        ])
+        .unwrap()
+        .unwrap();
+
+        assert!(config.traces.https);
+        assert_eq!(config.metrics.tls, config.traces.tls);
+        assert_eq!(
+            config.traces.tls.ca_file,
+            Some(PathBuf::from("/etc/ssl/collector-ca.crt"))
+        );
+        assert_eq!(config.traces.tls.client_key, Some("client.key".into()));
+    }
+
+    #[test]
+    fn test_when_disabled_or_unsupported_endpoint_then_no_exporter() {
+        assert_eq!(config(&[("OTEL_SDK_DISABLED", "true")]), Ok(None));
+        assert!(config(&[("OTEL_EXPORTER_OTLP_ENDPOINT", "grpc://collector")]).is_err());
+    }
+
+    #[test]
+    fn test_when_child_span_then_json_has_parent_and_status() {
+        let span = Span {
+            trace_id: [1; 16],
+            span_id: [2; 8],
+            parent_span_id: Some([3; 8]),
+            name: "command",
+            start: UNIX_EPOCH + Duration::from_secs(1),
+            end: UNIX_EPOCH + Duration::from_secs(2),
+            attributes: vec![("command", "curl".into())],
+            error: true,
+        };
+
+        let json = span.to_json();
+
+        assert_eq!(json["traceId"], "01010101010101010101010101010101");
+        assert_eq!(json["parentSpanId"], "0303030303030303");
+        assert_eq!(json["startTimeUnixNano"], "1000000000");
+        assert_eq!(json["status"]["code"], 2);
+        assert_eq!(json["attributes"][0]["value"]["stringValue"], "curl");
+    }
+}
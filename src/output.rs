@@ -0,0 +1,1598 @@
+use std::{
+    fs::{File, OpenOptions, Permissions},
+    io::{self, BufWriter, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::unix::{fs::PermissionsExt, net::UnixStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use log::{Level, Record};
+use logging::{Append, JournaldAppender, Rotation, SyslogAppender};
+use regex::Regex;
+use serde_json::json;
+use settings::{
+    Column, EmptyOutput, Field, FileOptions, FlushPolicy, OutputFormat, OutputTarget, Redactions,
+    Watchdog,
+};
+
+use crate::rotation;
+
+/// Longest stderr of a command that's kept in its output, in bytes.
+const MAX_STDERR_LEN: usize = 4096;
+
+/// The result of a command run for a match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOutput {
+    pub command: String,
+    pub stdout: String,
+    /// At most [`MAX_STDERR_LEN`] bytes of the command's stderr
+    pub stderr: String,
+    /// `None` if the command was killed by a signal
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+}
+
+impl CommandOutput {
+    pub fn new(command: &str, output: &std::process::Output, duration: Duration) -> Self {
+        let mut stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        if stderr.len() > MAX_STDERR_LEN {
+            let mut end = MAX_STDERR_LEN;
+            while !stderr.is_char_boundary(end) {
+                end -= 1;
+            }
+            stderr.truncate(end);
+            stderr.push_str("...");
+        }
+
+        Self {
+            command: command.to_string(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr,
+            exit_code: output.status.code(),
+            duration,
+        }
+    }
+
+    /// If false, the command printed nothing, or only whitespace.
+    pub fn has_stdout(&self) -> bool {
+        !self.stdout.trim().is_empty()
+    }
+
+    /// If true, both commands printed and exited the same, however long they
+    /// took.
+    fn same_result(&self, other: &Self) -> bool {
+        self.command == other.command
+            && self.stdout == other.stdout
+            && self.stderr == other.stderr
+            && self.exit_code == other.exit_code
+    }
+}
+
+/// Everything written to the output file for a single match.
+#[derive(Debug)]
+pub struct MatchRecord<'a> {
+    pub timestamp: SystemTime,
+    pub watchdog: &'a str,
+    pub line: &'a str,
+    /// The regex's capture groups that matched, by name or index
+    pub captures: Vec<(String, String)>,
+    pub commands: &'a [CommandOutput],
+}
+
+/// The capture groups of `regex` that matched `line`, named groups by name and
+/// the others by their index.
+pub fn captures(regex: &Regex, line: &str) -> Vec<(String, String)> {
+    let Some(captures) = regex.captures(line) else {
+        return Vec::new();
+    };
+
+    regex
+        .capture_names()
+        .enumerate()
+        .skip(1)
+        .filter_map(|(i, name)| {
+            let value = captures.get(i)?.as_str().to_string();
+            Some((
+                name.map_or_else(|| i.to_string(), ToString::to_string),
+                value,
+            ))
+        })
+        .collect()
+}
+
+/// A watchdog's outputs, writing match records to each in its format.
+pub struct Output {
+    watchdog: String,
+    target: String,
+    flush: FlushPolicy,
+    sinks: Vec<(Sink, OutputFormat)>,
+    repeats: Option<Repeats>,
+    empty: EmptyOutput,
+    suppressed: bool,
+    redact: Redactions,
+}
+
+enum Sink {
+    /// Buffered, and flushed according to the watchdog's flush policy
+    File(Arc<Mutex<OutputFile>>),
+    /// Stdout or stderr, flushed after every record
+    Stream(Box<dyn Write + Send>),
+    /// Syslog or journald, a message per line of a record
+    Appender(Box<dyn Append>),
+    /// A unix or TCP socket, reconnected when a write fails
+    Socket(Socket),
+    /// Loki's push API, a request per record
+    #[cfg(feature = "loki")]
+    Loki(crate::loki::LokiPush),
+    /// Elasticsearch's bulk API, a request per record
+    #[cfg(feature = "elasticsearch")]
+    Elasticsearch(crate::elasticsearch::ElasticsearchBulk),
+}
+
+impl Output {
+    pub fn open(watchdog: &Watchdog) -> io::Result<Self> {
+        let sinks = watchdog
+            .outputs
+            .iter()
+            .map(|output| {
+                let sink = match &output.target {
+                    OutputTarget::File(path) => {
+                        let (file, opened) =
+                            shared_file(path, &watchdog.file_options, header(&output.format))?;
+                        if let (true, FlushPolicy::Interval(interval)) =
+                            (opened, watchdog.file_options.flush)
+                        {
+                            let weak = Arc::downgrade(&file);
+                            std::thread::spawn(move || flush_periodically(&weak, interval));
+                        }
+                        Sink::File(file)
+                    }
+                    OutputTarget::Stdout => Sink::Stream(Box::new(io::stdout())),
+                    OutputTarget::Stderr => Sink::Stream(Box::new(io::stderr())),
+                    OutputTarget::Syslog(facility) => {
+                        Sink::Appender(Box::new(SyslogAppender::new(*facility)?))
+                    }
+                    OutputTarget::Journald => Sink::Appender(Box::new(JournaldAppender::new()?)),
+                    OutputTarget::Unix(_) | OutputTarget::Tcp(_) => {
+                        Sink::Socket(Socket::new(output.target.clone()))
+                    }
+                    #[cfg(feature = "loki")]
+                    OutputTarget::Loki(url) => {
+                        Sink::Loki(crate::loki::LokiPush::new(url, &output.tls, &watchdog.name)?)
+                    }
+                    #[cfg(not(feature = "loki"))]
+                    OutputTarget::Loki(_) => {
+                        return Err(io::Error::other(
+                            "loki outputs need log-watchdog built with the loki feature",
+                        ))
+                    }
+                    #[cfg(feature = "elasticsearch")]
+                    OutputTarget::Elasticsearch { url, index } => Sink::Elasticsearch(
+                        crate::elasticsearch::ElasticsearchBulk::new(
+                            url,
+                            &output.tls,
+                            index,
+                            &watchdog.name,
+                        )?,
+                    ),
+                    #[cfg(not(feature = "elasticsearch"))]
+                    OutputTarget::Elasticsearch { .. } => {
+                        return Err(io::Error::other(
+                            "elasticsearch outputs need log-watchdog built with the elasticsearch feature",
+                        ))
+                    }
+                };
+                Ok((sink, output.format.clone()))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            watchdog: watchdog.name.clone(),
+            target: logging::watchdog_target(&watchdog.name),
+            flush: watchdog.file_options.flush,
+            sinks,
+            repeats: watchdog.file_options.dedup.then(Repeats::default),
+            empty: watchdog.file_options.empty,
+            suppressed: watchdog.file_options.suppressed,
+            redact: watchdog.redact.clone(),
+        })
+    }
+
+    /// If true, records need the regex's captures.
+    pub fn needs_captures(&self) -> bool {
+        self.sinks.iter().any(|(_, format)| format.needs_captures())
+    }
+
+    /// Writes a record to every output, each with a single write so records
+    /// of watchdogs sharing stdout aren't interleaved. A failing output doesn't
+    /// keep the record from the others, the first error is returned.
+    ///
+    /// With `output_dedup`, a record with the same command output as the
+    /// previous one is only counted, see [`Repeats`].
+    ///
+    /// With `output_empty: skip`, commands without stdout are left out of the
+    /// record, and a record without any commands left isn't written.
+    ///
+    /// The watchdog's `redact` patterns are masked in the line, its captures
+    /// and the commands' output.
+    pub fn write(&mut self, record: &MatchRecord) -> io::Result<()> {
+        let (line, commands): (String, Vec<_>);
+        let redacted;
+        let record = if self.redact.is_empty() {
+            record
+        } else {
+            line = self.redact.redact(record.line).into_owned();
+            commands = record
+                .commands
+                .iter()
+                .map(|output| CommandOutput {
+                    stdout: self.redact.redact(&output.stdout).into_owned(),
+                    stderr: self.redact.redact(&output.stderr).into_owned(),
+                    ..output.clone()
+                })
+                .collect();
+            redacted = MatchRecord {
+                line: &line,
+                captures: record
+                    .captures
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = self.redact.redact_part(record.line, value);
+                        (name.clone(), value.into_owned())
+                    })
+                    .collect(),
+                commands: &commands,
+                ..*record
+            };
+            &redacted
+        };
+
+        let kept: Vec<_>;
+        let skipped;
+        let record = if self.empty == EmptyOutput::Skip {
+            kept = record
+                .commands
+                .iter()
+                .filter(|output| output.has_stdout())
+                .cloned()
+                .collect();
+            if kept.is_empty() {
+                return Ok(());
+            }
+            skipped = MatchRecord {
+                captures: record.captures.clone(),
+                commands: &kept,
+                ..*record
+            };
+            &skipped
+        } else {
+            record
+        };
+
+        let mut result = Ok(());
+        if let Some(repeats) = &mut self.repeats {
+            let repeated = repeats.repeated(record.commands);
+            if let Some(count) = repeats.summary(!repeated) {
+                result = self.write_summary(record.timestamp, count);
+            }
+            if repeated {
+                return result;
+            }
+        }
+
+        let empty = self.empty;
+        let written =
+            self.write_messages(Some(record.line), |format| messages(format, record, empty));
+        result.and(written)
+    }
+
+    /// With `output_suppressed`, writes "N matches suppressed by debounce"
+    /// in each output's format, for the debounce that just expired.
+    pub fn write_suppressed(&mut self, timestamp: SystemTime, count: u64) -> io::Result<()> {
+        if !self.suppressed {
+            return Ok(());
+        }
+        let watchdog = self.watchdog.clone();
+        self.write_messages(None, |format| {
+            vec![(
+                None,
+                summary(format, &watchdog, timestamp, Summary::Suppressed(count)),
+            )]
+        })
+    }
+
+    /// Writes "last record repeated N times" in each output's format.
+    fn write_summary(&mut self, timestamp: SystemTime, count: u64) -> io::Result<()> {
+        let watchdog = self.watchdog.clone();
+        self.write_messages(None, |format| {
+            vec![(
+                None,
+                summary(format, &watchdog, timestamp, Summary::Repeated(count)),
+            )]
+        })
+    }
+
+    fn write_messages<'r>(
+        &mut self,
+        line: Option<&str>,
+        messages: impl Fn(&OutputFormat) -> Vec<(Option<&'r str>, String)>,
+    ) -> io::Result<()> {
+        let mut result = Ok(());
+        for (sink, format) in &mut self.sinks {
+            let messages = messages(format);
+            let written = match sink {
+                Sink::File(file) => lock(file).and_then(|mut file| {
+                    file.write(render(&messages).as_bytes())?;
+                    match self.flush {
+                        FlushPolicy::EveryWrite => file.flush()?,
+                        FlushPolicy::Interval(_) | FlushPolicy::OnExit => (),
+                    }
+                    file.roll_if_due()
+                }),
+                Sink::Stream(writer) => writer
+                    .write_all(render(&messages).as_bytes())
+                    .and_then(|()| writer.flush()),
+                Sink::Appender(appender) => {
+                    append(appender.as_ref(), &self.target, line, &messages)
+                }
+                Sink::Socket(socket) => socket.write(render(&messages).as_bytes()),
+                #[cfg(feature = "loki")]
+                Sink::Loki(loki) => loki.push(&messages),
+                #[cfg(feature = "elasticsearch")]
+                Sink::Elasticsearch(bulk) => bulk.index(&messages, SystemTime::now().into()),
+            };
+            if result.is_ok() {
+                result = written;
+            }
+        }
+        result
+    }
+}
+
+impl Drop for Output {
+    /// Writes the summary of a run of repeats that's still going.
+    fn drop(&mut self) {
+        if let Some(count) = self.repeats.as_mut().and_then(Repeats::take) {
+            let _ = self.write_summary(SystemTime::now(), count);
+        }
+    }
+}
+
+/// Tracks runs of records with the same command output, so that they can be
+/// written once along with a "last record repeated N times" summary.
+///
+/// The summary is written when a different record arrives, when the watchdog
+/// stops, and otherwise with the first repeat after [`REPEAT_SUMMARY_INTERVAL`],
+/// so a flapping condition still shows up in the output.
+#[derive(Debug, Default)]
+struct Repeats {
+    last: Option<Vec<CommandOutput>>,
+    count: u64,
+    since: Option<Instant>,
+}
+
+/// How often an ongoing run of repeated records is summarized at most.
+const REPEAT_SUMMARY_INTERVAL: Duration = Duration::from_mins(1);
+
+impl Repeats {
+    /// Counts the record if it repeats the last one, or makes it the last one.
+    fn repeated(&mut self, commands: &[CommandOutput]) -> bool {
+        let repeated = self.last.as_ref().is_some_and(|last| {
+            last.len() == commands.len() && last.iter().zip(commands).all(|(a, b)| a.same_result(b))
+        });
+        if repeated {
+            self.count += 1;
+            return true;
+        }
+        self.last = Some(commands.to_vec());
+        false
+    }
+
+    /// Takes the repeat count if a summary is due: always on a new record,
+    /// otherwise once the interval has passed.
+    fn summary(&mut self, new_record: bool) -> Option<u64> {
+        let since = *self.since.get_or_insert_with(Instant::now);
+        if new_record || since.elapsed() >= REPEAT_SUMMARY_INTERVAL {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self) -> Option<u64> {
+        self.since = Some(Instant::now());
+        Some(std::mem::take(&mut self.count)).filter(|count| *count > 0)
+    }
+}
+
+/// Opens an output file for appending, or truncates it if `append` is off,
+/// setting the configured permissions and owner if it's created.
+fn open_file(path: &Path, options: &FileOptions) -> io::Result<File> {
+    let created = !path.exists();
+    let file = OpenOptions::new()
+        .append(options.append)
+        .write(true)
+        .truncate(!options.append)
+        .create(true)
+        .open(path)?;
+    if !created {
+        return Ok(file);
+    }
+
+    if let Some(owner) = &options.owner {
+        let uid = owner
+            .user
+            .as_deref()
+            .map(|user| lookup_id(Path::new("/etc/passwd"), user))
+            .transpose()?;
+        let gid = owner
+            .group
+            .as_deref()
+            .map(|group| lookup_id(Path::new("/etc/group"), group))
+            .transpose()?;
+        std::os::unix::fs::fchown(&file, uid, gid)?;
+    }
+    // set explicitly rather than on open, where the umask would apply
+    if let Some(mode) = options.mode {
+        file.set_permissions(Permissions::from_mode(mode))?;
+    }
+
+    Ok(file)
+}
+
+/// Resolves a user or group to its id, from `/etc/passwd` or `/etc/group`.
+pub(crate) fn lookup_id(database: &Path, name: &str) -> io::Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+
+    std::fs::read_to_string(database)?
+        .lines()
+        .find_map(|entry| {
+            let mut fields = entry.split(':');
+            (fields.next()? == name).then(|| fields.nth(1)?.parse().ok())?
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{name} not found in {}", database.display()),
+            )
+        })
+}
+
+/// An output file, synced to disk on every flush if `fsync` is set, and rolled
+/// over according to `output_rotation`. Whatever is still buffered is flushed
+/// when the watchdog stops.
+struct OutputFile {
+    path: PathBuf,
+    options: FileOptions,
+    /// Written first to every new or empty file, e.g. a CSV header row
+    header: Option<String>,
+    writer: BufWriter<File>,
+    /// Size of the file, to check size based rotation against
+    size: u64,
+    /// When the file was opened, to check daily rotation against
+    opened: NaiveDate,
+    /// The last rolled file's compression, finished before the next roll
+    /// renames it
+    compressing: Option<JoinHandle<()>>,
+}
+
+impl OutputFile {
+    fn open(path: &Path, options: &FileOptions, header: Option<String>) -> io::Result<Self> {
+        let file = open_file(path, options)?;
+        let mut file = Self {
+            path: path.to_path_buf(),
+            options: options.clone(),
+            size: file.metadata()?.len(),
+            writer: BufWriter::new(file),
+            opened: Utc::now().date_naive(),
+            header,
+            compressing: None,
+        };
+        if file.size == 0 {
+            if let Some(header) = file.header.clone() {
+                file.write(header.as_bytes())?;
+            }
+        }
+        Ok(file)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Rolls the file over once it reaches its size limit, or on the first
+    /// write of a new day.
+    fn roll_if_due(&mut self) -> io::Result<()> {
+        let due = match self.options.rotation {
+            None => false,
+            Some(Rotation::Size(limit)) => self.size >= limit,
+            Some(Rotation::Daily) => Utc::now().date_naive() != self.opened,
+        };
+        if !due {
+            return Ok(());
+        }
+
+        self.flush()?;
+        self.finish_compressing();
+        let compressing =
+            rotation::roll(&self.path, self.options.retention, self.options.compression)?;
+        let mut rolled = Self::open(&self.path, &self.options, self.header.take())?;
+        rolled.compressing = compressing;
+        // the old writer is already flushed, replacing it needn't flush again
+        *self = rolled;
+        Ok(())
+    }
+
+    /// Waits for the last rolled file to be compressed, which logs its own
+    /// errors.
+    fn finish_compressing(&mut self) {
+        if let Some(compressing) = self.compressing.take() {
+            let _ = compressing.join();
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.options.fsync {
+            self.writer.get_ref().sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        self.finish_compressing();
+    }
+}
+
+/// The output files that are open, by path, so that watchdogs writing to the
+/// same file share it, and their records are written whole, one at a time,
+/// instead of interleaved by separate buffers.
+static OUTPUT_FILES: Mutex<Vec<(PathBuf, Weak<Mutex<OutputFile>>)>> = Mutex::new(Vec::new());
+
+/// The output file at `path` if it's open, or opened now, along with whether
+/// it was. A shared file is written as the watchdog that opened it says, so
+/// the others must have the same file options and header.
+fn shared_file(
+    path: &Path,
+    options: &FileOptions,
+    header: Option<String>,
+) -> io::Result<(Arc<Mutex<OutputFile>>, bool)> {
+    let absolute = std::path::absolute(path)?;
+    let mut files = OUTPUT_FILES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    files.retain(|(_, file)| file.strong_count() > 0);
+    if let Some(file) = files
+        .iter()
+        .find(|(open, _)| *open == absolute)
+        .and_then(|(_, file)| file.upgrade())
+    {
+        let shared = lock(&file)?;
+        if !shared.options.same_file(options) || shared.header != header {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path:?} is written by another watchdog with different file_options or formats"),
+            ));
+        }
+        drop(shared);
+        return Ok((file, false));
+    }
+    let file = Arc::new(Mutex::new(OutputFile::open(path, options, header)?));
+    files.push((absolute, Arc::downgrade(&file)));
+    Ok((file, true))
+}
+
+fn lock(file: &Mutex<OutputFile>) -> io::Result<MutexGuard<'_, OutputFile>> {
+    file.lock()
+        .map_err(|_| io::Error::other("output file lock poisoned"))
+}
+
+/// Flushes the file until the watchdog's output is dropped.
+fn flush_periodically(file: &Weak<Mutex<OutputFile>>, interval: Duration) {
+    loop {
+        std::thread::sleep(interval);
+        let Some(file) = file.upgrade() else {
+            return;
+        };
+        if let Ok(mut file) = file.lock() {
+            let _ = file.flush();
+        };
+    }
+}
+
+/// A connection to a collector listening on a unix or TCP socket.
+///
+/// The collector needn't be listening when the watchdog starts: the socket is
+/// connected on the first write, and reconnected once when a write fails, so
+/// a restarted collector picks up where it left off.
+struct Socket {
+    target: OutputTarget,
+    stream: Option<Box<dyn Write + Send>>,
+}
+
+/// How long connecting to, or writing to, a TCP collector may take.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Socket {
+    const fn new(target: OutputTarget) -> Self {
+        Self {
+            target,
+            stream: None,
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        // a stale connection is only noticed when writing to it
+        let reconnected = self.stream.is_none();
+        match self.write_once(buf) {
+            Err(_) if !reconnected => self.write_once(buf),
+            written => written,
+        }
+    }
+
+    fn write_once(&mut self, buf: &[u8]) -> io::Result<()> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => self.stream.insert(self.connect()?),
+        };
+        let written = stream.write_all(buf).and_then(|()| stream.flush());
+        if written.is_err() {
+            self.stream = None;
+        }
+        written
+    }
+
+    fn connect(&self) -> io::Result<Box<dyn Write + Send>> {
+        match &self.target {
+            OutputTarget::Unix(path) => Ok(Box::new(UnixStream::connect(path)?)),
+            OutputTarget::Tcp(address) => {
+                let address = address
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| io::Error::other(format!("{address} did not resolve")))?;
+                let stream = TcpStream::connect_timeout(&address, SOCKET_TIMEOUT)?;
+                stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+                Ok(Box::new(stream))
+            }
+            target => unreachable!("{target:?} is not a socket"),
+        }
+    }
+}
+
+/// Sends every message as a log record of the watchdog's target, with the
+/// matched line and command as `line` and `command` MDC fields.
+fn append(
+    appender: &dyn Append,
+    target: &str,
+    line: Option<&str>,
+    messages: &[(Option<&str>, String)],
+) -> io::Result<()> {
+    let _line = line.map(|line| log_mdc::insert_scoped("line", line));
+    for (command, message) in messages {
+        let _command = command.map(|command| log_mdc::insert_scoped("command", command));
+        appender
+            .append(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target(target)
+                    .args(format_args!("{message}"))
+                    .build(),
+            )
+            .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+/// Renders messages as lines.
+fn render(messages: &[(Option<&str>, String)]) -> String {
+    messages
+        .iter()
+        .map(|(_, message)| format!("{message}\n"))
+        .collect()
+}
+
+/// What a summary record counts.
+#[derive(Debug, Clone, Copy)]
+enum Summary {
+    /// A run of records with the same command output, see [`Repeats`]
+    Repeated(u64),
+    /// The matches a debounce kept from being acted on
+    Suppressed(u64),
+}
+
+/// A summary record, a JSON object for JSON Lines and a row with the message
+/// as `stdout` for CSV.
+fn summary(
+    format: &OutputFormat,
+    watchdog: &str,
+    timestamp: SystemTime,
+    summary: Summary,
+) -> String {
+    let (message, key, count) = match summary {
+        Summary::Repeated(count) => (
+            format!("last record repeated {count} times"),
+            "repeated",
+            count,
+        ),
+        Summary::Suppressed(count) => (
+            format!("{count} matches suppressed by debounce"),
+            "suppressed",
+            count,
+        ),
+    };
+    match format {
+        OutputFormat::Template(_) => message,
+        OutputFormat::Csv(columns) => {
+            let timestamp = rfc3339(timestamp);
+            csv_row(columns.iter().map(|column| match column {
+                Column::Field(Field::Timestamp) => timestamp.as_str(),
+                Column::Field(Field::Watchdog) => watchdog,
+                Column::Field(Field::Stdout) => message.as_str(),
+                Column::Field(_) | Column::Capture(_) => "",
+            }))
+        }
+        OutputFormat::Jsonl => {
+            let mut summary = json!({
+                "timestamp": rfc3339(timestamp),
+                "watchdog": watchdog,
+            });
+            summary[key] = count.into();
+            summary.to_string()
+        }
+    }
+}
+
+/// The messages of a match record, along with the command each is for: one
+/// per command for templates and CSV, or a single one for JSON Lines.
+fn messages<'r>(
+    format: &OutputFormat,
+    record: &'r MatchRecord,
+    empty: EmptyOutput,
+) -> Vec<(Option<&'r str>, String)> {
+    let timestamp = rfc3339(record.timestamp);
+
+    match format {
+        OutputFormat::Template(template) => record
+            .commands
+            .iter()
+            .map(|output| {
+                let fields = Fields::new(record, output, &timestamp, empty);
+                let message = template.render(|field| fields.get(field));
+                (Some(output.command.as_str()), message)
+            })
+            .collect(),
+        OutputFormat::Jsonl => vec![(None, jsonl(record, timestamp, empty).to_string())],
+        OutputFormat::Csv(columns) => record
+            .commands
+            .iter()
+            .map(|output| {
+                let fields = Fields::new(record, output, &timestamp, empty);
+                let row = csv_row(columns.iter().map(|column| {
+                    match column {
+                        Column::Field(field) => fields.get(*field),
+                        Column::Capture(name) => record
+                            .captures
+                            .iter()
+                            .find(|(capture, _)| capture == name)
+                            .map_or("", |(_, value)| value),
+                    }
+                }));
+                (Some(output.command.as_str()), row)
+            })
+            .collect(),
+    }
+}
+
+/// The values of a command's fields in a match record.
+struct Fields<'a> {
+    record: &'a MatchRecord<'a>,
+    output: &'a CommandOutput,
+    timestamp: &'a str,
+    exit_code: String,
+    duration_ms: String,
+    empty: EmptyOutput,
+}
+
+impl<'a> Fields<'a> {
+    fn new(
+        record: &'a MatchRecord,
+        output: &'a CommandOutput,
+        timestamp: &'a str,
+        empty: EmptyOutput,
+    ) -> Self {
+        Self {
+            record,
+            output,
+            timestamp,
+            exit_code: output
+                .exit_code
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+            duration_ms: output.duration.as_millis().to_string(),
+            empty,
+        }
+    }
+
+    fn get(&self, field: Field) -> &str {
+        match field {
+            Field::Timestamp => self.timestamp,
+            Field::Watchdog => self.record.watchdog,
+            Field::Line => self.record.line,
+            Field::Command => &self.output.command,
+            Field::Stdout if self.empty == EmptyOutput::Mark && !self.output.has_stdout() => {
+                NO_OUTPUT
+            }
+            Field::Stdout => trim_newline(&self.output.stdout),
+            Field::Stderr => trim_newline(&self.output.stderr),
+            Field::ExitCode => &self.exit_code,
+            Field::DurationMs => &self.duration_ms,
+        }
+    }
+}
+
+/// The header row of the `csv` format, other formats have none.
+fn header(format: &OutputFormat) -> Option<String> {
+    match format {
+        OutputFormat::Csv(columns) => Some(csv_row(columns.iter().map(Column::name)) + "\n"),
+        OutputFormat::Template(_) | OutputFormat::Jsonl => None,
+    }
+}
+
+/// Joins values into a CSV row, quoting the ones that contain a separator,
+/// quote or line break as in RFC 4180.
+fn csv_row<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values
+        .map(|value| {
+            if value.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// What `output_empty: mark` writes as the stdout of a command that printed
+/// nothing.
+const NO_OUTPUT: &str = "(no output)";
+
+fn jsonl(record: &MatchRecord, timestamp: String, empty: EmptyOutput) -> serde_json::Value {
+    let captures: serde_json::Map<_, _> = record
+        .captures
+        .iter()
+        .map(|(name, value)| (name.clone(), value.as_str().into()))
+        .collect();
+    let commands: Vec<_> = record
+        .commands
+        .iter()
+        .map(|output| {
+            json!({
+                "command": output.command,
+                "stdout": (empty != EmptyOutput::Mark || output.has_stdout())
+                    .then(|| trim_newline(&output.stdout)),
+                "stderr": trim_newline(&output.stderr),
+                "exit_code": output.exit_code,
+                "duration_ms": u64::try_from(output.duration.as_millis()).unwrap_or(u64::MAX),
+            })
+        })
+        .collect();
+
+    json!({
+        "timestamp": timestamp,
+        "watchdog": record.watchdog,
+        "line": record.line,
+        "captures": captures,
+        "commands": commands,
+    })
+}
+
+fn trim_newline(output: &str) -> &str {
+    output.strip_suffix('\n').unwrap_or(output)
+}
+
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        os::unix::net::UnixListener,
+        str::FromStr,
+    };
+
+    use settings::{Compression, CompressionAlgorithm, FileOptions, OutputSettings, Tls};
+
+    use super::*;
+
+    #[test]
+    fn test_when_created_then_mode_and_owner_applied() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("out.txt");
+        let options = FileOptions {
+            mode: Some(0o600),
+            owner: Some(":0".parse().unwrap()),
+            ..FileOptions::default()
+        };
+
+        match open_file(&path, &options) {
+            Ok(_) => assert_eq!(
+                std::fs::metadata(&path).unwrap().permissions().mode() & 0o7777,
+                0o600
+            ),
+            // chown to a group the user isn't in needs privileges
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
+        }
+
+        std::fs::set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+        open_file(&path, &options).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o7777,
+            0o644,
+            "an existing file is left alone"
+        );
+    }
+
+    #[test]
+    fn test_when_not_append_then_truncated() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("out.txt");
+        std::fs::write(&path, "stale\n").unwrap();
+
+        let mut file = open_file(&path, &FileOptions::default()).unwrap();
+        writeln!(file, "appended").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "stale\nappended\n");
+
+        let options = FileOptions {
+            append: false,
+            ..FileOptions::default()
+        };
+        let mut file = open_file(&path, &options).unwrap();
+        writeln!(file, "fresh").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fresh\n");
+    }
+
+    #[test]
+    fn test_when_lookup_id_then_name_or_number_resolved() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let passwd = dir.path().join("passwd");
+        std::fs::write(
+            &passwd,
+            "root:x:0:0:root:/root:/bin/sh\npostgres:x:70:70::/var/lib/postgresql:/bin/sh\n",
+        )
+        .unwrap();
+
+        assert_eq!(lookup_id(&passwd, "postgres").unwrap(), 70);
+        assert_eq!(lookup_id(&passwd, "1000").unwrap(), 1000);
+        assert!(lookup_id(&passwd, "nobody").is_err());
+    }
+
+    fn watchdog(output: &Path, file_options: FileOptions) -> Watchdog {
+        Watchdog {
+            name: "pgbouncer".into(),
+            log_file: "pgbouncer.log".into(),
+            outputs: vec![OutputSettings {
+                target: OutputTarget::File(output.to_path_buf()),
+                format: OutputFormat::default(),
+                tls: Tls::default(),
+            }],
+            file_options,
+            debounce: 0,
+            debounce_mode: settings::DebounceMode::default(),
+            oneshot: false,
+            regex: Regex::new(".*").unwrap(),
+            commands: Vec::new(),
+            dry_run: false,
+            log_level: None,
+            log_sample_rate: 1,
+            max_read_bytes: settings::DEFAULT_MAX_READ_BYTES,
+            read_buffer_size: settings::DEFAULT_READ_BUFFER_SIZE,
+            partial_line_timeout: None,
+            file_missing: settings::FileMissing::default(),
+            on_file_missing: Vec::new(),
+            encoding: settings::Encoding::default(),
+            backend: None,
+            poll_interval: settings::DEFAULT_POLL_INTERVAL,
+            redact: settings::Redactions::default(),
+            alertmanager: None,
+            passive_check: None,
+            zabbix: None,
+            allowed_commands: None,
+            audit_log: None,
+            executor: None,
+            max_concurrent_commands: None,
+        }
+    }
+
+    /// Collects messages along with their `line` and `command` MDC fields.
+    #[derive(Debug, Default, Clone)]
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    impl Append for Collect {
+        fn append(&self, record: &Record) -> anyhow::Result<()> {
+            let field = |key| log_mdc::get(key, |v| v.unwrap_or_default().to_string());
+            self.0.lock().unwrap().push(format!(
+                "{} [{}] [{}] {}",
+                record.target(),
+                field("line"),
+                field("command"),
+                record.args()
+            ));
+            Ok(())
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn outputs() -> Vec<CommandOutput> {
+        vec![
+            CommandOutput {
+                command: "echo".into(),
+                stdout: "hello world!\n".into(),
+                stderr: String::new(),
+                exit_code: Some(0),
+                duration: Duration::from_millis(12),
+            },
+            CommandOutput {
+                command: "false".into(),
+                stdout: String::new(),
+                stderr: "failed\n".into(),
+                exit_code: Some(1),
+                duration: Duration::from_millis(3),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_when_default_format_then_stdout_per_command() {
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs,
+        };
+
+        assert_eq!(
+            render(&messages(
+                &OutputFormat::default(),
+                &record,
+                EmptyOutput::Write
+            )),
+            "hello world!\n\n"
+        );
+    }
+
+    #[test]
+    fn test_when_captures_then_named_or_indexed() {
+        let regex = Regex::new(r"^(?<level>\w+): (\d+)( connections)?(!)?").unwrap();
+
+        assert_eq!(
+            captures(&regex, "FATAL: 100 connections"),
+            vec![
+                ("level".into(), "FATAL".into()),
+                ("2".into(), "100".into()),
+                ("3".into(), " connections".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_jsonl_then_one_object_per_match() {
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "FATAL: 100",
+            captures: vec![("level".into(), "FATAL".into())],
+            commands: &outputs,
+        };
+
+        let rendered = render(&messages(&OutputFormat::Jsonl, &record, EmptyOutput::Write));
+
+        assert_eq!(rendered.lines().count(), 1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&rendered).unwrap(),
+            json!({
+                "timestamp": "1970-01-01T00:00:00.000Z",
+                "watchdog": "pgbouncer",
+                "line": "FATAL: 100",
+                "captures": { "level": "FATAL" },
+                "commands": [
+                    {
+                        "command": "echo",
+                        "stdout": "hello world!",
+                        "stderr": "",
+                        "exit_code": 0,
+                        "duration_ms": 12,
+                    },
+                    {
+                        "command": "false",
+                        "stdout": "",
+                        "stderr": "failed",
+                        "exit_code": 1,
+                        "duration_ms": 3,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_when_template_then_rendered_per_command() {
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1_500),
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs,
+        };
+        let format = OutputFormat::from_str(
+            "{timestamp} {watchdog} {command}: {stdout}{stderr} ({exit_code}, {duration_ms}ms)",
+        )
+        .unwrap();
+
+        assert_eq!(
+            render(&messages(&format, &record, EmptyOutput::Write)),
+            "1970-01-01T00:00:01.500Z pgbouncer echo: hello world! (0, 12ms)\n\
+             1970-01-01T00:00:01.500Z pgbouncer false: failed (1, 3ms)\n"
+        );
+    }
+
+    #[test]
+    fn test_when_appender_then_message_per_command_with_fields() {
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs,
+        };
+        let collect = Collect::default();
+
+        append(
+            &collect,
+            "log_watchdog::watchdog::pgbouncer",
+            Some(record.line),
+            &messages(
+                &OutputFormat::from_str("{command}: {stdout}").unwrap(),
+                &record,
+                EmptyOutput::Write,
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            *collect.0.lock().unwrap(),
+            vec![
+                "log_watchdog::watchdog::pgbouncer [aaa] [echo] echo: hello world!",
+                "log_watchdog::watchdog::pgbouncer [aaa] [false] false: ",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_flush_policy_then_flushed_accordingly() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs[..1],
+        };
+        let open = |name, flush| {
+            let path = dir.path().join(name);
+            let output = Output::open(&watchdog(
+                &path,
+                FileOptions {
+                    flush,
+                    fsync: true,
+                    ..FileOptions::default()
+                },
+            ))
+            .unwrap();
+            (path, output)
+        };
+
+        let (every_write, mut output) = open("every_write.out", FlushPolicy::EveryWrite);
+        output.write(&record).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(every_write).unwrap(),
+            "hello world!\n"
+        );
+
+        let (on_exit, mut output) = open("on_exit.out", FlushPolicy::OnExit);
+        output.write(&record).unwrap();
+        assert_eq!(std::fs::read_to_string(&on_exit).unwrap(), "");
+        drop(output);
+        assert_eq!(std::fs::read_to_string(&on_exit).unwrap(), "hello world!\n");
+
+        let (interval, mut output) = open(
+            "interval.out",
+            FlushPolicy::Interval(Duration::from_millis(50)),
+        );
+        output.write(&record).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(std::fs::read_to_string(interval).unwrap(), "hello world!\n");
+    }
+
+    #[test]
+    fn test_when_watchdogs_share_output_file_then_records_written_in_order() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("shared.out");
+        let outputs = outputs();
+        let record = |line| MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line,
+            captures: Vec::new(),
+            commands: &outputs[..1],
+        };
+        let mut watchdog = watchdog(
+            &path,
+            FileOptions {
+                flush: FlushPolicy::OnExit,
+                ..FileOptions::default()
+            },
+        );
+        watchdog.outputs[0].format = OutputFormat::Jsonl;
+        let mut first = Output::open(&watchdog).unwrap();
+        let mut second = Output::open(&watchdog).unwrap();
+
+        first.write(&record("a")).unwrap();
+        second.write(&record("b")).unwrap();
+        first.write(&record("c")).unwrap();
+        drop(first);
+        drop(second);
+
+        // the buffer is shared too, rather than each flushed in turn
+        let lines: Vec<_> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["line"].clone())
+            .collect();
+        assert_eq!(lines, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_when_watchdogs_share_output_file_with_other_options_then_error() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("shared.out");
+        let first = watchdog(&path, FileOptions::default());
+        let second = watchdog(
+            &path,
+            FileOptions {
+                fsync: true,
+                ..FileOptions::default()
+            },
+        );
+        let mut csv = first.clone();
+        csv.outputs[0].format = OutputFormat::Csv(Column::defaults());
+
+        let _first = Output::open(&first).unwrap();
+
+        assert!(Output::open(&first).is_ok());
+        assert_eq!(
+            Output::open(&second).err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidInput)
+        );
+        assert!(Output::open(&csv).is_err());
+    }
+
+    #[test]
+    fn test_when_size_reached_then_rolled_over() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("out.txt");
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs[..1],
+        };
+        let mut output = Output::open(&watchdog(
+            &path,
+            FileOptions {
+                rotation: Some(Rotation::Size(20)),
+                retention: 1,
+                ..FileOptions::default()
+            },
+        ))
+        .unwrap();
+
+        for _ in 0..3 {
+            output.write(&record).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("out.txt.1")).unwrap(),
+            "hello world!\nhello world!\n"
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world!\n");
+    }
+
+    #[test]
+    fn test_when_rolled_quickly_then_every_rolled_file_compressed() {
+        if std::process::Command::new("gzip")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("out.txt");
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs[..1],
+        };
+        let mut output = Output::open(&watchdog(
+            &path,
+            FileOptions {
+                rotation: Some(Rotation::Size(1)),
+                retention: 3,
+                compression: Some(Compression {
+                    algorithm: CompressionAlgorithm::Gzip,
+                    level: None,
+                }),
+                ..FileOptions::default()
+            },
+        ))
+        .unwrap();
+
+        // every write after the first rolls the file over
+        for _ in 0..20 {
+            output.write(&record).unwrap();
+        }
+        drop(output);
+
+        for i in 1..=3 {
+            assert!(!dir.path().join(format!("out.txt.{i}")).exists());
+            assert!(dir.path().join(format!("out.txt.{i}.gz")).exists());
+        }
+    }
+
+    #[test]
+    fn test_when_dedup_then_repeats_summarized() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("out.txt");
+        let outputs = outputs();
+        let record = |commands| MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands,
+        };
+        let mut output = Output::open(&watchdog(
+            &path,
+            FileOptions {
+                dedup: true,
+                ..FileOptions::default()
+            },
+        ))
+        .unwrap();
+
+        for _ in 0..3 {
+            output.write(&record(&outputs[..1])).unwrap();
+        }
+        output.write(&record(&outputs[1..])).unwrap();
+        output.write(&record(&outputs[1..])).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "hello world!\nlast record repeated 2 times\n\n"
+        );
+
+        drop(output);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "hello world!\nlast record repeated 2 times\n\nlast record repeated 1 times\n"
+        );
+    }
+
+    #[test]
+    fn test_when_socket_then_records_sent_as_json_lines() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("collector.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs,
+        };
+        let mut watchdog = watchdog(&path, FileOptions::default());
+        watchdog.outputs = vec![OutputSettings {
+            target: OutputTarget::Unix(path.clone()),
+            format: OutputFormat::Jsonl,
+            tls: Tls::default(),
+        }];
+        let mut output = Output::open(&watchdog).unwrap();
+
+        output.write(&record).unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["watchdog"], "pgbouncer");
+        assert_eq!(value["commands"][0]["stdout"], "hello world!");
+    }
+
+    #[test]
+    fn test_when_collector_restarted_then_reconnected() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("collector.sock");
+        let mut socket = Socket::new(OutputTarget::Unix(path.clone()));
+
+        let listener = UnixListener::bind(&path).unwrap();
+        socket.write(b"first\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut first = String::new();
+        BufReader::new(stream).read_line(&mut first).unwrap();
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        let listener = UnixListener::bind(&path).unwrap();
+        socket.write(b"second\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut second = String::new();
+        BufReader::new(stream).read_line(&mut second).unwrap();
+
+        assert_eq!((first.as_str(), second.as_str()), ("first\n", "second\n"));
+    }
+
+    #[test]
+    fn test_when_tcp_then_written() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut socket = Socket::new(OutputTarget::Tcp(
+            listener.local_addr().unwrap().to_string(),
+        ));
+
+        socket.write(b"hello\n").unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "hello\n");
+    }
+
+    #[test]
+    fn test_when_no_collector_then_write_fails() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let mut socket = Socket::new(OutputTarget::Unix(dir.path().join("collector.sock")));
+
+        assert!(socket.write(b"hello\n").is_err());
+    }
+
+    #[test]
+    fn test_when_csv_then_row_per_command_with_captures() {
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "FATAL: \"db\", 100",
+            captures: vec![("level".into(), "FATAL".into())],
+            commands: &outputs,
+        };
+        let format = OutputFormat::Csv(vec![
+            Column::Field(Field::Watchdog),
+            Column::Capture("level".into()),
+            Column::Capture("missing".into()),
+            Column::Field(Field::Line),
+            Column::Field(Field::ExitCode),
+        ]);
+
+        assert_eq!(
+            render(&messages(&format, &record, EmptyOutput::Write)),
+            "pgbouncer,FATAL,,\"FATAL: \"\"db\"\", 100\",0\n\
+             pgbouncer,FATAL,,\"FATAL: \"\"db\"\", 100\",1\n"
+        );
+    }
+
+    #[test]
+    fn test_when_csv_file_empty_then_header_written() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("out.csv");
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs[..1],
+        };
+        let mut watchdog = watchdog(&path, FileOptions::default());
+        watchdog.outputs[0].format = OutputFormat::Csv(vec![
+            Column::Field(Field::Command),
+            Column::Field(Field::Stdout),
+        ]);
+
+        for _ in 0..2 {
+            Output::open(&watchdog).unwrap().write(&record).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "command,stdout\necho,hello world!\necho,hello world!\n"
+        );
+    }
+
+    #[test]
+    fn test_when_empty_skipped_then_only_commands_with_stdout_written() {
+        let dir = tempdir::TempDir::new("output_").unwrap();
+        let path = dir.path().join("out.txt");
+        let outputs = outputs();
+        let record = |commands| MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands,
+        };
+        let mut output = Output::open(&watchdog(
+            &path,
+            FileOptions {
+                empty: EmptyOutput::Skip,
+                ..FileOptions::default()
+            },
+        ))
+        .unwrap();
+
+        output.write(&record(&outputs)).unwrap();
+        output.write(&record(&outputs[1..])).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world!\n");
+    }
+
+    #[test]
+    fn test_when_empty_marked_then_no_output_written() {
+        let outputs = outputs();
+        let record = MatchRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            watchdog: "pgbouncer",
+            line: "aaa",
+            captures: Vec::new(),
+            commands: &outputs,
+        };
+
+        assert_eq!(
+            render(&messages(
+                &OutputFormat::default(),
+                &record,
+                EmptyOutput::Mark
+            )),
+            "hello world!\n(no output)\n"
+        );
+        let rendered = render(&messages(&OutputFormat::Jsonl, &record, EmptyOutput::Mark));
+        let value: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(value["commands"][0]["stdout"], "hello world!");
+        assert_eq!(value["commands"][1]["stdout"], serde_json::Value::Null);
+    }
+}
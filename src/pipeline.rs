@@ -0,0 +1,443 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::info;
+use regex::Regex;
+use settings::{Watchdog, WatcherBackend};
+
+use crate::{
+    output::CommandOutput,
+    watcher::{self, Backend, Subscription},
+    Lines, MatchEvent, Observer, Tailer, WatchdogError,
+};
+
+/// Where a pipeline's lines come from, e.g. a log file or journald.
+pub trait Source: Send {
+    /// Waits at most `timeout` for new lines, returning none if there are
+    /// none yet, or `None` once there won't be any more.
+    ///
+    /// # Errors
+    ///
+    /// If the source can't be read, which stops the watchdog. An error of
+    /// kind `NotFound` is the source's file being removed instead, which the
+    /// watchdog handles as its `file_missing` says; reading on waits for the
+    /// file to be recreated.
+    fn read(&mut self, timeout: Duration) -> io::Result<Option<Lines>>;
+}
+
+/// Which lines match, and what they captured.
+pub trait Matcher: Send {
+    /// The captures of a matching line, by name or index, or `None` if it
+    /// doesn't match.
+    fn captures(&self, line: &str) -> Option<Vec<(String, String)>>;
+
+    /// Like [`Matcher::captures`], for a line as it was read, which may not
+    /// be UTF-8. By default, it's converted with invalid UTF-8 replaced;
+    /// matchers that can match bytes skip converting lines that don't match.
+    fn captures_bytes(&self, line: &[u8]) -> Option<Vec<(String, String)>> {
+        self.captures(&String::from_utf8_lossy(line))
+    }
+}
+
+/// What's done on a match, after the observers are told about it.
+pub trait Action: Send {
+    /// Acts on the match, adding what it did to `outputs` to be written to the
+    /// watchdog's outputs; including the outputs from before a failure.
+    ///
+    /// # Errors
+    ///
+    /// If acting failed, which stops the watchdog.
+    fn run(
+        &mut self,
+        event: &MatchEvent,
+        outputs: &mut Vec<CommandOutput>,
+    ) -> Result<(), WatchdogError>;
+}
+
+/// The lines appended to a log file, as a watchdog reads them by default.
+pub struct FileSource {
+    tailer: Tailer,
+    backend: Backend,
+    events: Receiver<notify::Result<notify::Event>>,
+    /// Dropping it stops the events; `None` while the file is missing
+    subscription: Option<Subscription>,
+    /// How often the file is read without an event, and when it last was,
+    /// if it's polled
+    polled: Option<(Duration, Instant)>,
+}
+
+impl FileSource {
+    /// Watches the file from its end.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be watched or opened.
+    pub fn open(path: impl Into<PathBuf>) -> notify::Result<Self> {
+        Self::open_with_backend(path, None, Duration::ZERO)
+    }
+
+    /// Watches the file from its end with the backend, instead of the
+    /// platform's own notifications if there is one, polling it every
+    /// `poll_interval` with the `poll` backend.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be watched or opened, e.g. with a backend that
+    /// isn't the platform's.
+    pub fn open_with_backend(
+        path: impl Into<PathBuf>,
+        backend: Option<WatcherBackend>,
+        poll_interval: Duration,
+    ) -> notify::Result<Self> {
+        let path = path.into();
+        let polled =
+            (backend == Some(WatcherBackend::Poll)).then(|| (poll_interval, Instant::now()));
+        let backend = Backend::new(backend, poll_interval);
+        let (subscription, events) = watcher::subscribe(&path, backend)?;
+        let tailer = Tailer::open(path).map_err(notify::Error::io)?;
+        Ok(Self {
+            tailer,
+            backend,
+            events,
+            subscription: Some(subscription),
+            polled,
+        })
+    }
+
+    /// Keeps a buffer of this many bytes between reads, see
+    /// [`Tailer::read_buffer_size`].
+    #[must_use]
+    pub fn read_buffer_size(mut self, read_buffer_size: usize) -> Self {
+        self.tailer = self.tailer.read_buffer_size(read_buffer_size);
+        self
+    }
+
+    /// Reads a partial line after waiting this long for the rest of it, see
+    /// [`Tailer::partial_line_timeout`].
+    #[must_use]
+    pub fn partial_line_timeout(mut self, timeout: Duration) -> Self {
+        self.tailer = self.tailer.partial_line_timeout(timeout);
+        self
+    }
+
+    /// Decodes lines as `encoding` says, see [`Tailer::encoding`].
+    #[must_use]
+    pub fn encoding(mut self, encoding: settings::Encoding) -> Self {
+        self.tailer = self.tailer.encoding(encoding);
+        self
+    }
+
+    /// Reads at most this many bytes at once, see [`Tailer::max_read_bytes`].
+    #[must_use]
+    pub fn max_read_bytes(mut self, max_read_bytes: u64) -> Self {
+        self.tailer = self.tailer.max_read_bytes(max_read_bytes);
+        self
+    }
+}
+
+impl Source for FileSource {
+    fn read(&mut self, timeout: Duration) -> io::Result<Option<Lines>> {
+        if self.subscription.is_none() {
+            return self.wait_for_file(timeout);
+        }
+        let lines = self.read_file(timeout)?;
+        // what was left of a removed file is read first, the tailer has it open
+        if lines.as_ref().is_some_and(Lines::is_empty) && !self.tailer.path().exists() {
+            // unwatched, to be watched anew once it's recreated
+            self.subscription = None;
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} was removed", self.tailer.path().display()),
+            ));
+        }
+        Ok(lines)
+    }
+}
+
+impl FileSource {
+    /// Waits for the removed file to be recreated, then watches it again,
+    /// reading it from the start.
+    fn wait_for_file(&mut self, timeout: Duration) -> io::Result<Option<Lines>> {
+        let path = self.tailer.path();
+        if !path.exists() {
+            std::thread::sleep(timeout);
+            return Ok(Some(Lines::default()));
+        }
+        let (subscription, events) =
+            watcher::subscribe(path, self.backend).map_err(io::Error::other)?;
+        info!(
+            "log file {:?} was recreated, watching it again",
+            path.as_os_str()
+        );
+        self.subscription = Some(subscription);
+        self.events = events;
+        self.tailer.poll_lines().map(Some)
+    }
+
+    fn read_file(&mut self, timeout: Duration) -> io::Result<Option<Lines>> {
+        // catching up, a chunk at a time, letting the other watchdogs read theirs
+        if self.tailer.is_behind() {
+            std::thread::yield_now();
+            return self.tailer.poll_lines().map(Some);
+        }
+        // notify's poll watcher compares modification times in whole
+        // seconds, so what's appended in the second of a poll would only be
+        // read with the next change otherwise
+        let timeout = match self.polled {
+            Some((interval, polled)) => {
+                timeout.min((polled + interval).saturating_duration_since(Instant::now()))
+            }
+            None => timeout,
+        };
+        let event = match self.events.recv_timeout(timeout) {
+            // told by the file not existing, once what was left of it is read
+            Ok(Err(e)) if watcher::is_not_found(&e) => return Ok(Some(Lines::default())),
+            Ok(event) => event.map_err(io::Error::other)?,
+            Err(RecvTimeoutError::Timeout)
+                if self
+                    .polled
+                    .is_some_and(|(interval, polled)| polled.elapsed() >= interval) =>
+            {
+                self.polled = self.polled.map(|(interval, _)| (interval, Instant::now()));
+                return self.tailer.poll_lines().map(Some);
+            }
+            // a partial line's writer may not write the rest for a while, if ever
+            Err(RecvTimeoutError::Timeout) if self.tailer.has_partial_line() => {
+                return self.tailer.poll_lines().map(Some)
+            }
+            Err(RecvTimeoutError::Timeout) => return Ok(Some(Lines::default())),
+            Err(RecvTimeoutError::Disconnected) => return Ok(None),
+        };
+        match event.kind {
+            notify::EventKind::Modify(_) => self.tailer.poll_lines().map(Some),
+            notify::EventKind::Any
+            | notify::EventKind::Access(_)
+            | notify::EventKind::Create(_)
+            | notify::EventKind::Remove(_)
+            | notify::EventKind::Other => Ok(Some(Lines::default())), // do nothing on these events for now
+        }
+    }
+}
+
+impl Matcher for Regex {
+    fn captures(&self, line: &str) -> Option<Vec<(String, String)>> {
+        self.is_match(line)
+            .then(|| crate::output::captures(self, line))
+    }
+}
+
+/// A watchdog's regex, matched on bytes so that only matching lines are
+/// converted to strings; its default matcher. A log file's lines are decoded
+/// as its watchdog's `encoding` says first, but in lines from elsewhere,
+/// bytes that aren't UTF-8 don't match `.` or character classes, only
+/// themselves, e.g. `(?-u:\xff)`.
+impl Matcher for regex::bytes::Regex {
+    fn captures(&self, line: &str) -> Option<Vec<(String, String)>> {
+        self.captures_bytes(line.as_bytes())
+    }
+
+    fn captures_bytes(&self, line: &[u8]) -> Option<Vec<(String, String)>> {
+        if !self.is_match(line) {
+            return None;
+        }
+        let captures = regex::bytes::Regex::captures(self, line)?;
+        let values = self
+            .capture_names()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(i, name)| {
+                let value = captures.get(i)?;
+                let name = name.map_or_else(|| i.to_string(), ToString::to_string);
+                Some((name, String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            });
+        Some(values.collect())
+    }
+}
+
+/// The matchers of watchdogs without a custom one, compiled once per pattern
+/// and shared by the watchdogs with the same regex, as a `Regex`'s clones do.
+#[derive(Default)]
+pub(crate) struct DefaultMatchers(HashMap<String, regex::bytes::Regex>);
+
+impl DefaultMatchers {
+    pub(crate) fn get(&mut self, watchdog: &Watchdog) -> Box<dyn Matcher> {
+        let pattern = watchdog.regex.as_str();
+        if let Some(regex) = self.0.get(pattern) {
+            return Box::new(regex.clone());
+        }
+        // the same pattern compiles on bytes, but keep matching if it somehow doesn't
+        match regex::bytes::Regex::new(pattern) {
+            Ok(regex) => {
+                self.0.insert(pattern.to_string(), regex.clone());
+                Box::new(regex)
+            }
+            Err(_) => Box::new(watchdog.regex.clone()),
+        }
+    }
+}
+
+/// Running a watchdog's commands, its default action.
+pub(crate) struct Commands {
+    pub(crate) watchdog: Arc<Watchdog>,
+    pub(crate) target: String,
+    pub(crate) observer: Option<Arc<dyn Observer>>,
+}
+
+impl Action for Commands {
+    fn run(
+        &mut self,
+        event: &MatchEvent,
+        outputs: &mut Vec<CommandOutput>,
+    ) -> Result<(), WatchdogError> {
+        crate::execute_commands(
+            &self.watchdog,
+            &event.line,
+            &self.target,
+            self.observer.as_deref(),
+            outputs,
+        )
+        .map_err(|e| e.into_watchdog_error(&self.watchdog))
+    }
+}
+
+/// A watchdog whose source, matcher or action are swapped for custom ones,
+/// started by [`crate::run_pipelines`]; what isn't swapped is the
+/// watchdog's.
+///
+/// The watchdog's other settings, like its debounce, outputs and logging,
+/// are used whatever the stages. With a custom source, its `log_file` only
+/// names where the lines come from in events and logs.
+///
+/// ```no_run
+/// # use log_watchdog::{pipeline::{Action, WatchdogPipeline}, MatchEvent, WatchdogError};
+/// struct Page;
+///
+/// impl Action for Page {
+///     fn run(
+///         &mut self,
+///         event: &MatchEvent,
+///         _outputs: &mut Vec<log_watchdog::CommandOutput>,
+///     ) -> Result<(), WatchdogError> {
+///         eprintln!("paging on call: {}", event.line);
+///         Ok(())
+///     }
+/// }
+///
+/// let watchdog = settings::Watchdog::builder()
+///     .name("pgbouncer")
+///     .log_file("/var/log/pgbouncer/pgbouncer.log")
+///     .regex("FATAL")
+///     .build()
+///     .unwrap();
+/// let handle = log_watchdog::run_pipelines(vec![WatchdogPipeline::new(watchdog).action(Page)]);
+/// ```
+pub struct WatchdogPipeline {
+    // shared with the threads and the action that run it, instead of cloned
+    pub(crate) watchdog: Arc<Watchdog>,
+    pub(crate) source: Option<Box<dyn Source>>,
+    pub(crate) matcher: Option<Box<dyn Matcher>>,
+    pub(crate) action: Option<Box<dyn Action>>,
+}
+
+impl WatchdogPipeline {
+    /// The watchdog's own pipeline: its log file, regex and commands.
+    pub fn new(watchdog: Watchdog) -> Self {
+        Self {
+            watchdog: Arc::new(watchdog),
+            source: None,
+            matcher: None,
+            action: None,
+        }
+    }
+
+    #[must_use]
+    pub fn source(mut self, source: impl Source + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    #[must_use]
+    pub fn matcher(mut self, matcher: impl Matcher + 'static) -> Self {
+        self.matcher = Some(Box::new(matcher));
+        self
+    }
+
+    #[must_use]
+    pub fn action(mut self, action: impl Action + 'static) -> Self {
+        self.action = Some(Box::new(action));
+        self
+    }
+}
+
+impl From<Watchdog> for WatchdogPipeline {
+    fn from(watchdog: Watchdog) -> Self {
+        Self::new(watchdog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_line_not_utf8_then_default_matcher_matches_bytes() {
+        let watchdog = Watchdog::builder()
+            .name("bytes")
+            .log_file("bytes.log")
+            .regex("^ERROR (?<code>[0-9]+)( .+)?$")
+            .build()
+            .unwrap();
+        let matcher = DefaultMatchers::default().get(&watchdog);
+
+        assert_eq!(
+            matcher.captures_bytes(b"ERROR 42"),
+            Some(vec![("code".to_string(), "42".to_string())])
+        );
+        // bytes that aren't UTF-8 don't match `.`, rather than being replaced first
+        assert_eq!(matcher.captures_bytes(b"ERROR 42 \xff"), None);
+        assert_eq!(matcher.captures_bytes(b"INFO \xff"), None);
+        assert_eq!(matcher.captures("ERROR 7 x").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_when_polled_then_appends_read_without_events() {
+        let dir = tempdir::TempDir::new("test_pipeline").unwrap();
+        let path = dir.path().join("polled.log");
+        std::fs::write(&path, "before\n").unwrap();
+        let mut source = FileSource::open_with_backend(
+            &path,
+            Some(WatcherBackend::Poll),
+            Duration::from_millis(10),
+        )
+        .unwrap();
+
+        let mut read = Vec::new();
+        for line in ["first", "second"] {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            std::io::Write::write_all(&mut file, format!("{line}\n").as_bytes()).unwrap();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while read.last().is_none_or(|last| last != line) && Instant::now() < deadline {
+                let lines = source.read(Duration::from_millis(50)).unwrap().unwrap();
+                read.extend(
+                    lines
+                        .iter()
+                        .map(|line| String::from_utf8_lossy(line).into_owned()),
+                );
+            }
+        }
+
+        // appended within the same second, which the modification time doesn't tell
+        assert_eq!(read, ["first", "second"]);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+
+/// Where each watchdog stopped reading its log file, saved between `--once`
+/// scans so that the next one only reads what was appended since.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Positions(HashMap<String, (PathBuf, u64)>);
+
+impl Positions {
+    /// Loads the positions saved at `path`, none if it doesn't exist yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid {path:?}"));
+
+        let value: serde_json::Value = serde_json::from_str(&contents)?;
+        value
+            .as_object()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|(name, position)| {
+                let file = position["file"].as_str().ok_or_else(invalid)?;
+                let position = position["position"].as_u64().ok_or_else(invalid)?;
+                Ok((name.clone(), (PathBuf::from(file), position)))
+            })
+            .collect::<io::Result<_>>()
+            .map(Self)
+    }
+
+    /// Saves the positions to `path`, replacing the previous ones at once so
+    /// that an interrupted save doesn't lose them.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let value: serde_json::Map<_, _> = self
+            .0
+            .iter()
+            .map(|(name, (file, position))| {
+                (name.clone(), json!({ "file": file, "position": position }))
+            })
+            .collect();
+
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, serde_json::Value::Object(value).to_string())?;
+        std::fs::rename(&temporary, path)
+    }
+
+    /// The position the watchdog stopped at in `file`, 0 if it hasn't read
+    /// that file before.
+    pub fn get(&self, watchdog: &str, file: &Path) -> u64 {
+        match self.0.get(watchdog) {
+            Some((saved, position)) if saved == file => *position,
+            _ => 0,
+        }
+    }
+
+    pub fn set(&mut self, watchdog: &str, file: &Path, position: u64) {
+        self.0
+            .insert(watchdog.to_string(), (file.to_path_buf(), position));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_saved_then_loaded() {
+        let dir = tempdir::TempDir::new("position_").unwrap();
+        let path = dir.path().join("positions.json");
+        assert_eq!(Positions::load(&path).unwrap(), Positions::default());
+
+        let mut positions = Positions::default();
+        positions.set("pgbouncer", Path::new("/var/log/pgbouncer.log"), 42);
+        positions.save(&path).unwrap();
+
+        let positions = Positions::load(&path).unwrap();
+        assert_eq!(
+            positions.get("pgbouncer", Path::new("/var/log/pgbouncer.log")),
+            42
+        );
+        assert_eq!(
+            positions.get("pgbouncer", Path::new("/var/log/other.log")),
+            0,
+            "a different file starts from the beginning"
+        );
+        assert_eq!(positions.get("nginx", Path::new("/var/log/nginx.log")), 0);
+    }
+
+    #[test]
+    fn test_when_invalid_then_error() {
+        let dir = tempdir::TempDir::new("position_").unwrap();
+        let path = dir.path().join("positions.json");
+        std::fs::write(&path, r#"{"pgbouncer": {"file": "a.log"}}"#).unwrap();
+
+        assert!(Positions::load(&path).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Reloading the settings files when they change, for [`crate::run_reloading`].
+//!
+//! The files' directories are watched rather than the files themselves, so
+//! that replaced files are noticed too: editors that save by renaming, and
+//! Kubernetes ConfigMap volumes, whose files are symlinks through a `..data`
+//! symlink that an update swaps atomically for one to a new directory. A
+//! watch on the file would stay on the old, deleted one, and miss every
+//! update.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use log::{debug, error, info};
+use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use settings::{Settings, SettingsError};
+
+/// How long the directories must be quiet before the files are read, so that
+/// an update's events are handled once, after all of it is in place.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Watches the settings files from a separate thread, returning a receiver
+/// of the settings every time their contents change, after `configure` is
+/// called with them, e.g. to apply command line overrides.
+///
+/// Settings that aren't valid are logged and skipped, so the watchdogs keep
+/// running with the last valid ones. Watching stops once the receiver is
+/// dropped.
+///
+/// # Errors
+///
+/// If a file's directory can't be watched.
+pub fn watch_files(
+    paths: Vec<PathBuf>,
+    configure: impl Fn(&mut Settings) -> Result<(), SettingsError> + Send + 'static,
+) -> notify::Result<Receiver<Settings>> {
+    let (events_tx, events) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(events_tx, Config::default())?;
+    let directories: BTreeSet<_> = paths
+        .iter()
+        .map(|path| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        })
+        .collect();
+    for directory in directories {
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut contents = read(&paths);
+    std::thread::spawn(move || {
+        // dropped with the thread, which stops it watching
+        let _watcher = watcher;
+        while events.recv().is_ok() {
+            while events.recv_timeout(SETTLE).is_ok() {}
+            let read = read(&paths);
+            if read.is_none() || read == contents {
+                continue;
+            }
+            contents = read;
+            let settings = Settings::try_from(paths.as_slice())
+                .and_then(|mut settings| configure(&mut settings).map(|()| settings));
+            match settings {
+                Ok(settings) => {
+                    info!("settings files changed");
+                    if tx.send(settings).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => error!("invalid settings, keeping the current ones: {e}"),
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// The files' contents, following symlinks, or none if one can't be read,
+/// e.g. while it's being replaced.
+fn read(paths: &[PathBuf]) -> Option<Vec<Vec<u8>>> {
+    paths
+        .iter()
+        .map(|path| {
+            std::fs::read(path)
+                .map_err(|e| debug!("failed to read settings file {path:?}: {e}"))
+                .ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn settings(name: &str) -> String {
+        format!(
+            r#"
+watchdogs:
+  {name}:
+    log_file: /var/log/pgbouncer.log
+    output_file: "-"
+    debounce: 0
+    oneshot: false
+    regex: ^FATAL
+    commands:
+      systemctl:
+        args: [restart, pgbouncer]
+"#
+        )
+    }
+
+    #[test]
+    fn test_when_configmap_symlink_swapped_then_settings_reloaded() {
+        let dir = tempdir::TempDir::new("test_reload").unwrap();
+        let mount = dir.path();
+        // how the kubelet lays out a ConfigMap volume
+        std::fs::create_dir(mount.join("..2025_01_13_11_30_00.1")).unwrap();
+        std::fs::write(
+            mount.join("..2025_01_13_11_30_00.1/config.yml"),
+            settings("before"),
+        )
+        .unwrap();
+        symlink("..2025_01_13_11_30_00.1", mount.join("..data")).unwrap();
+        symlink("..data/config.yml", mount.join("config.yml")).unwrap();
+
+        let reloads = watch_files(vec![mount.join("config.yml")], |settings| {
+            settings.set_dry_run();
+            Ok(())
+        })
+        .unwrap();
+        std::fs::create_dir(mount.join("..2025_01_13_11_31_00.2")).unwrap();
+        std::fs::write(
+            mount.join("..2025_01_13_11_31_00.2/config.yml"),
+            settings("after"),
+        )
+        .unwrap();
+        symlink("..2025_01_13_11_31_00.2", mount.join("..data_tmp")).unwrap();
+        std::fs::rename(mount.join("..data_tmp"), mount.join("..data")).unwrap();
+        std::fs::remove_dir_all(mount.join("..2025_01_13_11_30_00.1")).unwrap();
+
+        let settings = reloads.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(settings.watchdogs()[0].name, "after");
+        assert!(settings.watchdogs()[0].dry_run);
+    }
+
+    #[test]
+    fn test_when_settings_invalid_or_unchanged_then_not_reloaded() {
+        let dir = tempdir::TempDir::new("test_reload").unwrap();
+        let path = dir.path().join("config.yml");
+        std::fs::write(&path, settings("before")).unwrap();
+
+        let reloads = watch_files(vec![path.clone()], |_| Ok(())).unwrap();
+        std::fs::write(&path, settings("before")).unwrap();
+        std::thread::sleep(SETTLE * 3);
+        std::fs::write(&path, "watchdogs: [").unwrap();
+        std::thread::sleep(SETTLE * 3);
+        assert!(reloads.try_recv().is_err());
+
+        std::fs::write(&path, settings("after")).unwrap();
+        let settings = reloads.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(settings.watchdogs()[0].name, "after");
+    }
+}
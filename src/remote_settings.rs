@@ -0,0 +1,397 @@
+//! Settings kept in a key of Consul's KV store or etcd, instead of a file, so
+//! that a fleet's watchdogs are changed in one place: they're loaded from the
+//! key, and the key is polled for changes, which restart the watchdogs with
+//! [`crate::run_reloading`].
+
+use std::{
+    fmt, io,
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use settings::{Settings, SettingsError, Tls};
+
+use crate::http::{self, base64_encode, Url, BASE64};
+
+/// How long a request to Consul or etcd may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the key is checked for changes by default.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Where the settings are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Store {
+    /// Consul's KV store, read with its HTTP API, with the ACL token in
+    /// `CONSUL_HTTP_TOKEN` if there is one
+    Consul,
+    /// etcd, read with the v3 API's JSON gateway
+    Etcd,
+}
+
+/// A key holding the settings as YAML, given as `consul://host[:port]/key` or
+/// `etcd://host[:port]/key`, e.g. `consul://localhost:8500/log-watchdog/web`,
+/// or `consul+https://` and `etcd+https://` to fetch it over HTTPS. The ports
+/// default to Consul's 8500 and etcd's 2379.
+#[derive(Debug, Clone)]
+pub struct RemoteSettings {
+    store: Store,
+    url: Url,
+    key: String,
+    /// The value the key had when it was last fetched
+    fetched: Option<String>,
+}
+
+impl FromStr for RemoteSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unsupported =
+            || format!("unsupported settings location {s}, expected consul:// or etcd://");
+        let (scheme, rest) = s.split_once("://").ok_or_else(unsupported)?;
+        let (store, https) = match scheme {
+            "consul" => (Store::Consul, false),
+            "consul+https" => (Store::Consul, true),
+            "etcd" => (Store::Etcd, false),
+            "etcd+https" => (Store::Etcd, true),
+            _ => return Err(unsupported()),
+        };
+        let default_port = match store {
+            Store::Consul => 8500,
+            Store::Etcd => 2379,
+        };
+        let scheme = if https { "https" } else { "http" };
+        let (authority, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if key.is_empty() {
+            return Err(format!("missing key in settings location {s}"));
+        }
+        let url = if authority.contains(':') {
+            Url::from_str(&format!("{scheme}://{authority}"))
+        } else {
+            Url::from_str(&format!("{scheme}://{authority}:{default_port}"))
+        }?;
+
+        Ok(Self {
+            store,
+            url,
+            key: key.to_string(),
+            fetched: None,
+        })
+    }
+}
+
+impl fmt::Display for RemoteSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.store {
+            Store::Consul => "consul",
+            Store::Etcd => "etcd",
+        };
+        let tls = if self.url.https { "+https" } else { "" };
+        write!(
+            f,
+            "{scheme}{tls}://{}:{}/{}",
+            self.url.host, self.url.port, self.key
+        )
+    }
+}
+
+impl RemoteSettings {
+    /// Fetches and parses the settings.
+    ///
+    /// # Errors
+    ///
+    /// If the key can't be read, or its settings aren't valid.
+    pub fn load(&mut self) -> io::Result<Settings> {
+        let yaml = self.fetch()?;
+        self.fetched = Some(yaml.clone());
+        Settings::from_str(&yaml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Polls the key every `interval` from a separate thread, returning a
+    /// receiver of the settings every time they change, after `configure` is
+    /// called with them, e.g. to apply command line overrides.
+    ///
+    /// Settings that aren't valid are logged and skipped, so the watchdogs
+    /// keep running with the last valid ones. Polling stops once the receiver
+    /// is dropped.
+    pub fn watch(
+        mut self,
+        interval: Duration,
+        configure: impl Fn(&mut Settings) -> Result<(), SettingsError> + Send + 'static,
+    ) -> Receiver<Settings> {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let yaml = match self.fetch() {
+                Ok(yaml) if self.fetched.as_ref() != Some(&yaml) => yaml,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("failed to fetch settings from {self}: {e}");
+                    continue;
+                }
+            };
+            self.fetched = Some(yaml.clone());
+            let settings = Settings::from_str(&yaml)
+                .and_then(|mut settings| configure(&mut settings).map(|()| settings));
+            match settings {
+                Ok(settings) => {
+                    info!("settings in {self} changed");
+                    if tx.send(settings).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => error!("invalid settings in {self}, keeping the current ones: {e}"),
+            }
+        });
+        rx
+    }
+
+    /// The key's value.
+    fn fetch(&self) -> io::Result<String> {
+        // the variables the stores' own clients verify and authenticate with
+        let (ca_file, client_cert, client_key) = match self.store {
+            Store::Consul => ("CONSUL_CACERT", "CONSUL_CLIENT_CERT", "CONSUL_CLIENT_KEY"),
+            Store::Etcd => ("ETCDCTL_CACERT", "ETCDCTL_CERT", "ETCDCTL_KEY"),
+        };
+        let path = |var| std::env::var_os(var).map(PathBuf::from);
+        let url = self.url.clone().with_tls(&Tls {
+            ca_file: path(ca_file),
+            client_cert: path(client_cert),
+            client_key: path(client_key),
+            min_version: None,
+        });
+
+        let (status, body) = match self.store {
+            Store::Consul => {
+                let url = url.join("v1/kv").join(&self.key);
+                let headers: Vec<_> = std::env::var("CONSUL_HTTP_TOKEN")
+                    .ok()
+                    .map(|token| ("X-Consul-Token".to_string(), token))
+                    .into_iter()
+                    .collect();
+                http::get(&url, &format!("{}?raw", url.path), &headers, TIMEOUT)?
+            }
+            Store::Etcd => {
+                let request = json!({ "key": base64_encode(self.key.as_bytes()) });
+                http::post_for_body(
+                    &url.join("v3/kv/range"),
+                    &[],
+                    "application/json",
+                    request.to_string().as_bytes(),
+                    TIMEOUT,
+                )?
+            }
+        };
+        match status {
+            200..=299 => (),
+            404 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no key {}", self.key),
+                ))
+            }
+            status => {
+                return Err(io::Error::other(format!(
+                    "request failed with status {status}: {}",
+                    String::from_utf8_lossy(&body).trim()
+                )))
+            }
+        }
+
+        let value = match self.store {
+            Store::Consul => body,
+            Store::Etcd => {
+                let response: Value = serde_json::from_slice(&body)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                // etcd leaves `kvs` out for a key that doesn't exist
+                let Some(value) = response.get("kvs").and_then(|kvs| kvs.get(0)?.get("value"))
+                else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("no key {}", self.key),
+                    ));
+                };
+                value.as_str().and_then(base64_decode).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid etcd range response")
+                })?
+            }
+        };
+        String::from_utf8(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Decodes padded base64, as etcd's JSON gateway returns values.
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(s.len() / 4 * 3);
+    for chunk in s.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64.iter().position(|b| b == c)?;
+            n |= u32::try_from(value).ok()? << (18 - 6 * i);
+        }
+        decoded.extend(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    /// Answers every request with the next of `responses`, returning their
+    /// request lines and bodies.
+    fn serve(responses: Vec<String>) -> (String, std::thread::JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(length) = header.strip_prefix("Content-Length: ") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                (&stream).write_all(response.as_bytes()).unwrap();
+                requests.push((request, String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+        (address, server)
+    }
+
+    const SETTINGS: &str = r#"
+watchdogs:
+  pgbouncer:
+    log_file: /var/log/pgbouncer.log
+    output_file: "-"
+    debounce: 0
+    oneshot: false
+    regex: ^FATAL
+    commands:
+      systemctl:
+        args: [restart, pgbouncer]
+"#;
+
+    #[test]
+    fn test_when_location_parsed_then_store_url_and_key_kept() {
+        let remote = RemoteSettings::from_str("consul://consul/log-watchdog/web").unwrap();
+        assert_eq!(remote.store, Store::Consul);
+        assert_eq!(remote.url, Url::from_str("http://consul:8500").unwrap());
+        assert_eq!(remote.key, "log-watchdog/web");
+        assert_eq!(remote.to_string(), "consul://consul:8500/log-watchdog/web");
+
+        let remote = RemoteSettings::from_str("etcd://10.0.0.1:12379/watchdogs").unwrap();
+        assert_eq!(remote.store, Store::Etcd);
+        assert_eq!(remote.url.port, 12379);
+
+        let remote = RemoteSettings::from_str("etcd+https://etcd/watchdogs").unwrap();
+        assert_eq!(remote.url, Url::from_str("https://etcd:2379").unwrap());
+        assert_eq!(remote.to_string(), "etcd+https://etcd:2379/watchdogs");
+
+        assert!(RemoteSettings::from_str("zookeeper://zk/watchdogs").is_err());
+        assert!(RemoteSettings::from_str("consul://consul").is_err());
+        assert!(RemoteSettings::from_str("consul://consul:port/key").is_err());
+    }
+
+    #[test]
+    fn test_when_base64_then_round_trips() {
+        assert_eq!(base64_encode(b"log-watchdog"), "bG9nLXdhdGNoZG9n");
+        assert_eq!(base64_encode(b"key"), "a2V5");
+        assert_eq!(base64_encode(b"ke"), "a2U=");
+        assert_eq!(base64_encode(b"k"), "aw==");
+        for s in ["", "k", "ke", "key", SETTINGS] {
+            assert_eq!(
+                base64_decode(&base64_encode(s.as_bytes())).unwrap(),
+                s.as_bytes()
+            );
+        }
+        assert!(base64_decode("a2V").is_none());
+        assert!(base64_decode("a2V!").is_none());
+    }
+
+    #[test]
+    fn test_when_loaded_from_consul_then_raw_value_parsed() {
+        let (address, server) = serve(vec![format!("HTTP/1.1 200 OK\r\n\r\n{SETTINGS}")]);
+        let mut remote =
+            RemoteSettings::from_str(&format!("consul://{address}/log-watchdog/web")).unwrap();
+
+        let settings = remote.load().unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests[0].0,
+            "GET /v1/kv/log-watchdog/web?raw HTTP/1.1\r\n"
+        );
+        assert_eq!(settings.watchdogs()[0].name, "pgbouncer");
+    }
+
+    #[test]
+    fn test_when_loaded_from_etcd_then_value_decoded() {
+        let response = json!({ "kvs": [{ "value": base64_encode(SETTINGS.as_bytes()) }] });
+        let (address, server) = serve(vec![
+            format!("HTTP/1.1 200 OK\r\n\r\n{response}"),
+            "HTTP/1.1 200 OK\r\n\r\n{}".into(),
+        ]);
+        let mut remote = RemoteSettings::from_str(&format!("etcd://{address}/watchdogs")).unwrap();
+
+        let settings = remote.load().unwrap();
+        let missing = remote.load().unwrap_err();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].0, "POST /v3/kv/range HTTP/1.1\r\n");
+        assert_eq!(requests[0].1, r#"{"key":"d2F0Y2hkb2dz"}"#);
+        assert_eq!(settings.watchdogs()[0].name, "pgbouncer");
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_when_watched_then_only_valid_changes_received() {
+        let changed = SETTINGS.replace("pgbouncer", "postgres");
+        let (address, server) = serve(vec![
+            format!("HTTP/1.1 200 OK\r\n\r\n{SETTINGS}"),
+            format!("HTTP/1.1 200 OK\r\n\r\n{SETTINGS}"),
+            "HTTP/1.1 200 OK\r\n\r\nwatchdogs: [".into(),
+            format!("HTTP/1.1 200 OK\r\n\r\n{changed}"),
+        ]);
+        let mut remote = RemoteSettings::from_str(&format!("consul://{address}/key")).unwrap();
+        remote.load().unwrap();
+
+        let reloads = remote.watch(Duration::from_millis(10), |settings| {
+            settings.set_dry_run();
+            Ok(())
+        });
+        let settings = reloads.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        server.join().unwrap();
+        assert_eq!(settings.watchdogs()[0].name, "postgres");
+        assert!(settings.watchdogs()[0].dry_run);
+    }
+}
@@ -0,0 +1,280 @@
+use std::io::{self, BufRead};
+
+use regex::Regex;
+use settings::{Command, Watchdog};
+
+use crate::{output::captures, tailer::decode};
+
+/// A line of the input that a watchdog would have triggered on.
+#[derive(Debug)]
+pub struct Hit<'a> {
+    /// 1-based line number in the input
+    pub line_number: usize,
+    pub watchdog: &'a Watchdog,
+    pub line: String,
+    /// The regex's capture groups that matched, by name or index
+    pub captures: Vec<(String, String)>,
+}
+
+/// Runs every line of `input` through the watchdogs' regexes, calling `hit`
+/// for every match without running any commands. Returns the number of lines
+/// read.
+///
+/// A oneshot watchdog only triggers on its first match. Debounce is ignored,
+/// since the lines of a replay have no timing.
+pub fn replay<'a>(
+    watchdogs: &'a [Watchdog],
+    input: impl BufRead,
+    mut hit: impl FnMut(Hit<'a>),
+) -> io::Result<usize> {
+    let mut replay = Replay::new(watchdogs);
+    let mut lines = 0;
+
+    for (i, line) in input.split(b'\n').enumerate() {
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        lines = i + 1;
+        replay
+            .hits_bytes(i + 1, &line)
+            .into_iter()
+            .for_each(&mut hit);
+    }
+
+    Ok(lines)
+}
+
+/// Matches lines one at a time, like [`replay`], e.g. as they're typed.
+pub struct Replay<'a> {
+    watchdogs: &'a [Watchdog],
+    done: Vec<bool>,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(watchdogs: &'a [Watchdog]) -> Self {
+        Self {
+            watchdogs,
+            done: vec![false; watchdogs.len()],
+        }
+    }
+
+    /// The watchdogs that would have triggered on the line.
+    pub fn hits(&mut self, line_number: usize, line: &str) -> Vec<Hit<'a>> {
+        self.hits_bytes(line_number, line.as_bytes())
+    }
+
+    /// Like [`Replay::hits`], for a line as it was read, which each watchdog
+    /// decodes as its `encoding` says.
+    pub fn hits_bytes(&mut self, line_number: usize, line: &[u8]) -> Vec<Hit<'a>> {
+        let mut hits = Vec::new();
+        for (watchdog, done) in self.watchdogs.iter().zip(&mut self.done) {
+            let Some(line) = decode(watchdog.encoding, line) else {
+                continue;
+            };
+            let line = line.as_ref();
+            if *done || !watchdog.regex.is_match(line) {
+                continue;
+            }
+            *done = watchdog.oneshot;
+            hits.push(Hit {
+                line_number,
+                watchdog,
+                line: line.to_string(),
+                captures: captures(&watchdog.regex, line),
+            });
+        }
+        hits
+    }
+}
+
+/// The line with the regex's capture groups, or the whole match if it has
+/// none, highlighted in bold red for a terminal.
+pub fn highlight(regex: &Regex, line: &str) -> String {
+    let Some(captures) = regex.captures(line) else {
+        return line.to_string();
+    };
+    let mut groups: Vec<_> = captures.iter().skip(1).flatten().collect();
+    if groups.is_empty() {
+        groups.extend(captures.get(0));
+    }
+    groups.sort_by_key(|group| (group.start(), std::cmp::Reverse(group.end())));
+
+    let mut highlighted = String::new();
+    let mut end = 0;
+    // nested groups are highlighted as part of the outermost one
+    for group in groups {
+        if group.start() < end {
+            continue;
+        }
+        highlighted.push_str(&line[end..group.start()]);
+        highlighted.push_str(HIGHLIGHT);
+        highlighted.push_str(group.as_str());
+        highlighted.push_str(RESET);
+        end = group.end();
+    }
+    highlighted.push_str(&line[end..]);
+    highlighted
+}
+
+const HIGHLIGHT: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// A command as it would be typed into a shell, quoting arguments where
+/// needed.
+pub fn command_line(command: &Command) -> String {
+    std::iter::once(&command.name)
+        .chain(&command.args)
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings::FileOptions;
+
+    use super::*;
+
+    fn watchdog(name: &str, regex: &str, oneshot: bool) -> Watchdog {
+        Watchdog {
+            name: name.into(),
+            log_file: "pgbouncer.log".into(),
+            outputs: Vec::new(),
+            file_options: FileOptions::default(),
+            debounce: 1000,
+            debounce_mode: settings::DebounceMode::default(),
+            oneshot,
+            regex: Regex::new(regex).unwrap(),
+            commands: Vec::new(),
+            dry_run: false,
+            log_level: None,
+            log_sample_rate: 1,
+            max_read_bytes: settings::DEFAULT_MAX_READ_BYTES,
+            read_buffer_size: settings::DEFAULT_READ_BUFFER_SIZE,
+            partial_line_timeout: None,
+            file_missing: settings::FileMissing::default(),
+            on_file_missing: Vec::new(),
+            encoding: settings::Encoding::default(),
+            backend: None,
+            poll_interval: settings::DEFAULT_POLL_INTERVAL,
+            redact: settings::Redactions::default(),
+            alertmanager: None,
+            passive_check: None,
+            zabbix: None,
+            allowed_commands: None,
+            audit_log: None,
+            executor: None,
+            max_concurrent_commands: None,
+        }
+    }
+
+    #[test]
+    fn test_when_replayed_then_matches_reported_per_watchdog() {
+        let watchdogs = [
+            watchdog("fatal", r"^(?<level>FATAL)", false),
+            watchdog("once", "connections", true),
+        ];
+        let input = "FATAL: too many connections\nLOG: ok\nFATAL: no more connections\n";
+
+        let mut hits = Vec::new();
+        let lines = replay(&watchdogs, input.as_bytes(), |hit| {
+            hits.push((hit.line_number, hit.watchdog.name.as_str(), hit.captures));
+        })
+        .unwrap();
+
+        assert_eq!(lines, 3);
+        let level = || vec![("level".to_string(), "FATAL".to_string())];
+        assert_eq!(
+            hits,
+            vec![
+                (1, "fatal", level()),
+                (1, "once", Vec::new()),
+                (3, "fatal", level()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_line_not_utf8_then_decoded_per_watchdog() {
+        let mut strict = watchdog("strict", "FATAL", false);
+        strict.encoding = settings::Encoding::Strict;
+        let mut latin1 = watchdog("latin1", "FATAL", false);
+        latin1.encoding = settings::Encoding::Latin1;
+        let watchdogs = [strict, latin1];
+
+        let mut hits = Vec::new();
+        let input = &b"FATAL: caf\xe9\r\nFATAL: ok\n"[..];
+        replay(&watchdogs, input, |hit| {
+            hits.push((hit.line_number, hit.watchdog.name.as_str(), hit.line));
+        })
+        .unwrap();
+
+        assert_eq!(
+            hits,
+            vec![
+                (1, "latin1", "FATAL: café".to_string()),
+                (2, "strict", "FATAL: ok".to_string()),
+                (2, "latin1", "FATAL: ok".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_lines_matched_one_at_a_time_then_oneshot_remembered() {
+        let watchdogs = [watchdog("once", "FATAL", true)];
+        let mut replay = Replay::new(&watchdogs);
+
+        assert_eq!(replay.hits(1, "LOG: ok").len(), 0);
+        assert_eq!(replay.hits(2, "FATAL: too many connections").len(), 1);
+        assert_eq!(replay.hits(3, "FATAL: too many connections").len(), 0);
+    }
+
+    #[test]
+    fn test_when_highlighted_then_outermost_groups_or_match_marked() {
+        let groups = Regex::new(r"(?<level>FATAL): too many (conn(ections))").unwrap();
+        assert_eq!(
+            highlight(&groups, "2025 FATAL: too many connections!"),
+            "2025 \x1b[1;31mFATAL\x1b[0m: too many \x1b[1;31mconnections\x1b[0m!"
+        );
+
+        let plain = Regex::new("FATAL").unwrap();
+        assert_eq!(
+            highlight(&plain, "LOG FATAL x"),
+            "LOG \x1b[1;31mFATAL\x1b[0m x"
+        );
+        assert_eq!(highlight(&plain, "LOG ok"), "LOG ok");
+    }
+
+    #[test]
+    fn test_when_command_line_then_arguments_quoted() {
+        let command = Command {
+            name: "curl".into(),
+            args: vec![
+                "https://example.com/restart?now=1".into(),
+                "hello world!".into(),
+                "it's".into(),
+                String::new(),
+            ],
+            sandbox: None,
+            container: None,
+        };
+
+        assert_eq!(
+            command_line(&command),
+            r"curl 'https://example.com/restart?now=1' 'hello world!' 'it'\''s' ''"
+        );
+    }
+}
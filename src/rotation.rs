@@ -0,0 +1,153 @@
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+    thread::JoinHandle,
+};
+
+use log::error;
+use settings::Compression;
+
+/// Rolls `path` over to `{path}.1`, shifting older rolled files up to
+/// `{path}.{retention}` and deleting the oldest. With a retention of 0 the file
+/// is deleted instead.
+///
+/// If `compression` is set, `{path}.1` is compressed in the background; the
+/// returned handle finishes when it is, and must be joined before `path` is
+/// rolled again, which would otherwise rename `{path}.1` while it's being
+/// compressed.
+pub fn roll(
+    path: &Path,
+    retention: u32,
+    compression: Option<Compression>,
+) -> io::Result<Option<JoinHandle<()>>> {
+    if retention == 0 {
+        std::fs::remove_file(path)?;
+        return Ok(None);
+    }
+
+    // a rolled file may not be compressed yet, so both names are shifted
+    let mut extensions = vec![""];
+    extensions.extend(compression.map(|c| c.algorithm.program().1));
+    for extension in extensions {
+        remove_if_exists(&rolled(path, retention, extension))?;
+        for i in (1..retention).rev() {
+            rename_if_exists(&rolled(path, i, extension), &rolled(path, i + 1, extension))?;
+        }
+    }
+
+    let first = rolled(path, 1, "");
+    std::fs::rename(path, &first)?;
+
+    Ok(compression.map(|compression| std::thread::spawn(move || compress(&first, compression))))
+}
+
+/// `{path}.{i}{extension}`
+fn rolled(path: &Path, i: u32, extension: &str) -> PathBuf {
+    let mut rolled = OsString::from(path);
+    rolled.push(format!(".{i}{extension}"));
+    PathBuf::from(rolled)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Compresses `path` in place with the `gzip` or `zstd` binary, which replace
+/// it with a `.gz` or `.zst` file.
+fn compress(path: &Path, compression: Compression) {
+    let (program, _) = compression.algorithm.program();
+    let mut command = Command::new(program);
+    command.arg("--force").arg("--quiet");
+    if program == "zstd" {
+        command.arg("--rm");
+    }
+    if let Some(level) = compression.level {
+        command.arg(format!("-{level}"));
+    }
+
+    match crate::init::output(command.arg(path)) {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => error!(
+            "failed to compress {path:?} with {program}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => error!("failed to compress {path:?} with {program}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings::CompressionAlgorithm;
+
+    use super::*;
+
+    fn read(path: &Path, i: u32, extension: &str) -> Option<String> {
+        std::fs::read_to_string(rolled(path, i, extension)).ok()
+    }
+
+    #[test]
+    fn test_when_rolled_then_shifted_and_oldest_deleted() {
+        let dir = tempdir::TempDir::new("rotation_").unwrap();
+        let path = dir.path().join("out.txt");
+
+        for contents in ["first", "second", "third"] {
+            std::fs::write(&path, contents).unwrap();
+            assert!(roll(&path, 2, None).unwrap().is_none());
+        }
+
+        assert!(!path.exists());
+        assert_eq!(read(&path, 1, "").as_deref(), Some("third"));
+        assert_eq!(read(&path, 2, "").as_deref(), Some("second"));
+        assert_eq!(read(&path, 3, ""), None);
+    }
+
+    #[test]
+    fn test_when_retention_zero_then_deleted() {
+        let dir = tempdir::TempDir::new("rotation_").unwrap();
+        let path = dir.path().join("out.txt");
+        std::fs::write(&path, "first").unwrap();
+
+        roll(&path, 0, None).unwrap();
+
+        assert!(!path.exists());
+        assert_eq!(read(&path, 1, ""), None);
+    }
+
+    #[test]
+    fn test_when_compressed_then_rolled_file_replaced() {
+        let dir = tempdir::TempDir::new("rotation_").unwrap();
+        let path = dir.path().join("out.txt");
+        let compression = Compression {
+            algorithm: CompressionAlgorithm::Gzip,
+            level: Some(9),
+        };
+        if Command::new("gzip").arg("--version").output().is_err() {
+            return;
+        }
+
+        for contents in ["first", "second"] {
+            std::fs::write(&path, contents).unwrap();
+            roll(&path, 3, Some(compression))
+                .unwrap()
+                .unwrap()
+                .join()
+                .unwrap();
+        }
+
+        assert_eq!(read(&path, 1, ""), None);
+        assert!(rolled(&path, 1, ".gz").exists());
+        assert!(rolled(&path, 2, ".gz").exists());
+    }
+}
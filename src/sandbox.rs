@@ -0,0 +1,209 @@
+use std::{ffi::OsString, path::Path, process::Command};
+
+use settings::{Container, Sandbox, SandboxWrapper};
+
+/// The command that runs `program` with `args`, under the sandbox if there is
+/// one: wrapped in `bwrap`, `nsjail` or `setpriv`, with only the environment
+/// variables it passes on.
+pub(crate) fn command(program: &Path, args: &[String], sandbox: Option<&Sandbox>) -> Command {
+    let Some(sandbox) = sandbox else {
+        let mut command = Command::new(program);
+        command.args(args);
+        return command;
+    };
+
+    let mut env: Vec<(&str, OsString)> = sandbox
+        .env
+        .iter()
+        .filter_map(|name| Some((name.as_str(), std::env::var_os(name)?)))
+        .collect();
+    if !env.iter().any(|(name, _)| *name == "PATH") {
+        env.push(("PATH", Sandbox::DEFAULT_PATH.into()));
+    }
+
+    let mut argv: Vec<OsString> = match sandbox.wrapper {
+        Some(SandboxWrapper::Bubblewrap) => [
+            "bwrap",
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+            "--unshare-all",
+            "--share-net",
+            "--die-with-parent",
+            "--new-session",
+        ]
+        .map(OsString::from)
+        .into(),
+        Some(SandboxWrapper::Nsjail) => {
+            let mut argv: Vec<OsString> = [
+                "nsjail",
+                "--mode",
+                "o",
+                "--chroot",
+                "/",
+                "--disable_clone_newnet",
+                "--time_limit",
+                "0",
+                "--quiet",
+            ]
+            .map(OsString::from)
+            .into();
+            // nsjail clears the environment, but for these
+            for (name, _) in &env {
+                argv.extend(["--env".into(), (*name).into()]);
+            }
+            if let Some(policy) = &sandbox.seccomp_policy {
+                argv.extend(["--seccomp_policy".into(), policy.into()]);
+            }
+            argv
+        }
+        None if sandbox.no_new_privileges => vec!["setpriv".into(), "--no-new-privs".into()],
+        None => Vec::new(),
+    };
+    if !argv.is_empty() {
+        argv.push("--".into());
+    }
+    argv.push(program.into());
+    argv.extend(args.iter().map(OsString::from));
+
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]).env_clear().envs(env);
+    command
+}
+
+/// The command that runs `name` with `args` in the container, with `runtime`,
+/// the path of its program: removed when it exits, its root filesystem
+/// read-only but for a private `/tmp`, without capabilities, and with only
+/// the mounts it's given. The runtime keeps the daemon's environment, e.g.
+/// `DOCKER_HOST`, which isn't passed on to the container.
+pub(crate) fn container(
+    runtime: &Path,
+    name: &str,
+    args: &[String],
+    container: &Container,
+) -> Command {
+    let mut command = Command::new(runtime);
+    command.args([
+        "run",
+        "--rm",
+        "--read-only",
+        "--tmpfs",
+        "/tmp",
+        "--cap-drop",
+        "ALL",
+        "--security-opt",
+        "no-new-privileges",
+    ]);
+    for mount in &container.mounts {
+        let mut option = OsString::from("type=bind,source=");
+        option.push(&mount.source);
+        option.push(",target=");
+        option.push(&mount.target);
+        if !mount.writable {
+            option.push(",readonly");
+        }
+        command.arg("--mount").arg(option);
+    }
+    command
+        .args(["--entrypoint", name, &container.image])
+        .args(args);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use settings::ContainerRuntime;
+    use std::ffi::OsStr;
+
+    fn argv(command: &Command) -> Vec<&OsStr> {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .collect()
+    }
+
+    #[test]
+    fn test_when_sandboxed_then_wrapped_with_minimal_environment() {
+        let args = ["-s".to_string()];
+        let sandbox = Sandbox::default();
+
+        let command = command(Path::new("/usr/bin/curl"), &args, Some(&sandbox));
+
+        assert_eq!(
+            argv(&command),
+            ["setpriv", "--no-new-privs", "--", "/usr/bin/curl", "-s"]
+        );
+        let env: Vec<_> = command.get_envs().filter(|(_, v)| v.is_some()).collect();
+        assert_eq!(
+            env,
+            [(OsStr::new("PATH"), Some(OsStr::new(Sandbox::DEFAULT_PATH)))]
+        );
+    }
+
+    #[test]
+    fn test_when_nsjail_then_kept_environment_and_policy_passed() {
+        let sandbox = Sandbox {
+            env: vec!["PATH".into()],
+            wrapper: Some(SandboxWrapper::Nsjail),
+            seccomp_policy: Some("/etc/curl.policy".into()),
+            ..Sandbox::default()
+        };
+
+        let command = command(Path::new("/usr/bin/curl"), &[], Some(&sandbox));
+
+        let argv = argv(&command);
+        assert_eq!(argv[0], "nsjail");
+        assert!(argv.windows(2).any(|pair| pair == ["--env", "PATH"]));
+        assert!(argv
+            .windows(2)
+            .any(|pair| pair == ["--seccomp_policy", "/etc/curl.policy"]));
+        assert_eq!(argv[argv.len() - 2..], ["--", "/usr/bin/curl"]);
+    }
+
+    #[test]
+    fn test_when_containerized_then_run_in_image_with_mounts() {
+        let container = Container {
+            runtime: ContainerRuntime::Podman,
+            image: "registry.example.com/pg-tools:1.2".into(),
+            mounts: vec!["/var/lib/pgbouncer:/data".parse().unwrap()],
+        };
+
+        let command = super::container(
+            Path::new("/usr/bin/podman"),
+            "pg_repair",
+            &["--fix".to_string()],
+            &container,
+        );
+
+        let argv = argv(&command);
+        assert_eq!(argv[..3], ["/usr/bin/podman", "run", "--rm"]);
+        assert!(argv.windows(2).any(|pair| pair
+            == [
+                "--mount",
+                "type=bind,source=/var/lib/pgbouncer,target=/data,readonly"
+            ]));
+        assert_eq!(
+            argv[argv.len() - 4..],
+            [
+                "--entrypoint",
+                "pg_repair",
+                "registry.example.com/pg-tools:1.2",
+                "--fix"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_not_sandboxed_then_run_as_is() {
+        let command = command(Path::new("ls"), &["-a".to_string()], None);
+
+        assert_eq!(argv(&command), ["ls", "-a"]);
+        assert_eq!(command.get_envs().count(), 0);
+    }
+}
@@ -0,0 +1,440 @@
+//! On-demand scans: an HTTP endpoint, `POST /scan/<watchdog>`, that scans a
+//! watchdog's log file right away, outside the event-driven tailing, e.g. to
+//! re-check after fixing something by hand.
+//!
+//! A scan matches the lines written since the previous scan of the watchdog,
+//! or since the endpoint started listening for its first, and with
+//! `?full=true` the whole file. It runs the watchdog's commands for every
+//! match and writes its outputs, like `--once` does, apart from the watchdog
+//! that's tailing the file. The reply is a JSON object with the `watchdog`,
+//! its `matches` and the `position` the scan read up to.
+//!
+//! The watchdogs are looked up in a [`Scannable`] on every request, which is
+//! replaced when the settings are reloaded.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use serde_json::json;
+use settings::{sha256::sha256, Watchdog};
+
+use crate::{
+    concurrency::Connections, pipeline::DefaultMatchers, CancellationToken, MatchEvent, Observer,
+};
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest request head read, so that a client can't make the endpoint
+/// buffer without bounds.
+const MAX_HEAD_LEN: u64 = 16 << 10;
+
+/// The most requests served at once; more wait to be accepted.
+const MAX_CONNECTIONS: usize = 64;
+
+/// A watchdog, and where its next scan starts.
+struct Scanned {
+    watchdog: Arc<Watchdog>,
+    /// Held for the whole scan, so scans of the same watchdog run one at a time
+    position: Mutex<u64>,
+}
+
+/// The watchdogs that can be scanned, by name, shared with the endpoint so
+/// that a reload can replace them.
+#[derive(Clone, Default)]
+pub struct Scannable(Arc<Mutex<HashMap<String, Arc<Scanned>>>>);
+
+impl Scannable {
+    /// The watchdogs, whose first scans start at the end of their files.
+    pub fn new(watchdogs: &[Watchdog]) -> Self {
+        let scannable = Self::default();
+        scannable.replace(watchdogs);
+        scannable
+    }
+
+    /// Replaces the watchdogs with reloaded ones. Scans of a watchdog that
+    /// still reads the same file carry on from where its previous scan
+    /// stopped, and those of the new ones start at the end of their files.
+    pub fn replace(&self, watchdogs: &[Watchdog]) {
+        let mut scannable = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let replaced = watchdogs
+            .iter()
+            .map(|watchdog| {
+                let position = match scannable.get(&watchdog.name) {
+                    Some(scanned) if scanned.watchdog.log_file == watchdog.log_file => *scanned
+                        .position
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner),
+                    _ => std::fs::metadata(&watchdog.log_file).map_or(0, |file| file.len()),
+                };
+                let scanned = Scanned {
+                    watchdog: Arc::new(watchdog.clone()),
+                    position: Mutex::new(position),
+                };
+                (watchdog.name.clone(), Arc::new(scanned))
+            })
+            .collect();
+        *scannable = replaced;
+    }
+
+    /// The watchdog named `name`, as the settings are now.
+    fn get(&self, name: &str) -> Option<Arc<Scanned>> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned()
+    }
+}
+
+/// Listens for scan requests on `address`, from a separate thread, returning
+/// the address it's bound to, e.g. the port picked for port 0.
+///
+/// With a `token`, requests need it as an `Authorization: Bearer <token>`
+/// header. Anyone who can reach the address could trigger scans otherwise,
+/// so only a loopback address can be listened on without one.
+///
+/// # Errors
+///
+/// If the address can't be listened on, or it isn't a loopback address and
+/// there's no token.
+pub fn listen(
+    address: impl ToSocketAddrs,
+    token: Option<String>,
+    watchdogs: Scannable,
+) -> io::Result<SocketAddr> {
+    if token.as_deref() == Some("") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the scan token is empty",
+        ));
+    }
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+    if token.is_none() && !address.ip().is_loopback() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("scans on {address}, which isn't a loopback address, need a token"),
+        ));
+    }
+    info!("scan endpoint listening on {address}");
+
+    let token = Arc::new(token);
+    std::thread::spawn(move || {
+        let connections = Connections::new(MAX_CONNECTIONS);
+        loop {
+            let connection = connections.open();
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let (watchdogs, token) = (watchdogs.clone(), Arc::clone(&token));
+                    std::thread::spawn(move || {
+                        if let Err(e) = serve(stream, &watchdogs, token.as_deref()) {
+                            error!("scan endpoint connection failed: {e}");
+                        }
+                        drop(connection);
+                    });
+                }
+                Err(e) => error!("scan endpoint failed to accept connection: {e}"),
+            }
+        }
+    });
+
+    Ok(address)
+}
+
+/// A request's method, path and query, and its bearer token, if any.
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    token: Option<String>,
+}
+
+impl Request {
+    fn read(reader: &mut impl BufRead) -> io::Result<Self> {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid http request line: {request_line:?}"),
+            ));
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let mut token = None;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header)?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("authorization") {
+                    token = value.trim().strip_prefix("Bearer ").map(String::from);
+                }
+            }
+        }
+
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            token,
+        })
+    }
+
+    /// If true, the query asks for the whole file to be scanned.
+    fn full(&self) -> bool {
+        self.query
+            .split('&')
+            .any(|param| matches!(param, "full" | "full=true" | "full=1"))
+    }
+}
+
+/// Counts the matches of a scan.
+#[derive(Default)]
+struct Matches(Mutex<u64>);
+
+impl Observer for Matches {
+    fn matched(&self, _event: &MatchEvent) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+    }
+}
+
+fn serve(stream: TcpStream, watchdogs: &Scannable, token: Option<&str>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let request = Request::read(&mut BufReader::new(stream.take(MAX_HEAD_LEN)))?;
+
+    let (status, body) = match request.path.strip_prefix("/scan/") {
+        _ if token.is_some_and(|token| !authorized(request.token.as_deref(), token)) => {
+            (401, json!({ "error": "missing or invalid bearer token" }))
+        }
+        Some(name) => match (request.method.as_str(), watchdogs.get(name)) {
+            ("POST", Some(scanned)) => scan(&scanned, request.full()),
+            ("POST", None) => (404, json!({ "error": format!("no watchdog named {name}") })),
+            _ => (405, json!({ "error": "scans are requested with POST" })),
+        },
+        None => (
+            404,
+            json!({ "error": "not found, scans are at /scan/<watchdog>" }),
+        ),
+    };
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let body = body.to_string();
+    write!(
+        writer,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    writer.flush()
+}
+
+/// If true, the request's token is the one required. They're compared by
+/// their digests, in constant time, so that how long the comparison takes
+/// doesn't tell a client how much of a guessed token is right.
+fn authorized(given: Option<&str>, token: &str) -> bool {
+    let given = sha256(given.unwrap_or_default().as_bytes());
+    sha256(token.as_bytes())
+        .iter()
+        .zip(given)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Scans the watchdog's file, from where its previous scan stopped unless
+/// it's a `full` scan, returning the status and body of the reply.
+fn scan(scanned: &Scanned, full: bool) -> (u16, serde_json::Value) {
+    let watchdog = &scanned.watchdog;
+    let target = logging::watchdog_target(&watchdog.name);
+    let mut position = scanned
+        .position
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let from = if full { 0 } else { *position };
+    info!(
+        target: &target,
+        "watchdog::{}: scanning {:?} from {from} on request",
+        watchdog.name,
+        watchdog.log_file.as_os_str()
+    );
+
+    let matches = Matches::default();
+    let matcher = DefaultMatchers::default().get(watchdog);
+    match crate::scan(
+        watchdog,
+        matcher.as_ref(),
+        from,
+        Some(&matches),
+        &CancellationToken::new(),
+    ) {
+        Ok(scanned) => {
+            *position = scanned;
+            let matches = *matches.0.lock().unwrap_or_else(PoisonError::into_inner);
+            info!(
+                target: &target,
+                "watchdog::{}: scanned up to {scanned} on request, {matches} matches",
+                watchdog.name
+            );
+            (
+                200,
+                json!({ "watchdog": watchdog.name, "matches": matches, "position": scanned }),
+            )
+        }
+        Err(e) => {
+            warn!(target: &target, "watchdog::{}: scan on request failed: {e}", watchdog.name);
+            (
+                500,
+                json!({ "watchdog": watchdog.name, "error": e.to_string() }),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::path::Path;
+
+    fn request(address: SocketAddr, head: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(head.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn watchdog(log_file: &Path, output: &Path) -> Watchdog {
+        Watchdog::builder()
+            .name("pgbouncer")
+            .log_file(log_file)
+            .regex("^FATAL")
+            .command("echo", ["restarted"])
+            .output_file(output)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_when_request_read_then_path_query_and_token_parsed() {
+        let head = "POST /scan/pgbouncer?full=true HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer s3cr3t\r\n\r\n";
+        let request = Request::read(&mut head.as_bytes()).unwrap();
+
+        assert_eq!(
+            request,
+            Request {
+                method: "POST".into(),
+                path: "/scan/pgbouncer".into(),
+                query: "full=true".into(),
+                token: Some("s3cr3t".into()),
+            }
+        );
+        assert!(request.full());
+        assert!(Request::read(&mut "\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_when_scan_requested_then_new_lines_or_whole_file_scanned() {
+        let dir = tempdir::TempDir::new("test_scan_server").unwrap();
+        let (log_file, output) = (dir.path().join("log.txt"), dir.path().join("out.txt"));
+        std::fs::write(&log_file, "FATAL before\n").unwrap();
+        let watchdogs = Scannable::new(&[watchdog(&log_file, &output)]);
+        let address = listen("127.0.0.1:0", None, watchdogs).unwrap();
+        std::fs::write(&log_file, "FATAL before\nFATAL after\nINFO fine\n").unwrap();
+
+        let (status, body) = request(address, "POST /scan/pgbouncer HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert_eq!(body["matches"], 1);
+        assert_eq!(body["position"], 35);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "restarted\n");
+
+        let (_, body) = request(address, "POST /scan/pgbouncer HTTP/1.1\r\n\r\n");
+        assert_eq!(body["matches"], 0);
+        let (_, body) = request(address, "POST /scan/pgbouncer?full=true HTTP/1.1\r\n\r\n");
+        assert_eq!(body["matches"], 2);
+    }
+
+    #[test]
+    fn test_when_watchdogs_replaced_then_requests_scan_current_ones() {
+        let dir = tempdir::TempDir::new("test_scan_server").unwrap();
+        let (log_file, output) = (dir.path().join("log.txt"), dir.path().join("out.txt"));
+        std::fs::write(&log_file, "").unwrap();
+        let watchdogs = Scannable::new(&[watchdog(&log_file, &output)]);
+        let address = listen("127.0.0.1:0", None, watchdogs.clone()).unwrap();
+        std::fs::write(&log_file, "FATAL before\n").unwrap();
+
+        let mut nginx = watchdog(&log_file, &output);
+        nginx.name = "nginx".into();
+        watchdogs.replace(&[watchdog(&log_file, &output), nginx]);
+        std::fs::write(&log_file, "FATAL before\nFATAL after\n").unwrap();
+
+        // kept where it was, rather than starting again at the end
+        let (status, body) = request(address, "POST /scan/pgbouncer HTTP/1.1\r\n\r\n");
+        assert_eq!((status, body["matches"].clone()), (200, 2.into()));
+        let (status, body) = request(address, "POST /scan/nginx HTTP/1.1\r\n\r\n");
+        assert_eq!((status, body["matches"].clone()), (200, 1.into()));
+
+        watchdogs.replace(&[]);
+        let (status, _) = request(address, "POST /scan/pgbouncer HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn test_when_not_loopback_or_empty_token_then_not_listened_on() {
+        let none = Scannable::default;
+        assert!(listen("0.0.0.0:0", None, none()).is_err());
+        assert!(listen("127.0.0.1:0", Some(String::new()), none()).is_err());
+        assert!(listen("0.0.0.0:0", Some("s3cr3t".into()), none()).is_ok());
+    }
+
+    #[test]
+    fn test_when_token_compared_then_only_same_token_authorized() {
+        assert!(authorized(Some("s3cr3t"), "s3cr3t"));
+        assert!(!authorized(Some("s3cr3"), "s3cr3t"));
+        assert!(!authorized(Some("s3cr3t "), "s3cr3t"));
+        assert!(!authorized(None, "s3cr3t"));
+    }
+
+    #[test]
+    fn test_when_request_not_for_watchdog_or_unauthorized_then_refused() {
+        let dir = tempdir::TempDir::new("test_scan_server").unwrap();
+        let log_file = dir.path().join("log.txt");
+        std::fs::write(&log_file, "").unwrap();
+        let watchdogs = Scannable::new(&[watchdog(&log_file, &dir.path().join("out.txt"))]);
+        let address = listen("127.0.0.1:0", Some("s3cr3t".into()), watchdogs).unwrap();
+
+        let status = |head: &str| request(address, head).0;
+        assert_eq!(status("POST /scan/pgbouncer HTTP/1.1\r\n\r\n"), 401);
+        assert_eq!(
+            status("POST /scan/other HTTP/1.1\r\nAuthorization: Bearer s3cr3t\r\n\r\n"),
+            404
+        );
+        assert_eq!(
+            status("GET /scan/pgbouncer HTTP/1.1\r\nAuthorization: Bearer s3cr3t\r\n\r\n"),
+            405
+        );
+        assert_eq!(
+            status("POST /scan/pgbouncer HTTP/1.1\r\nAuthorization: Bearer s3cr3t\r\n\r\n"),
+            200
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use settings::{OutputTarget, Settings};
+
+/// Generates a hardened unit that runs the watchdogs in the settings file
+/// with `executable`, or with `template`, a `log-watchdog@.service` template
+/// unit that runs the watchdog its instance names. The settings are validated
+/// before the daemon starts, so that invalid settings fail the start.
+///
+/// The filesystem is read-only to the daemon, but for the directories that
+/// the watchdogs in `settings` write to, when the file could be loaded.
+pub fn generate(
+    executable: &Path,
+    settings_path: &Path,
+    settings: Option<&Settings>,
+    template: bool,
+) -> String {
+    let (executable, settings_path) = (executable.display(), settings_path.display());
+    let mut unit = String::new();
+
+    unit.push_str("[Unit]\n");
+    if template {
+        unit.push_str("Description=log-watchdog for the %i watchdog\n");
+    } else {
+        unit.push_str("Description=log-watchdog, running commands when log files match\n");
+    }
+    unit.push_str("Documentation=man:log-watchdog(1)\n");
+    unit.push_str("After=network-online.target\nWants=network-online.target\n\n");
+
+    unit.push_str("[Service]\nType=exec\n");
+    let _ = writeln!(
+        unit,
+        "ExecStartPre={executable} validate --settings {settings_path}"
+    );
+    if template {
+        let _ = writeln!(
+            unit,
+            "ExecStart={executable} run --settings {settings_path} --watchdog %i"
+        );
+    } else {
+        let _ = writeln!(unit, "ExecStart={executable} --settings {settings_path}");
+    }
+    unit.push_str(
+        "Restart=on-failure\nRestartSec=5\n\
+         # invalid settings, which a restart doesn't fix\n\
+         RestartPreventExitStatus=2\n\n",
+    );
+
+    unit.push_str(
+        "# commands that need more privileges than this, e.g. to restart services,\n\
+         # are best run by `log-watchdog executor` from a unit of its own\n\
+         NoNewPrivileges=yes\n\
+         ProtectSystem=strict\n",
+    );
+    match settings {
+        Some(settings) => {
+            for directory in writable_directories(settings) {
+                let _ = writeln!(unit, "ReadWritePaths=-{}", directory.display());
+            }
+        }
+        None => unit.push_str(
+            "# the directories of the watchdogs' output files and audit logs\n\
+             #ReadWritePaths=\n",
+        ),
+    }
+    unit.push_str(
+        "ProtectHome=read-only\n\
+         PrivateTmp=yes\n\
+         PrivateDevices=yes\n\
+         ProtectKernelTunables=yes\n\
+         ProtectKernelModules=yes\n\
+         ProtectKernelLogs=yes\n\
+         ProtectControlGroups=yes\n\
+         ProtectClock=yes\n\
+         ProtectHostname=yes\n\
+         RestrictRealtime=yes\n\
+         RestrictSUIDSGID=yes\n\
+         LockPersonality=yes\n\
+         SystemCallArchitectures=native\n\n",
+    );
+
+    unit.push_str("[Install]\nWantedBy=multi-user.target\n");
+    unit
+}
+
+/// The directories of the files that the daemon and its watchdogs write: the
+/// daemon's log file, and the watchdogs' output files and audit logs.
+fn writable_directories(settings: &Settings) -> BTreeSet<PathBuf> {
+    let outputs = settings.watchdogs().iter().flat_map(|watchdog| {
+        watchdog
+            .outputs
+            .iter()
+            .filter_map(|output| match &output.target {
+                OutputTarget::File(path) => Some(path),
+                _ => None,
+            })
+            .chain(&watchdog.audit_log)
+    });
+    outputs
+        .chain(&settings.logging().file)
+        .filter_map(|path| path.parent())
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        let watchdog = |name: &str, output: &str| {
+            settings::Watchdog::builder()
+                .name(name)
+                .log_file("/var/log/pgbouncer.log")
+                .regex("^FATAL")
+                .output_file(output)
+                .audit_log("/var/log/log-watchdog/audit.jsonl")
+                .build()
+                .unwrap()
+        };
+        Settings::new(vec![
+            watchdog("pgbouncer", "/var/lib/log-watchdog/pgbouncer.txt"),
+            watchdog("postgres", "-"),
+        ])
+    }
+
+    #[test]
+    fn test_when_generated_then_settings_validated_and_outputs_writable() {
+        let unit = generate(
+            Path::new("/usr/bin/log-watchdog"),
+            Path::new("/etc/log-watchdog/config.yml"),
+            Some(&settings()),
+            false,
+        );
+
+        assert!(unit.contains(
+            "ExecStartPre=/usr/bin/log-watchdog validate --settings /etc/log-watchdog/config.yml\n"
+        ));
+        assert!(unit
+            .contains("ExecStart=/usr/bin/log-watchdog --settings /etc/log-watchdog/config.yml\n"));
+        assert!(unit.contains("ProtectSystem=strict\nReadWritePaths=-/var/lib/log-watchdog\nReadWritePaths=-/var/log/log-watchdog\nProtectHome"));
+        assert!(unit.ends_with("[Install]\nWantedBy=multi-user.target\n"));
+    }
+
+    #[test]
+    fn test_when_template_then_instance_watchdog_run() {
+        let unit = generate(
+            Path::new("/usr/bin/log-watchdog"),
+            Path::new("/etc/log-watchdog/config.yml"),
+            None,
+            true,
+        );
+
+        assert!(unit.contains("Description=log-watchdog for the %i watchdog\n"));
+        assert!(unit.contains(
+            "ExecStart=/usr/bin/log-watchdog run --settings /etc/log-watchdog/config.yml --watchdog %i\n"
+        ));
+        assert!(unit.contains("#ReadWritePaths=\n"));
+    }
+}
@@ -0,0 +1,603 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use settings::Encoding;
+
+/// Reads the lines appended to a file, following it when it's truncated or
+/// rotated, without matching or running anything.
+///
+/// Only complete lines are read: a line that's still being written is read
+/// by the poll after its newline is.
+///
+/// ```no_run
+/// let mut tailer = log_watchdog::Tailer::open("/var/log/syslog")?;
+/// loop {
+///     for line in tailer.poll()? {
+///         println!("{line}");
+///     }
+///     std::thread::sleep(std::time::Duration::from_secs(1));
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Tailer {
+    path: PathBuf,
+    file: File,
+    /// The device and inode of the open file, to tell when the path is
+    /// rotated to a new file
+    id: (u64, u64),
+    position: u64,
+    /// Reused between polls, for what's read before it's split into lines
+    buffer: Vec<u8>,
+    read_buffer_size: usize,
+    max_read_bytes: u64,
+    /// Whether the previous poll stopped at `max_read_bytes`
+    behind: bool,
+    partial_line_timeout: Option<Duration>,
+    /// When the line that's still being written was first read
+    partial_since: Option<Instant>,
+    /// How lines are decoded, if they are
+    encoding: Option<Encoding>,
+}
+
+impl Tailer {
+    /// Opens the file at its end, so that only lines appended from now on
+    /// are read.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        Self::open_at(path, u64::MAX)
+    }
+
+    /// Opens the file at `position`, e.g. where a previous tailer stopped. If
+    /// the file is shorter than that now, it's opened at its end instead.
+    pub fn open_at(path: impl Into<PathBuf>, position: u64) -> io::Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        Ok(Self {
+            path,
+            file,
+            id: (metadata.dev(), metadata.ino()),
+            position: position.min(metadata.len()),
+            buffer: Vec::new(),
+            read_buffer_size: settings::DEFAULT_READ_BUFFER_SIZE,
+            max_read_bytes: u64::MAX,
+            behind: false,
+            partial_line_timeout: None,
+            partial_since: None,
+            encoding: None,
+        })
+    }
+
+    /// Reads at most this many bytes per poll, so that catching up on a file
+    /// that's far ahead takes several polls instead of reading it all at once;
+    /// a line longer than this is still read whole. At least a byte is read,
+    /// so that 0 doesn't leave the tailer behind for good.
+    #[must_use]
+    pub const fn max_read_bytes(mut self, max_read_bytes: u64) -> Self {
+        self.max_read_bytes = if max_read_bytes == 0 {
+            1
+        } else {
+            max_read_bytes
+        };
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The byte offset in the file up to which lines have been read: the bytes
+    /// consumed, line endings and invalid UTF-8 included, so that a tailer
+    /// opened at it continues at the next line.
+    pub const fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Keeps a buffer of this many bytes between polls, which reads this much
+    /// at once: more memory for fewer reads of a busy file. A poll that reads
+    /// more grows it for as long as the poll.
+    #[must_use]
+    pub const fn read_buffer_size(mut self, read_buffer_size: usize) -> Self {
+        self.read_buffer_size = read_buffer_size;
+        self
+    }
+
+    /// Reads a line that's still being written after waiting this long for
+    /// the rest of it, which is then read as a line of its own; by default,
+    /// it's waited for however long that takes.
+    #[must_use]
+    pub const fn partial_line_timeout(mut self, timeout: Duration) -> Self {
+        self.partial_line_timeout = Some(timeout);
+        self
+    }
+
+    /// Decodes lines as `encoding` says, so that they're valid UTF-8, or
+    /// skipped; by default, they're read as they are.
+    #[must_use]
+    pub const fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Whether a line that's still being written is waited for, which a poll
+    /// reads once its [`Tailer::partial_line_timeout`] has passed.
+    pub const fn has_partial_line(&self) -> bool {
+        self.partial_since.is_some()
+    }
+
+    /// Whether there may be more to read right away, because the previous poll
+    /// stopped at [`Tailer::max_read_bytes`].
+    pub const fn is_behind(&self) -> bool {
+        self.behind
+    }
+
+    /// Reads the lines appended since the previous poll, like
+    /// [`Tailer::poll_lines`], as strings with invalid UTF-8 replaced.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read, or reopened after it's rotated.
+    pub fn poll(&mut self) -> io::Result<Vec<String>> {
+        Ok(self
+            .poll_lines()?
+            .iter()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect())
+    }
+
+    /// Reads the lines appended since the previous poll, as bytes sharing a
+    /// single buffer.
+    ///
+    /// If the file was truncated, it's read from the start. If it was rotated,
+    /// the rest of the rotated file is read, then the new file from the
+    /// start: every line of the rotated file, including one it ends without a
+    /// newline, comes before the new file's, even when catching up on it
+    /// takes several polls.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read, or reopened after it's rotated.
+    pub fn poll_lines(&mut self) -> io::Result<Lines> {
+        self.buffer.clear();
+        // back down to its size after catching up, or up to it on the first poll
+        self.buffer.shrink_to(self.read_buffer_size);
+        self.buffer.reserve(self.read_buffer_size);
+
+        match std::fs::metadata(&self.path) {
+            Ok(metadata) if (metadata.dev(), metadata.ino()) != self.id => {
+                // a line the rotated file ends with won't be finished in it
+                self.read_lines(true)?;
+                // the new file is read once the rotated one is caught up on
+                if self.behind {
+                    return Ok(Lines::new(&self.buffer));
+                }
+                let mut rotated = Self::open_at(self.path.clone(), 0)?
+                    .read_buffer_size(self.read_buffer_size)
+                    .max_read_bytes(self.max_read_bytes);
+                rotated.partial_line_timeout = self.partial_line_timeout;
+                rotated.encoding = self.encoding;
+                rotated.buffer = std::mem::take(&mut self.buffer);
+                *self = rotated;
+            }
+            Ok(metadata) if metadata.len() < self.position => self.position = 0,
+            // rotated away, and not recreated yet
+            Ok(_) | Err(_) => (),
+        }
+
+        self.read_lines(false)?;
+        Ok(Lines::new(&self.buffer))
+    }
+
+    /// Appends the complete lines from the position on to the buffer; a
+    /// line that's still being written is read again by the next poll, until
+    /// the partial line timeout, or right away if `to_end`.
+    fn read_lines(&mut self, to_end: bool) -> io::Result<()> {
+        let start = self.buffer.len();
+        self.file.seek(SeekFrom::Start(self.position))?;
+
+        // up to max_read_bytes, and on to the end of a line longer than that
+        let mut complete = None;
+        while complete.is_none() {
+            let chunk = self.buffer.len();
+            let read = (&mut self.file)
+                .take(self.max_read_bytes)
+                .read_to_end(&mut self.buffer)?;
+            self.behind = read as u64 == self.max_read_bytes;
+            complete =
+                memchr::memrchr(b'\n', &self.buffer[chunk..]).map(|newline| chunk + newline + 1);
+            if !self.behind {
+                break;
+            }
+        }
+
+        let mut complete = complete.unwrap_or(start);
+        let mut flushed = false;
+        if self.behind || complete == self.buffer.len() {
+            self.partial_since = None;
+        } else {
+            let since = *self.partial_since.get_or_insert_with(Instant::now);
+            if to_end
+                || self
+                    .partial_line_timeout
+                    .is_some_and(|timeout| since.elapsed() >= timeout)
+            {
+                complete = self.buffer.len();
+                flushed = true;
+                self.partial_since = None;
+            }
+        }
+
+        self.buffer.truncate(complete);
+        self.position += (complete - start) as u64;
+        if flushed {
+            // ended like the complete lines, though it isn't in the file
+            self.buffer.push(b'\n');
+        }
+        self.decode(start);
+        Ok(())
+    }
+
+    /// Decodes the lines read from `start` on in place.
+    fn decode(&mut self, start: usize) {
+        let Some(encoding) = self.encoding else {
+            return;
+        };
+        let read = &self.buffer[start..];
+        let decoded = match encoding {
+            Encoding::Strict => {
+                if std::str::from_utf8(read).is_ok() {
+                    return;
+                }
+                let mut skipped = 0;
+                let valid: Vec<u8> = read
+                    .split_inclusive(|byte| *byte == b'\n')
+                    .filter(|line| {
+                        let valid = std::str::from_utf8(line).is_ok();
+                        skipped += usize::from(!valid);
+                        valid
+                    })
+                    .flatten()
+                    .copied()
+                    .collect();
+                warn!(
+                    "skipped {skipped} lines of {:?} that aren't UTF-8",
+                    self.path.as_os_str()
+                );
+                valid
+            }
+            Encoding::Lossy | Encoding::Latin1 => match decode(encoding, read) {
+                Some(Cow::Owned(decoded)) => decoded.into_bytes(),
+                // nothing to decode
+                Some(Cow::Borrowed(_)) | None => return,
+            },
+        };
+        self.buffer.truncate(start);
+        self.buffer.extend_from_slice(&decoded);
+    }
+}
+
+/// The line as text, decoded as `encoding` says, or `None` if it's skipped.
+pub(crate) fn decode(encoding: Encoding, line: &[u8]) -> Option<Cow<'_, str>> {
+    match encoding {
+        Encoding::Strict => std::str::from_utf8(line).ok().map(Cow::Borrowed),
+        Encoding::Lossy => Some(String::from_utf8_lossy(line)),
+        Encoding::Latin1 if line.is_ascii() => std::str::from_utf8(line).ok().map(Cow::Borrowed),
+        Encoding::Latin1 => Some(line.iter().copied().map(char::from).collect()),
+    }
+}
+
+/// Lines read at once, without their line endings, sharing one buffer
+/// instead of allocating each line.
+#[derive(Debug, Clone, Default)]
+pub struct Lines {
+    buffer: Arc<[u8]>,
+    lines: Vec<Range<usize>>,
+}
+
+impl Lines {
+    /// Splits complete lines, each ending in a newline.
+    fn new(complete: &[u8]) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for newline in memchr::memchr_iter(b'\n', complete) {
+            let end = if newline > start && complete[newline - 1] == b'\r' {
+                newline - 1
+            } else {
+                newline
+            };
+            lines.push(start..end);
+            start = newline + 1;
+        }
+        Self {
+            buffer: Arc::from(complete),
+            lines,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.lines.iter().map(|line| &self.buffer[line.clone()])
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+/// Lines from anywhere, e.g. a custom [`crate::pipeline::Source`].
+impl From<Vec<String>> for Lines {
+    fn from(lines: Vec<String>) -> Self {
+        let mut complete = Vec::new();
+        for line in lines {
+            complete.extend_from_slice(line.as_bytes());
+            complete.push(b'\n');
+        }
+        Self::new(&complete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn log_file(dir: &tempdir::TempDir) -> (PathBuf, File) {
+        let path = dir.path().join("test_tailer.txt");
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)
+            .unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn test_when_opened_at_0_then_every_line_read() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        writeln!(file, "Hello, world!").unwrap();
+        writeln!(file, "Goodbye, world!").unwrap();
+
+        let mut tailer = Tailer::open_at(&path, 0).unwrap();
+
+        assert_eq!(
+            tailer.poll().unwrap(),
+            vec!["Hello, world!", "Goodbye, world!"]
+        );
+        assert_eq!(tailer.position(), file.metadata().unwrap().len());
+    }
+
+    #[test]
+    fn test_when_opened_then_only_appended_lines_read() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        writeln!(file, "Hello, world!").unwrap();
+
+        let mut tailer = Tailer::open(&path).unwrap();
+        writeln!(file, "Goodbye, world!").unwrap();
+
+        assert_eq!(tailer.poll().unwrap(), vec!["Goodbye, world!"]);
+        assert_eq!(tailer.position(), file.metadata().unwrap().len());
+    }
+
+    #[test]
+    fn test_when_line_incomplete_then_read_once_complete() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap();
+
+        write!(file, "Hello, ").unwrap();
+        assert!(tailer.poll().unwrap().is_empty());
+        assert_eq!(tailer.position(), 0);
+
+        writeln!(file, "world!").unwrap();
+        assert_eq!(tailer.poll().unwrap(), vec!["Hello, world!"]);
+    }
+
+    #[test]
+    fn test_when_crlf_split_between_writes_then_line_read_once_complete() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap();
+
+        write!(file, "one\r\ntwo\r").unwrap();
+        assert_eq!(tailer.poll().unwrap(), vec!["one"]);
+        assert_eq!(tailer.position(), 5);
+
+        writeln!(file).unwrap();
+        assert_eq!(tailer.poll().unwrap(), vec!["two"]);
+        assert_eq!(tailer.position(), 10);
+    }
+
+    #[test]
+    fn test_when_partial_line_timeout_passed_then_read_as_line() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path)
+            .unwrap()
+            .partial_line_timeout(Duration::from_millis(20));
+
+        write!(file, "one\nhalf").unwrap();
+        assert_eq!(tailer.poll().unwrap(), vec!["one"]);
+        assert!(tailer.has_partial_line());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(tailer.poll().unwrap(), vec!["half"]);
+        assert!(!tailer.has_partial_line());
+        assert_eq!(tailer.position(), 8);
+
+        writeln!(file, " and the rest").unwrap();
+        assert_eq!(tailer.poll().unwrap(), vec![" and the rest"]);
+    }
+
+    #[test]
+    fn test_when_truncated_then_read_from_start() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        writeln!(file, "Hello, world!").unwrap();
+        let mut tailer = Tailer::open(&path).unwrap();
+
+        file.set_len(0).unwrap();
+        writeln!(file, "Bye").unwrap();
+
+        assert_eq!(tailer.poll().unwrap(), vec!["Bye"]);
+        assert_eq!(tailer.position(), 4);
+    }
+
+    #[test]
+    fn test_when_rotated_then_rest_of_old_file_and_new_file_read() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap();
+
+        writeln!(file, "old").unwrap();
+        std::fs::rename(&path, dir.path().join("test_tailer.txt.1")).unwrap();
+        let (_, mut file) = log_file(&dir);
+        writeln!(file, "new").unwrap();
+
+        assert_eq!(tailer.poll().unwrap(), vec!["old", "new"]);
+        assert_eq!(tailer.position(), 4);
+    }
+
+    #[test]
+    fn test_when_rotated_file_ends_in_partial_line_then_read_before_new_file() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap();
+
+        write!(file, "old 1\nold 2, unfinished").unwrap();
+        assert_eq!(tailer.poll().unwrap(), vec!["old 1"]);
+        std::fs::rename(&path, dir.path().join("test_tailer.txt.1")).unwrap();
+        let (_, mut file) = log_file(&dir);
+        writeln!(file, "new").unwrap();
+
+        assert_eq!(tailer.poll().unwrap(), vec!["old 2, unfinished", "new"]);
+    }
+
+    #[test]
+    fn test_when_rotated_while_behind_then_old_file_read_first() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap().max_read_bytes(8);
+
+        for i in 0..5 {
+            writeln!(file, "old {i}").unwrap();
+        }
+        std::fs::rename(&path, dir.path().join("test_tailer.txt.1")).unwrap();
+        let (_, mut file) = log_file(&dir);
+        for i in 0..5 {
+            writeln!(file, "new {i}").unwrap();
+        }
+
+        let mut lines = Vec::new();
+        loop {
+            let polled = tailer.poll().unwrap();
+            if polled.is_empty() && !tailer.is_behind() {
+                break;
+            }
+            lines.extend(polled);
+        }
+        let expected: Vec<_> = ["old", "new"]
+            .iter()
+            .flat_map(|file| (0..5).map(move |i| format!("{file} {i}")))
+            .collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn test_when_far_behind_then_caught_up_in_chunks() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap().max_read_bytes(8);
+
+        writeln!(file, "one\ntwo\na line longer than 8 bytes\nend").unwrap();
+
+        assert_eq!(tailer.poll().unwrap(), vec!["one", "two"]);
+        assert!(tailer.is_behind());
+        // read on to the end of the chunk with the long line's newline
+        assert_eq!(
+            tailer.poll().unwrap(),
+            vec!["a line longer than 8 bytes", "end"]
+        );
+        assert!(!tailer.is_behind());
+        assert_eq!(tailer.position(), file.metadata().unwrap().len());
+    }
+
+    #[test]
+    fn test_when_max_read_bytes_zero_then_still_catches_up() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap().max_read_bytes(0);
+
+        writeln!(file, "one\ntwo").unwrap();
+
+        let mut lines = Vec::new();
+        for _ in 0..16 {
+            lines.extend(tailer.poll().unwrap());
+            if !tailer.is_behind() {
+                break;
+            }
+        }
+        assert_eq!(lines, vec!["one", "two"]);
+        assert!(!tailer.is_behind());
+    }
+
+    #[test]
+    fn test_when_caught_up_then_buffer_back_to_its_size() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap().read_buffer_size(16);
+
+        writeln!(file, "{}", "x".repeat(4096)).unwrap();
+        assert_eq!(tailer.poll().unwrap()[0].len(), 4096);
+        assert!(tailer.buffer.capacity() > 4096);
+
+        assert!(tailer.poll().unwrap().is_empty());
+        assert!(tailer.buffer.capacity() < 4096);
+    }
+
+    #[test]
+    fn test_when_lines_polled_then_bytes_read_without_line_endings() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut tailer = Tailer::open(&path).unwrap();
+
+        file.write_all(b"dos\r\n\xff latin-1\n\nlast").unwrap();
+        let lines = tailer.poll_lines().unwrap();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines.iter().collect::<Vec<_>>(),
+            vec![&b"dos"[..], b"\xff latin-1", b""]
+        );
+    }
+
+    #[test]
+    fn test_when_lines_not_utf8_then_decoded_as_encoding_says() {
+        let dir = tempdir::TempDir::new("test_tailer").unwrap();
+        let (path, mut file) = log_file(&dir);
+        let mut strict = Tailer::open(&path).unwrap().encoding(Encoding::Strict);
+        let mut lossy = Tailer::open(&path).unwrap().encoding(Encoding::Lossy);
+        let mut latin1 = Tailer::open(&path).unwrap().encoding(Encoding::Latin1);
+
+        file.write_all(b"caf\xe9\nutf-8 \xc3\xa9\n").unwrap();
+
+        assert_eq!(strict.poll().unwrap(), ["utf-8 é"]);
+        assert_eq!(lossy.poll().unwrap(), ["caf\u{fffd}", "utf-8 é"]);
+        assert_eq!(latin1.poll().unwrap(), ["café", "utf-8 Ã©"]);
+        // the skipped line's bytes are read all the same
+        assert_eq!(strict.position(), file.metadata().unwrap().len());
+    }
+}
@@ -0,0 +1,182 @@
+//! Helpers for testing watchdogs and their settings, e.g. that a rule set
+//! matches the lines it should, without sleeping for long enough.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use log_watchdog::testing::{self, TestLog};
+//!
+//! let mut log = TestLog::new("app")?;
+//! let watchdog = settings::Watchdog::builder()
+//!     .name("app")
+//!     .log_file(log.path())
+//!     .regex("^ERROR")
+//!     .build()
+//!     .unwrap();
+//! let (handle, events) = log_watchdog::events(settings::Settings::new(vec![watchdog]));
+//!
+//! assert!(testing::wait_until_running(&handle, Duration::from_secs(5)));
+//! log.append("ERROR disk full")?;
+//! let event = testing::next_match(&events, Duration::from_secs(5)).unwrap();
+//! assert_eq!(event.line, "ERROR disk full");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Receiver,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{MatchEvent, WatchdogHandle, WatchdogStatus};
+
+/// How often [`wait_for`] checks its condition.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Makes the directories of tests running in parallel unique.
+static TEST_LOG: AtomicU64 = AtomicU64::new(0);
+
+/// A log file in a temporary directory of its own, which is deleted with
+/// everything in it, e.g. output files, when it's dropped.
+#[derive(Debug)]
+pub struct TestLog {
+    dir: PathBuf,
+    path: PathBuf,
+    file: File,
+}
+
+impl TestLog {
+    /// Creates an empty `{name}.log`.
+    ///
+    /// # Errors
+    ///
+    /// If the directory or file can't be created.
+    pub fn new(name: &str) -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "log-watchdog-{name}-{}-{}",
+            std::process::id(),
+            TEST_LOG.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{name}.log"));
+        let file = File::create(&path)?;
+        Ok(Self { dir, path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The log file's directory, for other files of the test.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Appends a line, flushed so that it's seen by the watchdogs.
+    ///
+    /// # Errors
+    ///
+    /// If the line can't be written.
+    pub fn append(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.file, "{line}")?;
+        self.file.flush()
+    }
+
+    /// Appends the lines `interval` apart, e.g. to test debouncing.
+    ///
+    /// # Errors
+    ///
+    /// If a line can't be written.
+    pub fn append_every<'a>(
+        &mut self,
+        interval: Duration,
+        lines: impl IntoIterator<Item = &'a str>,
+    ) -> io::Result<()> {
+        for (i, line) in lines.into_iter().enumerate() {
+            if i > 0 {
+                std::thread::sleep(interval);
+            }
+            self.append(line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TestLog {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Checks `condition` until it's true, or `timeout` has passed, returning
+/// whether it became true.
+pub fn wait_for(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if condition() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Waits until every watchdog is watching its log file, so that the lines
+/// appended from now on are read. False if one stopped, or `timeout` passed.
+pub fn wait_until_running(handle: &WatchdogHandle, timeout: Duration) -> bool {
+    let mut statuses = Vec::new();
+    wait_for(timeout, || {
+        statuses = handle.statuses();
+        statuses
+            .iter()
+            .all(|(_, status)| !matches!(status, WatchdogStatus::Starting))
+    }) && statuses
+        .iter()
+        .all(|(_, status)| matches!(status, WatchdogStatus::Running))
+}
+
+/// The next match from [`crate::events`], or `None` if there's none within
+/// `timeout`.
+pub fn next_match(events: &Receiver<MatchEvent>, timeout: Duration) -> Option<MatchEvent> {
+    events.recv_timeout(timeout).ok()
+}
+
+/// Waits until the file at `path`, e.g. a watchdog's output file, contains
+/// `expected`, returning whether it did within `timeout`.
+pub fn wait_for_output(path: &Path, expected: &str, timeout: Duration) -> bool {
+    wait_for(timeout, || {
+        std::fs::read_to_string(path).is_ok_and(|contents| contents.contains(expected))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_test_log_dropped_then_directory_removed() {
+        let mut log = TestLog::new("dropped").unwrap();
+        let dir = log.dir().to_path_buf();
+        log.append_every(Duration::ZERO, ["a", "b"]).unwrap();
+
+        assert_eq!(std::fs::read_to_string(log.path()).unwrap(), "a\nb\n");
+        drop(log);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_when_condition_never_true_then_wait_times_out() {
+        let started = Instant::now();
+
+        assert!(!wait_for(Duration::from_millis(50), || false));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(wait_for(Duration::ZERO, || true));
+    }
+}
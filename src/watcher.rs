@@ -0,0 +1,422 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use notify::{Config, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use settings::WatcherBackend;
+
+type Events = Sender<notify::Result<notify::Event>>;
+
+/// Who's subscribed to the events of each watched path.
+#[derive(Default)]
+struct Subscribers {
+    by_path: HashMap<PathBuf, Vec<(u64, Events)>>,
+    next_id: u64,
+}
+
+/// What a file is watched with: the platform's own notifications unless
+/// there's a backend, and how often it's polled, for the poll backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Backend {
+    backend: Option<WatcherBackend>,
+    poll_interval: Duration,
+}
+
+impl Backend {
+    pub(crate) fn new(backend: Option<WatcherBackend>, poll_interval: Duration) -> Self {
+        Self {
+            backend,
+            // so that the other backends' watchers are shared whatever it is
+            poll_interval: if backend == Some(WatcherBackend::Poll) {
+                poll_interval
+            } else {
+                Duration::ZERO
+            },
+        }
+    }
+}
+
+/// One watcher for every file watched with the same backend, instead of a
+/// watcher, and its thread, per watchdog; its events are sent on to the
+/// subscribers of their paths.
+struct SharedWatcher {
+    watcher: Box<dyn Watcher + Send>,
+    subscribers: Arc<Mutex<Subscribers>>,
+}
+
+impl SharedWatcher {
+    fn new(backend: Backend) -> notify::Result<Self> {
+        let subscribers = Arc::new(Mutex::new(Subscribers::default()));
+        let events = Arc::clone(&subscribers);
+        let handler = move |event| dispatch(&events, event);
+        let watcher: Box<dyn Watcher + Send> = match backend.backend {
+            None => Box::new(RecommendedWatcher::new(handler, Config::default())?),
+            Some(WatcherBackend::Poll) => Box::new(PollWatcher::new(
+                handler,
+                Config::default().with_poll_interval(backend.poll_interval),
+            )?),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Some(WatcherBackend::Inotify) => {
+                Box::new(notify::INotifyWatcher::new(handler, Config::default())?)
+            }
+            #[cfg(any(
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd",
+                target_os = "dragonfly",
+                target_os = "ios"
+            ))]
+            Some(WatcherBackend::Kqueue) => {
+                Box::new(notify::KqueueWatcher::new(handler, Config::default())?)
+            }
+            Some(backend) => {
+                return Err(notify::Error::generic(&format!(
+                    "the {backend} backend isn't available on this platform"
+                )))
+            }
+        };
+        Ok(Self {
+            watcher,
+            subscribers,
+        })
+    }
+}
+
+/// A watcher per backend, created by its first subscription.
+static WATCHERS: Mutex<BTreeMap<Backend, SharedWatcher>> = Mutex::new(BTreeMap::new());
+
+/// Unsubscribes from the path's events when dropped, and stops watching it if
+/// it was the last subscription; the watcher, and its thread, are stopped
+/// with the last subscription of all, e.g. once the oneshot watchdogs are
+/// done.
+pub(crate) struct Subscription {
+    path: PathBuf,
+    backend: Backend,
+    id: u64,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut watchers = WATCHERS.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(watcher) = watchers.get_mut(&self.backend) else {
+            return;
+        };
+        let (last, none_left) = {
+            let mut subscribers = watcher
+                .subscribers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let Some(events) = subscribers.by_path.get_mut(&self.path) else {
+                return;
+            };
+            events.retain(|(id, _)| *id != self.id);
+            let last = events.is_empty();
+            if last {
+                subscribers.by_path.remove(&self.path);
+            }
+            (last, subscribers.by_path.is_empty())
+        };
+        // outside the lock, which the watcher's thread waits for to dispatch
+        if none_left {
+            watchers.remove(&self.backend);
+        } else if last {
+            let _ = watcher.watcher.unwatch(&self.path);
+        }
+    }
+}
+
+/// Watches the file at `path` with the backend, receiving its events until
+/// the subscription is dropped.
+pub(crate) fn subscribe(
+    path: &Path,
+    backend: Backend,
+) -> notify::Result<(Subscription, Receiver<notify::Result<notify::Event>>)> {
+    // the path of the events, which notify makes absolute like this
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map_err(notify::Error::io)?
+            .join(path)
+    };
+
+    let mut watchers = WATCHERS.lock().unwrap_or_else(PoisonError::into_inner);
+    let watcher = match watchers.entry(backend) {
+        Entry::Occupied(watcher) => watcher.into_mut(),
+        Entry::Vacant(entry) => entry.insert(SharedWatcher::new(backend)?),
+    };
+
+    let watched = watcher
+        .subscribers
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .by_path
+        .contains_key(&path);
+    // outside the lock, which the watcher's thread waits for to dispatch
+    if !watched {
+        watcher.watcher.watch(&path, RecursiveMode::NonRecursive)?;
+    }
+
+    let mut subscribers = watcher
+        .subscribers
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let (tx, rx) = std::sync::mpsc::channel();
+    let id = subscribers.next_id;
+    subscribers.next_id += 1;
+    subscribers
+        .by_path
+        .entry(path.clone())
+        .or_default()
+        .push((id, tx));
+
+    Ok((Subscription { path, backend, id }, rx))
+}
+
+/// Sends an event to the subscribers of its paths, and an error to every
+/// subscriber if it doesn't say which paths it's about.
+fn dispatch(subscribers: &Mutex<Subscribers>, event: notify::Result<notify::Event>) {
+    let subscribers = subscribers.lock().unwrap_or_else(PoisonError::into_inner);
+    let paths = match &event {
+        Ok(event) => &event.paths,
+        Err(e) => &e.paths,
+    };
+    let mut sent = Vec::new();
+    let mut send = |(id, events): &(u64, Events)| {
+        if sent.contains(id) {
+            return;
+        }
+        sent.push(*id);
+        let _ = events.send(match &event {
+            Ok(event) => Ok(event.clone()),
+            Err(e) => Err(clone_error(e)),
+        });
+    };
+
+    if paths.is_empty() && event.is_err() {
+        subscribers.by_path.values().flatten().for_each(&mut send);
+    } else {
+        paths
+            .iter()
+            .filter_map(|path| subscribers.by_path.get(path))
+            .flatten()
+            .for_each(&mut send);
+    }
+}
+
+/// The filesystems, by their type in `/proc/self/mountinfo`, whose files get
+/// no notifications for what's written to them from elsewhere: by other
+/// hosts to a network share, or to an overlay's lower layers from under it.
+const POLLED_FILESYSTEMS: [(&str, &str); 6] = [
+    ("nfs", "NFS"),
+    ("nfs4", "NFS"),
+    ("cifs", "CIFS"),
+    ("smb3", "SMB3"),
+    ("smbfs", "SMB"),
+    ("overlay", "overlayfs"),
+];
+
+/// The name of the filesystem the file at `path` is on, if it's one whose
+/// files need to be polled, see [`POLLED_FILESYSTEMS`].
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn polled_filesystem(path: &Path) -> Option<&'static str> {
+    let path = std::fs::canonicalize(path).ok()?;
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let filesystem = mounted_filesystem(&mountinfo, &path)?;
+    POLLED_FILESYSTEMS
+        .iter()
+        .find(|(polled, _)| *polled == filesystem)
+        .map(|(_, name)| *name)
+}
+
+/// The type of the filesystem mounted over the absolute `path`, from the
+/// lines of `/proc/self/mountinfo`: the one on the deepest mount point the
+/// path is under, and of those the last mounted, which hides the others.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn mounted_filesystem<'a>(mountinfo: &'a str, path: &Path) -> Option<&'a str> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // the mount point is the fifth field, and the type the first after `-`
+            let (fields, filesystem) = line.split_once(" - ")?;
+            let mount_point = unescape(fields.split(' ').nth(4)?);
+            let filesystem = filesystem.split(' ').next()?;
+            path.starts_with(&mount_point)
+                .then(|| (mount_point.components().count(), filesystem))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, filesystem)| filesystem)
+}
+
+/// A mount point of `/proc/self/mountinfo`, where spaces, tabs, newlines and
+/// backslashes are escaped as octal, e.g. `\040` for a space.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn unescape(mount_point: &str) -> std::path::PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = Vec::with_capacity(mount_point.len());
+    let mut rest = mount_point.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let octal = tail
+            .get(..3)
+            .filter(|_| byte == b'\\')
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match octal {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    std::ffi::OsString::from_vec(bytes).into()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn polled_filesystem(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+/// A copy of the error, which can't be cloned, keeping the kind of an I/O
+/// error, e.g. the poll watcher's for a removed file.
+fn clone_error(e: &notify::Error) -> notify::Error {
+    let error = match &e.kind {
+        notify::ErrorKind::Io(io) => notify::Error::io(io::Error::new(io.kind(), io.to_string())),
+        _ => notify::Error::generic(&e.to_string()),
+    };
+    error.set_paths(e.paths.clone())
+}
+
+/// If true, the error is a watched file not being found, which the poll
+/// watcher reports on every poll while a file is removed.
+pub(crate) fn is_not_found(e: &notify::Error) -> bool {
+    matches!(&e.kind, notify::ErrorKind::Io(io) if io.kind() == io::ErrorKind::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, time::Duration};
+
+    #[test]
+    fn test_when_same_file_subscribed_twice_then_both_get_events() {
+        let dir = tempdir::TempDir::new("test_watcher").unwrap();
+        let path = dir.path().join("watched.log");
+        let mut file = std::fs::File::create(&path).unwrap();
+
+        let (first, first_events) = subscribe(&path, Backend::default()).unwrap();
+        let (_second, second_events) = subscribe(&path, Backend::default()).unwrap();
+        drop(first);
+        writeln!(file, "line").unwrap();
+
+        assert!(second_events.recv_timeout(Duration::from_secs(5)).is_ok());
+        // the first subscription's sender is gone with it
+        assert!(first_events.recv_timeout(Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_when_last_subscription_dropped_then_path_unwatched() {
+        let dir = tempdir::TempDir::new("test_watcher").unwrap();
+        let path = dir.path().join("watched.log");
+        std::fs::File::create(&path).unwrap();
+        let watched = || {
+            WATCHERS
+                .lock()
+                .unwrap()
+                .get(&Backend::default())
+                .is_some_and(|watcher| {
+                    watcher
+                        .subscribers
+                        .lock()
+                        .unwrap()
+                        .by_path
+                        .contains_key(&path)
+                })
+        };
+
+        let (subscription, events) = subscribe(&path, Backend::default()).unwrap();
+        assert!(watched());
+        drop(subscription);
+
+        assert!(!watched());
+        assert!(events.recv_timeout(Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_when_polled_then_events_without_notifications() {
+        let dir = tempdir::TempDir::new("test_watcher").unwrap();
+        let path = dir.path().join("watched.log");
+        std::fs::File::create(&path).unwrap();
+        let backend = Backend::new(Some(WatcherBackend::Poll), Duration::from_millis(10));
+
+        let (_subscription, events) = subscribe(&path, backend).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(WATCHERS.lock().unwrap().contains_key(&backend));
+        loop {
+            match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Ok(event) if event.kind.is_remove() => break,
+                Ok(event) => panic!("unexpected event {event:?}"),
+                Err(e) => assert!(is_not_found(&e), "unexpected error {e}"),
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_when_backend_not_of_platform_then_error() {
+        let dir = tempdir::TempDir::new("test_watcher").unwrap();
+        let path = dir.path().join("watched.log");
+        std::fs::File::create(&path).unwrap();
+
+        let backend = Backend::new(Some(WatcherBackend::Kqueue), Duration::from_secs(1));
+        assert!(subscribe(&path, backend).is_err());
+        let backend = Backend::new(Some(WatcherBackend::Inotify), Duration::from_secs(1));
+        assert!(subscribe(&path, backend).is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_when_mountinfo_then_deepest_last_mount_is_the_filesystem() {
+        let mountinfo = "\
+22 1 0:21 / / rw,relatime - overlay overlay rw,lowerdir=/l,upperdir=/u
+23 22 0:22 / /proc rw,relatime - proc proc rw
+40 22 0:35 / /var/log/shared\\040logs rw,relatime shared:5 - nfs4 nas:/logs rw
+41 22 0:36 / /mnt/data rw - ext4 /dev/sda1 rw
+42 41 0:37 / /mnt/data rw - cifs //nas/data rw
+";
+        let filesystem = |path: &str| mounted_filesystem(mountinfo, Path::new(path));
+
+        assert_eq!(filesystem("/var/log/shared logs/app.log"), Some("nfs4"));
+        assert_eq!(filesystem("/var/log/shared/app.log"), Some("overlay"));
+        assert_eq!(filesystem("/mnt/data/app.log"), Some("cifs"));
+        assert_eq!(filesystem("/proc/self/status"), Some("proc"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_when_file_on_local_filesystem_then_not_polled() {
+        let dir = tempdir::TempDir::new("test_watcher").unwrap();
+        let path = dir.path().join("watched.log");
+        std::fs::File::create(&path).unwrap();
+
+        assert_eq!(polled_filesystem(Path::new("/proc/self/status")), None);
+        assert_eq!(polled_filesystem(&dir.path().join("missing.log")), None);
+        // unless the tests run on a share or in a container's overlay
+        if let Some(filesystem) = polled_filesystem(&path) {
+            assert!(POLLED_FILESYSTEMS
+                .iter()
+                .any(|(_, name)| *name == filesystem));
+        }
+    }
+}
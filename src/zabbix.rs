@@ -0,0 +1,180 @@
+//! Values pushed to a watchdog's `zabbix` trapper item, with the protocol of
+//! `zabbix_sender`: a `ZBXD` header and the length of a JSON `sender data`
+//! request, answered likewise with how many values were processed.
+
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, SystemTime},
+};
+
+use serde_json::{json, Value};
+use settings::{Field, Redactions, Zabbix};
+
+use crate::{http, output::rfc3339, MatchEvent};
+
+/// How long connecting, and every read and write of pushing a value, may
+/// take, as for Alertmanager's alerts.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The header of every message, before its length: `ZBXD` and the protocol
+/// version.
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+
+/// The longest response read, so that a server can't make the sender buffer
+/// without bounds.
+const MAX_RESPONSE_LEN: u64 = 64 << 10;
+
+/// Pushes the match's value, rendered from it, to the trapper item.
+pub(crate) fn send(zabbix: &Zabbix, event: &MatchEvent, redact: &Redactions) -> io::Result<()> {
+    let (timestamp, line) = (rfc3339(event.timestamp), redact.redact(&event.line));
+    let value = zabbix.value.render(|field| match field {
+        Field::Timestamp => &timestamp,
+        Field::Watchdog => &event.watchdog,
+        Field::Line => &line,
+        _ => "",
+    });
+    let clock = event
+        .timestamp
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let request = json!({
+        "request": "sender data",
+        "data": [{
+            "host": zabbix.host,
+            "key": zabbix.key,
+            "value": value,
+            "clock": clock.as_secs(),
+            "ns": clock.subsec_nanos(),
+        }],
+    });
+
+    let mut stream = http::connect(zabbix.server.as_str(), TIMEOUT)?;
+    stream.write_all(&message(request.to_string().as_bytes()))?;
+    let response = read_message(&mut stream)?;
+    let response: Value = serde_json::from_slice(&response).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("invalid response: {e}"))
+    })?;
+
+    // e.g. "processed: 0; failed: 1; total: 1; seconds spent: 0.000042"
+    let info = response["info"].as_str().unwrap_or_default();
+    if response["response"] != "success" || !info.contains("failed: 0") {
+        return Err(io::Error::other(format!(
+            "zabbix at {} didn't process the value of {:?} on {:?}, is it a trapper item? {info}",
+            zabbix.server, zabbix.key, zabbix.host
+        )));
+    }
+    Ok(())
+}
+
+/// The message of the data: the header, and its length as 8 bytes, little
+/// endian.
+fn message(data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER.len() + 8 + data.len());
+    message.extend_from_slice(HEADER);
+    message.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    message.extend_from_slice(data);
+    message
+}
+
+/// Reads a message, returning its data.
+fn read_message(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut header = [0; HEADER.len() + 8];
+    reader.read_exact(&mut header)?;
+    if !header.starts_with(HEADER) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "response without a ZBXD header, is it a zabbix server?",
+        ));
+    }
+    let len = u64::from_le_bytes(header[HEADER.len()..].try_into().unwrap_or_default());
+    if len > MAX_RESPONSE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("response of {len} bytes is too long"),
+        ));
+    }
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, path::PathBuf};
+
+    /// Answers a value with `info`, returning the request.
+    fn server(info: &'static str) -> (String, std::thread::JoinHandle<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_message(&mut stream).unwrap();
+            let response = json!({ "response": "success", "info": info });
+            stream
+                .write_all(&message(response.to_string().as_bytes()))
+                .unwrap();
+            serde_json::from_slice(&request).unwrap()
+        });
+        (address, server)
+    }
+
+    fn event(line: &str) -> MatchEvent {
+        MatchEvent {
+            watchdog: "pgbouncer".into(),
+            log_file: PathBuf::from("/var/log/pgbouncer.log"),
+            line: line.into(),
+            captures: Vec::new(),
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1_736_767_800_250),
+            match_id: 1,
+            suppressed: 0,
+        }
+    }
+
+    #[test]
+    fn test_when_message_read_then_data_after_header_and_length() {
+        let message = message(b"{}");
+        assert_eq!(&message[..13], b"ZBXD\x01\x02\0\0\0\0\0\0\0");
+        assert_eq!(read_message(&mut message.as_slice()).unwrap(), b"{}");
+        assert!(read_message(&mut b"HTTP/1.1 400 Bad Request\r\n".as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_when_sent_then_value_rendered_for_host_and_key() {
+        let (address, server) = server("processed: 1; failed: 0; total: 1; seconds spent: 0.0001");
+        let mut zabbix = Zabbix::new(address, "db1", "pgbouncer.log.fatal");
+        zabbix.value = "{watchdog}: {line}".parse().unwrap();
+
+        send(
+            &zabbix,
+            &event("FATAL password=hunter2"),
+            &Redactions::new(vec![regex::Regex::new("hunter2").unwrap()]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            json!({
+                "request": "sender data",
+                "data": [{
+                    "host": "db1",
+                    "key": "pgbouncer.log.fatal",
+                    "value": "pgbouncer: FATAL password=[REDACTED]",
+                    "clock": 1_736_767_800,
+                    "ns": 250_000_000,
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_when_value_not_processed_then_error() {
+        let (address, server) = server("processed: 0; failed: 1; total: 1; seconds spent: 0.0001");
+        let zabbix = Zabbix::new(address, "db1", "pgbouncer.log.fatal");
+
+        let error = send(&zabbix, &event("FATAL"), &Redactions::default()).unwrap_err();
+
+        assert!(error.to_string().contains("failed: 1"));
+        server.join().unwrap();
+    }
+}
@@ -4,7 +4,13 @@ mod tests {
     use std::io::Write;
     use std::path::{Path, PathBuf};
 
-    use log_watchdog::run;
+    use log_watchdog::pipeline::{Action, Matcher, Source, WatchdogPipeline};
+    use log_watchdog::testing::{self, TestLog};
+    use log_watchdog::{
+        events, events_async, run, run_async, run_collect, run_once, run_once_until, run_pipelines,
+        run_reloading, run_until, run_with, run_with_observer, CancellationToken, CommandOutput,
+        Observer, WatchdogError, WatchdogStatus,
+    };
     use settings::Settings;
 
     struct TestSettings {
@@ -79,10 +85,7 @@ watchdogs:
 
         write!(log_file, "foo bar baz").unwrap();
 
-        // run binary on different thread
-        std::thread::spawn(move || {
-            run(settings);
-        });
+        let handle = run(settings);
 
         std::thread::sleep(std::time::Duration::from_secs(1));
 
@@ -93,7 +96,11 @@ watchdogs:
 
         let contents = std::fs::read_to_string(outfile_path).unwrap();
 
-        assert_eq!(contents, "hello world!\n\n");
+        assert_eq!(contents, "hello world!\n");
+        assert!(
+            matches!(&handle.join()[..], [(name, WatchdogStatus::Completed)] if name == "stdout_txt"),
+            "a oneshot watchdog completes after its match"
+        );
     }
 
     #[test]
@@ -119,10 +126,7 @@ watchdogs:
 
         write!(log_file, "foo bar baz").unwrap();
 
-        // run binary on different thread
-        std::thread::spawn(move || {
-            run(settings);
-        });
+        let handle = run(settings);
 
         std::thread::sleep(std::time::Duration::from_secs(1));
 
@@ -133,5 +137,1024 @@ watchdogs:
 
         let contents = std::fs::read_to_string(&outfile_path).unwrap_or_default();
         assert!(contents.is_empty());
+
+        handle.stop();
+        assert!(matches!(
+            &handle.join()[..],
+            [(name, WatchdogStatus::Stopped)] if name == "stdout_txt"
+        ));
+    }
+
+    #[test]
+    fn when_once_then_scanned_from_saved_position() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+
+        let (settings_path, log_path, outfile_path) = setup_settings(dir.path(), settings);
+        let state_path = dir.path().join("positions.json");
+
+        std::fs::write(&log_path, "aaa\nbbb\n").unwrap();
+        let settings = Settings::try_from(settings_path.as_path()).unwrap();
+        run_once(settings, Some(&state_path)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&outfile_path).unwrap(),
+            "hello world!\n"
+        );
+
+        let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        writeln!(log_file, "aaaa").unwrap();
+        let settings = Settings::try_from(settings_path.as_path()).unwrap();
+        run_once(settings, Some(&state_path)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&outfile_path).unwrap(),
+            "hello world!\nhello world!\n",
+            "only the appended line is matched"
+        );
+    }
+
+    #[test]
+    fn when_once_with_crlf_multibyte_and_partial_lines_then_position_exact() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+
+        let (settings_path, log_path, outfile_path) = setup_settings(dir.path(), settings);
+        let state_path = dir.path().join("positions.json");
+        let scan = || {
+            let settings = Settings::try_from(settings_path.as_path()).unwrap();
+            run_once(settings, Some(&state_path)).unwrap();
+            std::fs::read_to_string(&outfile_path)
+                .unwrap_or_default()
+                .lines()
+                .count()
+        };
+
+        std::fs::write(
+            &log_path,
+            b"aaa \xc3\xa9t\xc3\xa9\r\nbbb \xff\r\naaa still being wr",
+        )
+        .unwrap();
+        assert_eq!(scan(), 1);
+
+        let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        write!(log_file, "itten\r\n").unwrap();
+        assert_eq!(scan(), 2, "the partial line is matched once it's complete");
+        assert_eq!(scan(), 2, "nothing is read twice");
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_settings_from_stdin_then_loaded() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+        let (settings_path, _, _) = setup_settings(dir.path(), settings);
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .args(["list", "--settings", "-", "--names"])
+            .stdin(std::fs::File::open(settings_path).unwrap())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "stdout_txt\n");
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_settings_before_subcommand_then_loaded() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+        let (settings_path, _, _) = setup_settings(dir.path(), settings);
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .arg("--settings")
+            .arg(&settings_path)
+            .args(["list", "--names"])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "stdout_txt\n");
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_reload_and_settings_from_stdin_then_refused() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+        let (settings_path, _, _) = setup_settings(dir.path(), settings);
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .args(["--reload", "--settings", "-"])
+            .stdin(std::fs::File::open(settings_path).unwrap())
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("can't be reloaded"));
+    }
+
+    /// Starts the daemon with `--init`, and a watchdog that runs `sh -c
+    /// <script>` for every `aaa` appended to its log, until `started` exists.
+    #[cfg(feature = "cli")]
+    fn start_init(dir: &Path, script: &str, started: &Path) -> std::process::Child {
+        let log_path = dir.join("log.txt");
+        let settings_path = dir.join("settings.yml");
+        std::fs::write(&log_path, "").unwrap();
+        std::fs::write(
+            &settings_path,
+            format!(
+                r#"
+watchdogs:
+  init:
+    log_file: {}
+    output_file: {}
+    debounce: 0
+    oneshot: false
+    regex: ^aaa
+    commands:
+      sh:
+        args: ["-c", {script:?}]
+"#,
+                log_path.display(),
+                dir.join("out.txt").display(),
+            ),
+        )
+        .unwrap();
+
+        let mut daemon = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .args([
+                "--no-log-stdout",
+                "--init",
+                "--grace-period",
+                "5",
+                "--settings",
+            ])
+            .arg(&settings_path)
+            .spawn()
+            .unwrap();
+        for _ in 0..100 {
+            if started.exists() {
+                return daemon;
+            }
+            let mut log = OpenOptions::new().append(true).open(&log_path).unwrap();
+            log.write_all(b"aaa\n").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+        panic!("the command never started");
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_init_and_sigterm_then_forwarded_to_commands_and_exited() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let (started, terminated) = (dir.path().join("started"), dir.path().join("terminated"));
+        let script = format!(
+            "trap 'kill $!; touch {}; exit 0' TERM; touch {}; sleep 30 & wait",
+            terminated.display(),
+            started.display()
+        );
+        let mut daemon = start_init(dir.path(), &script, &started);
+
+        std::process::Command::new("kill")
+            .args(["-TERM", &daemon.id().to_string()])
+            .status()
+            .unwrap();
+        let mut status = None;
+        for _ in 0..50 {
+            status = daemon.try_wait().unwrap();
+            if status.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        assert_eq!(status.and_then(|status| status.code()), Some(0));
+        assert!(terminated.exists());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_init_and_command_orphans_process_then_zombie_reaped() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let started = dir.path().join("started");
+        let script = format!("sleep 0.1 & touch {}", started.display());
+        let mut daemon = start_init(dir.path(), &script, &started);
+        let zombies = || {
+            std::fs::read_dir("/proc")
+                .unwrap()
+                .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("stat")).ok())
+                .filter(|stat| {
+                    // the fields after the command's name: state, ppid, ...
+                    let fields: Vec<_> = stat
+                        .rsplit(')')
+                        .next()
+                        .unwrap()
+                        .split_whitespace()
+                        .collect();
+                    fields[0] == "Z" && fields[1] == daemon.id().to_string()
+                })
+                .count()
+        };
+
+        std::thread::sleep(std::time::Duration::from_secs(3));
+        let zombies = zombies();
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+
+        assert_eq!(zombies, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_public_key_and_settings_unsigned_then_not_loaded() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+        let (settings_path, _, _) = setup_settings(dir.path(), settings);
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .args(["list", "--names", "--settings"])
+            .arg(&settings_path)
+            .env(
+                "LOG_WATCHDOG_PUBLIC_KEY",
+                "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3",
+            )
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("isn't signed"));
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn when_settings_built_in_code_then_scanned() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let outfile_path = dir.path().join("out.txt");
+        std::fs::write(&log_path, "aaa\nbbb\n").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("in_code")
+            .log_file(&log_path)
+            .regex("^bbb")
+            .command("echo", ["hello world!"])
+            .output_file(&outfile_path)
+            .build()
+            .unwrap();
+        run_once(Settings::new(vec![watchdog]), None).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&outfile_path).unwrap(),
+            "hello world!\n"
+        );
+    }
+
+    #[test]
+    fn when_run_with_callback_then_called_for_every_match() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        std::fs::write(&log_path, "").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("callback")
+            .log_file(&log_path)
+            .regex("^(?<level>FATAL)")
+            .build()
+            .unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = run_with(Settings::new(vec![watchdog]), move |event| {
+            tx.send(event).unwrap();
+        });
+
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        writeln!(log_file, "FATAL: too many connections").unwrap();
+        writeln!(log_file, "LOG: ok").unwrap();
+
+        let event = rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(event.watchdog, "callback");
+        assert_eq!(event.log_file, log_path);
+        assert_eq!(event.line, "FATAL: too many connections");
+        assert_eq!(
+            event.captures,
+            vec![("level".to_string(), "FATAL".to_string())]
+        );
+
+        handle.stop();
+        handle.join();
+        assert!(rx.try_recv().is_err(), "only the matching line is an event");
+    }
+
+    #[test]
+    fn when_events_then_matches_streamed_until_stopped() {
+        let timeout = std::time::Duration::from_secs(5);
+        let mut log = TestLog::new("events").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("events")
+            .log_file(log.path())
+            .regex("^aaa")
+            .build()
+            .unwrap();
+        let (handle, events) = events(Settings::new(vec![watchdog]));
+
+        assert!(testing::wait_until_running(&handle, timeout));
+        log.append_every(std::time::Duration::ZERO, ["aaa", "bbb", "aaaa"])
+            .unwrap();
+        let first = testing::next_match(&events, timeout).unwrap();
+        let second = testing::next_match(&events, timeout).unwrap();
+
+        handle.stop();
+        assert_eq!([first.line, second.line], ["aaa", "aaaa"]);
+        assert!(events.iter().next().is_none());
+        handle.join();
+    }
+
+    #[test]
+    fn when_partial_line_times_out_then_matched_without_newline() {
+        let timeout = std::time::Duration::from_secs(5);
+        let log = TestLog::new("partial").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("partial")
+            .log_file(log.path())
+            .regex("^aaa")
+            .partial_line_timeout(50)
+            .build()
+            .unwrap();
+        let (handle, events) = events(Settings::new(vec![watchdog]));
+
+        assert!(testing::wait_until_running(&handle, timeout));
+        let mut writer = OpenOptions::new().append(true).open(log.path()).unwrap();
+        write!(writer, "aaa, and no newline yet").unwrap();
+        let event = testing::next_match(&events, timeout).unwrap();
+
+        handle.stop();
+        assert_eq!(event.line, "aaa, and no newline yet");
+        handle.join();
+    }
+
+    #[test]
+    fn when_log_file_removed_then_watched_again_once_recreated() {
+        let timeout = std::time::Duration::from_secs(5);
+        let log = TestLog::new("removed").unwrap();
+        let missing = log.dir().join("missing");
+
+        let watchdog = settings::Watchdog::builder()
+            .name("removed")
+            .log_file(log.path())
+            .regex("^aaa")
+            .on_file_missing("touch", [missing.to_str().unwrap()])
+            .build()
+            .unwrap();
+        let (handle, events) = events(Settings::new(vec![watchdog]));
+
+        assert!(testing::wait_until_running(&handle, timeout));
+        std::fs::remove_file(log.path()).unwrap();
+        assert!(testing::wait_for(timeout, || missing.exists()));
+        std::fs::write(log.path(), "aaa, recreated\n").unwrap();
+        let event = testing::next_match(&events, timeout).unwrap();
+
+        handle.stop();
+        assert_eq!(event.line, "aaa, recreated");
+        handle.join();
+    }
+
+    #[test]
+    fn when_polled_then_matched_and_watched_again_once_recreated() {
+        let timeout = std::time::Duration::from_secs(5);
+        let mut log = TestLog::new("polled").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("polled")
+            .log_file(log.path())
+            .regex("^aaa")
+            .backend(settings::WatcherBackend::Poll)
+            .poll_interval(20)
+            .build()
+            .unwrap();
+        let (handle, events) = events(Settings::new(vec![watchdog]));
+
+        assert!(testing::wait_until_running(&handle, timeout));
+        log.append_every(std::time::Duration::ZERO, ["aaa, polled"])
+            .unwrap();
+        let first = testing::next_match(&events, timeout).unwrap();
+        std::fs::remove_file(log.path()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        std::fs::write(log.path(), "aaa, recreated\n").unwrap();
+        let second = testing::next_match(&events, timeout).unwrap();
+
+        handle.stop();
+        assert_eq!([first.line, second.line], ["aaa, polled", "aaa, recreated"]);
+        handle.join();
+    }
+
+    #[test]
+    fn when_log_file_removed_and_file_missing_stop_then_watchdog_fails() {
+        let timeout = std::time::Duration::from_secs(5);
+        let log = TestLog::new("stop_removed").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("stop_removed")
+            .log_file(log.path())
+            .regex("^aaa")
+            .file_missing(settings::FileMissing::Stop)
+            .build()
+            .unwrap();
+        let handle = run(Settings::new(vec![watchdog]));
+
+        assert!(testing::wait_until_running(&handle, timeout));
+        std::fs::remove_file(log.path()).unwrap();
+
+        assert!(matches!(
+            &handle.join()[..],
+            [(_, WatchdogStatus::Failed(WatchdogError::Io { .. }))]
+        ));
+    }
+
+    #[test]
+    fn when_token_cancelled_then_watchdogs_stopped() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        std::fs::write(&log_path, "").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("cancelled")
+            .log_file(&log_path)
+            .regex(".*")
+            .build()
+            .unwrap();
+        let token = CancellationToken::new();
+        let handle = run_until(Settings::new(vec![watchdog]), token.clone());
+
+        token.cancel();
+        let statuses = handle.join();
+
+        assert_eq!(statuses.len(), 1);
+        assert!(matches!(statuses[0].1, WatchdogStatus::Stopped));
+    }
+
+    #[test]
+    fn when_settings_reloaded_then_watchdogs_restarted_with_them() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let out_path = dir.path().join("out.txt");
+        std::fs::write(&log_path, "").unwrap();
+        let watchdog = |name: &str, regex: &str| {
+            settings::Watchdog::builder()
+                .name(name)
+                .log_file(&log_path)
+                .regex(regex)
+                .command("echo", [name])
+                .output_file(&out_path)
+                .oneshot(true)
+                .build()
+                .unwrap()
+        };
+
+        let (reload, reloads) = std::sync::mpsc::channel();
+        let reloading = {
+            let settings = Settings::new(vec![watchdog("before", "^before")]);
+            std::thread::spawn(move || run_reloading(settings, &reloads))
+        };
+        reload
+            .send(Settings::new(vec![watchdog("after", "^after")]))
+            .unwrap();
+        // appended until the reloaded watchdog is running and matches
+        let timeout = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !reloading.is_finished() && std::time::Instant::now() < timeout {
+            let mut log = OpenOptions::new().append(true).open(&log_path).unwrap();
+            log.write_all(b"after\n").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        assert!(reloading.join().unwrap().is_ok());
+        assert_eq!(std::fs::read_to_string(&out_path).unwrap(), "after\n");
+    }
+
+    /// Polls the future on this thread until it's ready, like an async
+    /// runtime would.
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::sync::Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(output) => return output,
+                std::task::Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn when_events_async_then_matches_streamed_until_stopped() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        std::fs::write(&log_path, "").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("events")
+            .log_file(&log_path)
+            .regex("^aaa")
+            .build()
+            .unwrap();
+        let (handle, mut events) = events_async(Settings::new(vec![watchdog]));
+
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        writeln!(log_file, "aaa").unwrap();
+        writeln!(log_file, "bbb").unwrap();
+
+        let event = block_on(events.next()).unwrap();
+        assert_eq!(event.line, "aaa");
+        handle.stop();
+        assert!(block_on(events.next()).is_none());
+    }
+
+    #[test]
+    fn when_run_async_and_watchdog_fails_then_error() {
+        let watchdog = settings::Watchdog::builder()
+            .name("missing")
+            .log_file("does/not/exist.log")
+            .regex(".*")
+            .build()
+            .unwrap();
+
+        let error = block_on(run_async(Settings::new(vec![watchdog]))).unwrap_err();
+
+        assert_eq!(error.watchdog(), Some("missing"));
+    }
+
+    #[test]
+    fn when_run_collect_then_matches_returned_and_commands_not_run() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let touched = dir.path().join("touched");
+        std::fs::write(&log_path, "").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("collect")
+            .log_file(&log_path)
+            .regex("^ERROR (?<code>[0-9]+)")
+            .command("touch", [touched.to_str().unwrap()])
+            .build()
+            .unwrap();
+        let writer = {
+            let log_path = log_path.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+                writeln!(log_file, "INFO 200").unwrap();
+                writeln!(log_file, "ERROR 500").unwrap();
+            })
+        };
+
+        let matches = run_collect(
+            Settings::new(vec![watchdog]),
+            std::time::Duration::from_secs(2),
+        );
+        writer.join().unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line, "ERROR 500");
+        assert_eq!(matches[0].captures, vec![("code".into(), "500".into())]);
+        assert!(!touched.exists());
+    }
+
+    #[derive(Default)]
+    struct CountingObserver {
+        lines: std::sync::atomic::AtomicUsize,
+        matches: std::sync::atomic::AtomicUsize,
+        commands: std::sync::Mutex<Vec<(String, Option<i32>)>>,
+    }
+
+    impl Observer for CountingObserver {
+        fn line_read(&self, _watchdog: &str, _line: &str) {
+            self.lines
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        fn matched(&self, _event: &log_watchdog::MatchEvent) {
+            self.matches
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        fn command_finished(
+            &self,
+            _watchdog: &str,
+            command: &settings::Command,
+            exit_code: Option<i32>,
+            _duration: std::time::Duration,
+        ) {
+            self.commands
+                .lock()
+                .unwrap()
+                .push((command.name.clone(), exit_code));
+        }
+    }
+
+    #[test]
+    fn when_observed_then_lines_matches_and_commands_reported() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        std::fs::write(&log_path, "").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("observed")
+            .log_file(&log_path)
+            .regex("^aaa")
+            .command("true", Vec::<String>::new())
+            .build()
+            .unwrap();
+        let observer = std::sync::Arc::new(CountingObserver::default());
+        let handle = run_with_observer(Settings::new(vec![watchdog]), observer.clone());
+
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        writeln!(log_file, "aaa").unwrap();
+        writeln!(log_file, "bbb").unwrap();
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        handle.stop();
+        handle.join();
+
+        let ordering = std::sync::atomic::Ordering::Relaxed;
+        assert_eq!(observer.lines.load(ordering), 2);
+        assert_eq!(observer.matches.load(ordering), 1);
+        assert_eq!(
+            *observer.commands.lock().unwrap(),
+            vec![("true".into(), Some(0))]
+        );
+    }
+
+    struct Given(Vec<String>);
+
+    impl Source for Given {
+        fn read(
+            &mut self,
+            _timeout: std::time::Duration,
+        ) -> std::io::Result<Option<log_watchdog::Lines>> {
+            // every line at once, then none
+            let lines = std::mem::take(&mut self.0);
+            Ok((!lines.is_empty()).then(|| lines.into()))
+        }
+    }
+
+    struct StartsWithA;
+
+    impl Matcher for StartsWithA {
+        fn captures(&self, line: &str) -> Option<Vec<(String, String)>> {
+            line.starts_with('a').then(Vec::new)
+        }
+    }
+
+    struct Page;
+
+    impl Action for Page {
+        fn run(
+            &mut self,
+            event: &log_watchdog::MatchEvent,
+            outputs: &mut Vec<CommandOutput>,
+        ) -> Result<(), WatchdogError> {
+            outputs.push(CommandOutput {
+                command: "page".into(),
+                stdout: format!("paged {}", event.line),
+                stderr: String::new(),
+                exit_code: Some(0),
+                duration: std::time::Duration::ZERO,
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn when_pipeline_stages_swapped_then_output_written() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let out_path = dir.path().join("out.txt");
+
+        let watchdog = settings::Watchdog::builder()
+            .name("pipeline")
+            .log_file("lines")
+            .regex("unused")
+            .output_file(&out_path)
+            .build()
+            .unwrap();
+        let pipeline = WatchdogPipeline::new(watchdog)
+            .source(Given(vec!["a 1".into(), "b 2".into(), "a 3".into()]))
+            .matcher(StartsWithA)
+            .action(Page);
+        let statuses = run_pipelines(vec![pipeline]).join();
+
+        assert!(matches!(statuses[0].1, WatchdogStatus::Completed));
+        assert_eq!(
+            std::fs::read_to_string(&out_path).unwrap(),
+            "paged a 1\npaged a 3\n"
+        );
+    }
+
+    #[test]
+    fn when_log_file_missing_then_error_names_watchdog() {
+        let watchdog = settings::Watchdog::builder()
+            .name("missing")
+            .log_file("does/not/exist.log")
+            .regex(".*")
+            .build()
+            .unwrap();
+
+        let error = run_once(Settings::new(vec![watchdog]), None).unwrap_err();
+
+        assert!(matches!(error, WatchdogError::Io { .. }));
+        assert_eq!(error.watchdog(), Some("missing"));
+    }
+
+    #[test]
+    fn when_command_sandboxed_then_run_with_minimal_environment() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let outfile_path = dir.path().join("out.txt");
+        std::fs::write(&log_path, "aaa\n").unwrap();
+
+        let mut watchdog = settings::Watchdog::builder()
+            .name("sandboxed")
+            .log_file(&log_path)
+            .regex("^aaa")
+            .command("env", Vec::<String>::new())
+            .output_file(&outfile_path)
+            .build()
+            .unwrap();
+        watchdog.commands[0].sandbox = Some(settings::Sandbox::default());
+        run_once(Settings::new(vec![watchdog]), None).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&outfile_path).unwrap(),
+            format!("PATH={}\n", settings::Sandbox::DEFAULT_PATH)
+        );
+    }
+
+    #[test]
+    fn when_run_once_stopped_then_scan_stops_and_position_saved() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let outfile_path = dir.path().join("out.txt");
+        let state_path = dir.path().join("state.json");
+        std::fs::write(&log_path, "aaa\n").unwrap();
+        let settings = || {
+            let watchdog = settings::Watchdog::builder()
+                .name("stopped")
+                .log_file(&log_path)
+                .regex("^aaa")
+                .command("echo", ["matched"])
+                .output_file(&outfile_path)
+                .build()
+                .unwrap();
+            Settings::new(vec![watchdog])
+        };
+
+        let token = CancellationToken::new();
+        token.cancel();
+        run_once_until(settings(), Some(&state_path), &token).unwrap();
+        assert!(std::fs::read_to_string(&outfile_path)
+            .unwrap_or_default()
+            .is_empty());
+
+        // the next run carries on from where the stopped one was
+        run_once(settings(), Some(&state_path)).unwrap();
+        assert_eq!(std::fs::read_to_string(&outfile_path).unwrap(), "matched\n");
+    }
+
+    #[test]
+    fn when_redact_patterns_then_secrets_masked_in_output() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let outfile_path = dir.path().join("out.jsonl");
+        std::fs::write(&log_path, "GET /hook?token=abc123 503\n").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("redacted")
+            .log_file(&log_path)
+            .regex(r"^GET (?<url>\S+) 503")
+            .command("echo", ["https://alerts.example.com/?token=s3cr3t"])
+            .output(settings::OutputSettings {
+                target: settings::OutputTarget::File(outfile_path.clone()),
+                format: settings::OutputFormat::Jsonl,
+                tls: settings::Tls::default(),
+            })
+            .redact(r"token=([^&\s]+)")
+            .build()
+            .unwrap();
+        run_once(Settings::new(vec![watchdog]), None).unwrap();
+
+        let record = std::fs::read_to_string(&outfile_path).unwrap();
+        assert!(!record.contains("abc123"), "{record}");
+        assert!(!record.contains("s3cr3t"), "{record}");
+        assert!(
+            record.contains(r#""url":"/hook?token=[REDACTED]""#),
+            "{record}"
+        );
+    }
+
+    #[test]
+    fn when_audit_log_then_every_command_recorded_and_verified() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let audit_path = dir.path().join("audit.jsonl");
+        std::fs::write(&log_path, "aaa\nbbb\naaa\n").unwrap();
+
+        let watchdog = settings::Watchdog::builder()
+            .name("audited")
+            .log_file(&log_path)
+            .regex("^aaa")
+            .command("true", Vec::<String>::new())
+            .command("echo", ["done"])
+            .audit_log(&audit_path)
+            .build()
+            .unwrap();
+        run_once(Settings::new(vec![watchdog]), None).unwrap();
+
+        assert_eq!(log_watchdog::audit::verify(&audit_path).unwrap(), 4);
+        let audit = std::fs::read_to_string(&audit_path).unwrap();
+        let entry: serde_json::Value = serde_json::from_str(audit.lines().last().unwrap()).unwrap();
+        assert_eq!(entry["watchdog"], "audited");
+        assert_eq!(entry["argv"][1], "done");
+        assert_eq!(entry["exit_code"], 0);
+    }
+
+    #[test]
+    fn when_allowed_command_replaced_then_not_run() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let log_path = dir.path().join("log.txt");
+        let script = dir.path().join("notify");
+        std::fs::write(&log_path, "aaa\n").unwrap();
+        std::fs::write(&script, "#!/bin/sh\necho notified\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let hash = settings::sha256::sha256(&std::fs::read(&script).unwrap());
+
+        let watchdog = settings::Watchdog::builder()
+            .name("allowed")
+            .log_file(&log_path)
+            .regex("^aaa")
+            .command(script.to_str().unwrap(), Vec::<String>::new())
+            .output_file(dir.path().join("out.txt"))
+            .allowed_commands(settings::AllowedCommands::new(vec![
+                settings::AllowedCommand::Sha256(hash),
+            ]))
+            .build()
+            .unwrap();
+        std::fs::write(&script, "#!/bin/sh\necho tampered\n").unwrap();
+        let error = run_once(Settings::new(vec![watchdog]), None).unwrap_err();
+
+        assert!(matches!(error, WatchdogError::NotAllowed { .. }));
+        assert!(!std::fs::read_to_string(dir.path().join("out.txt"))
+            .unwrap_or_default()
+            .contains("tampered"));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_executor_then_commands_run_by_it_for_the_reader() {
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+        let (settings_path, log_path, outfile_path) = setup_settings(dir.path(), settings);
+        let socket = dir.path().join("executor.sock");
+        let settings = std::fs::read_to_string(&settings_path).unwrap();
+        let settings = format!("executor: {}\n{settings}", socket.display());
+        std::fs::write(&settings_path, settings).unwrap();
+        std::fs::write(&log_path, "aaa\nbbb\n").unwrap();
+
+        let mut executor = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .args(["executor", "--no-log-stdout", "--settings"])
+            .arg(&settings_path)
+            .spawn()
+            .unwrap();
+        for _ in 0..50 {
+            if socket.exists() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .args(["--once", "--no-log-stdout", "--settings"])
+            .arg(&settings_path)
+            .status()
+            .unwrap();
+        let exited = executor.try_wait().unwrap();
+        executor.kill().unwrap();
+        executor.wait().unwrap();
+
+        assert_eq!(exited, None, "the executor runs until it's killed");
+        assert!(status.success());
+        assert_eq!(
+            std::fs::read_to_string(&outfile_path).unwrap(),
+            "hello world!\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn when_scan_requested_then_lines_from_before_start_matched() {
+        use std::io::Read;
+
+        let dir = tempdir::TempDir::new("test_").unwrap();
+        let settings = TestSettings {
+            log_name: "log.txt",
+            out_name: "out.txt",
+            debounce: 0,
+            oneshot: false,
+            regex: "^aaa",
+        };
+        let (settings_path, log_path, outfile_path) = setup_settings(dir.path(), settings);
+        std::fs::write(
+            &log_path, "aaa
+bbb
+",
+        )
+        .unwrap();
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = format!("127.0.0.1:{port}");
+
+        let mut watchdog = std::process::Command::new(env!("CARGO_BIN_EXE_log-watchdog"))
+            .args(["--no-log-stdout", "--scan-address", &address, "--settings"])
+            .arg(&settings_path)
+            .spawn()
+            .unwrap();
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(connected) = std::net::TcpStream::connect(&address) {
+                stream = Some(connected);
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        let mut stream = stream.expect("the scan endpoint is listening");
+        stream
+            .write_all(b"POST /scan/stdout_txt?full=true HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        watchdog.kill().unwrap();
+        watchdog.wait().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with(r#""matches":1,"position":8,"watchdog":"stdout_txt"}"#));
+        assert_eq!(
+            std::fs::read_to_string(&outfile_path).unwrap(),
+            "hello world!\n"
+        );
     }
 }